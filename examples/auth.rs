@@ -9,7 +9,7 @@ const RTM_AUTH_EX_ID: &str = "config";
 async fn main() -> Result<(), anyhow::Error> {
     let config: rememberthemilk::RTMConfig = confy::load(RTM_AUTH_APP_NAME, Some(RTM_AUTH_EX_ID))?;
     let mut api = if config.api_key.is_some() && config.api_secret.is_some() {
-        API::from_config(config)
+        API::from_config(config)?
     } else {
         let args: Vec<String> = env::args().collect();
         let api_key = args[1].clone();
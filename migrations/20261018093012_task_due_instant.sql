@@ -0,0 +1,28 @@
+-- Store the due date as a plain UTC instant, with a separate flag for
+-- whether it has a time.  Date-only tasks are due on the user's local day
+-- starting at `due_time`, which filters compare against local day starts
+-- rather than adding a fixed 24 hours (which is wrong across DST changes).
+DROP TABLE IF EXISTS tasks_old;
+ALTER TABLE tasks RENAME TO tasks_old;
+
+CREATE TABLE tasks (
+    list_id TEXT NOT NULL,
+    taskseries_id TEXT NOT NULL,
+    task_id TEXT NOT NULL,
+    deleted BOOLEAN DEFAULT FALSE,
+    data JSONB NOT NULL,
+    due_time DATETIME GENERATED ALWAYS AS (
+        CASE json_extract(data, "$.due")
+            WHEN "" THEN NULL
+            ELSE datetime(json_extract(data, "$.due"))
+            END)
+        STORED,
+    has_due_time BOOLEAN GENERATED ALWAYS AS (
+        json_extract(data, "$.has_due_time") = "1")
+        STORED,
+    PRIMARY KEY (list_id, taskseries_id, task_id)
+);
+INSERT INTO tasks (list_id, taskseries_id, task_id, deleted, data)
+   SELECT list_id, taskseries_id, task_id, deleted, data
+   FROM tasks_old;
+DROP TABLE tasks_old;
@@ -0,0 +1,4 @@
+-- Keep the search filter of smart lists, so that they can be expanded when
+-- filtering locally.
+ALTER TABLE lists ADD COLUMN smart BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE lists ADD COLUMN filter TEXT;
@@ -0,0 +1,13 @@
+-- Short handles for tasks, for use on the command line.  Handles are never
+-- reused, so a handle always refers to the same task.
+CREATE TABLE task_handles (
+    handle INTEGER PRIMARY KEY AUTOINCREMENT,
+    taskseries_id TEXT NOT NULL,
+    task_id TEXT NOT NULL,
+    UNIQUE (taskseries_id, task_id)
+);
+
+INSERT INTO task_handles(taskseries_id, task_id)
+    SELECT taskseries_id, task_id FROM tasks
+    WHERE deleted IS NOT TRUE
+    ORDER BY rowid;
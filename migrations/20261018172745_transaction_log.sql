@@ -0,0 +1,16 @@
+-- Timelines, so that they can be reused between runs.
+CREATE TABLE timelines (
+    timeline TEXT PRIMARY KEY,
+    created DATETIME NOT NULL
+);
+
+-- Undoable transactions, most recent last, for `rtm undo` and
+-- `rtm history`.
+CREATE TABLE transactions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timeline TEXT NOT NULL,
+    transaction_id TEXT NOT NULL,
+    description TEXT NOT NULL,
+    created DATETIME NOT NULL,
+    undone BOOLEAN NOT NULL DEFAULT FALSE
+);
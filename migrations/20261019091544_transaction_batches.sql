@@ -0,0 +1,3 @@
+-- Transactions made together, such as by `rtm bulk`, share a batch (the
+-- id of the first of them) so that they are undone as one change.
+ALTER TABLE transactions ADD COLUMN batch INTEGER;
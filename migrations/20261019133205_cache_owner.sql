@@ -0,0 +1,6 @@
+-- The RTM user whose tasks are cached, so that a cache can't be used with
+-- another account's token.
+CREATE TABLE cache_owner (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    user_id TEXT NOT NULL
+);
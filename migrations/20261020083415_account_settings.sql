@@ -0,0 +1,6 @@
+-- Settings from the RTM account, saved when syncing.  The time zone is
+-- used to interpret dates such as "today" in filters.
+CREATE TABLE account_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    timezone TEXT
+);
@@ -0,0 +1,203 @@
+//! Arranging tasks by the day they are due, for agenda and calendar views.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone};
+
+use crate::sort::TaskEntry;
+
+/// A task due on a particular day.
+#[derive(Debug, Clone, Copy)]
+pub struct AgendaItem<'a> {
+    /// The task.
+    pub entry: TaskEntry<'a>,
+    /// The local date the task is due.
+    pub date: NaiveDate,
+    /// The local time the task is due, if it has one.
+    pub time: Option<NaiveTime>,
+    /// True if this is a future occurrence of a repeating task, rather than
+    /// the task's current due date.
+    pub repeat: bool,
+}
+
+/// The tasks due on one day.
+#[derive(Debug, Clone)]
+pub struct AgendaDay<'a> {
+    /// The local date.
+    pub date: NaiveDate,
+    /// The tasks, with those without a time first and then by time.
+    pub items: Vec<AgendaItem<'a>>,
+}
+
+/// Tasks arranged by the local day they are due.
+#[derive(Debug, Clone)]
+pub struct Agenda<'a> {
+    /// Incomplete tasks due before the first day.
+    pub overdue: Vec<AgendaItem<'a>>,
+    /// Each day in the range, including those with nothing due.
+    pub days: Vec<AgendaDay<'a>>,
+}
+
+fn sort_items(items: &mut [AgendaItem<'_>]) {
+    items.sort_by(|a, b| {
+        (a.date, a.time, &a.entry.series.name).cmp(&(b.date, b.time, &b.entry.series.name))
+    });
+}
+
+impl<'a> Agenda<'a> {
+    /// Arrange the incomplete tasks in `entries` into days, starting at
+    /// `first` and covering `days` days, using `tz` for day boundaries.
+    /// Tasks which repeat on a fixed schedule ("every" rather than "after")
+    /// also appear on each future day they will be due.
+    pub fn build<Tz: TimeZone>(
+        entries: &[TaskEntry<'a>],
+        first: NaiveDate,
+        days: u32,
+        tz: &Tz,
+    ) -> Agenda<'a> {
+        let last = first + Days::new(u64::from(days.max(1)) - 1);
+        let mut overdue = Vec::new();
+        let mut by_day: BTreeMap<NaiveDate, Vec<AgendaItem<'a>>> = BTreeMap::new();
+        for &entry in entries {
+            let task = entry.task;
+            let Some(due) = task.due else { continue };
+            if task.completed.is_some() || task.deleted.is_some() {
+                continue;
+            }
+            let local: DateTime<Tz> = due.with_timezone(tz);
+            let date = local.date_naive();
+            let time = task.has_due_time.then(|| local.time());
+            let item = AgendaItem {
+                entry,
+                date,
+                time,
+                repeat: false,
+            };
+            if date < first {
+                overdue.push(item);
+            } else if date <= last {
+                by_day.entry(date).or_default().push(item);
+            }
+            let Some(repeat) = &entry.series.repeat else {
+                continue;
+            };
+            if !repeat.every {
+                continue;
+            }
+            match repeat.recurrence() {
+                Ok(recurrence) => {
+                    for date in recurrence.dates_after(date, last) {
+                        if date >= first {
+                            by_day.entry(date).or_default().push(AgendaItem {
+                                date,
+                                repeat: true,
+                                ..item
+                            });
+                        }
+                    }
+                }
+                Err(e) => log::warn!("Not expanding {:?}: {e}", repeat.rule),
+            }
+        }
+        sort_items(&mut overdue);
+        let days = first
+            .iter_days()
+            .take_while(|d| *d <= last)
+            .map(|date| {
+                let mut items = by_day.remove(&date).unwrap_or_default();
+                sort_items(&mut items);
+                AgendaDay { date, items }
+            })
+            .collect();
+        Agenda { overdue, days }
+    }
+
+    /// The number of tasks due on each day.
+    pub fn counts(&self) -> BTreeMap<NaiveDate, usize> {
+        self.days
+            .iter()
+            .map(|day| (day.date, day.items.len()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Agenda;
+    use crate::RTMTasks;
+    use chrono::NaiveDate;
+    use serde_json::Value;
+
+    fn series(name: &str, due: &str, has_due_time: bool, rule: Option<&str>) -> Value {
+        let mut json = crate::tests::series(
+            name,
+            serde_json::json!({}),
+            serde_json::json!({"due": due, "has_due_time": if has_due_time { "1" } else { "0" }}),
+        );
+        if let Some(rule) = rule {
+            json["rrule"] = serde_json::json!({"every": "1", "$t": rule});
+        }
+        json
+    }
+
+    #[test]
+    fn test_agenda() {
+        let tz = chrono_tz::Europe::London;
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [
+                    series("late", "2026-10-16T23:00:00Z", false, None),
+                    // 00:30 on the 20th, local time.
+                    series("early", "2026-10-19T23:30:00Z", true, None),
+                    series("all day", "2026-10-19T23:00:00Z", false, None),
+                    series("weekly", "2026-10-18T23:00:00Z", false,
+                        Some("FREQ=WEEKLY;INTERVAL=1;BYDAY=MO,WE")),
+                    series("later", "2026-11-30T00:00:00Z", false, None),
+                ],
+            }],
+        }))
+        .unwrap();
+        let entries = tasks.entries();
+        let first = NaiveDate::from_ymd_opt(2026, 10, 19).unwrap();
+        let agenda = Agenda::build(&entries, first, 3, &tz);
+
+        let names: Vec<_> = agenda
+            .overdue
+            .iter()
+            .map(|i| i.entry.series.name.as_str())
+            .collect();
+        assert_eq!(names, ["late"]);
+        // Each day as "date: [time ]name[ (repeat)], ...".
+        let days: Vec<String> = agenda
+            .days
+            .iter()
+            .map(|day| {
+                let items: Vec<String> = day
+                    .items
+                    .iter()
+                    .map(|i| {
+                        let time = i.time.map(|t| t.format("%H:%M ").to_string());
+                        let repeat = if i.repeat { " (repeat)" } else { "" };
+                        format!(
+                            "{}{}{repeat}",
+                            time.unwrap_or_default(),
+                            i.entry.series.name
+                        )
+                    })
+                    .collect();
+                format!("{}: {}", day.date, items.join(", "))
+            })
+            .collect();
+        assert_eq!(
+            days,
+            [
+                "2026-10-19: weekly",
+                "2026-10-20: all day, 00:30 early",
+                "2026-10-21: weekly (repeat)",
+            ]
+        );
+        assert_eq!(agenda.counts().values().sum::<usize>(), 4);
+    }
+}
@@ -0,0 +1,660 @@
+//! Backing up a whole account, and restoring it into another.
+//!
+//! A [Backup] keeps RTM's own JSON for lists, tasks (including completed
+//! and deleted ones), locations, contacts and settings, so nothing is lost
+//! even where it can't be restored.  [API::restore] recreates lists, smart
+//! lists, tasks and contacts.  RTM has no methods for creating locations or
+//! changing settings, so tasks are only given a location if one with the
+//! same name exists, and settings are left alone.  Completion and creation
+//! dates can't be set either, so they become the time of the restore.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bulk::Throttle;
+use crate::sort::{parse_estimate, TaskEntry};
+use crate::{RTMLists, RTMTasks, Task, API};
+
+/// The archive format version written by [API::backup].
+pub const BACKUP_VERSION: u32 = 1;
+
+/// A complete copy of an account, as written by `rtm backup`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    /// The archive format version; see [BACKUP_VERSION].
+    pub version: u32,
+    /// When the backup was made.
+    pub created: DateTime<Utc>,
+    /// The `list` array from `rtm.lists.getList`.
+    pub lists: serde_json::Value,
+    /// The `tasks` object from `rtm.tasks.getList`, including deleted
+    /// tasks.
+    pub tasks: serde_json::Value,
+    /// The `locations` object from `rtm.locations.getList`.
+    pub locations: serde_json::Value,
+    /// The `contacts` object from `rtm.contacts.getList`.
+    pub contacts: serde_json::Value,
+    /// The `settings` object from `rtm.settings.getList`.
+    pub settings: serde_json::Value,
+}
+
+/// A list as stored in a backup.
+#[derive(Debug, Clone, Deserialize)]
+struct BackupList {
+    id: String,
+    name: String,
+    #[serde(default, deserialize_with = "crate::bool_from_string")]
+    deleted: bool,
+    /// The built-in Inbox and Sent lists.
+    #[serde(default, deserialize_with = "crate::bool_from_string")]
+    locked: bool,
+    #[serde(default, deserialize_with = "crate::bool_from_string")]
+    archived: bool,
+    #[serde(default, deserialize_with = "crate::bool_from_string")]
+    smart: bool,
+    #[serde(default)]
+    filter: Option<String>,
+}
+
+/// A contact or location as stored in a backup; only the name is needed.
+#[derive(Debug, Clone, Deserialize)]
+struct Named {
+    #[serde(default)]
+    id: String,
+    #[serde(default, alias = "username")]
+    name: String,
+}
+
+/// Return the items of a container such as `{"contact": [...]}`, which RTM
+/// sends as `[]` when empty.
+fn items<T: serde::de::DeserializeOwned>(container: &serde_json::Value, key: &str) -> Vec<T> {
+    match container.get(key) {
+        Some(serde_json::Value::Array(values)) => values
+            .iter()
+            .filter_map(|v| serde_json::from_value(v.clone()).ok())
+            .collect(),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(|v| vec![v])
+            .unwrap_or_default(),
+        None => Vec::new(),
+    }
+}
+
+/// What [API::restore] did.
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+    /// Old list ids to new ones.
+    pub lists: HashMap<String, String>,
+    /// Old task ids to the new list, task series and task ids.
+    pub tasks: HashMap<String, (String, String, String)>,
+    /// The number of contacts added.
+    pub contacts: usize,
+    /// Things which couldn't be restored.
+    pub warnings: Vec<String>,
+    /// Requests which failed, leaving something partly restored.
+    pub failures: Vec<String>,
+}
+
+impl RestoreReport {
+    fn failed(&mut self, step: String, error: crate::Error) {
+        self.failures.push(format!("{step}: {error}"));
+    }
+
+    fn check<T>(&mut self, step: impl FnOnce() -> String, result: Result<T, crate::Error>) {
+        if let Err(e) = result {
+            self.failed(step(), e);
+        }
+    }
+}
+
+impl Backup {
+    /// The tasks in the backup.
+    pub fn tasks(&self) -> Result<RTMTasks, crate::Error> {
+        Ok(serde_json::from_value(self.tasks.clone())?)
+    }
+
+    /// The location id of each task series, which [TaskSeries](crate::TaskSeries)
+    /// doesn't keep.
+    fn series_locations(&self) -> HashMap<String, String> {
+        let mut result = HashMap::new();
+        for list in self.tasks["list"].as_array().into_iter().flatten() {
+            for series in list["taskseries"].as_array().into_iter().flatten() {
+                if let (Some(id), Some(location)) =
+                    (series["id"].as_str(), series["location_id"].as_str())
+                {
+                    if !location.is_empty() {
+                        result.insert(id.to_string(), location.to_string());
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Order the tasks to restore so that each comes after its parent, leaving
+/// out deleted ones.
+fn restore_order<'a>(entries: &[TaskEntry<'a>]) -> Vec<TaskEntry<'a>> {
+    let mut remaining: Vec<TaskEntry<'a>> = entries
+        .iter()
+        .filter(|e| e.task.deleted.is_none())
+        .copied()
+        .collect();
+    let mut result: Vec<TaskEntry<'a>> = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let before = remaining.len();
+        let (ready, waiting): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|e| {
+            e.series.parent_task_id.as_ref().is_none_or(|parent| {
+                result.iter().any(|r| r.task.id == *parent)
+                    || !entries
+                        .iter()
+                        .any(|o| o.task.id == *parent && o.task.deleted.is_none())
+            })
+        });
+        result.extend(ready);
+        remaining = waiting;
+        if remaining.len() == before {
+            result.append(&mut remaining);
+        }
+    }
+    result
+}
+
+impl API {
+    /// Make a complete backup of the account.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn backup(&self) -> Result<Backup, crate::Error> {
+        let created = Utc::now();
+        let mut lists = self.call_method("rtm.lists.getList", &[]).await?;
+        // Passing a last sync time includes deleted tasks.
+        let tasks = self
+            .get_tasks_filtered_sync_json("", Some(DateTime::UNIX_EPOCH))
+            .await?;
+        let mut locations = self.call_method("rtm.locations.getList", &[]).await?;
+        let mut contacts = self.call_method("rtm.contacts.getList", &[]).await?;
+        let mut settings = self.call_method("rtm.settings.getList", &[]).await?;
+        Ok(Backup {
+            version: BACKUP_VERSION,
+            created,
+            lists: lists["lists"]["list"].take(),
+            tasks,
+            locations: locations["locations"].take(),
+            contacts: contacts["contacts"].take(),
+            settings: settings["settings"].take(),
+        })
+    }
+
+    /// Recreate the lists, tasks and contacts from a backup, which should
+    /// be into an empty account: unless `force` is true, this fails if the
+    /// account already has tasks.  Requests are spaced at least `interval`
+    /// apart (see [RTM_REQUEST_INTERVAL](crate::bulk::RTM_REQUEST_INTERVAL)).
+    /// `progress` is called with a description of each step.
+    ///
+    /// Once the restore has started, anything which fails is recorded in
+    /// the report's failures and the rest carries on, so that the report
+    /// always describes what was restored.
+    ///
+    /// Requires a valid user authentication token with write permission.
+    pub async fn restore(
+        &self,
+        backup: &Backup,
+        interval: Duration,
+        force: bool,
+        mut progress: impl FnMut(&str),
+    ) -> Result<RestoreReport, crate::Error> {
+        if backup.version > BACKUP_VERSION {
+            bail!(
+                "Backup version {} is newer than this version supports ({})",
+                backup.version,
+                BACKUP_VERSION
+            );
+        }
+        let lists: Vec<BackupList> = serde_json::from_value(backup.lists.clone())?;
+        let tasks = backup.tasks()?;
+        let mut throttle = Throttle::new(interval);
+        throttle.wait().await;
+        if !force && !self.get_all_tasks().await?.entries().is_empty() {
+            bail!("The account already has tasks; restore with force to add to them anyway.");
+        }
+        let mut report = RestoreReport::default();
+        throttle.wait().await;
+        let timeline = self.get_timeline().await?;
+        throttle.wait().await;
+        let existing = self.get_lists().await?;
+
+        // Archived lists are archived once their tasks have been added, as
+        // tasks can't be added to an archived list.
+        let mut to_archive = Vec::new();
+        for list in lists.iter().filter(|l| !l.deleted) {
+            let new_id = if list.locked {
+                match existing.iter().find(|l| l.name == list.name) {
+                    Some(l) => l.id.clone(),
+                    None => {
+                        report
+                            .warnings
+                            .push(format!("No built-in list {:?}", list.name));
+                        continue;
+                    }
+                }
+            } else {
+                progress(&format!("Adding list {}", list.name));
+                let filter = list.filter.as_deref().filter(|_| list.smart);
+                throttle.wait().await;
+                let new_list = match self.add_list(&timeline, &list.name, filter).await {
+                    Ok(new_list) => new_list,
+                    Err(e) => {
+                        report.failed(format!("Adding list {:?}", list.name), e);
+                        continue;
+                    }
+                };
+                if list.archived {
+                    to_archive.push((new_list.id.clone(), &list.name));
+                }
+                new_list.id
+            };
+            report.lists.insert(list.id.clone(), new_id);
+        }
+
+        throttle.wait().await;
+        let new_locations: HashMap<String, String> =
+            match self.call_method("rtm.locations.getList", &[]).await {
+                Ok(mut rsp) => items::<Named>(&rsp["locations"].take(), "location")
+                    .into_iter()
+                    .map(|l| (l.name, l.id))
+                    .collect(),
+                Err(e) => {
+                    report.failed("Getting locations".into(), e);
+                    HashMap::new()
+                }
+            };
+        let old_locations: HashMap<String, String> = items::<Named>(&backup.locations, "location")
+            .into_iter()
+            .map(|l| (l.id, l.name))
+            .collect();
+        let series_locations = backup.series_locations();
+
+        let entries = tasks.entries();
+        let mut new_tasks: HashMap<String, Task> = HashMap::new();
+        for entry in restore_order(&entries) {
+            let (series, task) = (entry.series, entry.task);
+            progress(&format!("Adding task {}", series.name));
+            let list = report.lists.get(entry.list_id).map(|id| RTMLists {
+                id: id.clone(),
+                taskseries: None,
+            });
+            let parent = series.parent_task_id.as_ref().and_then(|p| {
+                let parent = new_tasks.get(p);
+                if parent.is_none()
+                    && entries
+                        .iter()
+                        .any(|e| e.task.id == *p && e.task.deleted.is_none())
+                {
+                    report.warnings.push(format!(
+                        "{:?} added without its parent, which wasn't restored",
+                        series.name
+                    ));
+                }
+                parent
+            });
+            throttle.wait().await;
+            let added = self
+                .add_task(&timeline, &series.name, list.as_ref(), parent, None, false)
+                .await;
+            let added = match added {
+                Ok(added) => added.and_then(|list| {
+                    let new_series = list.taskseries.as_ref()?.first()?.clone();
+                    let new_task = new_series.task.first()?.clone();
+                    Some((list, new_series, new_task))
+                }),
+                Err(e) => {
+                    report.failed(format!("Adding task {:?}", series.name), e);
+                    continue;
+                }
+            };
+            let Some((added, new_series, new_task)) = added else {
+                report.failures.push(format!(
+                    "Adding task {:?}: RTM didn't return the new task",
+                    series.name
+                ));
+                continue;
+            };
+            let ids = (
+                added.id.as_str(),
+                new_series.id.as_str(),
+                new_task.id.as_str(),
+            );
+            report.tasks.insert(
+                task.id.clone(),
+                (ids.0.to_string(), ids.1.to_string(), ids.2.to_string()),
+            );
+            let step = |what: &str| format!("Setting the {what} of {:?}", series.name);
+            if let Some(due) = task.due {
+                throttle.wait().await;
+                let result = self
+                    .set_due_date_id(&timeline, ids.0, ids.1, ids.2, due, task.has_due_time)
+                    .await;
+                report.check(|| step("due date"), result);
+            }
+            if task.priority != "N" {
+                throttle.wait().await;
+                let result = self
+                    .set_priority_id(&timeline, ids.0, ids.1, ids.2, &task.priority)
+                    .await;
+                report.check(|| step("priority"), result);
+            }
+            if !series.tags.is_empty() {
+                let tags: Vec<&str> = series.tags.iter().map(String::as_str).collect();
+                throttle.wait().await;
+                let result = self
+                    .add_tags_id(&timeline, ids.0, ids.1, ids.2, &tags)
+                    .await;
+                report.check(|| step("tags"), result);
+            }
+            for note in &series.notes {
+                throttle.wait().await;
+                let result = self
+                    .add_note_id(&timeline, ids.0, ids.1, ids.2, &note.title, &note.text)
+                    .await;
+                report.check(|| step("notes"), result);
+            }
+            if !series.url.is_empty() {
+                throttle.wait().await;
+                let result = self
+                    .set_url_id(&timeline, ids.0, ids.1, ids.2, &series.url)
+                    .await;
+                report.check(|| step("URL"), result);
+            }
+            if let Some(estimate) = parse_estimate(&task.estimate) {
+                let minutes = format!("{} minutes", estimate.num_minutes());
+                throttle.wait().await;
+                let result = self
+                    .set_estimate_id(&timeline, ids.0, ids.1, ids.2, &minutes)
+                    .await;
+                report.check(|| step("estimate"), result);
+            }
+            if let Some(location) = series_locations.get(&series.id) {
+                let name = old_locations.get(location);
+                match name.and_then(|name| new_locations.get(name)) {
+                    Some(new_location) => {
+                        throttle.wait().await;
+                        let result = self
+                            .set_location_id(&timeline, ids.0, ids.1, ids.2, new_location)
+                            .await;
+                        report.check(|| step("location"), result);
+                    }
+                    None => report.warnings.push(format!(
+                        "No location {:?} for {:?}",
+                        name.map_or(location, |n| n),
+                        series.name
+                    )),
+                }
+            }
+            // Only the current instance of a repeating task repeats.  RTM
+            // takes an "every" rule as it is, but an "after" one has to be
+            // written out as text.
+            if let (Some(repeat), None) = (&series.repeat, task.completed) {
+                let rule = if repeat.every {
+                    Ok(repeat.rule.clone())
+                } else {
+                    repeat
+                        .recurrence()
+                        .map(|recurrence| format!("after {}", recurrence.period_text()))
+                };
+                let result = match rule {
+                    Ok(rule) => {
+                        throttle.wait().await;
+                        self.set_recurrence_id(&timeline, ids.0, ids.1, ids.2, &rule)
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    report.warnings.push(format!(
+                        "Repeat {:?} for {:?}: {e}",
+                        repeat.rule, series.name
+                    ));
+                }
+            }
+            if task.completed.is_some() {
+                throttle.wait().await;
+                let result = self.mark_complete_id(&timeline, ids.0, ids.1, ids.2).await;
+                report.check(|| format!("Completing {:?}", series.name), result);
+            }
+            new_tasks.insert(task.id.clone(), new_task);
+        }
+
+        for (list_id, name) in to_archive {
+            progress(&format!("Archiving list {name}"));
+            throttle.wait().await;
+            let result = self.archive_list(&timeline, &list_id).await;
+            report.check(|| format!("Archiving list {name:?}"), result);
+        }
+
+        for contact in items::<Named>(&backup.contacts, "contact") {
+            progress(&format!("Adding contact {}", contact.name));
+            throttle.wait().await;
+            match self.add_contact(&timeline, &contact.name).await {
+                Ok(()) => report.contacts += 1,
+                Err(e) => report
+                    .warnings
+                    .push(format!("Contact {:?}: {e}", contact.name)),
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mockito::Matcher;
+
+    use super::{items, restore_order, Backup, Named};
+    use crate::{RTMConfig, API};
+
+    #[test]
+    fn test_restore_order() {
+        let series = |id: &str, parent: &str, deleted: &str| {
+            crate::tests::series(
+                id,
+                serde_json::json!({
+                    "location_id": if id == "child" { "7" } else { "" },
+                    "parent_task_id": parent,
+                }),
+                serde_json::json!({"deleted": deleted}),
+            )
+        };
+        let backup = Backup {
+            version: 1,
+            created: chrono::Utc::now(),
+            lists: serde_json::json!([]),
+            tasks: serde_json::json!({
+                "rev": "",
+                "list": [{
+                    "id": "1",
+                    "taskseries": [
+                        series("grandchild", "child", ""),
+                        series("child", "parent", ""),
+                        series("parent", "", ""),
+                        series("gone", "", "2026-10-02T00:00:00Z"),
+                        // Its parent was deleted.
+                        series("orphan", "gone", ""),
+                    ],
+                }],
+            }),
+            locations: serde_json::json!([]),
+            contacts: serde_json::json!({"contact": {"id": "1", "username": "bob"}}),
+            settings: serde_json::json!({}),
+        };
+        let tasks = backup.tasks().unwrap();
+        let order: Vec<_> = restore_order(&tasks.entries())
+            .iter()
+            .map(|e| e.series.name.as_str())
+            .collect();
+        assert_eq!(order, ["parent", "orphan", "child", "grandchild"]);
+        assert_eq!(backup.series_locations()["child"], "7");
+
+        let contacts: Vec<Named> = items(&backup.contacts, "contact");
+        assert_eq!(contacts[0].name, "bob");
+        assert!(items::<Named>(&backup.locations, "location").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore() {
+        let backup = Backup {
+            version: 1,
+            created: chrono::Utc::now(),
+            lists: serde_json::json!([
+                {"id": "10", "name": "Inbox", "locked": "1"},
+                {"id": "11", "name": "Work", "archived": "1"},
+                {"id": "12", "name": "Old", "deleted": "1"},
+            ]),
+            tasks: serde_json::json!({
+                "rev": "",
+                "list": [{
+                    "id": "11",
+                    "taskseries": [{
+                        "id": "20",
+                        "created": "2026-10-01T09:00:00Z",
+                        "modified": "2026-10-01T09:00:00Z",
+                        "name": "Report",
+                        "source": "js",
+                        "url": "",
+                        "location_id": "",
+                        "tags": {"tag": ["urgent"]},
+                        "participants": [],
+                        "notes": [],
+                        "parent_task_id": "",
+                        "rrule": {"every": "0", "$t": "FREQ=WEEKLY;INTERVAL=1"},
+                        "task": [{
+                            "id": "21", "due": "2026-10-20T00:00:00Z", "has_due_time": "0",
+                            "added": "", "completed": "", "deleted": "",
+                            "priority": "N", "postponed": "0", "estimate": ""
+                        }],
+                    }],
+                }],
+            }),
+            locations: serde_json::json!([]),
+            contacts: serde_json::json!([]),
+            settings: serde_json::json!({}),
+        };
+        let config = || RTMConfig {
+            api_key: Some("key".into()),
+            api_secret: Some("secret".into()),
+            token: Some("token".into()),
+            user: None,
+            secret_store: None,
+        };
+        let method = |name: &str| Matcher::UrlEncoded("method".into(), name.into());
+
+        // An account with tasks is left alone unless forced.
+        let mut server = mockito::Server::new_async().await;
+        let _tasks = server
+            .mock("GET", "/")
+            .match_query(method("rtm.tasks.getList"))
+            .with_body(
+                r#"{"rsp":{"stat":"ok","tasks":{"rev":"1","list":[{"id":"1","taskseries":[{"id":"2","created":"2026-10-01T09:00:00Z","modified":"2026-10-01T09:00:00Z","name":"Existing","source":"js","url":"","location_id":"","tags":[],"participants":[],"notes":[],"parent_task_id":"","task":[{"id":"3","due":"","has_due_time":"0","added":"2026-10-01T09:00:00Z","completed":"","deleted":"","priority":"N","postponed":"0","estimate":""}]}]}]}}}"#,
+            )
+            .create_async()
+            .await;
+        let api = API::from_config_test(config(), server);
+        let result = api.restore(&backup, Duration::ZERO, false, |_| ()).await;
+        assert!(result.is_err());
+
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = vec![];
+        for (name, body) in [
+            ("rtm.tasks.getList", r#""tasks":{"rev":"1"}"#),
+            ("rtm.timelines.create", r#""timeline":"tl""#),
+            (
+                "rtm.lists.getList",
+                r#""lists":{"list":[{"id":"1","name":"Inbox","smart":"0"}]}"#,
+            ),
+            (
+                "rtm.lists.add",
+                r#""list":{"id":"2","name":"Work","smart":"0"}"#,
+            ),
+            ("rtm.locations.getList", r#""locations":[]"#),
+            (
+                "rtm.tasks.add",
+                r#""list":{"id":"2","taskseries":[{"id":"3","created":"2026-10-18T09:00:00Z","modified":"2026-10-18T09:00:00Z","name":"Report","source":"api","url":"","location_id":"","tags":[],"participants":[],"notes":[],"parent_task_id":"","task":[{"id":"4","due":"","has_due_time":"0","added":"2026-10-18T09:00:00Z","completed":"","deleted":"","priority":"N","postponed":"0","estimate":""}]}]}"#,
+            ),
+            (
+                "rtm.tasks.addTags",
+                r#""transaction":{"id":"5","undoable":"1"}"#,
+            ),
+            (
+                "rtm.lists.archive",
+                r#""list":{"id":"2","name":"Work","archived":"1"}"#,
+            ),
+        ] {
+            mocks.push(
+                server
+                    .mock("GET", "/")
+                    .match_query(method(name))
+                    .with_body(format!(r#"{{"rsp":{{"stat":"ok",{body}}}}}"#))
+                    .expect(1)
+                    .create_async()
+                    .await,
+            );
+        }
+        mocks.push(
+            server
+                .mock("GET", "/")
+                .match_query(Matcher::AllOf(vec![
+                    method("rtm.tasks.setRecurrence"),
+                    Matcher::UrlEncoded("repeat".into(), "after 1 week".into()),
+                ]))
+                .with_body(r#"{"rsp":{"stat":"ok","transaction":{"id":"6","undoable":"1"}}}"#)
+                .expect(1)
+                .create_async()
+                .await,
+        );
+        // A failed request doesn't stop the rest of the restore.
+        mocks.push(
+            server
+                .mock("GET", "/")
+                .match_query(method("rtm.tasks.setDueDate"))
+                .with_body(r#"{"rsp":{"stat":"fail","err":{"code":"4000","msg":"Bad date"}}}"#)
+                .expect(1)
+                .create_async()
+                .await,
+        );
+        let api = API::from_config_test(config(), server);
+        let mut steps = Vec::new();
+        let report = api
+            .restore(&backup, Duration::ZERO, false, |step| {
+                steps.push(step.to_string())
+            })
+            .await
+            .unwrap();
+        for mock in &mocks {
+            mock.assert_async().await;
+        }
+        // The list is archived after its tasks are added.
+        assert_eq!(
+            steps,
+            [
+                "Adding list Work",
+                "Adding task Report",
+                "Archiving list Work"
+            ]
+        );
+        assert_eq!(report.lists.len(), 2);
+        assert_eq!(report.lists["10"], "1");
+        assert_eq!(report.lists["11"], "2");
+        assert_eq!(
+            report.tasks["21"],
+            ("2".to_string(), "3".to_string(), "4".to_string())
+        );
+        assert_eq!(report.failures.len(), 1);
+        assert!(report.failures[0].contains("due date"));
+        assert!(report.warnings.is_empty());
+    }
+}
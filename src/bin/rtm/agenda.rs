@@ -0,0 +1,176 @@
+//! The `rtm agenda` and `rtm cal` commands.
+
+use std::io::Write;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail};
+use chrono::{Datelike, Days, Months, NaiveDate, Utc};
+use rememberthemilk::agenda::{Agenda, AgendaItem};
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::source::{Freshness, FreshnessArgs, TaskSource};
+use crate::Opt;
+
+/// By default, use the cache however old it is, only syncing if it never
+/// has been.
+#[cfg(feature = "cache")]
+const DEFAULT_FRESHNESS: Freshness = Freshness::MaxAge(chrono::TimeDelta::MAX);
+#[cfg(not(feature = "cache"))]
+const DEFAULT_FRESHNESS: Freshness = Freshness::Online;
+
+/// The incomplete tasks, and the account's time zone for placing them on
+/// days.
+async fn get_tasks(
+    freshness: &FreshnessArgs,
+) -> Result<(rememberthemilk::RTMTasks, chrono_tz::Tz), anyhow::Error> {
+    let source = TaskSource::open(freshness.or(DEFAULT_FRESHNESS)).await?;
+    let tasks = source.get_tasks_filtered("status:incomplete").await?;
+    Ok((tasks, source.timezone().await?))
+}
+
+fn write_item(
+    stdout: &mut impl WriteColor,
+    item: &AgendaItem<'_>,
+    with_date: bool,
+) -> Result<(), anyhow::Error> {
+    if with_date {
+        write!(stdout, "  {}", item.date.format("%a %d %b"))?;
+    } else {
+        match item.time {
+            Some(time) => write!(stdout, "  {}", time.format("%H:%M"))?,
+            None => write!(stdout, "       ")?,
+        }
+    }
+    write!(stdout, "  {}", item.entry.series.name)?;
+    if item.repeat {
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+        write!(stdout, " (repeat)")?;
+        stdout.reset()?;
+    }
+    writeln!(stdout)?;
+    Ok(())
+}
+
+/// Show the tasks due on each of the next `days` days.
+pub async fn agenda(
+    opts: &Opt,
+    days: u32,
+    freshness: &FreshnessArgs,
+) -> Result<ExitCode, anyhow::Error> {
+    if days == 0 {
+        bail!("--days must be at least 1.");
+    }
+    let (tasks, tz) = get_tasks(freshness).await?;
+    let entries = tasks.entries();
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let agenda = Agenda::build(&entries, today, days, &tz);
+
+    let mut stdout = opts.get_stdout();
+    let heading = ColorSpec::new().set_fg(Some(Color::Magenta)).clone();
+    if !agenda.overdue.is_empty() {
+        stdout.set_color(ColorSpec::new().set_fg(Some(Color::Red)))?;
+        writeln!(stdout, "Overdue")?;
+        stdout.reset()?;
+        for item in &agenda.overdue {
+            write_item(&mut stdout, item, true)?;
+        }
+    }
+    for day in &agenda.days {
+        stdout.set_color(&heading)?;
+        write!(stdout, "{}", day.date.format("%a %d %b"))?;
+        if day.date == today {
+            write!(stdout, " (today)")?;
+        }
+        stdout.reset()?;
+        writeln!(stdout)?;
+        for item in &day.items {
+            write_item(&mut stdout, item, false)?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Parse a month given as `YYYY-MM`.
+pub fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid month {s:?}; expected e.g. 2026-10"))
+}
+
+/// Width of a day in the calendar grid.
+const CELL_WIDTH: usize = 7;
+
+/// Show a month as a grid, with the number of tasks due each day.
+pub async fn cal(
+    opts: &Opt,
+    month: Option<NaiveDate>,
+    freshness: &FreshnessArgs,
+) -> Result<ExitCode, anyhow::Error> {
+    let (tasks, tz) = get_tasks(freshness).await?;
+    let today = Utc::now().with_timezone(&tz).date_naive();
+    let first = month.unwrap_or_else(|| today.with_day(1).unwrap());
+    let next = first
+        .checked_add_months(Months::new(1))
+        .ok_or_else(|| anyhow!("Month out of range"))?;
+    let days = (next - first).num_days() as u32;
+
+    let entries = tasks.entries();
+    let counts = Agenda::build(&entries, first, days, &tz).counts();
+
+    let mut stdout = opts.get_stdout();
+    let width = 7 * CELL_WIDTH;
+    let title = first.format("%B %Y").to_string();
+    writeln!(stdout, "{title:^width$}")?;
+    for day in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"] {
+        write!(stdout, "{day:<CELL_WIDTH$}")?;
+    }
+    writeln!(stdout)?;
+    let offset = first.weekday().num_days_from_monday() as usize;
+    write!(stdout, "{}", " ".repeat(offset * CELL_WIDTH))?;
+    for date in first.iter_days().take_while(|d| *d < next) {
+        let count = counts.get(&date).copied().unwrap_or(0);
+        let cell = if count > 0 {
+            format!("{}({count})", date.day())
+        } else {
+            date.day().to_string()
+        };
+        if date == today {
+            stdout.set_color(ColorSpec::new().set_bold(true).set_underline(true))?;
+            write!(stdout, "{cell}")?;
+            stdout.reset()?;
+        } else if count > 0 {
+            stdout.set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))?;
+            write!(stdout, "{cell}")?;
+            stdout.reset()?;
+        } else {
+            write!(stdout, "{cell}")?;
+        }
+        let last_in_week = date.weekday() == chrono::Weekday::Sun;
+        let last_in_month = date + Days::new(1) == next;
+        if last_in_week || last_in_month {
+            writeln!(stdout)?;
+        } else {
+            write!(
+                stdout,
+                "{}",
+                " ".repeat(CELL_WIDTH.saturating_sub(cell.len()))
+            )?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_month;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_parse_month() {
+        assert_eq!(
+            parse_month("2026-02"),
+            Ok(NaiveDate::from_ymd_opt(2026, 2, 1).unwrap())
+        );
+        assert!(parse_month("2026-13").is_err());
+        assert!(parse_month("2026").is_err());
+    }
+}
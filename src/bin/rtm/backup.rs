@@ -0,0 +1,72 @@
+//! The `rtm backup` and `rtm restore` commands.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rememberthemilk::backup::Backup;
+use rememberthemilk::bulk::RTM_REQUEST_INTERVAL;
+use rememberthemilk::Perms;
+
+use crate::export::replace_file;
+use crate::get_rtm_api;
+
+/// Options for `rtm backup`.
+#[derive(clap::Args, Debug)]
+pub struct BackupArgs {
+    #[clap(short, long)]
+    /// Write to this file instead of stdout.
+    output: Option<PathBuf>,
+}
+
+/// Options for `rtm restore`.
+#[derive(clap::Args, Debug)]
+pub struct RestoreArgs {
+    /// The file written by `rtm backup`.
+    file: PathBuf,
+
+    #[clap(long)]
+    /// Restore even if the account already has tasks.
+    force: bool,
+}
+
+/// Write a backup of the whole account.
+pub async fn backup(args: &BackupArgs) -> Result<ExitCode, anyhow::Error> {
+    let api = get_rtm_api(Perms::Read).await?;
+    let backup = api.backup().await?;
+    let output = serde_json::to_string_pretty(&backup)? + "\n";
+    match &args.output {
+        Some(path) => replace_file(path, output.as_bytes())?,
+        None => std::io::stdout().write_all(output.as_bytes())?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Restore a backup into an empty account.
+pub async fn restore(args: &RestoreArgs) -> Result<ExitCode, anyhow::Error> {
+    let backup: Backup = serde_json::from_slice(&std::fs::read(&args.file)?)?;
+    let api = get_rtm_api(Perms::Write).await?;
+    let report = api
+        .restore(&backup, RTM_REQUEST_INTERVAL, args.force, |step| {
+            eprintln!("{step}")
+        })
+        .await?;
+    println!(
+        "Restored {} lists, {} tasks and {} contacts.",
+        report.lists.len(),
+        report.tasks.len(),
+        report.contacts
+    );
+    for warning in &report.warnings {
+        println!("Warning: {warning}");
+    }
+    println!("Locations and settings can't be restored; they are kept in the backup.");
+    if !report.failures.is_empty() {
+        eprintln!("Some of the backup couldn't be restored:");
+        for failure in &report.failures {
+            eprintln!("  {failure}");
+        }
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
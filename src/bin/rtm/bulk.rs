@@ -0,0 +1,179 @@
+//! The `rtm bulk` command.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+
+use anyhow::bail;
+use rememberthemilk::bulk::{plan_bulk, BulkEdit, RTM_REQUEST_INTERVAL};
+use rememberthemilk::filter::expand_saved;
+use rememberthemilk::{system_timezone, Perms};
+
+use crate::history::ChangeLog;
+use crate::ops::parse_priority;
+use crate::{get_rtm_api, get_settings};
+
+/// Options for `rtm bulk`.
+#[derive(clap::Args, Debug)]
+pub struct BulkArgs {
+    #[clap(long)]
+    /// Change the tasks matching this filter.
+    filter: String,
+
+    #[clap(long = "tag")]
+    /// Add this tag (may be repeated).
+    add_tags: Vec<String>,
+
+    #[clap(long = "untag")]
+    /// Remove this tag (may be repeated).
+    remove_tags: Vec<String>,
+
+    #[clap(long, value_parser = parse_priority)]
+    /// Set the priority: 1, 2, 3 or none.
+    priority: Option<String>,
+
+    #[clap(long, value_parser = parse_shift, allow_hyphen_values = true)]
+    /// Move due dates by a number of days or weeks, e.g. +7d, -2d or 1w.
+    shift_due: Option<i64>,
+
+    #[clap(long = "move")]
+    /// Move to this list.
+    move_to: Option<String>,
+
+    #[clap(long)]
+    /// Postpone by a day.
+    postpone: bool,
+
+    #[clap(long)]
+    /// Mark complete.
+    complete: bool,
+
+    #[clap(long)]
+    /// Show the changes without making them.
+    dry_run: bool,
+
+    #[clap(short, long)]
+    /// Make the changes without asking first.
+    yes: bool,
+}
+
+/// Parse a due date shift such as `+7d`, `-2d`, `1w` or `3` (days) into a
+/// number of days.
+fn parse_shift(s: &str) -> Result<i64, String> {
+    let error = || format!("Invalid shift {s:?}: expected e.g. +7d, -2d or 1w");
+    let (number, days_per_unit) = match s.strip_suffix(['d', 'w']) {
+        Some(number) if s.ends_with('w') => (number, 7),
+        Some(number) => (number, 1),
+        None => (s, 1),
+    };
+    let number: i64 = number
+        .strip_prefix('+')
+        .unwrap_or(number)
+        .parse()
+        .map_err(|_| error())?;
+    number.checked_mul(days_per_unit).ok_or_else(error)
+}
+
+/// Ask whether to go ahead.
+fn confirm(question: &str) -> Result<bool, anyhow::Error> {
+    print!("{question} [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Preview and then apply edits to the tasks matching a filter.
+pub async fn bulk(args: &BulkArgs) -> Result<ExitCode, anyhow::Error> {
+    let api = get_rtm_api(if args.dry_run {
+        Perms::Read
+    } else {
+        Perms::Write
+    })
+    .await?;
+    let lists = api.get_lists().await?;
+    let mut edits = Vec::new();
+    if let Some(name) = &args.move_to {
+        match lists.iter().find(|l| l.name == *name) {
+            Some(list) if list.smart => bail!("Can't move tasks to smart list {name:?}."),
+            Some(list) => edits.push(BulkEdit::Move(list.id.clone())),
+            None => bail!("No list called {name:?}."),
+        }
+    }
+    if let Some(days) = args.shift_due {
+        edits.push(BulkEdit::ShiftDue(days));
+    }
+    if args.postpone {
+        edits.push(BulkEdit::Postpone);
+    }
+    edits.extend(args.add_tags.iter().cloned().map(BulkEdit::AddTag));
+    edits.extend(args.remove_tags.iter().cloned().map(BulkEdit::RemoveTag));
+    if let Some(priority) = &args.priority {
+        edits.push(BulkEdit::Priority(priority.clone()));
+    }
+    if args.complete {
+        edits.push(BulkEdit::Complete);
+    }
+    if edits.is_empty() {
+        bail!("Nothing to change: give at least one of --tag, --untag, --priority, --shift-due, --move, --postpone or --complete.");
+    }
+
+    let settings = get_settings()?;
+    let filter = expand_saved(&args.filter, &settings.saved)?;
+    let tasks = api.get_tasks_filtered(&filter).await?;
+    let list_names: HashMap<String, String> =
+        lists.into_iter().map(|list| (list.id, list.name)).collect();
+    let entries = tasks.entries();
+    let tz = api.get_timezone().await?.unwrap_or_else(system_timezone);
+    let plan = plan_bulk(&entries, &edits, &list_names, &tz);
+    if plan.is_empty() {
+        eprintln!("No tasks to change.");
+        return Ok(ExitCode::from(1));
+    }
+    for item in &plan {
+        println!("{}", item.entry.series.name);
+        for change in &item.changes {
+            println!("    {change}");
+        }
+    }
+    if args.dry_run || !(args.yes || confirm(&format!("Change {} tasks?", plan.len()))?) {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let log = ChangeLog::open(&api).await?;
+    let timeline = log.timeline().await?;
+    let total = plan.len();
+    let outcome = api
+        .bulk_apply(&timeline, &plan, RTM_REQUEST_INTERVAL, |index, item| {
+            eprintln!("[{}/{total}] {}", index + 1, item.entry.series.name);
+        })
+        .await;
+    log.record_batch(&timeline, &outcome.transactions).await?;
+    println!("Changed {} of {total} tasks.", outcome.changed);
+    if outcome.failures.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("Failed:");
+    for failure in &outcome.failures {
+        println!(
+            "    {}: {}: {:#}",
+            failure.name, failure.change, failure.error
+        );
+    }
+    Ok(ExitCode::from(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_shift;
+
+    #[test]
+    fn test_parse_shift() {
+        assert_eq!(parse_shift("+7d"), Ok(7));
+        assert_eq!(parse_shift("-2d"), Ok(-2));
+        assert_eq!(parse_shift("1w"), Ok(7));
+        assert_eq!(parse_shift("3"), Ok(3));
+        assert!(parse_shift("d").is_err());
+        assert!(parse_shift("+1m").is_err());
+    }
+}
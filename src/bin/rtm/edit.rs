@@ -0,0 +1,92 @@
+//! The `rtm edit` command.
+
+use std::collections::HashMap;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail};
+use rememberthemilk::edit::TaskForm;
+use rememberthemilk::sort::TaskEntry;
+use rememberthemilk::{system_timezone, Perms};
+
+use crate::get_rtm_api;
+use crate::history::ChangeLog;
+use crate::ops::{select_tasks, TaskSelection};
+
+/// Open `path` in the user's editor, waiting for it to finish.
+fn run_editor(path: &std::path::Path) -> Result<(), anyhow::Error> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".into());
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| anyhow!("$EDITOR is empty"))?;
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| anyhow!("Unable to run {editor:?}: {e}"))?;
+    if !status.success() {
+        bail!("{editor:?} failed: {status}");
+    }
+    Ok(())
+}
+
+/// Edit a task as text in `$EDITOR`, then make the changes.
+pub async fn edit(selection: &TaskSelection) -> Result<ExitCode, anyhow::Error> {
+    let dry_run = selection.dry_run;
+    let api = get_rtm_api(if dry_run { Perms::Read } else { Perms::Write }).await?;
+    let selected = select_tasks(&api, selection).await?;
+    let (list_id, series) = match &selected[..] {
+        [task] => task,
+        [] => bail!("No tasks selected."),
+        _ => bail!("Select one task to edit, not {}.", selected.len()),
+    };
+    let entry = TaskEntry {
+        list_id,
+        series,
+        task: &series.task[0],
+    };
+    let lists = api.get_lists().await?;
+    let list_names: HashMap<String, String> = lists
+        .iter()
+        .map(|list| (list.id.clone(), list.name.clone()))
+        .collect();
+    let tz = api.get_timezone().await?.unwrap_or_else(system_timezone);
+    let original = TaskForm::from_entry(&entry, &list_names, &tz);
+
+    let path = std::env::temp_dir().join(format!("rtm-edit-{}.txt", std::process::id()));
+    std::fs::write(&path, original.to_string())?;
+    run_editor(&path)?;
+    let text = std::fs::read_to_string(&path)?;
+    let edits = match TaskForm::parse(&text).and_then(|edited| edited.changes(&original)) {
+        Ok(edits) => edits,
+        Err(e) => bail!("{e:#}\nYour changes are in {}.", path.display()),
+    };
+    std::fs::remove_file(&path)?;
+    if edits.is_empty() {
+        println!("No changes.");
+        return Ok(ExitCode::SUCCESS);
+    }
+    if dry_run {
+        for edit in &edits {
+            println!("{edit} (dry run): {}", series.name);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let log = ChangeLog::open(&api).await?;
+    let timeline = log.timeline().await?;
+    let mut transactions = Vec::new();
+    let result = api
+        .apply_task_edits(&timeline, &entry, &edits, &lists, &mut transactions)
+        .await;
+    // Record what was done even if a later change failed.
+    for (description, _) in &mut transactions {
+        description.push_str(&format!(": {}", series.name));
+    }
+    log.record_batch(&timeline, &transactions).await?;
+    result?;
+    for edit in &edits {
+        println!("{edit}: {}", series.name);
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,97 @@
+//! The `rtm export` command.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use rememberthemilk::filter::expand_saved;
+use rememberthemilk::ical::{to_ical, IcalOptions};
+use rememberthemilk::taskwarrior::to_taskwarrior;
+use rememberthemilk::todotxt::to_todotxt;
+
+use crate::get_settings;
+use crate::source::{Freshness, FreshnessArgs, TaskSource};
+
+/// Options for `rtm export`.
+#[derive(clap::Args, Debug)]
+#[clap(group(clap::ArgGroup::new("to").required(true)))]
+pub struct ExportArgs {
+    #[clap(long, group = "to")]
+    /// Export as iCalendar, with a VTODO for each task.
+    ical: bool,
+
+    #[clap(long, group = "to")]
+    /// Export as todo.txt lines, with lists as +projects and tags as
+    /// @contexts.
+    todotxt: bool,
+
+    #[clap(long, group = "to")]
+    /// Export as Taskwarrior JSON, for `task import`.
+    taskwarrior: bool,
+
+    #[clap(long, requires = "ical")]
+    /// Also add a VEVENT for each task with a due date, for calendars which
+    /// don't show VTODOs.
+    events: bool,
+
+    #[clap(long, default_value = "status:incomplete")]
+    /// Only export the tasks matching this filter.
+    filter: String,
+
+    #[clap(short, long)]
+    /// Write to this file instead of stdout.  It is replaced in one step,
+    /// so it can be served while being updated.
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    freshness: FreshnessArgs,
+}
+
+/// Replace the file at `path` with `contents`, by writing a temporary file
+/// next to it and renaming it over the original.
+pub(crate) fn replace_file(path: &Path, contents: &[u8]) -> Result<(), anyhow::Error> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Export the tasks matching a filter.
+pub async fn export(args: &ExportArgs) -> Result<ExitCode, anyhow::Error> {
+    let source = TaskSource::open(args.freshness.or(Freshness::Online)).await?;
+    let settings = get_settings()?;
+    let filter = expand_saved(&args.filter, &settings.saved)?;
+    let tasks = source.get_tasks_filtered(&filter).await?;
+    let list_names = || async {
+        Ok::<HashMap<String, String>, anyhow::Error>(
+            source
+                .get_lists()
+                .await?
+                .into_iter()
+                .map(|list| (list.id, list.name))
+                .collect(),
+        )
+    };
+    let tz = source.timezone().await?;
+    let output = if args.todotxt {
+        to_todotxt(&tasks.entries(), &list_names().await?, &tz)
+    } else if args.taskwarrior {
+        let tasks = to_taskwarrior(&tasks.entries(), &list_names().await?);
+        serde_json::to_string_pretty(&tasks)? + "\n"
+    } else {
+        let options = IcalOptions {
+            events: args.events,
+        };
+        to_ical(&tasks.entries(), &options, &tz)
+    };
+    match &args.output {
+        Some(path) => replace_file(path, output.as_bytes())?,
+        None => std::io::stdout().write_all(output.as_bytes())?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,440 @@
+//! Machine readable output for `rtm tasks` and `rtm lists`.
+//!
+//! Each task is output as a record with these fields, in this order:
+//!
+//! | Field           | Contents                                             |
+//! |-----------------|------------------------------------------------------|
+//! | `list_id`       | The id of the task's list                            |
+//! | `list_name`     | The name of the task's list                          |
+//! | `taskseries_id` | The task series id                                   |
+//! | `task_id`       | The task id                                          |
+//! | `name`          | The task name                                        |
+//! | `due`           | The due date/time (RFC 3339, UTC), or null           |
+//! | `has_due_time`  | True if `due` has a time, rather than just a date    |
+//! | `completed`     | The completion time (RFC 3339, UTC), or null         |
+//! | `priority`      | 1, 2 or 3, or null for no priority                   |
+//! | `tags`          | The tags (comma separated in CSV, TSV and tables)    |
+//! | `url`           | The task's URL, or an empty string                   |
+//! | `notes`         | The number of notes                                  |
+//! | `repeat`        | e.g. `every FREQ=WEEKLY;INTERVAL=1`, or null         |
+//! | `handle`        | The short handle for other commands, or null         |
+//!
+//! Each list has `id`, `name`, `smart` and `filter` (null except for smart
+//! lists).
+//!
+//! In CSV and TSV, null is an empty field and booleans are `true` or
+//! `false`.  CSV fields are quoted as in RFC 4180; TSV fields escape tab,
+//! newline, carriage return and backslash as `\t`, `\n`, `\r` and `\\`.
+
+use std::io::Write;
+
+use rememberthemilk::{sort::TaskEntry, RTMList};
+use serde::Serialize;
+
+/// An output format for `--format`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A JSON array of records.
+    Json,
+    /// One JSON record per line.
+    Ndjson,
+    /// Comma separated values, with a header line.
+    Csv,
+    /// Tab separated values, with a header line.
+    Tsv,
+    /// Plain aligned columns, with a header line.
+    Table,
+}
+
+impl std::str::FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Format, &'static str> {
+        match s {
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "table" => Ok(Format::Table),
+            _ => Err("Invalid option for --format"),
+        }
+    }
+}
+
+/// Something which can be output as a row.
+pub trait Record: Serialize {
+    /// The field names, in order.
+    const FIELDS: &'static [&'static str];
+
+    /// The values as strings, in the same order as `FIELDS`.
+    fn values(&self) -> Vec<String>;
+}
+
+/// A task, as output.
+#[derive(Serialize, Debug)]
+pub struct TaskRecord {
+    pub list_id: String,
+    pub list_name: String,
+    pub taskseries_id: String,
+    pub task_id: String,
+    pub name: String,
+    pub due: Option<String>,
+    pub has_due_time: bool,
+    pub completed: Option<String>,
+    pub priority: Option<u8>,
+    pub tags: Vec<String>,
+    pub url: String,
+    pub notes: usize,
+    pub repeat: Option<String>,
+    pub handle: Option<String>,
+}
+
+fn time_string(t: &chrono::DateTime<chrono::Utc>) -> String {
+    t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+impl TaskRecord {
+    pub fn new(entry: &TaskEntry<'_>, list_name: &str, handle: Option<String>) -> TaskRecord {
+        let TaskEntry {
+            list_id,
+            series,
+            task,
+        } = entry;
+        TaskRecord {
+            list_id: list_id.to_string(),
+            list_name: list_name.into(),
+            taskseries_id: series.id.clone(),
+            task_id: task.id.clone(),
+            name: series.name.clone(),
+            due: task.due.as_ref().map(time_string),
+            has_due_time: task.has_due_time,
+            completed: task.completed.as_ref().map(time_string),
+            priority: task.priority.parse().ok(),
+            tags: series.tags.clone(),
+            url: series.url.clone(),
+            notes: series.notes.len(),
+            repeat: series
+                .repeat
+                .as_ref()
+                .map(|r| format!("{} {}", if r.every { "every" } else { "after" }, r.rule)),
+            handle,
+        }
+    }
+}
+
+impl Record for TaskRecord {
+    const FIELDS: &'static [&'static str] = &[
+        "list_id",
+        "list_name",
+        "taskseries_id",
+        "task_id",
+        "name",
+        "due",
+        "has_due_time",
+        "completed",
+        "priority",
+        "tags",
+        "url",
+        "notes",
+        "repeat",
+        "handle",
+    ];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.list_id.clone(),
+            self.list_name.clone(),
+            self.taskseries_id.clone(),
+            self.task_id.clone(),
+            self.name.clone(),
+            self.due.clone().unwrap_or_default(),
+            self.has_due_time.to_string(),
+            self.completed.clone().unwrap_or_default(),
+            self.priority.map(|p| p.to_string()).unwrap_or_default(),
+            self.tags.join(","),
+            self.url.clone(),
+            self.notes.to_string(),
+            self.repeat.clone().unwrap_or_default(),
+            self.handle.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// A list, as output.
+#[derive(Serialize, Debug)]
+pub struct ListRecord {
+    pub id: String,
+    pub name: String,
+    pub smart: bool,
+    pub filter: Option<String>,
+}
+
+impl From<&RTMList> for ListRecord {
+    fn from(list: &RTMList) -> ListRecord {
+        ListRecord {
+            id: list.id.clone(),
+            name: list.name.clone(),
+            smart: list.smart,
+            filter: list.filter.clone(),
+        }
+    }
+}
+
+impl Record for ListRecord {
+    const FIELDS: &'static [&'static str] = &["id", "name", "smart", "filter"];
+
+    fn values(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.name.clone(),
+            self.smart.to_string(),
+            self.filter.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+fn tsv_field(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\t' => result.push_str("\\t"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\\' => result.push_str("\\\\"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Write the records in the given format.
+pub fn write_records<R: Record>(
+    out: &mut impl Write,
+    format: Format,
+    records: &[R],
+) -> Result<(), anyhow::Error> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, records)?;
+            writeln!(out)?;
+        }
+        Format::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut *out, record)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Csv => {
+            let header: Vec<_> = R::FIELDS.iter().map(|f| csv_field(f)).collect();
+            write!(out, "{}\r\n", header.join(","))?;
+            for record in records {
+                let values = record.values();
+                let fields: Vec<_> = values.iter().map(|v| csv_field(v)).collect();
+                write!(out, "{}\r\n", fields.join(","))?;
+            }
+        }
+        Format::Tsv => {
+            writeln!(out, "{}", R::FIELDS.join("\t"))?;
+            for record in records {
+                let fields: Vec<_> = record.values().iter().map(|v| tsv_field(v)).collect();
+                writeln!(out, "{}", fields.join("\t"))?;
+            }
+        }
+        Format::Table => {
+            use unicode_width::UnicodeWidthStr;
+            // Newlines and tabs would break the layout.
+            let rows: Vec<Vec<String>> = records
+                .iter()
+                .map(|r| {
+                    r.values()
+                        .iter()
+                        .map(|v| v.replace(['\n', '\r', '\t'], " "))
+                        .collect()
+                })
+                .collect();
+            let mut widths: Vec<usize> = R::FIELDS.iter().map(|f| f.width()).collect();
+            for row in &rows {
+                for (width, value) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(value.width());
+                }
+            }
+            let write_row = |out: &mut dyn Write, row: &[&str]| -> std::io::Result<()> {
+                let mut line = String::new();
+                for (i, (value, width)) in row.iter().zip(&widths).enumerate() {
+                    if i > 0 {
+                        line.push_str("  ");
+                    }
+                    line.push_str(value);
+                    if i + 1 < row.len() {
+                        line.extend(std::iter::repeat_n(' ', width - value.width()));
+                    }
+                }
+                writeln!(out, "{}", line.trim_end())
+            };
+            write_row(out, R::FIELDS)?;
+            for row in &rows {
+                let row: Vec<&str> = row.iter().map(|s| s.as_str()).collect();
+                write_row(out, &row)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_records, Format, ListRecord, TaskRecord};
+    use rememberthemilk::RTMTasks;
+
+    fn lists() -> Vec<ListRecord> {
+        vec![
+            ListRecord {
+                id: "1".into(),
+                name: "Inbox".into(),
+                smart: false,
+                filter: None,
+            },
+            ListRecord {
+                id: "22".into(),
+                name: "Odd, \"quoted\"\tname".into(),
+                smart: true,
+                filter: Some("tag:x\nOR tag:\\y".into()),
+            },
+        ]
+    }
+
+    fn output(format: Format) -> String {
+        let mut out = Vec::new();
+        write_records(&mut out, format, &lists()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            output(Format::Ndjson),
+            concat!(
+                r#"{"id":"1","name":"Inbox","smart":false,"filter":null}"#,
+                "\n",
+                r#"{"id":"22","name":"Odd, \"quoted\"\tname","smart":true,"filter":"tag:x\nOR tag:\\y"}"#,
+                "\n"
+            )
+        );
+        let json: serde_json::Value = serde_json::from_str(&output(Format::Json)).unwrap();
+        assert_eq!(json[1]["name"], "Odd, \"quoted\"\tname");
+        assert_eq!(
+            output(Format::Csv),
+            "id,name,smart,filter\r\n1,Inbox,false,\r\n22,\"Odd, \"\"quoted\"\"\tname\",true,\"tag:x\nOR tag:\\y\"\r\n"
+        );
+        assert_eq!(
+            output(Format::Tsv),
+            "id\tname\tsmart\tfilter\n1\tInbox\tfalse\t\n22\tOdd, \"quoted\"\\tname\ttrue\ttag:x\\nOR tag:\\\\y\n"
+        );
+        assert_eq!(
+            output(Format::Table),
+            concat!(
+                "id  name                smart  filter\n",
+                "1   Inbox               false\n",
+                "22  Odd, \"quoted\" name  true   tag:x OR tag:\\y\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_task_records() {
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [{
+                    "id": "2",
+                    "created": "2026-10-01T09:00:00Z",
+                    "modified": "2026-10-02T09:00:00Z",
+                    "name": "Write report, then send",
+                    "source": "js",
+                    "url": "https://example.com/",
+                    "location_id": "",
+                    "tags": {"tag": ["work", "urgent"]},
+                    "participants": [],
+                    "notes": {"note": [{
+                        "id": "4",
+                        "created": "2026-10-01T09:00:00Z",
+                        "modified": "2026-10-01T09:00:00Z",
+                        "title": "Outline",
+                        "$t": "Intro",
+                    }]},
+                    "parent_task_id": "",
+                    "rrule": {"every": "1", "$t": "FREQ=WEEKLY;INTERVAL=1"},
+                    "task": [{
+                        "id": "3", "due": "2026-10-19T23:00:00Z", "has_due_time": "0",
+                        "added": "2026-10-01T09:00:00Z", "completed": "", "deleted": "",
+                        "priority": "2", "postponed": "0", "estimate": ""
+                    }, {
+                        "id": "5", "due": "", "has_due_time": "0",
+                        "added": "2026-10-01T09:00:00Z", "completed": "2026-10-12T15:00:00Z",
+                        "deleted": "", "priority": "N", "postponed": "0", "estimate": ""
+                    }],
+                }],
+            }],
+        }))
+        .unwrap();
+        let entries = tasks.entries();
+        let records = [
+            TaskRecord::new(&entries[0], "Work", Some("a".into())),
+            TaskRecord::new(&entries[1], "Work", None),
+        ];
+        let output = |format| {
+            let mut out = Vec::new();
+            write_records(&mut out, format, &records).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let json: serde_json::Value = serde_json::from_str(&output(Format::Json)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "list_id": "1", "list_name": "Work", "taskseries_id": "2", "task_id": "3",
+                "name": "Write report, then send", "due": "2026-10-19T23:00:00Z",
+                "has_due_time": false, "completed": null, "priority": 2,
+                "tags": ["work", "urgent"], "url": "https://example.com/", "notes": 1,
+                "repeat": "every FREQ=WEEKLY;INTERVAL=1", "handle": "a"
+            }, {
+                "list_id": "1", "list_name": "Work", "taskseries_id": "2", "task_id": "5",
+                "name": "Write report, then send", "due": null,
+                "has_due_time": false, "completed": "2026-10-12T15:00:00Z", "priority": null,
+                "tags": ["work", "urgent"], "url": "https://example.com/", "notes": 1,
+                "repeat": "every FREQ=WEEKLY;INTERVAL=1", "handle": null
+            }])
+        );
+        let header = "list_id,list_name,taskseries_id,task_id,name,due,has_due_time,\
+                      completed,priority,tags,url,notes,repeat,handle";
+        assert_eq!(
+            output(Format::Csv),
+            format!(
+                "{header}\r\n\
+                 1,Work,2,3,\"Write report, then send\",2026-10-19T23:00:00Z,false,,2,\
+                 \"work,urgent\",https://example.com/,1,every FREQ=WEEKLY;INTERVAL=1,a\r\n\
+                 1,Work,2,5,\"Write report, then send\",,false,2026-10-12T15:00:00Z,,\
+                 \"work,urgent\",https://example.com/,1,every FREQ=WEEKLY;INTERVAL=1,\r\n"
+            )
+        );
+        assert_eq!(
+            output(Format::Tsv),
+            format!(
+                "{}\n\
+                 1\tWork\t2\t3\tWrite report, then send\t2026-10-19T23:00:00Z\tfalse\t\t2\t\
+                 work,urgent\thttps://example.com/\t1\tevery FREQ=WEEKLY;INTERVAL=1\ta\n\
+                 1\tWork\t2\t5\tWrite report, then send\t\tfalse\t2026-10-12T15:00:00Z\t\t\
+                 work,urgent\thttps://example.com/\t1\tevery FREQ=WEEKLY;INTERVAL=1\t\n",
+                header.replace(',', "\t")
+            )
+        );
+    }
+}
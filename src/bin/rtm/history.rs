@@ -0,0 +1,113 @@
+//! Recording changes so that `rtm undo` can revert them.
+
+#[cfg(feature = "cache")]
+use std::process::ExitCode;
+
+#[cfg(feature = "cache")]
+use rememberthemilk::cache::TaskCache;
+use rememberthemilk::{RTMTimeline, RTMTransaction, API};
+
+/// Somewhere to record changes.  Without the cache, changes are not
+/// recorded and can't be undone.
+pub struct ChangeLog {
+    #[cfg(feature = "cache")]
+    cache: TaskCache,
+    #[cfg(not(feature = "cache"))]
+    api: API,
+}
+
+impl ChangeLog {
+    pub async fn open(api: &API) -> Result<ChangeLog, anyhow::Error> {
+        Ok(ChangeLog {
+            #[cfg(feature = "cache")]
+            cache: crate::get_rtm_cache(api.clone()).await?,
+            #[cfg(not(feature = "cache"))]
+            api: api.clone(),
+        })
+    }
+
+    /// Return a timeline for making changes, reusing a recent one if
+    /// possible.
+    pub async fn timeline(&self) -> Result<RTMTimeline, anyhow::Error> {
+        #[cfg(feature = "cache")]
+        return self.cache.get_timeline().await;
+        #[cfg(not(feature = "cache"))]
+        return self.api.get_timeline().await;
+    }
+
+    /// Record a change made on `timeline`.
+    pub async fn record(
+        &self,
+        timeline: &RTMTimeline,
+        transaction: Option<&RTMTransaction>,
+        description: &str,
+    ) -> Result<(), anyhow::Error> {
+        #[cfg(feature = "cache")]
+        if let Some(transaction) = transaction {
+            self.cache
+                .record_transaction(timeline, transaction, description)
+                .await?;
+        }
+        #[cfg(not(feature = "cache"))]
+        let _ = (timeline, transaction, description);
+        Ok(())
+    }
+
+    /// Record changes made together, so that they are undone together.
+    pub async fn record_batch(
+        &self,
+        timeline: &RTMTimeline,
+        transactions: &[(String, RTMTransaction)],
+    ) -> Result<(), anyhow::Error> {
+        #[cfg(feature = "cache")]
+        self.cache.record_batch(timeline, transactions).await?;
+        #[cfg(not(feature = "cache"))]
+        let _ = (timeline, transactions);
+        Ok(())
+    }
+}
+
+/// Undo the `count` most recent changes.
+#[cfg(feature = "cache")]
+pub async fn undo(count: u32) -> Result<ExitCode, anyhow::Error> {
+    use rememberthemilk::Perms;
+
+    let cache = crate::get_rtm_cache(crate::get_rtm_api(Perms::Write).await?).await?;
+    let undone = cache.undo_latest(count).await?;
+    if undone.is_empty() {
+        eprintln!("Nothing to undo.");
+        return Ok(ExitCode::from(1));
+    }
+    for entry in undone {
+        println!("Undone: {}", entry.description);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Show the most recent changes.
+#[cfg(feature = "cache")]
+pub async fn history(limit: u32) -> Result<ExitCode, anyhow::Error> {
+    use rememberthemilk::Perms;
+
+    let cache = crate::get_rtm_cache(crate::get_rtm_api(Perms::Read).await?).await?;
+    let entries = cache.get_history(limit).await?;
+    // Number them as `rtm undo N` counts, oldest last.  Batches are undone
+    // together, so only their first line is numbered.
+    let mut pending = 0;
+    let mut last_batch = None;
+    for entry in entries {
+        let created = entry.created.with_timezone(&chrono::Local);
+        let created = created.format("%Y-%m-%d %H:%M");
+        let continues_batch = entry.batch.is_some() && entry.batch == last_batch;
+        last_batch = entry.batch;
+        if entry.undone {
+            println!("    {created}  {} (undone)", entry.description);
+        } else if continues_batch {
+            println!("    {created}  {}", entry.description);
+        } else {
+            pending += 1;
+            println!("{pending:>3} {created}  {}", entry.description);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,520 @@
+//! The `rtm import` command.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rememberthemilk::bulk::{Throttle, RTM_REQUEST_INTERVAL};
+use rememberthemilk::ical::{parse_todos, IcalTodo};
+use rememberthemilk::sort::TaskEntry;
+use rememberthemilk::taskwarrior::{parse_taskwarrior, TaskwarriorTask};
+use rememberthemilk::todotxt::{parse_todotxt, TodoTxtTask};
+use rememberthemilk::{system_timezone, Perms, RTMList, RTMTasks, RTMTimeline, Task, API};
+
+use crate::get_rtm_api;
+
+/// Options for `rtm import`.
+#[derive(clap::Args, Debug)]
+#[clap(group(clap::ArgGroup::new("from").required(true)))]
+pub struct ImportArgs {
+    #[clap(long, group = "from", value_name = "FILE")]
+    /// Import the VTODOs from an iCalendar file.
+    ical: Option<PathBuf>,
+
+    #[clap(long, group = "from", value_name = "FILE")]
+    /// Import the tasks from a todo.txt file.  The first +project of each
+    /// is used as its list, if there is one with that name.
+    todotxt: Option<PathBuf>,
+
+    #[clap(long, group = "from", value_name = "FILE")]
+    /// Import the tasks from Taskwarrior's `task export` JSON.  Projects are
+    /// used as lists, and dependencies as subtasks.
+    taskwarrior: Option<PathBuf>,
+
+    #[clap(long)]
+    /// Add the tasks to this list instead of the default one.
+    list: Option<String>,
+
+    #[clap(long)]
+    /// Show which tasks would be added without adding them.
+    dry_run: bool,
+}
+
+/// A task to add, read from another format.
+#[derive(Debug, Default)]
+struct ImportedTask {
+    /// Used as the external id, so that importing again skips the task.
+    id: Option<String>,
+    name: String,
+    /// The name of the list to add it to, instead of the default.
+    list: Option<String>,
+    /// The `id` of the task to add it as a subtask of.
+    parent: Option<String>,
+    due: Option<DateTime<Utc>>,
+    has_due_time: bool,
+    /// "1", "2", "3" or "N".
+    priority: &'static str,
+    tags: Vec<String>,
+    note: Option<String>,
+    url: Option<String>,
+    repeat: Option<String>,
+    completed: bool,
+}
+
+impl From<IcalTodo> for ImportedTask {
+    fn from(todo: IcalTodo) -> ImportedTask {
+        ImportedTask {
+            priority: todo.rtm_priority(),
+            id: todo.uid,
+            name: todo.summary,
+            list: None,
+            parent: None,
+            due: todo.due,
+            has_due_time: todo.has_due_time,
+            tags: todo.categories,
+            note: todo.description,
+            url: todo.url,
+            repeat: todo.rrule,
+            completed: todo.completed,
+        }
+    }
+}
+
+impl ImportedTask {
+    /// Convert a todo.txt task, whose due date is a day in `tz`.
+    fn from_todotxt_task(task: TodoTxtTask, tz: &Tz) -> Result<ImportedTask, anyhow::Error> {
+        let due = task.due.map(|date| {
+            let midnight = date.and_time(NaiveTime::MIN);
+            tz.from_local_datetime(&midnight)
+                .earliest()
+                .map_or_else(|| midnight.and_utc(), |t| t.with_timezone(&Utc))
+        });
+        Ok(ImportedTask {
+            parent: None,
+            priority: task.rtm_priority(),
+            repeat: task.rtm_repeat()?,
+            list: task.projects.into_iter().next(),
+            id: Some(task.id),
+            name: task.name,
+            due,
+            has_due_time: false,
+            tags: task.contexts,
+            note: None,
+            url: None,
+            completed: task.completed,
+        })
+    }
+
+    /// Convert a Taskwarrior task.
+    fn from_taskwarrior_task(
+        task: TaskwarriorTask,
+        tz: &Tz,
+    ) -> Result<ImportedTask, anyhow::Error> {
+        // Taskwarrior has no date-only due dates; treat midnight in `tz`
+        // as one.
+        let has_due_time = task
+            .due
+            .is_some_and(|due| due.with_timezone(tz).time() != NaiveTime::MIN);
+        Ok(ImportedTask {
+            priority: task.rtm_priority(),
+            repeat: task.rtm_repeat()?,
+            id: Some(task.uuid),
+            name: task.description,
+            list: task.project,
+            parent: None,
+            due: task.due,
+            has_due_time,
+            tags: task.tags,
+            note: None,
+            url: None,
+            completed: task.status == "completed",
+        })
+    }
+}
+
+/// Convert Taskwarrior tasks, with each task a subtask of the first one
+/// depending on it.  Deleted tasks, and those which came from RTM, are
+/// left out.
+fn from_taskwarrior(
+    tasks: Vec<TaskwarriorTask>,
+    tz: &Tz,
+) -> Result<Vec<ImportedTask>, anyhow::Error> {
+    let mut parents: HashMap<String, String> = HashMap::new();
+    for task in &tasks {
+        for dependency in &task.depends {
+            parents
+                .entry(dependency.clone())
+                .or_insert_with(|| task.uuid.clone());
+        }
+    }
+    let mut result = Vec::new();
+    for task in tasks {
+        if task.status == "deleted" {
+            continue;
+        }
+        if task.rtm_id.is_some() {
+            println!("Already in RTM: {}", task.description);
+            continue;
+        }
+        let notes: Vec<String> = task
+            .annotations
+            .iter()
+            .map(|a| a.description.clone())
+            .collect();
+        let parent = parents.get(&task.uuid).cloned();
+        let mut imported = ImportedTask::from_taskwarrior_task(task, tz)?;
+        imported.parent = parent;
+        imported.note = (!notes.is_empty()).then(|| notes.join("\n\n"));
+        result.push(imported);
+    }
+    Ok(result)
+}
+
+/// Convert todo.txt tasks, leaving out those which came from RTM.  Due
+/// dates are days in `tz`.
+fn from_todotxt(tasks: Vec<TodoTxtTask>, tz: &Tz) -> Result<Vec<ImportedTask>, anyhow::Error> {
+    let mut result = Vec::new();
+    for task in tasks {
+        if task.rtm_id().is_some() {
+            println!("Already in RTM: {}", task.name);
+            continue;
+        }
+        result.push(ImportedTask::from_todotxt_task(task, tz)?);
+    }
+    Ok(result)
+}
+
+/// Order tasks so that each comes after its parent, if that is being
+/// imported too.
+fn parents_first(tasks: Vec<ImportedTask>) -> Vec<ImportedTask> {
+    let mut remaining = tasks;
+    let mut result: Vec<ImportedTask> = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let before = remaining.len();
+        let (ready, waiting): (Vec<_>, Vec<_>) = remaining.into_iter().partition(|task| {
+            task.parent
+                .as_ref()
+                .is_none_or(|parent| result.iter().any(|t| t.id.as_ref() == Some(parent)))
+        });
+        result.extend(ready);
+        remaining = waiting;
+        if remaining.len() == before {
+            // A cycle, or parents which aren't being imported.
+            result.append(&mut remaining);
+        }
+    }
+    result
+}
+
+/// Read the tasks to import from whichever file was given, with dates
+/// which have no time zone taken to be in `tz`.
+fn read_tasks(args: &ImportArgs, tz: &Tz) -> Result<Vec<ImportedTask>, anyhow::Error> {
+    if let Some(path) = &args.ical {
+        let ics = std::fs::read_to_string(path)?;
+        Ok(parse_todos(&ics, tz)?
+            .into_iter()
+            .map(ImportedTask::from)
+            .collect())
+    } else if let Some(path) = &args.todotxt {
+        let text = std::fs::read_to_string(path)?;
+        from_todotxt(parse_todotxt(&text)?, tz)
+    } else if let Some(path) = &args.taskwarrior {
+        let json = std::fs::read_to_string(path)?;
+        Ok(parents_first(from_taskwarrior(
+            parse_taskwarrior(&json)?,
+            tz,
+        )?))
+    } else {
+        bail!("No file given")
+    }
+}
+
+/// Find a list by name, allowing `_` for spaces as in todo.txt projects.
+fn find_list<'a>(lists: &'a [RTMList], name: &str) -> Option<&'a str> {
+    lists
+        .iter()
+        .filter(|l| !l.smart)
+        .find(|l| l.name == name || l.name.replace(' ', "_") == name)
+        .map(|l| l.id.as_str())
+}
+
+/// Set everything which `rtm.tasks.add` can't and which `existing`
+/// doesn't have yet.  A task just added has none of it, and one from an
+/// earlier import which failed part way has only what was set before the
+/// failure, so importing again finishes it.  Returns the number of
+/// changes made.
+async fn finish_task(
+    api: &API,
+    throttle: &mut Throttle,
+    timeline: &RTMTimeline,
+    existing: &TaskEntry<'_>,
+    task: &ImportedTask,
+) -> Result<usize, anyhow::Error> {
+    let (series, current) = (existing.series, existing.task);
+    let ids = (existing.list_id, series.id.as_str(), current.id.as_str());
+    let mut changes = 0;
+    if let (Some(due), None) = (task.due, current.due) {
+        throttle.wait().await;
+        api.set_due_date_id(timeline, ids.0, ids.1, ids.2, due, task.has_due_time)
+            .await?;
+        changes += 1;
+    }
+    if task.priority != "N" && current.priority == "N" {
+        throttle.wait().await;
+        api.set_priority_id(timeline, ids.0, ids.1, ids.2, task.priority)
+            .await?;
+        changes += 1;
+    }
+    let tags: Vec<&str> = task
+        .tags
+        .iter()
+        .filter(|tag| !series.tags.contains(tag))
+        .map(String::as_str)
+        .collect();
+    if !tags.is_empty() {
+        throttle.wait().await;
+        api.add_tags_id(timeline, ids.0, ids.1, ids.2, &tags)
+            .await?;
+        changes += 1;
+    }
+    if let Some(note) = &task.note {
+        if !series.notes.iter().any(|n| n.text == *note) {
+            throttle.wait().await;
+            api.add_note_id(timeline, ids.0, ids.1, ids.2, "", note)
+                .await?;
+            changes += 1;
+        }
+    }
+    if let (Some(url), true) = (&task.url, series.url.is_empty()) {
+        throttle.wait().await;
+        api.set_url_id(timeline, ids.0, ids.1, ids.2, url).await?;
+        changes += 1;
+    }
+    if let (Some(repeat), None) = (&task.repeat, &series.repeat) {
+        throttle.wait().await;
+        api.set_recurrence_id(timeline, ids.0, ids.1, ids.2, repeat)
+            .await?;
+        changes += 1;
+    }
+    // Last, as completing a repeating task moves on to the next one.
+    if task.completed && current.completed.is_none() {
+        throttle.wait().await;
+        api.mark_complete_id(timeline, ids.0, ids.1, ids.2).await?;
+        changes += 1;
+    }
+    Ok(changes)
+}
+
+/// Import tasks from a file, skipping any imported before.  Tasks from an
+/// earlier import which failed part way are finished off.
+pub async fn import(args: &ImportArgs) -> Result<ExitCode, anyhow::Error> {
+    let perms = if args.dry_run {
+        Perms::Read
+    } else {
+        Perms::Write
+    };
+    let api = get_rtm_api(perms).await?;
+    let tz = api.get_timezone().await?.unwrap_or_else(system_timezone);
+    let tasks = read_tasks(args, &tz)?;
+    let lists = if args.list.is_some() || tasks.iter().any(|t| t.list.is_some()) {
+        api.get_lists().await?
+    } else {
+        Vec::new()
+    };
+    let default_list = match &args.list {
+        Some(name) => {
+            Some(find_list(&lists, name).ok_or_else(|| anyhow!("No list called {name:?}."))?)
+        }
+        None => None,
+    };
+    let timeline = if args.dry_run {
+        None
+    } else {
+        Some(api.get_timeline().await?)
+    };
+    let mut throttle = Throttle::new(RTM_REQUEST_INTERVAL);
+    // The tasks added or found, by import id, for adding subtasks.
+    let mut added: HashMap<String, Task> = HashMap::new();
+    for task in &tasks {
+        let existing = match &task.id {
+            Some(id) => {
+                throttle.wait().await;
+                Some(api.get_tasks_filtered(&api.get_filter_extid(id)).await?)
+            }
+            None => None,
+        };
+        let entries = existing.as_ref().map(RTMTasks::entries);
+        if let (Some(id), Some(entry)) = (&task.id, entries.iter().flatten().next()) {
+            let changes = match &timeline {
+                Some(timeline) => finish_task(&api, &mut throttle, timeline, entry, task)
+                    .await
+                    .map_err(|e| e.context(format!("Unable to finish {:?}", task.name)))?,
+                None => 0,
+            };
+            if changes > 0 {
+                println!("Finished: {}", task.name);
+            } else {
+                println!("Already imported: {}", task.name);
+            }
+            added.insert(id.clone(), entry.task.clone());
+            continue;
+        }
+        let list_id = match &task.list {
+            Some(name) => find_list(&lists, name).or_else(|| {
+                eprintln!(
+                    "No list called {name:?} for {:?}; using the default.",
+                    task.name
+                );
+                default_list
+            }),
+            None => default_list,
+        };
+        let Some(timeline) = &timeline else {
+            println!("Would add: {}", task.name);
+            continue;
+        };
+        let parent = task.parent.as_ref().and_then(|p| added.get(p));
+        throttle.wait().await;
+        let (new_list, _) = api
+            .add_task_id(
+                timeline,
+                &task.name,
+                list_id,
+                parent.map(|p| p.id.as_str()),
+                task.id.as_deref(),
+                false,
+            )
+            .await?;
+        let (new_list, series, new_task) = new_list
+            .as_ref()
+            .and_then(|list| {
+                let series = list.taskseries.as_ref()?.first()?;
+                Some((list, series, series.task.first()?))
+            })
+            .ok_or_else(|| anyhow!("RTM didn't return the new task {:?}", task.name))?;
+        let entry = TaskEntry {
+            list_id: &new_list.id,
+            series,
+            task: new_task,
+        };
+        // The task can be found by its external id from now on, so if
+        // this fails, importing again will finish it.
+        finish_task(&api, &mut throttle, timeline, &entry, task)
+            .await
+            .map_err(|e| e.context(format!("Added {:?}, but couldn't finish it", task.name)))?;
+        println!("Added: {}", task.name);
+        if let Some(id) = &task.id {
+            added.insert(id.clone(), new_task.clone());
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_taskwarrior, from_todotxt, parents_first};
+    use rememberthemilk::taskwarrior::parse_taskwarrior;
+    use rememberthemilk::todotxt::{parse_todotxt, to_todotxt};
+    use rememberthemilk::RTMTasks;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_from_taskwarrior() {
+        let tasks = parse_taskwarrior(
+            r#"[
+            {"uuid": "child", "description": "Child", "status": "pending",
+             "annotations": [{"entry": "20261001T090000Z", "description": "One"},
+                             {"entry": "20261002T090000Z", "description": "Two"}]},
+            {"uuid": "gone", "description": "Gone", "status": "deleted"},
+            {"uuid": "parent", "description": "Parent", "status": "completed",
+             "depends": ["child"], "priority": "M"},
+            {"uuid": "rtm", "description": "From RTM", "status": "pending",
+             "rtm_id": "1-2-3"}
+        ]"#,
+        )
+        .unwrap();
+        let tasks = parents_first(from_taskwarrior(tasks, &chrono_tz::UTC).unwrap());
+        let summary: Vec<_> = tasks
+            .iter()
+            .map(|t| {
+                (
+                    t.name.as_str(),
+                    t.parent.as_deref(),
+                    t.completed,
+                    t.priority,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Parent", None, true, "2"),
+                ("Child", Some("parent"), false, "N")
+            ]
+        );
+        assert_eq!(tasks[1].note.as_deref(), Some("One\n\nTwo"));
+    }
+
+    #[test]
+    fn test_due_dates_in_timezone() {
+        let tz = chrono_tz::Europe::London;
+        let todotxt = from_todotxt(parse_todotxt("Pay rent due:2026-10-20").unwrap(), &tz).unwrap();
+        assert_eq!(
+            todotxt[0].due.map(|due| due.to_rfc3339()).as_deref(),
+            Some("2026-10-19T23:00:00+00:00")
+        );
+        assert!(!todotxt[0].has_due_time);
+        let taskwarrior = parse_taskwarrior(
+            r#"[
+            {"uuid": "a", "description": "Midnight", "status": "pending",
+             "due": "20261019T230000Z"},
+            {"uuid": "b", "description": "UTC midnight", "status": "pending",
+             "due": "20261020T000000Z"}
+        ]"#,
+        )
+        .unwrap();
+        let taskwarrior = from_taskwarrior(taskwarrior, &tz).unwrap();
+        let has_due_time: Vec<_> = taskwarrior.iter().map(|t| t.has_due_time).collect();
+        assert_eq!(has_due_time, [false, true]);
+    }
+
+    #[test]
+    fn test_todotxt_round_trip() {
+        let series = |id: &str, name: &str, source: &str| {
+            serde_json::json!({
+                "id": id, "created": "2026-10-01T09:00:00Z",
+                "modified": "2026-10-01T09:00:00Z", "name": name,
+                "source": source, "url": "", "location_id": "", "tags": [],
+                "participants": [], "notes": [], "parent_task_id": "",
+                "task": [{
+                    "id": id, "due": "", "has_due_time": "0",
+                    "added": "2026-10-01T09:00:00Z", "completed": "", "deleted": "",
+                    "priority": "N", "postponed": "0", "estimate": ""
+                }],
+            })
+        };
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [
+                    series("2", "Imported", "api:key:todotxt:0123abcd"),
+                    series("3", "Added in RTM", "js"),
+                ],
+            }],
+        }))
+        .unwrap();
+        let text = to_todotxt(&tasks.entries(), &HashMap::new(), &chrono_tz::UTC);
+        let imported = from_todotxt(parse_todotxt(&text).unwrap(), &chrono_tz::UTC).unwrap();
+        // The imported task keeps its id, so importing finds it again, and
+        // the one from RTM is left out.
+        let summary: Vec<_> = imported
+            .iter()
+            .map(|t| (t.name.as_str(), t.id.as_deref()))
+            .collect();
+        assert_eq!(summary, [("Imported", Some("todotxt:0123abcd"))]);
+    }
+}
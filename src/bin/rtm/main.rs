@@ -1,14 +1,17 @@
 #![deny(warnings)]
 use anyhow::bail;
+use chrono::Utc;
 use clap::Parser;
-#[cfg(feature = "cache")]
-use etcetera::{AppStrategy, AppStrategyArgs};
 use log::{info, trace};
+use rememberthemilk::bulk::{plan_bulk, BulkEdit, RTM_REQUEST_INTERVAL};
 #[cfg(feature = "cache")]
 use rememberthemilk::cache::TaskCache;
-use rememberthemilk::{Perms, API};
+use rememberthemilk::filter::expand_saved;
+use rememberthemilk::smartadd::NewTask;
+use rememberthemilk::sort::{group_entries, sort_entries, GroupBy, GroupKey, Sort, TaskEntry};
+use rememberthemilk::{system_timezone, Perms, RTMLists, RTMTasks, API};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 use std::process::ExitCode;
 
@@ -23,41 +26,203 @@ pub struct Settings {
     /// The default search filter for `rtm tasks` when not otherwise
     /// specified.
     pub filter: String,
+    /// Named filters, used with `rtm tasks --saved NAME` or as `list:NAME`
+    /// within other filters.
+    #[serde(default)]
+    pub saved: BTreeMap<String, String>,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
             filter: "status:incomplete AND (dueBefore:today OR due:today)".into(),
+            saved: Default::default(),
         }
     }
 }
 
+#[derive(clap::Args, Debug)]
+struct TasksArgs {
+    #[clap(long)]
+    /// Provide a filter string in RTM format.
+    filter: Option<String>,
+
+    #[clap(long)]
+    /// Look only for items with the given external id.
+    extid: Option<String>,
+
+    #[clap(long)]
+    /// Use a saved filter or smart list by name.
+    saved: Option<String>,
+
+    #[clap(long, value_delimiter = ',')]
+    /// Sort by due, priority, name, added or estimate (or several,
+    /// comma separated).  Prefix with - to reverse.
+    sort: Vec<Sort>,
+
+    #[clap(long)]
+    /// Group by list (the default), tag, due, priority or parent.
+    group_by: Option<GroupBy>,
+
+    #[clap(flatten)]
+    freshness: FreshnessArgs,
+
+    #[clap(long)]
+    /// Output as json, ndjson, csv, tsv or table instead of text.  Each
+    /// task has list_id, list_name, taskseries_id, task_id, name, due,
+    /// has_due_time, completed, priority, tags, url, notes (a count),
+    /// repeat and handle.
+    format: Option<Format>,
+}
+
 #[derive(Parser, Debug)]
 enum Command {
     /// Operate on tasks
-    Tasks {
+    Tasks(TasksArgs),
+    /// Show the tasks due each day
+    Agenda {
+        #[clap(long, default_value = "7")]
+        /// How many days to show, starting today.
+        days: u32,
+
+        #[clap(flatten)]
+        freshness: FreshnessArgs,
+    },
+    /// Show a month with the number of tasks due each day
+    Cal {
+        #[clap(value_parser = agenda::parse_month)]
+        /// The month to show, e.g. 2026-10 (default this month).
+        month: Option<chrono::NaiveDate>,
+
+        #[clap(flatten)]
+        freshness: FreshnessArgs,
+    },
+    /// Export tasks to another format
+    Export(export::ExportArgs),
+    /// Import tasks from another format
+    Import(import::ImportArgs),
+    /// Write a Markdown or HTML report of tasks
+    Report(report::ReportArgs),
+    /// Show, add or remove profiles
+    Profiles {
+        #[clap(subcommand)]
+        cmd: Option<profile::ProfilesCommand>,
+    },
+    /// Create tasks from templates
+    Template {
+        #[clap(subcommand)]
+        cmd: template::TemplateCommand,
+    },
+    /// Write a complete backup of the account as JSON
+    Backup(backup::BackupArgs),
+    /// Recreate lists, tasks and contacts from a backup
+    Restore(backup::RestoreArgs),
+    #[cfg(feature = "cache")]
+    /// Report statistics on completed and overdue tasks
+    Stats(stats::StatsArgs),
+    /// Show all lists
+    Lists {
         #[clap(long)]
-        /// Provide a filter string in RTM format.
+        /// Output as json, ndjson, csv, tsv or table instead of text.  Each
+        /// list has id, name, smart and filter.
+        format: Option<Format>,
+    },
+    /// Show saved filters and smart lists
+    Saved,
+    /// Save a named filter, or remove it if no filter is given
+    SaveFilter {
+        name: String,
         filter: Option<String>,
-
-        #[clap(long)]
-        /// Look only for items with the given external id.
-        extid: Option<String>,
     },
-    /// Show all lists
-    Lists,
+    /// Preview and apply several changes to the tasks matching a filter
+    Bulk(bulk::BulkArgs),
     /// Add a tag to filtered messages
     AddTag {
         tag: String,
         #[clap(long)]
         filter: String,
     },
+    /// Mark tasks as complete
+    Complete {
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Mark completed tasks as incomplete
+    Uncomplete {
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Delete tasks
+    Delete {
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Postpone tasks by a day
+    Postpone {
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Edit a task's fields and notes as text in $EDITOR
+    Edit {
+        #[clap(flatten)]
+        task: TaskSelection,
+    },
+    /// Rename tasks
+    Rename {
+        name: String,
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Set tasks' priority (1, 2, 3 or none)
+    Priority {
+        #[clap(value_parser = ops::parse_priority)]
+        priority: String,
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Set tasks' due date, e.g. "tomorrow 3pm", or "" to clear it
+    Due {
+        due: String,
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Move tasks to another list
+    Move {
+        list: String,
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Add a note to tasks
+    Note {
+        text: String,
+        #[clap(long, default_value = "")]
+        title: String,
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Set tasks' URL, or "" to clear it
+    Url {
+        url: String,
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
+    /// Remove a tag from tasks
+    Untag {
+        tag: String,
+        #[clap(flatten)]
+        tasks: TaskSelection,
+    },
     /// Add a new task
     AddTask {
         name: String,
         #[clap(long)]
         external_id: Option<String>,
+        /// Parse Smart Add syntax locally and set each part separately.
+        #[clap(long, conflicts_with = "external_id")]
+        local: bool,
+        /// Show how the name parses as Smart Add text without adding it.
+        #[clap(long)]
+        preview: bool,
     },
     /// Authorise the app
     AuthApp {
@@ -71,11 +236,30 @@ enum Command {
     #[cfg(feature = "cache")]
     /// Synchronize the local db
     Sync,
+    #[cfg(feature = "cache")]
+    /// Undo the most recent changes
+    Undo {
+        #[clap(default_value = "1")]
+        /// How many changes to undo.
+        count: u32,
+    },
+    #[cfg(feature = "cache")]
+    /// Show recent changes which can be undone
+    History {
+        #[clap(long, default_value = "20")]
+        /// How many changes to show.
+        limit: u32,
+    },
     #[cfg(feature = "tui")]
     /// Run the TUI
     Tui,
     /// Remove the saved user token
     Logout,
+    /// Show or change where the API secret and token are kept
+    SecretStore {
+        #[clap(subcommand)]
+        cmd: Option<secrets::SecretStoreCommand>,
+    },
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -109,6 +293,10 @@ struct Opt {
     #[clap(default_value = "auto", long)]
     colour: ColourOption,
 
+    /// Use a named profile, with its own account, settings and cache.
+    #[clap(long, global = true)]
+    profile: Option<String>,
+
     #[clap(subcommand)]
     cmd: Command,
 }
@@ -125,16 +313,21 @@ impl Opt {
     }
 }
 
-async fn get_rtm_api(perm: Perms) -> Result<API, anyhow::Error> {
+/// Load the API configuration, without checking the token.
+fn load_rtm_api() -> Result<API, anyhow::Error> {
     trace!("Loading API configuration...");
-    let config: rememberthemilk::RTMConfig = confy::load(RTM_APP_NAME, Some(RTM_AUTH_ID))?;
-    let mut api = if config.api_key.is_some() && config.api_secret.is_some() {
+    let config: rememberthemilk::RTMConfig = profile::load(RTM_AUTH_ID)?;
+    if config.api_key.is_some() && (config.api_secret.is_some() || config.secret_store.is_some()) {
         info!("Creating API object.");
         API::from_config(config)
     } else {
         eprintln!("Error, no API key saved.  Use `rtm auth-app` to supply them.");
         bail!("No auth key");
-    };
+    }
+}
+
+async fn get_rtm_api(perm: Perms) -> Result<API, anyhow::Error> {
+    let mut api = load_rtm_api()?;
 
     trace!("Checking API permissions...");
     if !api.has_token(perm).await.unwrap() {
@@ -147,17 +340,10 @@ async fn get_rtm_api(perm: Perms) -> Result<API, anyhow::Error> {
 
 #[cfg(feature = "cache")]
 async fn get_rtm_cache(api: API) -> Result<TaskCache, anyhow::Error> {
-    let strategy = etcetera::choose_app_strategy(AppStrategyArgs {
-        top_level_domain: "org".into(),
-        author: "Chris Emerson".into(),
-        app_name: "rtm".into(),
-    })
-    .unwrap();
-
-    let cache_dir = strategy.cache_dir();
+    let cache_dir = profile::cache_dir();
     std::fs::create_dir_all(&cache_dir)?;
 
-    let db_path = strategy.in_cache_dir("sync.sqlite");
+    let db_path = cache_dir.join("sync.sqlite");
 
     Ok(TaskCache::new(&db_path, api).await?)
 }
@@ -176,7 +362,14 @@ async fn auth_user(api: &mut API, perm: Perms) -> Result<(), anyhow::Error> {
     if !api.check_auth(&auth).await? {
         bail!("Error authenticating");
     }
-    confy::store(RTM_APP_NAME, Some(RTM_AUTH_ID), api.to_config())?;
+    let mut config = api.to_config();
+    if config.secret_store.is_none() {
+        // Keep using the configured store after `rtm auth-app`.
+        let saved: rememberthemilk::RTMConfig = profile::load(RTM_AUTH_ID)?;
+        config.secret_store = saved.secret_store;
+    }
+    config.store_secrets(None)?;
+    profile::store(RTM_AUTH_ID, config)?;
     Ok(())
 }
 
@@ -189,9 +382,11 @@ async fn auth_app(key: String, secret: String, perm: Perms) -> Result<ExitCode,
 }
 
 async fn logout() -> Result<ExitCode, anyhow::Error> {
-    let mut config: rememberthemilk::RTMConfig = confy::load(RTM_APP_NAME, Some(RTM_AUTH_ID))?;
+    let mut config: rememberthemilk::RTMConfig = profile::load(RTM_AUTH_ID)?;
+    config.load_secrets(None)?;
     config.clear_user_data();
-    confy::store(RTM_APP_NAME, Some(RTM_AUTH_ID), config)?;
+    config.store_secrets(None)?;
+    profile::store(RTM_AUTH_ID, config)?;
     Ok(ExitCode::SUCCESS)
 }
 
@@ -219,110 +414,181 @@ fn format_human_time(secs: u64) -> String {
     }
 }
 
-fn get_default_filter() -> Result<String, anyhow::Error> {
-    let settings: Settings = confy::load(RTM_APP_NAME, RTM_SETTINGS)?;
-    Ok(settings.filter)
+fn get_settings() -> Result<Settings, anyhow::Error> {
+    profile::load(RTM_SETTINGS)
 }
 
-async fn list_tasks(
-    opts: &Opt,
-    filter: &Option<String>,
-    extid: &Option<String>,
-) -> Result<ExitCode, anyhow::Error> {
-    let api = get_rtm_api(Perms::Read).await?;
-    let default_filter = get_default_filter()?;
-    let extid_filter;
-    let filter = match (filter, extid) {
-        (Some(ref s), None) => &s[..],
-        (None, Some(ref s)) => {
-            extid_filter = api.get_filter_extid(s);
-            &extid_filter[..]
-        }
-        (Some(_), Some(_)) => {
-            bail!("Supplying both --filter and --extid is not supported.")
+/// Short handles for the tasks, keyed by task series and task id.
+#[cfg(feature = "cache")]
+async fn task_handles(
+    api: &API,
+    tasks: &RTMTasks,
+) -> Result<HashMap<(String, String), String>, anyhow::Error> {
+    let cache = get_rtm_cache(api.clone()).await?;
+    Ok(cache
+        .assign_handles(tasks)
+        .await?
+        .into_iter()
+        .map(|(ids, handle)| (ids, handle.to_string()))
+        .collect())
+}
+
+#[cfg(not(feature = "cache"))]
+async fn task_handles(
+    _api: &API,
+    _tasks: &RTMTasks,
+) -> Result<HashMap<(String, String), String>, anyhow::Error> {
+    Ok(HashMap::new())
+}
+
+fn handle_of<'a>(
+    handles: &'a HashMap<(String, String), String>,
+    entry: &TaskEntry<'_>,
+) -> Option<&'a String> {
+    handles.get(&(entry.series.id.clone(), entry.task.id.clone()))
+}
+
+async fn list_tasks(opts: &Opt, args: &TasksArgs) -> Result<ExitCode, anyhow::Error> {
+    let TasksArgs {
+        filter,
+        extid,
+        saved,
+        sort,
+        group_by,
+        format,
+        ..
+    } = args;
+    let (group_by, format) = (*group_by, *format);
+    if format.is_some() && group_by.is_some() {
+        bail!("--group-by can't be used with --format.");
+    }
+    let source = TaskSource::open(args.freshness.or(Freshness::Online)).await?;
+    let api = source.api();
+    let settings = get_settings()?;
+    let filter = match (filter, extid, saved) {
+        (Some(s), None, None) => s.clone(),
+        (None, Some(s), None) => api.get_filter_extid(s),
+        (None, None, Some(name)) => match settings.saved.get(name) {
+            Some(s) => s.clone(),
+            None => {
+                let lists = source.get_lists().await?;
+                match lists.into_iter().find(|l| l.smart && l.name == *name) {
+                    Some(list) => list.filter.unwrap_or_default(),
+                    None => bail!("No saved filter or smart list called {name:?}."),
+                }
+            }
+        },
+        (None, None, None) => settings.filter.clone(),
+        _ => {
+            bail!("Only one of --filter, --extid and --saved can be used.")
         }
-        (None, None) => &default_filter,
     };
-    let all_tasks = api.get_tasks_filtered(filter).await?;
+    let filter = expand_saved(&filter, &settings.saved)?;
+    let all_tasks = source.get_tasks_filtered(&filter).await?;
+    let handles = task_handles(api, &all_tasks).await?;
     let mut lists = HashMap::new();
     if !all_tasks.list.is_empty() {
-        let all_lists = api.get_lists().await?;
+        let all_lists = source.get_lists().await?;
         for list in all_lists {
             lists.insert(list.id.clone(), list);
         }
     }
     use termcolor::{Color, ColorSpec, WriteColor};
+    let mut entries = all_tasks.entries();
+    sort_entries(&mut entries, sort);
+    if let Some(format) = format {
+        let records: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                let list_name = lists.get(entry.list_id).map(|l| &l.name[..]);
+                let handle = handle_of(&handles, entry).cloned();
+                TaskRecord::new(entry, list_name.unwrap_or_default(), handle)
+            })
+            .collect();
+        write_records(&mut std::io::stdout().lock(), format, &records)?;
+        return Ok(if records.is_empty() {
+            ExitCode::from(1)
+        } else {
+            ExitCode::SUCCESS
+        });
+    }
     if all_tasks.list.is_empty() {
         return Ok(ExitCode::from(1));
     }
+    let handle_width = handles.values().map(|h| h.len()).max().unwrap_or(0);
+    let now = Utc::now().with_timezone(&source.timezone().await?);
+    let groups = group_entries(entries, group_by.unwrap_or(GroupBy::List), &now);
     let mut stdout = opts.get_stdout();
-    for list in all_tasks.list {
+    for group in groups {
         stdout.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
-        writeln!(stdout, "#{}", lists[&list.id].name)?;
-        if let Some(v) = list.taskseries {
-            stdout.reset()?;
-            for ts in v {
-                log::trace!("{:?}", ts.task);
-                for task in &ts.task {
-                    let time_left = task.get_time_left();
-                    use rememberthemilk::TimeLeft::*;
-                    match time_left {
-                        Remaining(secs) => {
-                            let colour = if secs < 60 * 60 {
-                                ColorSpec::new().set_fg(Some(Color::Red)).clone()
-                            } else {
-                                ColorSpec::new().set_fg(Some(Color::Yellow)).clone()
-                            };
-                            stdout.set_color(&colour)?;
-                            write!(stdout, "{}", format_human_time(secs))?;
-                        }
-                        Overdue(secs) => {
-                            stdout.set_color(ColorSpec::new().set_bg(Some(Color::Red)))?;
-                            write!(stdout, "{} ago", format_human_time(secs))?;
-                        }
-                        Completed | NoDue => {
-                            ColorSpec::new().set_fg(Some(Color::Green));
-                        }
+        match &group.key {
+            GroupKey::List(id) => writeln!(stdout, "#{}", lists[id].name)?,
+            key => writeln!(stdout, "{key}")?,
+        }
+        stdout.reset()?;
+        for entry in group.tasks {
+            let TaskEntry {
+                series: ts, task, ..
+            } = entry;
+            log::trace!("{:?}", task);
+            if let Some(handle) = handle_of(&handles, &entry) {
+                stdout.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)))?;
+                write!(stdout, "{handle:>handle_width$}  ")?;
+                stdout.reset()?;
+            }
+            let time_left = task.get_time_left();
+            use rememberthemilk::TimeLeft::*;
+            match time_left {
+                Remaining(secs) => {
+                    let colour = if secs < 60 * 60 {
+                        ColorSpec::new().set_fg(Some(Color::Red)).clone()
+                    } else {
+                        ColorSpec::new().set_fg(Some(Color::Yellow)).clone()
                     };
+                    stdout.set_color(&colour)?;
+                    write!(stdout, "{}", format_human_time(secs))?;
                 }
-                write!(stdout, "  {}", ts.name)?;
-                stdout.set_color(ColorSpec::new().set_bg(Some(Color::Black)))?;
-                writeln!(stdout)?;
-                if opts.verbose {
-                    writeln!(stdout, "   id: {}", ts.id)?;
-                    writeln!(stdout, "   created: {}", ts.created)?;
-                    writeln!(stdout, "   modified: {}", ts.modified)?;
-                    writeln!(stdout, "   tags: {:?}", &ts.tags[..])?;
-                    if let Some(repeat) = ts.repeat {
-                        if repeat.every {
-                            writeln!(stdout, "   repeat: every {}", repeat.rule)?;
-                        } else {
-                            writeln!(stdout, "   repeat: after {}", repeat.rule)?;
-                        }
-                    }
+                Overdue(secs) => {
+                    stdout.set_color(ColorSpec::new().set_bg(Some(Color::Red)))?;
+                    write!(stdout, "{} ago", format_human_time(secs))?;
                 }
-
-                if opts.verbose && !ts.task.is_empty() {
-                    let task = &ts.task[0];
-                    writeln!(stdout, "    id: {}", task.id)?;
-                    if let Some(due) = task.due {
-                        if task.has_due_time {
-                            writeln!(stdout, "    due: {}", due)?;
-                        } else {
-                            // Remove the time parts, which aren't used.
-                            writeln!(stdout, "    due: {}", due.date_naive())?;
-                        }
-                    }
-                    if let Some(added) = task.added {
-                        writeln!(stdout, "    added: {}", added)?;
-                    }
-                    if let Some(completed) = task.completed {
-                        writeln!(stdout, "    completed: {}", completed)?;
+                Completed | NoDue => {
+                    ColorSpec::new().set_fg(Some(Color::Green));
+                }
+            };
+            write!(stdout, "  {}", ts.name)?;
+            stdout.set_color(ColorSpec::new().set_bg(Some(Color::Black)))?;
+            writeln!(stdout)?;
+            if opts.verbose {
+                writeln!(stdout, "   id: {}", ts.id)?;
+                writeln!(stdout, "   created: {}", ts.created)?;
+                writeln!(stdout, "   modified: {}", ts.modified)?;
+                writeln!(stdout, "   tags: {:?}", &ts.tags[..])?;
+                if let Some(repeat) = &ts.repeat {
+                    if repeat.every {
+                        writeln!(stdout, "   repeat: every {}", repeat.rule)?;
+                    } else {
+                        writeln!(stdout, "   repeat: after {}", repeat.rule)?;
                     }
-                    if let Some(deleted) = task.deleted {
-                        writeln!(stdout, "    deleted: {}", deleted)?;
+                }
+                writeln!(stdout, "    id: {}", task.id)?;
+                if let Some(due) = task.due {
+                    if task.has_due_time {
+                        writeln!(stdout, "    due: {}", due)?;
+                    } else {
+                        // Remove the time parts, which aren't used.
+                        writeln!(stdout, "    due: {}", due.date_naive())?;
                     }
                 }
+                if let Some(added) = task.added {
+                    writeln!(stdout, "    added: {}", added)?;
+                }
+                if let Some(completed) = task.completed {
+                    writeln!(stdout, "    completed: {}", completed)?;
+                }
+                if let Some(deleted) = task.deleted {
+                    writeln!(stdout, "    deleted: {}", deleted)?;
+                }
             }
         }
     }
@@ -330,32 +596,74 @@ async fn list_tasks(
     Ok(ExitCode::SUCCESS)
 }
 
-async fn list_lists() -> Result<ExitCode, anyhow::Error> {
+async fn list_lists(format: Option<Format>) -> Result<ExitCode, anyhow::Error> {
     let api = get_rtm_api(Perms::Read).await?;
     let all_lists = api.get_lists().await?;
+    if let Some(format) = format {
+        let records: Vec<ListRecord> = all_lists.iter().map(Into::into).collect();
+        write_records(&mut std::io::stdout().lock(), format, &records)?;
+        return Ok(ExitCode::SUCCESS);
+    }
     for list in all_lists {
         println!("{}", list.name);
     }
     Ok(ExitCode::SUCCESS)
 }
 
-async fn add_tag(filter: String, tag: String) -> Result<ExitCode, anyhow::Error> {
-    let api = get_rtm_api(Perms::Write).await?;
-    let timeline = api.get_timeline().await?;
-    let tasks = api.get_tasks_filtered(&filter).await?;
+async fn list_saved() -> Result<ExitCode, anyhow::Error> {
+    let settings = get_settings()?;
+    for (name, filter) in &settings.saved {
+        println!("{name}: {filter}");
+    }
+    let api = get_rtm_api(Perms::Read).await?;
+    for list in api.get_lists().await? {
+        if let (true, Some(filter)) = (list.smart, list.filter) {
+            if !settings.saved.contains_key(&list.name) {
+                println!("{} (smart list): {filter}", list.name);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-    for list in tasks.list {
-        if let Some(ref v) = list.taskseries {
-            for ts in v {
-                let to_tag = !ts.tags.contains(&tag);
-                if to_tag {
-                    println!("  Adding tag to {}...", ts.name);
-                    api.add_tag(&timeline, &list, ts, &ts.task[0], &[&tag[..]])
-                        .await?;
-                }
+fn save_filter(name: String, filter: Option<String>) -> Result<ExitCode, anyhow::Error> {
+    let mut settings = get_settings()?;
+    match filter {
+        Some(filter) => {
+            // Check that it doesn't make a loop of saved filters.
+            let mut saved = settings.saved.clone();
+            saved.insert(name.clone(), filter.clone());
+            expand_saved(&filter, &saved)?;
+            settings.saved.insert(name, filter);
+        }
+        None => {
+            if settings.saved.remove(&name).is_none() {
+                eprintln!("No saved filter called {name:?}.");
+                return Ok(ExitCode::from(1));
             }
         }
     }
+    profile::store(RTM_SETTINGS, settings)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn add_tag(filter: String, tag: String) -> Result<ExitCode, anyhow::Error> {
+    let api = get_rtm_api(Perms::Write).await?;
+    let log = ChangeLog::open(&api).await?;
+    let timeline = log.timeline().await?;
+    let tasks = api.get_tasks_filtered(&filter).await?;
+    let entries = tasks.entries();
+    let tz = api.get_timezone().await?.unwrap_or_else(system_timezone);
+    let plan = plan_bulk(&entries, &[BulkEdit::AddTag(tag)], &HashMap::new(), &tz);
+    let outcome = api
+        .bulk_apply(&timeline, &plan, RTM_REQUEST_INTERVAL, |_, item| {
+            println!("  Adding tag to {}...", item.entry.series.name);
+        })
+        .await;
+    log.record_batch(&timeline, &outcome.transactions).await?;
+    if let Some(failure) = outcome.failures.into_iter().next() {
+        return Err(failure.error);
+    }
     Ok(ExitCode::SUCCESS)
 }
 
@@ -370,6 +678,34 @@ async fn add_task(
     let added = api
         .add_task(&timeline, name, None, None, external_id, opt.smart)
         .await?;
+    print_added(added);
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn add_task_local(name: &str, preview: bool) -> Result<ExitCode, anyhow::Error> {
+    let new_task = NewTask::parse(name);
+    println!("{new_task}");
+    if preview {
+        return Ok(ExitCode::SUCCESS);
+    }
+    let api = get_rtm_api(Perms::Write).await?;
+    let log = ChangeLog::open(&api).await?;
+    let timeline = log.timeline().await?;
+    let lists = api.get_lists().await?;
+    let outcome = api.add_new_task(&timeline, &new_task, &lists, None).await?;
+    log.record_batch(&timeline, &outcome.transactions).await?;
+    print_added(outcome.added);
+    if outcome.failures.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("Failed:");
+    for (change, error) in &outcome.failures {
+        println!("    {change}: {error:#}");
+    }
+    Ok(ExitCode::from(1))
+}
+
+fn print_added(added: Option<RTMLists>) {
     if let Some(list) = added {
         if let Some(taskseries) = list.taskseries {
             if !taskseries.is_empty() {
@@ -383,7 +719,6 @@ async fn add_task(
     } else {
         println!("Successful result, but no list returned.")
     }
-    Ok(ExitCode::SUCCESS)
 }
 
 fn print_taskseries(task: &rememberthemilk::TaskSeries) {
@@ -405,9 +740,30 @@ async fn run_sync(_opt: &Opt) -> Result<ExitCode, anyhow::Error> {
     Ok(ExitCode::SUCCESS)
 }
 
+mod agenda;
+mod backup;
+mod bulk;
+mod edit;
+mod export;
+mod format;
+mod history;
+mod import;
+mod ops;
+mod profile;
+mod report;
+mod secrets;
+mod source;
+#[cfg(feature = "cache")]
+mod stats;
+mod template;
 #[cfg(feature = "tui")]
 mod tui;
 
+use format::{write_records, Format, ListRecord, TaskRecord};
+use history::ChangeLog;
+use ops::{modify_tasks, TaskOp, TaskSelection};
+use source::{Freshness, FreshnessArgs, TaskSource};
+
 #[tokio::main]
 async fn main() -> Result<ExitCode, anyhow::Error> {
     env_logger::init();
@@ -416,23 +772,73 @@ async fn main() -> Result<ExitCode, anyhow::Error> {
     console_subscriber::init();
 
     let opt = Opt::parse();
+    profile::select(opt.profile.as_deref())?;
     Ok(match opt.cmd {
-        Command::Tasks {
-            ref filter,
-            ref extid,
-        } => list_tasks(&opt, filter, extid).await?,
-        Command::Lists => list_lists().await?,
+        Command::Tasks(ref args) => list_tasks(&opt, args).await?,
+        Command::Agenda {
+            days,
+            ref freshness,
+        } => agenda::agenda(&opt, days, freshness).await?,
+        Command::Cal {
+            month,
+            ref freshness,
+        } => agenda::cal(&opt, month, freshness).await?,
+        Command::Export(ref args) => export::export(args).await?,
+        Command::Import(ref args) => import::import(args).await?,
+        Command::Report(ref args) => report::report(args).await?,
+        Command::Profiles { ref cmd } => profile::profiles(cmd.as_ref()).await?,
+        Command::Template { ref cmd } => template::template(cmd).await?,
+        Command::Backup(ref args) => backup::backup(args).await?,
+        Command::Restore(ref args) => backup::restore(args).await?,
+        #[cfg(feature = "cache")]
+        Command::Stats(ref args) => stats::stats(&opt, args).await?,
+        Command::Lists { format } => list_lists(format).await?,
+        Command::Saved => list_saved().await?,
+        Command::SaveFilter { name, filter } => save_filter(name, filter)?,
+        Command::Bulk(ref args) => bulk::bulk(args).await?,
         Command::AddTag { filter, tag } => add_tag(filter, tag).await?,
+        Command::Complete { ref tasks } => modify_tasks(tasks, TaskOp::Complete).await?,
+        Command::Uncomplete { ref tasks } => modify_tasks(tasks, TaskOp::Uncomplete).await?,
+        Command::Delete { ref tasks } => modify_tasks(tasks, TaskOp::Delete).await?,
+        Command::Postpone { ref tasks } => modify_tasks(tasks, TaskOp::Postpone).await?,
+        Command::Edit { ref task } => edit::edit(task).await?,
+        Command::Rename { name, ref tasks } => modify_tasks(tasks, TaskOp::Rename(name)).await?,
+        Command::Priority {
+            priority,
+            ref tasks,
+        } => modify_tasks(tasks, TaskOp::Priority(priority)).await?,
+        Command::Due { due, ref tasks } => modify_tasks(tasks, TaskOp::Due(due)).await?,
+        Command::Move { list, ref tasks } => modify_tasks(tasks, TaskOp::Move(list)).await?,
+        Command::Note {
+            text,
+            title,
+            ref tasks,
+        } => modify_tasks(tasks, TaskOp::Note { title, text }).await?,
+        Command::Url { url, ref tasks } => modify_tasks(tasks, TaskOp::Url(url)).await?,
+        Command::Untag { tag, ref tasks } => modify_tasks(tasks, TaskOp::Untag(tag)).await?,
         Command::AddTask {
             ref name,
             ref external_id,
-        } => add_task(&opt, name, external_id.as_deref()).await?,
+            local,
+            preview,
+        } => {
+            if local || preview {
+                add_task_local(name, preview).await?
+            } else {
+                add_task(&opt, name, external_id.as_deref()).await?
+            }
+        }
         Command::AuthApp { key, secret, perm } => auth_app(key, secret, perm).await?,
         Command::Methods => get_methods(&opt).await?,
         #[cfg(feature = "cache")]
         Command::Sync => run_sync(&opt).await?,
+        #[cfg(feature = "cache")]
+        Command::Undo { count } => history::undo(count).await?,
+        #[cfg(feature = "cache")]
+        Command::History { limit } => history::history(limit).await?,
         #[cfg(feature = "tui")]
         Command::Tui => tui::tui().await?,
         Command::Logout => logout().await?,
+        Command::SecretStore { ref cmd } => secrets::secret_store(cmd.as_ref())?,
     })
 }
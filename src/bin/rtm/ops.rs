@@ -0,0 +1,300 @@
+//! Commands which change existing tasks, such as `rtm complete`.
+
+use std::process::ExitCode;
+
+use anyhow::bail;
+use rememberthemilk::bulk::{Throttle, RTM_REQUEST_INTERVAL};
+#[cfg(feature = "cache")]
+use rememberthemilk::cache::handle::TaskHandle;
+use rememberthemilk::filter::expand_saved;
+use rememberthemilk::{sort::TaskEntry, Perms, RTMTimeline, RTMTransaction, TaskSeries, API};
+
+use crate::{get_rtm_api, get_settings, history::ChangeLog};
+
+/// Which tasks a command should apply to.
+#[derive(clap::Args, Debug)]
+pub struct TaskSelection {
+    #[cfg(feature = "cache")]
+    /// The tasks to change, by the handles shown by `rtm tasks`.
+    handles: Vec<TaskHandle>,
+
+    #[clap(long = "id")]
+    /// Select the task with this id (may be repeated).
+    ids: Vec<String>,
+
+    #[clap(long)]
+    /// Select tasks with the given external id.
+    extid: Option<String>,
+
+    #[clap(long)]
+    /// Select tasks matching a filter in RTM format.  With --id, only the
+    /// matching tasks with those ids are selected.
+    filter: Option<String>,
+
+    #[clap(long)]
+    /// Show which tasks would be changed without changing them.
+    pub dry_run: bool,
+}
+
+/// A change to make to each selected task.
+#[derive(Debug)]
+pub enum TaskOp {
+    Complete,
+    Uncomplete,
+    Delete,
+    Postpone,
+    Rename(String),
+    Priority(String),
+    Due(String),
+    Move(String),
+    Note { title: String, text: String },
+    Url(String),
+    Untag(String),
+}
+
+impl TaskOp {
+    fn describe(&self) -> String {
+        match self {
+            TaskOp::Complete => "Complete".into(),
+            TaskOp::Uncomplete => "Uncomplete".into(),
+            TaskOp::Delete => "Delete".into(),
+            TaskOp::Postpone => "Postpone".into(),
+            TaskOp::Rename(name) => format!("Rename to {name:?}"),
+            TaskOp::Priority(priority) => format!("Set priority {priority}"),
+            TaskOp::Due(due) if due.is_empty() => "Clear due date".into(),
+            TaskOp::Due(due) => format!("Set due {due:?}"),
+            TaskOp::Move(list) => format!("Move to {list}"),
+            TaskOp::Note { title, .. } => format!("Add note {title:?}"),
+            TaskOp::Url(url) if url.is_empty() => "Clear URL".into(),
+            TaskOp::Url(url) => format!("Set URL {url}"),
+            TaskOp::Untag(tag) => format!("Remove tag {tag}"),
+        }
+    }
+
+    async fn apply(
+        &self,
+        api: &API,
+        timeline: &RTMTimeline,
+        entry: &TaskEntry<'_>,
+        to_list_id: Option<&str>,
+    ) -> Result<Option<RTMTransaction>, anyhow::Error> {
+        let (list, series, task) = (entry.list_id, &entry.series.id, &entry.task.id);
+        Ok(match self {
+            TaskOp::Complete => api.mark_complete_id(timeline, list, series, task).await?,
+            TaskOp::Uncomplete => api.mark_incomplete_id(timeline, list, series, task).await?,
+            TaskOp::Delete => api.delete_task_id(timeline, list, series, task).await?,
+            TaskOp::Postpone => api.postpone_id(timeline, list, series, task).await?,
+            TaskOp::Rename(name) => api.set_name_id(timeline, list, series, task, name).await?,
+            TaskOp::Priority(priority) => {
+                api.set_priority_id(timeline, list, series, task, priority)
+                    .await?
+            }
+            TaskOp::Due(due) => {
+                api.parse_due_date_id(timeline, list, series, task, due)
+                    .await?
+            }
+            TaskOp::Move(_) => {
+                let to_list_id = to_list_id.unwrap();
+                if to_list_id == list {
+                    return Ok(None);
+                }
+                api.move_to_id(timeline, list, to_list_id, series, task)
+                    .await?
+            }
+            TaskOp::Note { title, text } => {
+                api.add_note_id(timeline, list, series, task, title, text)
+                    .await?
+            }
+            TaskOp::Url(url) => api.set_url_id(timeline, list, series, task, url).await?,
+            TaskOp::Untag(tag) => {
+                api.remove_tags_id(timeline, list, series, task, &[tag])
+                    .await?
+            }
+        })
+    }
+}
+
+/// Parse a priority given on the command line into RTM's form.
+pub fn parse_priority(s: &str) -> Result<String, &'static str> {
+    match s.to_lowercase().as_str() {
+        "1" | "2" | "3" => Ok(s.into()),
+        "0" | "n" | "none" => Ok("N".into()),
+        _ => Err("Priority must be 1, 2, 3 or none"),
+    }
+}
+
+/// Look up tasks by handle, as (list id, task series) pairs where each
+/// task series contains just the selected task.  The cache is synced
+/// first, as handles may have been shown for online results, and tasks
+/// may have moved since.
+#[cfg(feature = "cache")]
+async fn tasks_by_handle(
+    api: &API,
+    handles: &[TaskHandle],
+) -> Result<Vec<(String, TaskSeries)>, anyhow::Error> {
+    if handles.is_empty() {
+        return Ok(Vec::new());
+    }
+    let cache = crate::get_rtm_cache(api.clone()).await?;
+    for &handle in handles {
+        if cache.handle_task_ids(handle).await?.is_none() {
+            bail!("No task with handle {handle}.");
+        }
+    }
+    cache.sync().await?;
+    let mut result = Vec::new();
+    for &handle in handles {
+        match cache.get_task_by_handle(handle).await? {
+            Some(task) => result.push(task),
+            None => bail!("Task {handle} has been deleted."),
+        }
+    }
+    Ok(result)
+}
+
+/// Find the selected tasks, as (list id, task series) pairs where each task
+/// series contains just the selected task.
+pub(crate) async fn select_tasks(
+    api: &API,
+    selection: &TaskSelection,
+) -> Result<Vec<(String, TaskSeries)>, anyhow::Error> {
+    let TaskSelection {
+        ids, extid, filter, ..
+    } = selection;
+    #[cfg(feature = "cache")]
+    let by_handle = tasks_by_handle(api, &selection.handles).await?;
+    #[cfg(not(feature = "cache"))]
+    let by_handle: Vec<(String, TaskSeries)> = Vec::new();
+    if by_handle.is_empty() && ids.is_empty() && extid.is_none() && filter.is_none() {
+        bail!("Select tasks by handle, or with --id, --extid or --filter.");
+    }
+    if !by_handle.is_empty() && ids.is_empty() && extid.is_none() && filter.is_none() {
+        return Ok(by_handle);
+    }
+
+    let settings = get_settings()?;
+    let mut filters = Vec::new();
+    if let Some(filter) = filter {
+        filters.push(expand_saved(filter, &settings.saved)?);
+    }
+    if let Some(extid) = extid {
+        filters.push(api.get_filter_extid(extid));
+    }
+    let filter = match &filters[..] {
+        [f1, f2] => format!("({f1}) AND ({f2})"),
+        [f] => f.clone(),
+        _ => String::new(),
+    };
+    let all_tasks = api.get_tasks_filtered(&filter).await?;
+    let mut entries = all_tasks.entries();
+    // Handles and ids select tasks, which must also match any filter.
+    let mut wanted: Vec<(&str, &str)> = ids.iter().map(|id| ("", id.as_str())).collect();
+    wanted.extend(
+        by_handle
+            .iter()
+            .map(|(_, ts)| (ts.id.as_str(), ts.task[0].id.as_str())),
+    );
+    if !wanted.is_empty() {
+        let is_wanted = |entry: &TaskEntry<'_>, (series_id, task_id): (&str, &str)| {
+            entry.task.id == task_id && (series_id.is_empty() || entry.series.id == series_id)
+        };
+        entries.retain(|entry| wanted.iter().any(|&w| is_wanted(entry, w)));
+        for id in ids {
+            if !entries.iter().any(|entry| entry.task.id == *id) {
+                bail!("No matching task with id {id}.");
+            }
+        }
+    }
+    Ok(entries
+        .into_iter()
+        .map(|entry| {
+            let mut series = entry.series.clone();
+            series.task = vec![entry.task.clone()];
+            (entry.list_id.to_string(), series)
+        })
+        .collect())
+}
+
+/// Apply `op` to each selected task, printing the transaction ids.
+pub async fn modify_tasks(
+    selection: &TaskSelection,
+    op: TaskOp,
+) -> Result<ExitCode, anyhow::Error> {
+    let dry_run = selection.dry_run;
+    let api = get_rtm_api(if dry_run { Perms::Read } else { Perms::Write }).await?;
+    let selected = select_tasks(&api, selection).await?;
+    let entries: Vec<TaskEntry<'_>> = selected
+        .iter()
+        .map(|(list_id, series)| TaskEntry {
+            list_id,
+            series,
+            task: &series.task[0],
+        })
+        .collect();
+    if entries.is_empty() {
+        eprintln!("No tasks selected.");
+        return Ok(ExitCode::from(1));
+    }
+
+    let to_list_id = match &op {
+        TaskOp::Move(name) => {
+            let lists = api.get_lists().await?;
+            match lists.into_iter().find(|l| l.name == *name) {
+                Some(list) if list.smart => bail!("Can't move tasks to smart list {name:?}."),
+                Some(list) => Some(list.id),
+                None => bail!("No list called {name:?}."),
+            }
+        }
+        _ => None,
+    };
+
+    let description = op.describe();
+    if dry_run {
+        for entry in &entries {
+            println!("{description} (dry run): {}", entry.series.name);
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    let log = ChangeLog::open(&api).await?;
+    let timeline = log.timeline().await?;
+    let mut throttle = Throttle::new(RTM_REQUEST_INTERVAL);
+    for entry in &entries {
+        throttle.wait().await;
+        let transaction = op
+            .apply(&api, &timeline, entry, to_list_id.as_deref())
+            .await?;
+        log.record(
+            &timeline,
+            transaction.as_ref(),
+            &format!("{description}: {}", entry.series.name),
+        )
+        .await?;
+        match transaction {
+            Some(RTMTransaction { id, undoable: true }) => {
+                println!("{description}: {} (transaction {id})", entry.series.name)
+            }
+            Some(RTMTransaction {
+                id,
+                undoable: false,
+            }) => println!(
+                "{description}: {} (transaction {id}, not undoable)",
+                entry.series.name
+            ),
+            None => println!("{description}: {}", entry.series.name),
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_priority;
+
+    #[test]
+    fn test_parse_priority() {
+        assert_eq!(parse_priority("2").unwrap(), "2");
+        assert_eq!(parse_priority("none").unwrap(), "N");
+        assert_eq!(parse_priority("N").unwrap(), "N");
+        assert!(parse_priority("4").is_err());
+    }
+}
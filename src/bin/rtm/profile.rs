@@ -0,0 +1,287 @@
+//! Named profiles, each with its own authentication, settings and cache,
+//! and the `rtm profiles` command.
+//!
+//! The default profile uses the top level configuration and cache
+//! directories, as before profiles existed.  Other profiles live in a
+//! `profiles/NAME` directory under each.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::OnceLock;
+
+use anyhow::bail;
+use rememberthemilk::secret::{restrict_permissions, SecretStoreConfig};
+use rememberthemilk::RTMConfig;
+
+use crate::{RTM_APP_NAME, RTM_AUTH_ID, RTM_SETTINGS};
+
+/// The name `--profile` accepts for the default profile.
+pub const DEFAULT_PROFILE: &str = "default";
+
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+
+#[derive(clap::Subcommand, Debug)]
+pub enum ProfilesCommand {
+    /// Show the profiles (the default)
+    List,
+    /// Add a profile
+    Add {
+        name: String,
+
+        #[clap(long, requires = "secret")]
+        /// The API key, if not the same as the default profile's.
+        key: Option<String>,
+
+        #[clap(long, requires = "key")]
+        /// The API secret, if not the same as the default profile's.
+        secret: Option<String>,
+    },
+    /// Remove a profile, with its settings and cache
+    Remove { name: String },
+}
+
+fn check_name(name: &str) -> Result<(), anyhow::Error> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        bail!("Invalid profile name {name:?}: use letters, digits, '-' and '_'.");
+    }
+    Ok(())
+}
+
+/// Choose the profile for this run.  Must be called before any
+/// configuration is loaded.
+pub fn select(name: Option<&str>) -> Result<(), anyhow::Error> {
+    let profile = match name {
+        None | Some(DEFAULT_PROFILE) => None,
+        Some(name) => {
+            check_name(name)?;
+            if !profile_dir(&base_config_dir()?, name).is_dir() {
+                bail!("No profile called {name:?}.  Use `rtm profiles add {name}` to add it.");
+            }
+            Some(name.to_string())
+        }
+    };
+    if PROFILE.set(profile).is_err() {
+        bail!("Profile already selected");
+    }
+    Ok(())
+}
+
+/// The selected profile's name, or `None` for the default profile.
+pub fn current() -> Option<&'static str> {
+    PROFILE.get().and_then(Option::as_deref)
+}
+
+fn profile_dir(base: &Path, name: &str) -> PathBuf {
+    base.join("profiles").join(name)
+}
+
+/// The top level configuration directory.
+fn base_config_dir() -> Result<PathBuf, anyhow::Error> {
+    let path = confy::get_configuration_file_path(RTM_APP_NAME, RTM_SETTINGS)?;
+    Ok(path.parent().map(Path::to_path_buf).unwrap_or_default())
+}
+
+/// The directory containing the selected profile's configuration.
+pub fn config_dir() -> Result<PathBuf, anyhow::Error> {
+    let base = base_config_dir()?;
+    Ok(match current() {
+        Some(name) => profile_dir(&base, name),
+        None => base,
+    })
+}
+
+/// The directory for the selected profile's cache.
+#[cfg(feature = "cache")]
+pub fn cache_dir() -> PathBuf {
+    let base = base_cache_dir();
+    match current() {
+        Some(name) => profile_dir(&base, name),
+        None => base,
+    }
+}
+
+#[cfg(feature = "cache")]
+fn base_cache_dir() -> PathBuf {
+    use etcetera::{AppStrategy, AppStrategyArgs};
+
+    let strategy = etcetera::choose_app_strategy(AppStrategyArgs {
+        top_level_domain: "org".into(),
+        author: "Chris Emerson".into(),
+        app_name: "rtm".into(),
+    })
+    .unwrap();
+    strategy.cache_dir()
+}
+
+fn config_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.toml"))
+}
+
+/// Load one of the selected profile's configuration files, as
+/// [confy::load].  The file is made private if it isn't, as it may hold
+/// secrets.
+pub fn load<T>(name: &str) -> Result<T, anyhow::Error>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + Default,
+{
+    let path = config_path(&config_dir()?, name);
+    let config = confy::load_path(&path)?;
+    restrict_permissions(&path)?;
+    Ok(config)
+}
+
+/// Save one of the selected profile's configuration files, as
+/// [confy::store], readable only by the user.
+pub fn store<T: serde::Serialize>(name: &str, config: T) -> Result<(), anyhow::Error> {
+    store_path(&config_path(&config_dir()?, name), config)
+}
+
+fn store_path<T: serde::Serialize>(path: &Path, config: T) -> Result<(), anyhow::Error> {
+    confy::store_path(path, config)?;
+    restrict_permissions(path)
+}
+
+/// The names of the profiles other than the default, sorted.
+fn profile_names() -> Result<Vec<String>, anyhow::Error> {
+    let dir = base_config_dir()?.join("profiles");
+    let mut names = vec![];
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            names.extend(entry.file_name().to_str().map(String::from));
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub async fn profiles(cmd: Option<&ProfilesCommand>) -> Result<ExitCode, anyhow::Error> {
+    let base = base_config_dir()?;
+    match cmd {
+        None | Some(ProfilesCommand::List) => {
+            let names = std::iter::once(None).chain(profile_names()?.into_iter().map(Some));
+            for name in names {
+                let dir = match &name {
+                    Some(name) => profile_dir(&base, name),
+                    None => base.clone(),
+                };
+                let config: RTMConfig = confy::load_path(config_path(&dir, RTM_AUTH_ID))?;
+                let marker = if name.as_deref() == current() {
+                    "*"
+                } else {
+                    " "
+                };
+                let user = match &config.user {
+                    Some(user) => user.username(),
+                    None if config.api_key.is_some() => "(not logged in)",
+                    None => "(no API key)",
+                };
+                println!(
+                    "{marker} {:<16} {user}",
+                    name.as_deref().unwrap_or(DEFAULT_PROFILE)
+                );
+            }
+        }
+        Some(ProfilesCommand::Add { name, key, secret }) => {
+            check_name(name)?;
+            if name == DEFAULT_PROFILE {
+                bail!("The default profile always exists.");
+            }
+            let dir = profile_dir(&base, name);
+            if dir.exists() {
+                bail!("Profile {name:?} already exists.");
+            }
+            let default: RTMConfig = confy::load_path(config_path(&base, RTM_AUTH_ID))?;
+            let (api_key, api_secret) = match (key, secret) {
+                (Some(key), Some(secret)) => (Some(key.clone()), Some(secret.clone())),
+                _ => {
+                    // The default profile's external commands can't be
+                    // reused, so then the secret must be given again.
+                    let secret = match &default.secret_store {
+                        None => default.api_secret.clone(),
+                        Some(SecretStoreConfig::Command { .. }) => None,
+                        Some(store) => store.open(None)?.load()?.and_then(|s| s.api_secret),
+                    };
+                    (default.api_key.clone(), secret)
+                }
+            };
+            // The new profile can't share the default profile's store, as
+            // that also holds its token, so it gets its own of the same
+            // kind.
+            let secret_store = match &default.secret_store {
+                Some(SecretStoreConfig::File { .. }) => Some(SecretStoreConfig::File {
+                    path: dir.join("secrets.json"),
+                }),
+                Some(SecretStoreConfig::Encrypted { .. }) => Some(SecretStoreConfig::Encrypted {
+                    path: dir.join("secrets.enc"),
+                }),
+                _ => None,
+            };
+            let mut config = RTMConfig {
+                api_key,
+                api_secret,
+                secret_store,
+                ..Default::default()
+            };
+            config.store_secrets(None)?;
+            std::fs::create_dir_all(&dir)?;
+            store_path(&config_path(&dir, RTM_AUTH_ID), &config)?;
+            if config.api_key.is_none() {
+                println!(
+                    "Added profile {name}.  Use `rtm --profile {name} auth-app` to supply an API key."
+                );
+            } else if config.api_secret.is_none() && config.secret_store.is_none() {
+                println!(
+                    "Added profile {name}.  Use `rtm --profile {name} secret-store command` to \
+                     choose where to keep its secrets, then `rtm --profile {name} auth-app` to \
+                     supply the API secret."
+                );
+            } else {
+                println!("Added profile {name}.  Run `rtm --profile {name} lists` to log in.");
+            }
+        }
+        Some(ProfilesCommand::Remove { name }) => {
+            check_name(name)?;
+            if name == DEFAULT_PROFILE {
+                bail!("The default profile can't be removed.");
+            }
+            let dir = profile_dir(&base, name);
+            if !dir.is_dir() {
+                bail!("No profile called {name:?}.");
+            }
+            std::fs::remove_dir_all(&dir)?;
+            #[cfg(feature = "cache")]
+            {
+                let cache = profile_dir(&base_cache_dir(), name);
+                if cache.is_dir() {
+                    std::fs::remove_dir_all(&cache)?;
+                }
+            }
+            println!("Removed profile {name}.");
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_name;
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("work").is_ok());
+        assert!(check_name("home_2-b").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("../work").is_err());
+        assert!(check_name("my work").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! The `rtm report` command.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::Utc;
+use rememberthemilk::filter::expand_saved;
+use rememberthemilk::report::{render_report, ReportFormat, ReportTemplates};
+
+use crate::export::replace_file;
+use crate::get_settings;
+use crate::source::{Freshness, FreshnessArgs, TaskSource};
+
+/// Options for `rtm report`.
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    #[clap(long, default_value = "md")]
+    /// The report format, md or html.
+    format: ReportFormat,
+
+    #[clap(long, default_value = "status:incomplete")]
+    /// Only report the tasks matching this filter.
+    filter: String,
+
+    #[clap(long)]
+    /// The report title (default the filter).
+    title: Option<String>,
+
+    #[clap(long)]
+    /// A directory of templates to use instead of the built-in ones, named
+    /// page.md, list.md and task.md (or .html).  Missing ones use the
+    /// defaults.
+    templates: Option<PathBuf>,
+
+    #[clap(short, long)]
+    /// Write to this file instead of stdout.
+    output: Option<PathBuf>,
+
+    #[clap(flatten)]
+    freshness: FreshnessArgs,
+}
+
+/// Load the templates for `format`, replacing the defaults with any found
+/// in `dir`.
+fn load_templates(
+    format: ReportFormat,
+    dir: Option<&PathBuf>,
+) -> Result<ReportTemplates, anyhow::Error> {
+    let mut templates = ReportTemplates::new(format);
+    if let Some(dir) = dir {
+        for (name, template) in [
+            ("page", &mut templates.page),
+            ("list", &mut templates.list),
+            ("task", &mut templates.task),
+        ] {
+            let path = dir.join(format!("{name}.{}", format.extension()));
+            match std::fs::read_to_string(&path) {
+                Ok(text) => *template = text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(anyhow::anyhow!("{}: {e}", path.display())),
+            }
+        }
+    }
+    Ok(templates)
+}
+
+/// Write a report of the tasks matching a filter.
+pub async fn report(args: &ReportArgs) -> Result<ExitCode, anyhow::Error> {
+    let templates = load_templates(args.format, args.templates.as_ref())?;
+    let source = TaskSource::open(args.freshness.or(Freshness::Online)).await?;
+    let settings = get_settings()?;
+    let filter = expand_saved(&args.filter, &settings.saved)?;
+    let tasks = source.get_tasks_filtered(&filter).await?;
+    let list_names: HashMap<String, String> = source
+        .get_lists()
+        .await?
+        .into_iter()
+        .map(|list| (list.id, list.name))
+        .collect();
+    let title = args.title.as_deref().unwrap_or(&args.filter);
+    let now = Utc::now().with_timezone(&source.timezone().await?);
+    let output = render_report(&tasks.entries(), &list_names, &templates, title, &now);
+    match &args.output {
+        Some(path) => replace_file(path, output.as_bytes())?,
+        None => std::io::stdout().write_all(output.as_bytes())?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,128 @@
+//! The `rtm secret-store` command.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rememberthemilk::secret::{SecretStoreConfig, PASSPHRASE_VAR};
+use rememberthemilk::RTMConfig;
+
+use crate::{profile, RTM_AUTH_ID};
+
+#[derive(clap::Subcommand, Debug)]
+pub enum SecretStoreCommand {
+    /// Show where the secrets are kept (the default)
+    Show,
+    /// Keep the secrets in the main configuration file
+    Config,
+    /// Keep the secrets in a separate file, readable only by you
+    File {
+        /// The file (default secrets.json in the configuration directory).
+        path: Option<PathBuf>,
+    },
+    /// Keep the secrets in a file encrypted with the passphrase in
+    /// RTM_PASSPHRASE
+    Encrypted {
+        /// The file (default secrets.enc in the configuration directory).
+        path: Option<PathBuf>,
+    },
+    /// Keep the secrets using external commands, which print or read them
+    /// as JSON
+    Command {
+        /// The command printing the secrets, such as "pass show rtm".  It
+        /// should print nothing if there are none; failing is an error.
+        #[clap(long)]
+        get: String,
+        /// The command saving the secrets from its standard input, such as
+        /// "pass insert -mf rtm".
+        #[clap(long)]
+        set: String,
+    },
+}
+
+fn describe(store: Option<&SecretStoreConfig>) -> String {
+    match store {
+        None => "the configuration file".into(),
+        Some(SecretStoreConfig::File { path }) => format!("file {}", path.display()),
+        Some(SecretStoreConfig::Encrypted { path }) => {
+            format!(
+                "encrypted file {} (passphrase from {PASSPHRASE_VAR})",
+                path.display()
+            )
+        }
+        Some(SecretStoreConfig::Command { get, set }) => {
+            format!("commands {get:?} and {set:?}")
+        }
+    }
+}
+
+pub fn secret_store(cmd: Option<&SecretStoreCommand>) -> Result<ExitCode, anyhow::Error> {
+    let mut config: RTMConfig = profile::load(RTM_AUTH_ID)?;
+    let dir = profile::config_dir()?;
+    let store = match cmd {
+        None | Some(SecretStoreCommand::Show) => {
+            println!(
+                "Secrets are kept in {}.",
+                describe(config.secret_store.as_ref())
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(SecretStoreCommand::Config) => None,
+        Some(SecretStoreCommand::File { path }) => Some(SecretStoreConfig::File {
+            path: path.clone().unwrap_or_else(|| dir.join("secrets.json")),
+        }),
+        Some(SecretStoreCommand::Encrypted { path }) => Some(SecretStoreConfig::Encrypted {
+            path: path.clone().unwrap_or_else(|| dir.join("secrets.enc")),
+        }),
+        Some(SecretStoreCommand::Command { get, set }) => Some(SecretStoreConfig::Command {
+            get: get.clone(),
+            set: set.clone(),
+        }),
+    };
+    // Move the secrets from the old store to the new one.
+    config.load_secrets(None)?;
+    let old = std::mem::replace(&mut config.secret_store, store);
+    config.store_secrets(None)?;
+    profile::store(RTM_AUTH_ID, &config)?;
+    println!(
+        "Secrets are now kept in {}.",
+        describe(config.secret_store.as_ref())
+    );
+    if old != config.secret_store {
+        remove_old_copy(old.as_ref(), config.secret_store.as_ref())?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Remove the secrets from the store they were moved out of, once they
+/// have been saved in the new one.
+fn remove_old_copy(
+    old: Option<&SecretStoreConfig>,
+    new: Option<&SecretStoreConfig>,
+) -> Result<(), anyhow::Error> {
+    let new_path = match new {
+        Some(SecretStoreConfig::File { path } | SecretStoreConfig::Encrypted { path }) => {
+            Some(path)
+        }
+        _ => None,
+    };
+    match old {
+        // The configuration file has just been saved without them.
+        None => println!("They have been removed from the configuration file."),
+        Some(SecretStoreConfig::File { path } | SecretStoreConfig::Encrypted { path }) => {
+            if new_path != Some(path) {
+                match std::fs::remove_file(path) {
+                    Ok(()) => println!("Removed {}.", path.display()),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                    Err(e) => {
+                        return Err(anyhow::Error::new(e)
+                            .context(format!("Removing the old copy {}", path.display())))
+                    }
+                }
+            }
+        }
+        Some(SecretStoreConfig::Command { get, .. }) => println!(
+            "The old copy, printed by {get:?}, still exists; remove it if it's no longer needed."
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,209 @@
+//! Where `rtm tasks` gets tasks from: RTM itself or the local cache.
+
+#[cfg(feature = "cache")]
+use anyhow::bail;
+#[cfg(feature = "cache")]
+use chrono::{TimeDelta, Utc};
+#[cfg(feature = "cache")]
+use rememberthemilk::cache::TaskCache;
+use rememberthemilk::{system_timezone, Perms, RTMList, RTMTasks, API};
+
+use crate::get_rtm_api;
+
+/// How up to date the tasks need to be.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Freshness {
+    /// Always ask RTM.
+    Online,
+    /// Only use the cache, however old.
+    #[cfg(feature = "cache")]
+    Offline,
+    /// Use the cache if it was synced recently enough, otherwise sync it
+    /// first.
+    #[cfg(feature = "cache")]
+    MaxAge(TimeDelta),
+    /// Sync the cache and then use it.
+    #[cfg(feature = "cache")]
+    Sync,
+}
+
+/// Options to choose how fresh tasks need to be.
+#[derive(clap::Args, Debug)]
+pub struct FreshnessArgs {
+    #[cfg(feature = "cache")]
+    #[clap(long, conflicts_with_all = ["max_age", "sync"])]
+    /// Use only the local cache, however old.
+    offline: bool,
+
+    #[cfg(feature = "cache")]
+    #[clap(long, value_parser = parse_age, conflicts_with = "sync")]
+    /// Use the local cache if it was synced within this time (e.g. 30s,
+    /// 5m, 2h or 1d), otherwise sync it first.
+    max_age: Option<TimeDelta>,
+
+    #[cfg(feature = "cache")]
+    #[clap(long)]
+    /// Sync the local cache and then use it.
+    sync: bool,
+}
+
+impl FreshnessArgs {
+    /// The freshness chosen, or `default` if none was.
+    pub fn or(&self, default: Freshness) -> Freshness {
+        #[cfg(feature = "cache")]
+        match (self.offline, self.max_age, self.sync) {
+            (true, _, _) => Freshness::Offline,
+            (_, Some(age), _) => Freshness::MaxAge(age),
+            (_, _, true) => Freshness::Sync,
+            _ => default,
+        }
+        #[cfg(not(feature = "cache"))]
+        default
+    }
+}
+
+/// Parse an age such as `30s`, `5m`, `2h` or `1d`.
+#[cfg(feature = "cache")]
+pub fn parse_age(s: &str) -> Result<TimeDelta, String> {
+    let err = || format!("Invalid age {s:?}; expected e.g. 30s, 5m, 2h or 1d");
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(err)?;
+    let (num, unit) = s.split_at(split);
+    let num: i64 = num.parse().map_err(|_| err())?;
+    let age = match unit {
+        "s" => TimeDelta::try_seconds(num),
+        "m" => TimeDelta::try_minutes(num),
+        "h" => TimeDelta::try_hours(num),
+        "d" => TimeDelta::try_days(num),
+        _ => None,
+    };
+    age.ok_or_else(err)
+}
+
+/// Somewhere to fetch tasks and lists from.
+pub struct TaskSource {
+    api: API,
+    #[cfg(feature = "cache")]
+    cache: Option<TaskCache>,
+    #[cfg(feature = "cache")]
+    offline: bool,
+}
+
+impl TaskSource {
+    /// Open a source of tasks at least as fresh as `freshness` requires,
+    /// syncing the cache if needed, and report which is used on stderr.
+    pub async fn open(freshness: Freshness) -> Result<TaskSource, anyhow::Error> {
+        #[cfg(feature = "cache")]
+        {
+            use crate::{format_human_time, get_rtm_cache, load_rtm_api};
+
+            let sync = |api: API| async move {
+                let cache = get_rtm_cache(api).await?;
+                cache.sync().await?;
+                eprintln!("Using the cache, synced just now.");
+                Ok::<_, anyhow::Error>(cache)
+            };
+            let cache = match freshness {
+                Freshness::Online => None,
+                Freshness::Sync => Some(sync(get_rtm_api(Perms::Read).await?).await?),
+                Freshness::Offline | Freshness::MaxAge(_) => {
+                    let cache = get_rtm_cache(load_rtm_api()?).await?;
+                    let age = cache.last_sync().await?.map(|t| Utc::now() - t);
+                    let stale = match freshness {
+                        Freshness::MaxAge(max_age) => age.is_none_or(|age| age > max_age),
+                        _ => false,
+                    };
+                    match age {
+                        _ if stale => Some(sync(get_rtm_api(Perms::Read).await?).await?),
+                        None => bail!("The cache has never been synced; use `rtm sync`."),
+                        Some(age) => {
+                            let secs = age.num_seconds().max(0) as u64;
+                            eprintln!("Using the cache, synced {} ago.", format_human_time(secs));
+                            Some(cache)
+                        }
+                    }
+                }
+            };
+            let api = match &cache {
+                Some(_) => load_rtm_api()?,
+                None => get_rtm_api(Perms::Read).await?,
+            };
+            Ok(TaskSource {
+                api,
+                cache,
+                offline: freshness == Freshness::Offline,
+            })
+        }
+        #[cfg(not(feature = "cache"))]
+        {
+            let Freshness::Online = freshness;
+            Ok(TaskSource {
+                api: get_rtm_api(Perms::Read).await?,
+            })
+        }
+    }
+
+    /// The API, for anything the source doesn't provide.
+    pub fn api(&self) -> &API {
+        &self.api
+    }
+
+    /// The cache, if the tasks come from it.
+    #[cfg(feature = "cache")]
+    pub fn cache(&self) -> Option<&TaskCache> {
+        self.cache.as_ref()
+    }
+
+    /// The account's time zone, or the system's if that isn't known.  From
+    /// the cache when it is being used, so that this works offline.
+    pub async fn timezone(&self) -> Result<chrono_tz::Tz, anyhow::Error> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            return Ok(cache.timezone().await?);
+        }
+        Ok(self
+            .api
+            .get_timezone()
+            .await?
+            .unwrap_or_else(system_timezone))
+    }
+
+    /// Return all the lists.
+    pub async fn get_lists(&self) -> Result<Vec<RTMList>, anyhow::Error> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            return cache.get_lists().await;
+        }
+        self.api.get_lists().await
+    }
+
+    /// Return the tasks matching a filter.  If the cache can't handle the
+    /// filter, RTM is asked instead unless running offline.
+    pub async fn get_tasks_filtered(&self, filter: &str) -> Result<RTMTasks, anyhow::Error> {
+        #[cfg(feature = "cache")]
+        if let Some(cache) = &self.cache {
+            match cache.get_tasks_filtered(filter).await {
+                Ok(tasks) => return Ok(tasks),
+                Err(e) if self.offline => return Err(e),
+                Err(e) => eprintln!("Asking RTM, as the cache can't be used: {e}"),
+            }
+        }
+        self.api.get_tasks_filtered(filter).await
+    }
+}
+
+#[cfg(all(test, feature = "cache"))]
+mod tests {
+    use super::parse_age;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30s"), Ok(TimeDelta::seconds(30)));
+        assert_eq!(parse_age("5m"), Ok(TimeDelta::minutes(5)));
+        assert_eq!(parse_age("2h"), Ok(TimeDelta::hours(2)));
+        assert_eq!(parse_age("1d"), Ok(TimeDelta::days(1)));
+        for bad in ["", "5", "m", "5x", "-5m", "5 m", "99999999999999999d"] {
+            assert!(parse_age(bad).is_err(), "{bad:?}");
+        }
+    }
+}
@@ -0,0 +1,157 @@
+//! The `rtm stats` command.
+
+use std::io::Write;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail};
+use chrono::{Days, TimeDelta, Utc};
+use rememberthemilk::cache::stats::{CompletionRate, StatsOptions};
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::source::{Freshness, FreshnessArgs, TaskSource};
+use crate::Opt;
+
+/// Options for `rtm stats`.
+#[derive(clap::Args, Debug)]
+pub struct StatsArgs {
+    #[clap(long, default_value = "28")]
+    /// How many days to report on, ending today.
+    days: u32,
+
+    #[clap(long, default_value = "10")]
+    /// How many of the longest-outstanding tasks to show.
+    longest: usize,
+
+    #[clap(long)]
+    /// Output the statistics as JSON.
+    json: bool,
+
+    #[clap(flatten)]
+    freshness: FreshnessArgs,
+}
+
+/// Format a number of minutes as e.g. `3h20m`.
+fn format_minutes(minutes: i64) -> String {
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{m}m"),
+        (h, 0) => format!("{h}h"),
+        (h, m) => format!("{h}h{m}m"),
+    }
+}
+
+fn write_heading(stdout: &mut impl WriteColor, heading: &str) -> Result<(), anyhow::Error> {
+    stdout.set_color(ColorSpec::new().set_fg(Some(Color::Magenta)))?;
+    writeln!(stdout, "{heading}")?;
+    stdout.reset()?;
+    Ok(())
+}
+
+fn write_rates(
+    stdout: &mut impl WriteColor,
+    heading: &str,
+    rates: &[CompletionRate],
+) -> Result<(), anyhow::Error> {
+    if rates.is_empty() {
+        return Ok(());
+    }
+    write_heading(stdout, heading)?;
+    let width = rates.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    for rate in rates {
+        writeln!(
+            stdout,
+            "  {:<width$}  {:>4}/{:<4} {:>3.0}%",
+            rate.name,
+            rate.completed,
+            rate.total,
+            rate.rate * 100.0
+        )?;
+    }
+    Ok(())
+}
+
+/// Report statistics over the tasks in the cache.
+pub async fn stats(opts: &Opt, args: &StatsArgs) -> Result<ExitCode, anyhow::Error> {
+    if args.days == 0 {
+        bail!("--days must be at least 1.");
+    }
+    let freshness = args.freshness.or(Freshness::MaxAge(TimeDelta::MAX));
+    let source = TaskSource::open(freshness).await?;
+    let cache = source
+        .cache()
+        .ok_or_else(|| anyhow!("Statistics need the local cache."))?;
+    let to = Utc::now()
+        .with_timezone(&cache.timezone().await?)
+        .date_naive();
+    let options = StatsOptions {
+        from: to - Days::new(u64::from(args.days) - 1),
+        to,
+        longest: args.longest,
+    };
+    let stats = cache.stats(&options).await?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut stdout = opts.get_stdout();
+    write_heading(&mut stdout, "Day         Completed  Overdue")?;
+    for (done, overdue) in stats.completed_per_day.iter().zip(&stats.overdue_per_day) {
+        writeln!(
+            stdout,
+            "  {}  {:>9}  {:>7}",
+            done.date.format("%a %d %b"),
+            done.count,
+            overdue.count
+        )?;
+    }
+    write_heading(&mut stdout, "Week        Completed")?;
+    for week in &stats.completed_per_week {
+        writeln!(
+            stdout,
+            "  {}  {:>9}",
+            week.date.format("%a %d %b"),
+            week.count
+        )?;
+    }
+    write!(
+        stdout,
+        "Completed on time: {}, late: {}",
+        stats.on_time, stats.late
+    )?;
+    if let Some(hours) = stats.average_lateness_hours {
+        write!(stdout, " (on average {hours:.1} hours late)")?;
+    }
+    writeln!(stdout)?;
+    write_rates(&mut stdout, "Lists", &stats.lists)?;
+    write_rates(&mut stdout, "Tags", &stats.tags)?;
+    writeln!(
+        stdout,
+        "Estimates: {} completed, {} outstanding",
+        format_minutes(stats.estimate_completed_minutes),
+        format_minutes(stats.estimate_outstanding_minutes)
+    )?;
+    if !stats.longest_outstanding.is_empty() {
+        write_heading(&mut stdout, "Longest outstanding")?;
+        for task in &stats.longest_outstanding {
+            writeln!(
+                stdout,
+                "  {:>5}d  {} ({})",
+                task.age_days, task.name, task.list
+            )?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_minutes;
+
+    #[test]
+    fn test_format_minutes() {
+        assert_eq!(format_minutes(0), "0m");
+        assert_eq!(format_minutes(45), "45m");
+        assert_eq!(format_minutes(120), "2h");
+        assert_eq!(format_minutes(200), "3h20m");
+    }
+}
@@ -0,0 +1,283 @@
+//! The `rtm template` commands.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use rememberthemilk::bulk::RTM_REQUEST_INTERVAL;
+use rememberthemilk::template::{due_date, CreatedTask, TaskTemplate, Template, TemplateProgress};
+use rememberthemilk::{system_timezone, Perms, RTMLists};
+use serde::{Deserialize, Serialize};
+
+use crate::export::replace_file;
+use crate::get_rtm_api;
+use crate::history::ChangeLog;
+
+#[derive(clap::Subcommand, Debug)]
+pub enum TemplateCommand {
+    /// Show the available templates
+    List,
+    /// Create the tasks in a template
+    Apply {
+        /// The template name, from the templates directory, or a path to a
+        /// template file.
+        name: String,
+
+        #[clap(value_parser = parse_variable)]
+        /// Values for the template's variables, as NAME=VALUE.
+        variables: Vec<(String, String)>,
+
+        #[clap(long)]
+        /// The list to add the tasks to (default the Inbox).
+        list: Option<String>,
+
+        #[clap(long)]
+        /// The day relative due dates count from (default today).
+        start: Option<NaiveDate>,
+
+        #[clap(long)]
+        /// Show the tasks without creating them.
+        dry_run: bool,
+    },
+    /// Delete the tasks created by the last `rtm template apply`
+    Undo,
+}
+
+/// A record of the tasks created from a template, for `rtm template undo`.
+#[derive(Debug, Serialize, Deserialize)]
+struct AppliedTemplate {
+    template: String,
+    applied: DateTime<Utc>,
+    tasks: Vec<CreatedTask>,
+}
+
+fn parse_variable(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.into(), value.into())),
+        _ => Err(format!("Expected NAME=VALUE, not {s:?}")),
+    }
+}
+
+/// The directory templates are kept in, as NAME.json.
+fn templates_dir() -> Result<PathBuf, anyhow::Error> {
+    Ok(crate::profile::config_dir()?.join("templates"))
+}
+
+/// The file recording the tasks created from templates.
+fn applied_path() -> Result<PathBuf, anyhow::Error> {
+    Ok(crate::profile::config_dir()?.join("applied-templates.json"))
+}
+
+fn load_applied() -> Result<Vec<AppliedTemplate>, anyhow::Error> {
+    match std::fs::read(applied_path()?) {
+        Ok(data) => Ok(serde_json::from_slice(&data)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save_applied(applied: &[AppliedTemplate]) -> Result<(), anyhow::Error> {
+    let path = applied_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    replace_file(&path, &serde_json::to_vec_pretty(applied)?)
+}
+
+/// Load a template by name or path.
+fn load_template(name: &str) -> Result<Template, anyhow::Error> {
+    let path = if name.contains(std::path::MAIN_SEPARATOR) || name.ends_with(".json") {
+        PathBuf::from(name)
+    } else {
+        templates_dir()?.join(format!("{name}.json"))
+    };
+    let json = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow!("Unable to read {}: {e}", path.display()))?;
+    Template::parse(&json).map_err(|e| anyhow!("{}: {e}", path.display()))
+}
+
+fn list_templates() -> Result<ExitCode, anyhow::Error> {
+    let dir = templates_dir()?;
+    let mut names: Vec<String> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                Some(name.strip_suffix(".json")?.to_string())
+            })
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    if names.is_empty() {
+        eprintln!("No templates in {}.", dir.display());
+        return Ok(ExitCode::from(1));
+    }
+    names.sort();
+    for name in names {
+        match load_template(&name) {
+            Ok(template) => {
+                let variables: Vec<String> = template.variables_used().into_iter().collect();
+                println!("{name}: {}", template.description);
+                if !variables.is_empty() {
+                    println!("    variables: {}", variables.join(", "));
+                }
+            }
+            Err(e) => println!("{name}: {e:#}"),
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_tree(tasks: &[TaskTemplate], today: NaiveDate, depth: usize) {
+    for task in tasks {
+        let due = match (due_date(task, today), &task.due) {
+            (Some(date), _) => format!(" (due {date})"),
+            (None, Some(due)) => format!(" (due {due})"),
+            (None, None) => String::new(),
+        };
+        println!("{:indent$}{}{due}", "", task.name, indent = depth * 2);
+        print_tree(&task.subtasks, today, depth + 1);
+    }
+}
+
+async fn apply(
+    name: &str,
+    variables: &[(String, String)],
+    list: Option<&str>,
+    start: Option<NaiveDate>,
+    dry_run: bool,
+) -> Result<ExitCode, anyhow::Error> {
+    let template = load_template(name)?;
+    let values: HashMap<String, String> = variables.iter().cloned().collect();
+    let tasks = template.instantiate(&values)?;
+    let perms = if dry_run { Perms::Read } else { Perms::Write };
+    let api = get_rtm_api(perms).await?;
+    let tz = api.get_timezone().await?.unwrap_or_else(system_timezone);
+    let start = match start {
+        Some(day) => tz
+            .from_local_datetime(&day.and_time(chrono::NaiveTime::MIN))
+            .earliest()
+            .ok_or_else(|| anyhow!("Invalid start date {day}"))?,
+        None => Utc::now().with_timezone(&tz),
+    };
+    if dry_run {
+        print_tree(&tasks, start.date_naive(), 0);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let list = match list {
+        Some(list_name) => {
+            let lists = api.get_lists().await?;
+            match lists.into_iter().find(|l| l.name == list_name) {
+                Some(l) if l.smart => bail!("Can't add tasks to smart list {list_name:?}."),
+                Some(l) => Some(RTMLists {
+                    id: l.id,
+                    taskseries: None,
+                }),
+                None => bail!("No list called {list_name:?}."),
+            }
+        }
+        None => None,
+    };
+    let log = ChangeLog::open(&api).await?;
+    let timeline = log.timeline().await?;
+    let mut progress = TemplateProgress::default();
+    let result = api
+        .apply_template(
+            &timeline,
+            &tasks,
+            list.as_ref(),
+            &start,
+            RTM_REQUEST_INTERVAL,
+            &mut progress,
+        )
+        .await;
+    for task in &progress.created {
+        println!("Added: {}", task.name);
+    }
+    // Record what was created even if a later task failed.
+    log.record_batch(&timeline, &progress.transactions).await?;
+    if !progress.created.is_empty() {
+        let mut applied = load_applied()?;
+        applied.push(AppliedTemplate {
+            template: name.to_string(),
+            applied: Utc::now(),
+            tasks: progress.created,
+        });
+        save_applied(&applied)?;
+    }
+    result?;
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn undo() -> Result<ExitCode, anyhow::Error> {
+    let mut applied = load_applied()?;
+    let Some(mut last) = applied.pop() else {
+        eprintln!("No templates to undo.");
+        return Ok(ExitCode::from(1));
+    };
+    let api = get_rtm_api(Perms::Delete).await?;
+    let log = ChangeLog::open(&api).await?;
+    let timeline = log.timeline().await?;
+    let mut transactions = Vec::new();
+    let mut result = Ok(());
+    // Subtasks first, as they were created after their parents.  Each
+    // task is forgotten once deleted, so that trying again after a failure
+    // only deletes those left.
+    while let Some(task) = last.tasks.last() {
+        let deleted = api
+            .delete_task_id(&timeline, &task.list_id, &task.taskseries_id, &task.task_id)
+            .await;
+        match deleted {
+            Ok(transaction) => {
+                println!("Deleted: {}", task.name);
+                transactions.extend(
+                    transaction.map(|t| (format!("Delete {}: {}", last.template, task.name), t)),
+                );
+                last.tasks.pop();
+            }
+            Err(e) => {
+                result = Err(e.context(format!("Unable to delete {}", task.name)));
+                break;
+            }
+        }
+    }
+    log.record_batch(&timeline, &transactions).await?;
+    if !last.tasks.is_empty() {
+        applied.push(last);
+    }
+    save_applied(&applied)?;
+    result?;
+    Ok(ExitCode::SUCCESS)
+}
+
+pub async fn template(cmd: &TemplateCommand) -> Result<ExitCode, anyhow::Error> {
+    match cmd {
+        TemplateCommand::List => list_templates(),
+        TemplateCommand::Apply {
+            name,
+            variables,
+            list,
+            start,
+            dry_run,
+        } => apply(name, variables, list.as_deref(), *start, *dry_run).await,
+        TemplateCommand::Undo => undo().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_variable;
+
+    #[test]
+    fn test_parse_variable() {
+        assert_eq!(
+            parse_variable("version=1.4=rc"),
+            Ok(("version".into(), "1.4=rc".into()))
+        );
+        assert!(parse_variable("=1").is_err());
+        assert!(parse_variable("version").is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Local, Utc};
+use chrono::{DateTime, Utc};
 use crossterm::{
     event::{Event, KeyCode},
     terminal::{disable_raw_mode, enable_raw_mode},
@@ -13,22 +13,28 @@ use ratatui::{
     Terminal,
 };
 use rememberthemilk::{
-    cache::TaskCache, Perms, RTMList, RTMLists, RTMTasks, RTMTimeline, Task, TaskSeries,
+    cache::{tree::TaskNode, TaskCache},
+    filter::expand_saved,
+    smartadd::NewTask,
+    sort::Sort,
+    Perms, RTMList, RTMLists, RTMTasks, RTMTimeline, RTMTransaction, Task, TaskSeries,
 };
 use std::process::ExitCode;
 use std::{borrow::Cow, io};
-use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, future::Future, sync::Arc, time::Duration};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio_stream::StreamExt;
 use tui_tree_widget::{Tree, TreeItem, TreeState};
 
-use crate::{get_default_filter, get_rtm_api, get_rtm_cache};
+use crate::{get_rtm_api, get_rtm_cache, get_settings};
 
 static HELP_TEXT: &str = r#"Key bindings:
 
 A       New task
 C       Mark current task complete
 g       Change filter
+s       Choose a saved filter
+o       Change sort order
 L       View lists
 q       Quit
 Up/k    Move up one
@@ -65,9 +71,22 @@ struct TaskInfo {
     list_id: String,
 }
 
+struct Picker {
+    title: &'static str,
+    items: Vec<String>,
+    pos: usize,
+}
+
+/// The sort orders which `o` cycles through.
+const SORT_ORDERS: &[&str] = &["name", "due,priority", "priority,due", "added"];
+
 struct UiState {
     display_mode: DisplayMode,
     filter: String,
+    sort_order: usize,
+    saved_filters: BTreeMap<String, String>,
+    // The account's time zone, for showing dates.
+    timezone: chrono_tz::Tz,
     list_pos: usize,
     tree_items: Vec<TreeItem<'static, usize>>,
     tree_state: TreeState<usize>,
@@ -79,6 +98,9 @@ struct UiState {
     input_prompt: &'static str,
     input_value: String,
     show_input: bool,
+    // Show how the input parses as Smart Add text.
+    smart_add_preview: bool,
+    picker: Option<Picker>,
     show_help: bool,
     refresh: bool,
     // Spinner with current state.
@@ -190,8 +212,6 @@ enum TuiEvent {
 
 struct Tui {
     api_cache: TaskCache,
-    current_timeline: Option<RTMTimeline>,
-    transactions: Vec<String>,
     event_rx: Receiver<TuiEvent>,
     terminal: Terminal<CrosstermBackend<std::io::Stdout>>,
     ui_state: std::sync::Arc<tokio::sync::Mutex<UiState>>,
@@ -262,15 +282,19 @@ impl Tui {
         info!("Getting API instance...");
         let api = get_rtm_api(Perms::Delete).await?;
         let api_cache = get_rtm_cache(api).await?;
+        let timezone = api_cache.timezone().await?;
         let tree_state: TreeState<usize> = Default::default();
-        let filter = get_default_filter()?;
+        let settings = get_settings()?;
         let show_task = false;
         let show_help = false;
         let display_mode = DisplayMode::Tasks;
 
         let ui_state = UiState {
             display_mode,
-            filter,
+            filter: settings.filter,
+            sort_order: 0,
+            saved_filters: settings.saved,
+            timezone,
             tree_state,
             list_pos: 0,
             tree_items: vec![],
@@ -283,6 +307,8 @@ impl Tui {
             input_prompt: "",
             input_value: String::new(),
             show_input: false,
+            smart_add_preview: false,
+            picker: None,
             refresh: false,
             event_tx: event_tx.clone(),
             spinner: None,
@@ -295,8 +321,6 @@ impl Tui {
             event_rx,
             terminal,
             ui_state: std::sync::Arc::new(tokio::sync::Mutex::new(ui_state)),
-            current_timeline: None,
-            transactions: Vec::new(),
         };
 
         {
@@ -330,86 +354,52 @@ impl Tui {
     }
     async fn update_tasks(&mut self) -> Result<(), anyhow::Error> {
         trace!("Getting filter...");
-        let filter = self.ui_state.lock().await.filter.clone();
+        let (filter, sorts) = {
+            let ui_state = self.ui_state.lock().await;
+            let sorts = SORT_ORDERS[ui_state.sort_order]
+                .split(',')
+                .map(|s| s.parse::<Sort>())
+                .collect::<Result<Vec<_>, _>>()?;
+            (
+                expand_saved(&ui_state.filter, &ui_state.saved_filters)?,
+                sorts,
+            )
+        };
         trace!("Requesting tasks...");
-        let tasks = self.api_cache.get_tasks_filtered(&filter).await?;
+        let mut tree = self.api_cache.get_task_tree(&filter).await?;
+        tree.sort(&sorts);
         trace!("Got tasks.");
-        let tasks = self.add_missing_children(tasks).await?;
         let list_pos = 0;
 
-        let flat_tasks: Vec<TaskInfo> = {
-            let mut ft: Vec<_> = RtmTaskListIterator::new(&tasks)
-                .map(|(l, t)| TaskInfo {
-                    ts: t.clone(),
-                    list_id: l.id.clone(),
-                })
+        // Tasks which are only shown as the parent or child of a matching
+        // task are dimmed.
+        fn add_item(node: &TaskNode, flat_tasks: &mut Vec<TaskInfo>) -> TreeItem<'static, usize> {
+            let ti = flat_tasks.len();
+            flat_tasks.push(TaskInfo {
+                ts: node.taskseries.clone(),
+                list_id: node.list_id.clone(),
+            });
+            let text = if node.matched {
+                Text::raw(node.taskseries.name.clone())
+            } else {
+                Text::styled(
+                    node.taskseries.name.clone(),
+                    Style::default().fg(Color::DarkGray),
+                )
+            };
+            let children = node
+                .children
+                .iter()
+                .map(|child| add_item(child, flat_tasks))
                 .collect();
-            ft.sort_by(|a, b| (&a.ts.name, &a.ts.id).cmp(&(&b.ts.name, &b.ts.id)));
-            ft
-        };
-
-        // Map id to (is_root, TreeItem)
-        let mut task_map = HashMap::new();
-        let mut children_map = HashMap::new();
-        // Map by id
-        for (ti, tinfo) in flat_tasks.iter().enumerate() {
-            let ts = &tinfo.ts;
-            let id = &ts.task[0].id;
-            task_map.insert(id, (true, TreeItem::new_leaf(ti, ts.name.clone())));
-            children_map.insert(id, Vec::new());
-        }
-        // Record children
-        for (ti, tinfo) in flat_tasks.iter().enumerate() {
-            let ts = &tinfo.ts;
-            let id = &ts.task[0].id;
-            if let Some(parent_task_id) = &ts.parent_task_id {
-                if !parent_task_id.is_empty() && task_map.contains_key(&parent_task_id) {
-                    children_map.get_mut(&parent_task_id).unwrap().push(ti);
-                    // Mark as not root
-                    task_map.get_mut(id).unwrap().0 = false;
-                }
-            }
-        }
-
-        fn add_item(
-            task_map: &mut HashMap<&String, (bool, TreeItem<'static, usize>)>,
-            children_map: &mut HashMap<&String, Vec<usize>>,
-            tasks: &Vec<TaskInfo>,
-            list: &mut Vec<TreeItem<'static, usize>>,
-            ti: usize,
-            mut item: TreeItem<'static, usize>,
-        ) {
-            let id = &tasks[ti].ts.task[0].id;
-            let children = children_map.remove(id).unwrap();
-            if !children.is_empty() {
-                let mut child_items = Vec::new();
-                for cti in children {
-                    let cid = &tasks[cti].ts.task[0].id;
-                    let (_, citem) = task_map.remove(cid).unwrap();
-                    add_item(task_map, children_map, tasks, &mut child_items, cti, citem);
-                }
-                for child in child_items {
-                    item.add_child(child).unwrap();
-                }
-            }
-            list.push(item);
-        }
-        let mut tree_items = Vec::new();
-        for (ti, tinfo) in flat_tasks.iter().enumerate() {
-            let ts = &tinfo.ts;
-            let id = &ts.task[0].id;
-            if let Some((true, _)) = task_map.get(id) {
-                let (_, item) = task_map.remove(id).unwrap();
-                add_item(
-                    &mut task_map,
-                    &mut children_map,
-                    &flat_tasks,
-                    &mut tree_items,
-                    ti,
-                    item,
-                );
-            }
+            TreeItem::new(ti, text, children).unwrap()
         }
+        let mut flat_tasks = Vec::new();
+        let mut tree_items: Vec<_> = tree
+            .roots
+            .iter()
+            .map(|node| add_item(node, &mut flat_tasks))
+            .collect();
         if tree_items.is_empty() {
             tree_items.push(TreeItem::new_leaf(0, "[No tasks in current list]"));
         }
@@ -482,6 +472,7 @@ impl Tui {
     async fn fetch_lists(
         api_cache: TaskCache,
         ui_state: std::sync::Arc<tokio::sync::Mutex<UiState>>,
+        filter: String,
     ) {
         let lists = api_cache.get_lists().await.unwrap();
         let tx = ui_state.lock().await.event_tx.clone();
@@ -502,14 +493,13 @@ impl Tui {
             .unwrap();
 
         // Now fetch each list
-        let (filter, ids) = {
+        let ids = {
             let ui_state = ui_state.lock().await;
             let mut ids = Vec::new();
             for (i, list_state) in ui_state.lists.iter().enumerate() {
                 ids.push((i, list_state.list.id.clone()));
             }
-
-            (ui_state.filter.clone(), ids)
+            ids
         };
         for (idx, list_id) in ids {
             let tasks = get_tasks(&api_cache, &filter, &list_id).await.unwrap();
@@ -531,16 +521,17 @@ impl Tui {
     }
 
     async fn update_lists(&mut self) -> Result<(), anyhow::Error> {
-        let event_tx = {
+        let (filter, event_tx) = {
             let mut ui_state = self.ui_state.lock().await;
             let ui_state = &mut *ui_state;
+            let filter = expand_saved(&ui_state.filter, &ui_state.saved_filters)?;
             ui_state.display_mode = DisplayMode::Lists;
             ui_state.lists_loading = true;
             ui_state.tree_state.select_first();
 
             ui_state.list_pos = 0;
             ui_state.show_task = false;
-            ui_state.event_tx.clone()
+            (filter, ui_state.event_tx.clone())
         };
         let api_cache = self.api_cache.clone();
         let ui_state_ptr = std::sync::Arc::clone(&self.ui_state);
@@ -549,7 +540,7 @@ impl Tui {
             "fetching lists...",
             &[".", "o", "O"],
             async move {
-                Tui::fetch_lists(api_cache, ui_state_ptr).await;
+                Tui::fetch_lists(api_cache, ui_state_ptr, filter).await;
                 event_tx.send(TuiEvent::ListSyncFinished).await.unwrap();
             },
         )
@@ -662,9 +653,10 @@ impl Tui {
                             value: &Option<DateTime<Utc>>,
                             has_time: bool,
                             color: Color,
+                            tz: &chrono_tz::Tz,
                         ) {
                             if let Some(date_utc) = value {
-                                let date = date_utc.with_timezone::<Local>(&Local);
+                                let date = date_utc.with_timezone(tz);
                                 let style = Style::default().fg(color).add_modifier(Modifier::BOLD);
                                 let mut spans = vec![Span::raw(heading)];
                                 if has_time {
@@ -687,6 +679,7 @@ impl Tui {
                             &task.due,
                             task.has_due_time,
                             Color::Yellow,
+                            &ui_state.timezone,
                         );
                         add_date_field(
                             &mut text,
@@ -694,8 +687,16 @@ impl Tui {
                             &task.completed,
                             true,
                             Color::Magenta,
+                            &ui_state.timezone,
+                        );
+                        add_date_field(
+                            &mut text,
+                            "Deleted: ",
+                            &task.deleted,
+                            true,
+                            Color::Red,
+                            &ui_state.timezone,
                         );
-                        add_date_field(&mut text, "Deleted: ", &task.deleted, true, Color::Red);
                         add_string_field(&mut text, "Task: ", &task.id, Color::Gray);
                     }
                     add_string_field(&mut text, "URL: ", &series.url, Color::Yellow);
@@ -724,12 +725,56 @@ impl Tui {
                     .borders(Borders::TOP)
                     .border_style(Style::default().fg(Color::White))
                     .style(Style::default().bg(Color::Black));
-                let area = Rect::new(0, size.height - 2, size.width, 2);
+                let height = if ui_state.smart_add_preview { 3 } else { 2 };
+                let area = Rect::new(0, size.height - height, size.width, height);
                 f.render_widget(Clear, area);
 
                 let visible_value = tail_end(&ui_state.input_value, size.width as usize - 1);
                 let text = vec![Span::raw(visible_value), Span::raw("_")];
-                f.render_widget(Paragraph::new(vec![Line::from(text)]).block(block), area);
+                let mut lines = vec![Line::from(text)];
+                if ui_state.smart_add_preview {
+                    let preview = NewTask::parse(&ui_state.input_value).to_string();
+                    lines.push(Line::styled(preview, Style::default().fg(Color::Cyan)));
+                }
+                f.render_widget(Paragraph::new(lines).block(block), area);
+            }
+            if let Some(picker) = &ui_state.picker {
+                let block = Block::default()
+                    .title(picker.title)
+                    .borders(Borders::all())
+                    .border_style(Style::default().fg(Color::Cyan))
+                    .style(Style::default().bg(Color::Black));
+                let lines: Vec<Line> = picker
+                    .items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| {
+                        if i == picker.pos {
+                            Line::styled(
+                                item.clone(),
+                                Style::default().add_modifier(Modifier::REVERSED),
+                            )
+                        } else {
+                            Line::raw(item.clone())
+                        }
+                    })
+                    .collect();
+                let max_w = picker
+                    .items
+                    .iter()
+                    .map(|item| unicode_width::UnicodeWidthStr::width(item.as_str()))
+                    .chain([picker.title.len()])
+                    .max()
+                    .unwrap_or(0);
+                let area = Rect::new(
+                    1,
+                    1,
+                    (max_w as u16 + 2).min(size.width.saturating_sub(2)),
+                    (lines.len() as u16 + 2).min(size.height.saturating_sub(2)),
+                );
+                let scroll = (picker.pos as u16).saturating_sub(area.height.saturating_sub(3));
+                f.render_widget(Clear, area);
+                f.render_widget(Paragraph::new(lines).block(block).scroll((scroll, 0)), area);
             }
             if ui_state.show_help {
                 let block = Block::default()
@@ -813,6 +858,81 @@ impl Tui {
         Ok(result)
     }
 
+    /// Let the user choose one of `items`, returning its index or `None`
+    /// if cancelled.
+    async fn pick(
+        &mut self,
+        title: &'static str,
+        items: Vec<String>,
+    ) -> Result<Option<usize>, anyhow::Error> {
+        if items.is_empty() {
+            return Ok(None);
+        }
+        let len = items.len();
+        self.ui_state.lock().await.picker = Some(Picker {
+            title,
+            items,
+            pos: 0,
+        });
+        let result = loop {
+            self.draw().await?;
+            match self.event_rx.recv().await {
+                None => break None,
+                Some(TuiEvent::Input(ev)) => {
+                    if let Event::Key(key) = ev? {
+                        use crossterm::event::KeyModifiers;
+                        let mut ui_state = self.ui_state.lock().await;
+                        let picker = ui_state.picker.as_mut().unwrap();
+                        match (key.code, key.modifiers) {
+                            (KeyCode::Up | KeyCode::Char('k'), KeyModifiers::NONE) => {
+                                picker.pos = picker.pos.saturating_sub(1);
+                            }
+                            (KeyCode::Down | KeyCode::Char('j'), KeyModifiers::NONE) => {
+                                picker.pos = (picker.pos + 1).min(len - 1);
+                            }
+                            (KeyCode::Enter, KeyModifiers::NONE) => break Some(picker.pos),
+                            (KeyCode::Esc | KeyCode::Char('q'), KeyModifiers::NONE) => break None,
+                            _ => (),
+                        }
+                    }
+                }
+                Some(TuiEvent::StateChanged) => (),
+                Some(TuiEvent::Tick) => {
+                    self.ui_state.lock().await.tick();
+                }
+                Some(TuiEvent::SyncFinished) => {
+                    self.update_tasks().await?;
+                }
+                Some(TuiEvent::ListSyncFinished) => {}
+            }
+        };
+        self.ui_state.lock().await.picker = None;
+        Ok(result)
+    }
+
+    /// Let the user choose a saved filter or smart list as the filter.
+    async fn pick_saved_filter(&mut self) -> Result<(), anyhow::Error> {
+        let mut names: Vec<String> = self
+            .ui_state
+            .lock()
+            .await
+            .saved_filters
+            .keys()
+            .cloned()
+            .collect();
+        for list in self.api_cache.get_lists().await? {
+            if list.smart && !names.contains(&list.name) {
+                names.push(list.name);
+            }
+        }
+        if let Some(idx) = self.pick("Saved filters", names.clone()).await? {
+            let filter = rememberthemilk::filter::Filter::list(&names[idx])?;
+            self.ui_state.lock().await.filter = filter.to_string();
+            self.update_tasks().await?;
+        }
+        Ok(())
+    }
+
     pub async fn step(&mut self) -> Result<StepResult, anyhow::Error> {
         self.draw().await?;
 
@@ -838,9 +958,26 @@ impl Tui {
                                 }
                                 StepResult::Cont
                             }
+                            (KeyCode::Char('o'), KeyModifiers::NONE) => {
+                                {
+                                    let mut ui_state = self.ui_state.lock().await;
+                                    ui_state.sort_order =
+                                        (ui_state.sort_order + 1) % SORT_ORDERS.len();
+                                }
+                                self.update_tasks().await?;
+                                StepResult::Cont
+                            }
+                            (KeyCode::Char('s'), KeyModifiers::NONE) => {
+                                self.pick_saved_filter().await?;
+                                StepResult::Cont
+                            }
                             (KeyCode::Char('A'), KeyModifiers::SHIFT) => {
-                                let task_desc = self.input("Enter new task:", "").await?;
+                                self.ui_state.lock().await.smart_add_preview = true;
+                                let task_desc = self.input("Enter new task:", "").await;
+                                self.ui_state.lock().await.smart_add_preview = false;
+                                let task_desc = task_desc?;
                                 if !task_desc.is_empty() {
+                                    // RTM parses the text; the preview is only a guide.
                                     let timeline = self.get_timeline().await?;
                                     let _added = self
                                         .api_cache
@@ -891,21 +1028,11 @@ impl Tui {
                                     DisplayMode::Tasks => {
                                         info!("Marking task as complete");
                                         self.for_each_selected(
+                                            "Complete",
                                             async |api_cache, tl, list, ts, task| {
-                                                let resp = api_cache
+                                                api_cache
                                                     .mark_complete_id(tl, list, &ts.id, &task.id)
-                                                    .await?;
-                                                if let Some(transaction) = resp {
-                                                    if transaction.undoable
-                                                        && !transaction.id.is_empty()
-                                                    {
-                                                        Ok(Some(transaction.id))
-                                                    } else {
-                                                        Ok(None)
-                                                    }
-                                                } else {
-                                                    Ok(None)
-                                                }
+                                                    .await
                                             },
                                         )
                                         .await?;
@@ -989,15 +1116,12 @@ impl Tui {
     }
 
     async fn get_timeline(&mut self) -> Result<RTMTimeline, anyhow::Error> {
-        if self.current_timeline.is_none() {
-            self.current_timeline = Some(self.api_cache.get_timeline().await?);
-            self.transactions.clear();
-        }
-        Ok(self.current_timeline.as_ref().unwrap().clone())
+        self.api_cache.get_timeline().await
     }
 
-    // The callback returns an optional transaction id for undo.
-    async fn for_each_selected<F>(&mut self, f: F) -> Result<(), anyhow::Error>
+    // The callback returns an optional transaction, which is recorded with
+    // the description for undo.
+    async fn for_each_selected<F>(&mut self, description: &str, f: F) -> Result<(), anyhow::Error>
     where
         F: AsyncFn(
             &TaskCache,
@@ -1005,7 +1129,7 @@ impl Tui {
             &str, // list id
             &TaskSeries,
             &Task,
-        ) -> Result<Option<String>, anyhow::Error>,
+        ) -> Result<Option<RTMTransaction>, anyhow::Error>,
     {
         let timeline = self.get_timeline().await?;
 
@@ -1013,83 +1137,22 @@ impl Tui {
         let tree_pos = ui_state.tree_state.selected();
         let tinfo = &ui_state.flat_tasks[*tree_pos.last().unwrap()];
         let task = &tinfo.ts.task[0];
-        if let Some(tid) = f(&self.api_cache, &timeline, &tinfo.list_id, &tinfo.ts, task).await? {
-            self.transactions.push(tid);
+        if let Some(transaction) =
+            f(&self.api_cache, &timeline, &tinfo.list_id, &tinfo.ts, task).await?
+        {
+            let description = format!("{description}: {}", tinfo.ts.name);
+            self.api_cache
+                .record_transaction(&timeline, &transaction, &description)
+                .await?;
         }
 
         Ok(())
     }
 
     async fn undo_latest(&mut self) -> Result<(), anyhow::Error> {
-        if let Some(tl) = &self.current_timeline {
-            if let Some(transaction) = self.transactions.pop() {
-                self.api_cache.undo_transaction(tl, &transaction).await?;
-            }
-        }
+        self.api_cache.undo_latest(1).await?;
         Ok(())
     }
-
-    async fn add_missing_children(&self, tasks: RTMTasks) -> Result<RTMTasks, anyhow::Error> {
-        use std::collections::hash_map::Entry;
-        let mut all_lists = HashMap::new();
-
-        let update_lists = |all_lists: &mut HashMap<String, HashMap<String, TaskSeries>>,
-                            tasks: RTMTasks| {
-            // Initially import the tasks into hash maps.
-            for list in tasks.list {
-                let list_map = all_lists.entry(list.id).or_default();
-                if let Some(tss) = list.taskseries {
-                    for ts in tss {
-                        match list_map.entry(ts.id.clone()) {
-                            Entry::Occupied(mut occupied_entry) => {
-                                let cur_ts = occupied_entry.get_mut();
-                                for new_t in ts.task {
-                                    if !cur_ts.task.iter().any(|t| t.id == new_t.id) {
-                                        cur_ts.task.push(new_t);
-                                    }
-                                }
-                            }
-                            Entry::Vacant(vacant_entry) => {
-                                vacant_entry.insert(ts);
-                            }
-                        }
-                    }
-                }
-            }
-        };
-        update_lists(&mut all_lists, tasks);
-
-        let mut task_ids = Vec::new();
-        for (_, l) in all_lists.iter() {
-            for (_, ts) in l.iter() {
-                for t in &ts.task {
-                    task_ids.push(t.id.clone());
-                }
-            }
-        }
-
-        while let Some(id) = task_ids.pop() {
-            let children = self.api_cache.get_task_children(&id).await?;
-            for (_list, ts) in RtmTaskListIterator::new(&children) {
-                for t in &ts.task {
-                    task_ids.push(t.id.clone());
-                }
-            }
-
-            update_lists(&mut all_lists, children);
-        }
-
-        // Now combine back into an RTMLists.
-        let mut result: RTMTasks = Default::default();
-        for (list_id, list) in all_lists.into_iter() {
-            let new_list = RTMLists {
-                id: list_id,
-                taskseries: Some(list.into_values().collect()),
-            };
-            result.list.push(new_list);
-        }
-        Ok(result)
-    }
 }
 
 async fn get_tasks(
@@ -0,0 +1,462 @@
+//! Applying the same edits to many tasks.
+//!
+//! [plan_bulk] works out what each [BulkEdit] would change on each task,
+//! which can be shown as a preview, and [API::bulk_apply] then makes the
+//! changes, spacing out requests to stay within RTM's rate limit and
+//! carrying on past tasks which fail.
+//!
+//! ```no_run
+//! # use rememberthemilk::bulk::{plan_bulk, BulkEdit, RTM_REQUEST_INTERVAL};
+//! # async fn example(api: rememberthemilk::API) -> Result<(), anyhow::Error> {
+//! let tasks = api.get_tasks_filtered("tag:sprint12").await?;
+//! let edits = [BulkEdit::ShiftDue(7), BulkEdit::AddTag("late".into())];
+//! let entries = tasks.entries();
+//! let plan = plan_bulk(&entries, &edits, &Default::default(), &chrono::Local);
+//! for item in &plan {
+//!     for change in &item.changes {
+//!         println!("{}: {change}", item.entry.series.name);
+//!     }
+//! }
+//! let timeline = api.get_timeline().await?;
+//! let outcome = api
+//!     .bulk_apply(&timeline, &plan, RTM_REQUEST_INTERVAL, |_, _| ())
+//!     .await;
+//! for failure in &outcome.failures {
+//!     eprintln!("{}: {}", failure.name, failure.error);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Days, TimeZone, Utc};
+
+use crate::sort::TaskEntry;
+use crate::{RTMTimeline, RTMTransaction, API};
+
+/// The shortest time between requests made by [API::bulk_apply] when
+/// following RTM's guideline of one request per second.
+pub const RTM_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An edit to make to every selected task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkEdit {
+    /// Add a tag.
+    AddTag(String),
+    /// Remove a tag.
+    RemoveTag(String),
+    /// Set the priority ("1", "2", "3" or "N").
+    Priority(String),
+    /// Move the due date by a number of days, keeping the time of day.
+    /// Tasks with no due date are left alone.
+    ShiftDue(i64),
+    /// Move to the list with this id.
+    Move(String),
+    /// Postpone by a day, as RTM does: tasks with no due date become due
+    /// today.
+    Postpone,
+    /// Mark complete.
+    Complete,
+}
+
+/// The request which makes a [BulkChange].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    AddTag(String),
+    RemoveTag(String),
+    Priority(String),
+    Due(DateTime<Utc>, bool),
+    Move(String),
+    Postpone,
+    Complete,
+}
+
+/// One change to one task, shown as `field: before -> after`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BulkChange {
+    /// The field changed: tags, priority, due, list or status.
+    pub field: &'static str,
+    /// The field's value before the change.
+    pub before: String,
+    /// The field's value after the change.
+    pub after: String,
+    action: Action,
+}
+
+impl fmt::Display for BulkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.before, self.after)
+    }
+}
+
+/// The changes to make to one task.
+#[derive(Debug, Clone)]
+pub struct BulkItem<'a> {
+    /// The task.
+    pub entry: TaskEntry<'a>,
+    /// The changes, in order.
+    pub changes: Vec<BulkChange>,
+}
+
+/// A task which [API::bulk_apply] couldn't change.
+#[derive(Debug)]
+pub struct BulkFailure {
+    /// The task's name.
+    pub name: String,
+    /// The change which failed; any before it were made.
+    pub change: String,
+    /// The error from RTM.
+    pub error: crate::Error,
+}
+
+/// The result of [API::bulk_apply].
+#[derive(Debug, Default)]
+pub struct BulkOutcome {
+    /// The number of tasks changed without errors.
+    pub changed: usize,
+    /// The transactions made, with a description of each, for undoing.
+    pub transactions: Vec<(String, RTMTransaction)>,
+    /// The tasks which failed.
+    pub failures: Vec<BulkFailure>,
+}
+
+/// Work out the changes `edits` would make to each task, using `tz` for
+/// dates and `list_names` (list id to name) to describe moves.  Edits
+/// which would change nothing, such as adding a tag the task already has,
+/// are left out, as are tasks with nothing to change.
+pub fn plan_bulk<'a, Tz: TimeZone>(
+    entries: &[TaskEntry<'a>],
+    edits: &[BulkEdit],
+    list_names: &HashMap<String, String>,
+    tz: &Tz,
+) -> Vec<BulkItem<'a>>
+where
+    Tz::Offset: fmt::Display,
+{
+    let list_name = |id: &str| list_names.get(id).cloned().unwrap_or_else(|| id.into());
+    let show_due = |due: Option<DateTime<Utc>>, has_time: bool| match due {
+        None => "none".to_string(),
+        Some(due) if has_time => due.with_timezone(tz).format("%Y-%m-%d %H:%M").to_string(),
+        Some(due) => due.with_timezone(tz).format("%Y-%m-%d").to_string(),
+    };
+    let show_tags = |tags: &[String]| match tags {
+        [] => "none".to_string(),
+        tags => tags.join(","),
+    };
+    let shift = |due: DateTime<Utc>, days: i64| {
+        let local = due.with_timezone(tz);
+        let shifted = if days >= 0 {
+            local.checked_add_days(Days::new(days as u64))
+        } else {
+            local.checked_sub_days(Days::new(days.unsigned_abs()))
+        };
+        shifted.map_or(due, |d| d.with_timezone(&Utc))
+    };
+    let mut plan = Vec::new();
+    for entry in entries {
+        let (series, task) = (entry.series, entry.task);
+        let mut tags = series.tags.clone();
+        let mut priority = task.priority.clone();
+        let mut due = task.due;
+        let mut has_time = task.has_due_time;
+        let mut list = entry.list_id.to_string();
+        let mut completed = task.completed.is_some();
+        let mut changes = Vec::new();
+        for edit in edits {
+            let change = |field, before: String, after: String, action| BulkChange {
+                field,
+                before,
+                after,
+                action,
+            };
+            match edit {
+                BulkEdit::AddTag(tag) if !tags.contains(tag) => {
+                    let before = show_tags(&tags);
+                    tags.push(tag.clone());
+                    changes.push(change(
+                        "tags",
+                        before,
+                        show_tags(&tags),
+                        Action::AddTag(tag.clone()),
+                    ));
+                }
+                BulkEdit::RemoveTag(tag) if tags.contains(tag) => {
+                    let before = show_tags(&tags);
+                    tags.retain(|t| t != tag);
+                    changes.push(change(
+                        "tags",
+                        before,
+                        show_tags(&tags),
+                        Action::RemoveTag(tag.clone()),
+                    ));
+                }
+                BulkEdit::Priority(new) if *new != priority => {
+                    let before = std::mem::replace(&mut priority, new.clone());
+                    changes.push(change(
+                        "priority",
+                        before,
+                        new.clone(),
+                        Action::Priority(new.clone()),
+                    ));
+                }
+                BulkEdit::ShiftDue(days) if *days != 0 => {
+                    if let Some(old) = due {
+                        let new = shift(old, *days);
+                        due = Some(new);
+                        changes.push(change(
+                            "due",
+                            show_due(Some(old), has_time),
+                            show_due(due, has_time),
+                            Action::Due(new, has_time),
+                        ));
+                    }
+                }
+                BulkEdit::Move(to) if *to != list => {
+                    let before = std::mem::replace(&mut list, to.clone());
+                    changes.push(change(
+                        "list",
+                        list_name(&before),
+                        list_name(to),
+                        Action::Move(to.clone()),
+                    ));
+                }
+                BulkEdit::Postpone => {
+                    let before = show_due(due, has_time);
+                    due = Some(match due {
+                        Some(old) => shift(old, 1),
+                        None => {
+                            has_time = false;
+                            let today = Utc::now().with_timezone(tz).date_naive();
+                            tz.from_local_datetime(&today.and_time(chrono::NaiveTime::MIN))
+                                .earliest()
+                                .map_or_else(Utc::now, |d| d.with_timezone(&Utc))
+                        }
+                    });
+                    changes.push(change(
+                        "due",
+                        before,
+                        show_due(due, has_time),
+                        Action::Postpone,
+                    ));
+                }
+                BulkEdit::Complete if !completed => {
+                    completed = true;
+                    changes.push(change(
+                        "status",
+                        "incomplete".into(),
+                        "complete".into(),
+                        Action::Complete,
+                    ));
+                }
+                _ => (),
+            }
+        }
+        if !changes.is_empty() {
+            plan.push(BulkItem {
+                entry: *entry,
+                changes,
+            });
+        }
+    }
+    plan
+}
+
+/// Spaces out requests, such as to keep to RTM's rate limit with
+/// [RTM_REQUEST_INTERVAL].
+#[derive(Debug)]
+pub struct Throttle {
+    interval: Duration,
+    #[cfg(not(target_arch = "wasm32"))]
+    last: Option<tokio::time::Instant>,
+}
+
+impl Throttle {
+    /// Allow a request every `interval`, starting immediately.
+    pub fn new(interval: Duration) -> Throttle {
+        Throttle {
+            interval,
+            #[cfg(not(target_arch = "wasm32"))]
+            last: None,
+        }
+    }
+
+    /// Wait until the next request is allowed.
+    pub async fn wait(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(last) = self.last {
+                tokio::time::sleep_until(last + self.interval).await;
+            }
+            self.last = Some(tokio::time::Instant::now());
+        }
+    }
+}
+
+impl API {
+    /// Make the changes planned by [plan_bulk], waiting at least `interval`
+    /// between requests.  `progress` is called with the index of each task
+    /// before it is changed.  If a change fails, the rest of that task's
+    /// changes are skipped and the error is included in the outcome.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn bulk_apply(
+        &self,
+        timeline: &RTMTimeline,
+        plan: &[BulkItem<'_>],
+        interval: Duration,
+        mut progress: impl FnMut(usize, &BulkItem<'_>),
+    ) -> BulkOutcome {
+        let mut throttle = Throttle::new(interval);
+        let mut outcome = BulkOutcome::default();
+        for (index, item) in plan.iter().enumerate() {
+            progress(index, item);
+            let series_id = &item.entry.series.id;
+            let task_id = &item.entry.task.id;
+            // Moving changes the list used to identify the task afterwards.
+            let mut list_id = item.entry.list_id.to_string();
+            let mut failed = false;
+            for change in &item.changes {
+                throttle.wait().await;
+                let (l, s, t) = (list_id.as_str(), series_id.as_str(), task_id.as_str());
+                let result = match &change.action {
+                    Action::AddTag(tag) => self.add_tags_id(timeline, l, s, t, &[tag]).await,
+                    Action::RemoveTag(tag) => self.remove_tags_id(timeline, l, s, t, &[tag]).await,
+                    Action::Priority(p) => self.set_priority_id(timeline, l, s, t, p).await,
+                    Action::Due(due, has_time) => {
+                        self.set_due_date_id(timeline, l, s, t, *due, *has_time)
+                            .await
+                    }
+                    Action::Move(to) => self.move_to_id(timeline, l, to, s, t).await,
+                    Action::Postpone => self.postpone_id(timeline, l, s, t).await,
+                    Action::Complete => self.mark_complete_id(timeline, l, s, t).await,
+                };
+                match result {
+                    Ok(transaction) => {
+                        if let Action::Move(to) = &change.action {
+                            list_id = to.clone();
+                        }
+                        if let Some(transaction) = transaction {
+                            let description = format!("{change}: {}", item.entry.series.name);
+                            outcome.transactions.push((description, transaction));
+                        }
+                    }
+                    Err(error) => {
+                        outcome.failures.push(BulkFailure {
+                            name: item.entry.series.name.clone(),
+                            change: change.to_string(),
+                            error,
+                        });
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+            if !failed {
+                outcome.changed += 1;
+            }
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_bulk, BulkEdit};
+    use crate::RTMTasks;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_plan_bulk() {
+        let series = |id: &str, tags: &[&str], due: &str, has_time: &str, completed: &str| {
+            crate::tests::series(
+                id,
+                serde_json::json!({"name": format!("Task {id}"), "tags": {"tag": tags}}),
+                serde_json::json!({
+                    "due": due, "has_due_time": has_time, "completed": completed, "priority": "2"
+                }),
+            )
+        };
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [
+                    // Across the end of summer time, the time of day is kept.
+                    series("a", &["sprint12"], "2026-10-22T08:30:00Z", "1", ""),
+                    series("b", &["sprint12", "late"], "", "0", ""),
+                ],
+            }, {
+                "id": "2",
+                "taskseries": [series("c", &[], "2026-10-19T23:00:00Z", "0", "2026-10-18T10:00:00Z")],
+            }],
+        }))
+        .unwrap();
+        let list_names = HashMap::from([
+            ("1".to_string(), "Sprint 12".to_string()),
+            ("2".to_string(), "Sprint 13".to_string()),
+        ]);
+        let edits = [
+            BulkEdit::Move("2".into()),
+            BulkEdit::ShiftDue(7),
+            BulkEdit::AddTag("late".into()),
+            BulkEdit::RemoveTag("sprint12".into()),
+            BulkEdit::Priority("2".into()),
+            BulkEdit::Complete,
+        ];
+        let entries = tasks.entries();
+        let plan = plan_bulk(&entries, &edits, &list_names, &chrono_tz::Europe::London);
+        let summary: Vec<(&str, Vec<String>)> = plan
+            .iter()
+            .map(|item| {
+                (
+                    item.entry.task.id.as_str(),
+                    item.changes.iter().map(|c| c.to_string()).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "a",
+                    vec![
+                        "list: Sprint 12 -> Sprint 13".to_string(),
+                        "due: 2026-10-22 09:30 -> 2026-10-29 09:30".into(),
+                        "tags: sprint12 -> sprint12,late".into(),
+                        "tags: sprint12,late -> late".into(),
+                        "status: incomplete -> complete".into(),
+                    ]
+                ),
+                (
+                    "b",
+                    vec![
+                        "list: Sprint 12 -> Sprint 13".to_string(),
+                        "tags: sprint12,late -> late".into(),
+                        "status: incomplete -> complete".into(),
+                    ]
+                ),
+                (
+                    "c",
+                    vec![
+                        "due: 2026-10-20 -> 2026-10-27".to_string(),
+                        "tags: none -> late".into(),
+                    ]
+                ),
+            ]
+        );
+
+        let postponed = plan_bulk(
+            &entries[..1],
+            &[BulkEdit::Postpone],
+            &list_names,
+            &chrono::Utc,
+        );
+        assert_eq!(
+            postponed[0].changes[0].to_string(),
+            "due: 2026-10-22 08:30 -> 2026-10-23 08:30"
+        );
+    }
+}
@@ -1,8 +1,8 @@
 //! Local caching of Remember The Milk entries.
 
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
-use chrono::{Local, Utc};
+use chrono::Utc;
 use sqlx::{
     migrate::{MigrateDatabase as _, MigrateError},
     Sqlite, SqlitePool,
@@ -11,7 +11,18 @@ type JsonValue = serde_json::Value;
 
 use crate::{RTMList, RTMLists, RTMTasks, RTMTimeline, RTMTransaction, Task, TaskSeries, API};
 
-mod filter;
+pub mod filter;
+pub mod handle;
+pub mod history;
+mod sql;
+pub mod stats;
+pub mod tree;
+
+use handle::TaskHandle;
+use history::HistoryEntry;
+use sql::SqlExpr;
+use stats::{Stats, StatsOptions};
+use tree::{TaskNode, TaskTree};
 
 /// Cache errors
 #[derive(thiserror::Error, Debug)]
@@ -31,11 +42,32 @@ pub enum CacheError {
     /// Error parsing response
     #[error("Error parsing RTM response")]
     ParseError(&'static str),
+    /// The cache belongs to a different user
+    #[error("The cache belongs to user {cached}, not {current}")]
+    WrongUser {
+        /// The user ID the cache was created for.
+        cached: String,
+        /// The user ID of the API's token.
+        current: String,
+    },
 }
 
 /// Task cache result type.
 pub type Result<T> = std::result::Result<T, CacheError>;
 
+/// Make a task series containing a single task from their JSON in the
+/// database.
+fn taskseries_from_json(ts_data: &str, t_data: &str) -> TaskSeries {
+    let mut ts_json: serde_json::Value = serde_json::from_str(ts_data).unwrap();
+    let t_json: serde_json::Value =
+        vec![serde_json::from_str::<serde_json::Value>(t_data).unwrap()].into();
+    ts_json
+        .as_object_mut()
+        .unwrap()
+        .insert("task".to_string(), t_json);
+    serde_json::from_value(ts_json).unwrap()
+}
+
 /// A cache instance
 #[derive(Clone)]
 pub struct TaskCache {
@@ -56,25 +88,55 @@ impl TaskCache {
 
         let pool = SqlitePool::connect(db_name).await?;
         sqlx::migrate!().run(&pool).await?;
+        Self::check_owner(&pool, &api).await?;
         Ok(TaskCache { pool, api })
     }
 
+    /// Check that the cache belongs to the API's user, recording the user
+    /// if the cache is new.  Nothing is checked if the user isn't known,
+    /// as when the cache is used offline before authenticating.
+    async fn check_owner(pool: &SqlitePool, api: &API) -> Result<()> {
+        let Some(user) = api.user() else {
+            return Ok(());
+        };
+        let owner: Option<(String,)> =
+            sqlx::query_as("SELECT user_id FROM cache_owner WHERE id = 1")
+                .fetch_optional(pool)
+                .await?;
+        match owner {
+            Some((cached,)) if cached != user.id() => Err(CacheError::WrongUser {
+                cached,
+                current: user.id().into(),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                sqlx::query("INSERT INTO cache_owner (id, user_id) VALUES (1, ?)")
+                    .bind(user.id())
+                    .execute(pool)
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Return when the cache was last synchronised, if ever.
+    pub async fn last_sync(&self) -> Result<Option<chrono::DateTime<Utc>>> {
+        match sqlx::query_as::<_, (chrono::DateTime<Utc>,)>(
+            "SELECT last_sync FROM task_meta WHERE id = 1",
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map(|(d,)| d)
+        {
+            Ok(d) => Ok(Some(d)),
+            Err(sqlx::Error::RowNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// WIP get all tasks
     pub async fn sync(&self) -> Result<()> {
-        let last_sync: Option<chrono::DateTime<Utc>> =
-            match sqlx::query_as::<_, (chrono::DateTime<Utc>,)>(
-                "SELECT last_sync FROM task_meta WHERE id = 1",
-            )
-            .fetch_one(&self.pool)
-            .await
-            .map(|(d,)| d)
-            {
-                Ok(d) => Some(d),
-                Err(sqlx::Error::RowNotFound) => None,
-                Err(e) => {
-                    return Err(e.into());
-                }
-            };
+        let last_sync = self.last_sync().await?;
 
         log::info!("last_sync: {last_sync:?}");
         let new_last_sync = Utc::now();
@@ -117,6 +179,16 @@ impl TaskCache {
                                 .bind(t.to_string())
                                 .execute(&mut *tx)
                                 .await?;
+                                sqlx::query(
+                                    "INSERT INTO task_handles(taskseries_id, task_id)
+                                    VALUES(?1, ?2)
+                                    ON CONFLICT DO NOTHING;
+                                ",
+                                )
+                                .bind(&taskseries_id)
+                                .bind(task_id)
+                                .execute(&mut *tx)
+                                .await?;
                             }
                         }
                     }
@@ -156,16 +228,27 @@ impl TaskCache {
         let lists = self.api.get_lists().await?;
         for list in lists {
             sqlx::query(
-                "INSERT INTO lists(list_id, name)
-                    VALUES(?1, ?2)
-                    ON CONFLICT DO UPDATE SET name = ?2;
+                "INSERT INTO lists(list_id, name, smart, filter)
+                    VALUES(?1, ?2, ?3, ?4)
+                    ON CONFLICT DO UPDATE SET name = ?2, smart = ?3, filter = ?4;
                     ",
             )
             .bind(&list.id)
             .bind(&list.name)
+            .bind(list.smart)
+            .bind(&list.filter)
             .execute(&mut *tx)
             .await?;
         }
+        let timezone = self.api.get_timezone().await?;
+        sqlx::query(
+            "INSERT INTO account_settings(id, timezone)
+            VALUES(1, ?1)
+            ON CONFLICT(id) DO UPDATE SET timezone = ?1",
+        )
+        .bind(timezone.map(|tz| tz.name()))
+        .execute(&mut *tx)
+        .await?;
         tx.commit().await?;
 
         sqlx::query(
@@ -183,30 +266,81 @@ impl TaskCache {
         Ok(())
     }
 
+    /// The time zone from the user's RTM settings when last synced, or the
+    /// system's if that isn't known.  See [crate::API::get_timezone].
+    pub async fn timezone(&self) -> Result<chrono_tz::Tz> {
+        let row: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT timezone FROM account_settings WHERE id = 1")
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row
+            .and_then(|(tz,)| tz?.parse().ok())
+            .unwrap_or_else(crate::system_timezone))
+    }
+
     /// Return tasks from the cache matching the filter.
     pub async fn get_tasks_filtered(
         &self,
         filt: &str,
     ) -> std::result::Result<RTMTasks, crate::Error> {
-        let mut filter_clause = String::new();
-        let mut filter_binds = Vec::new();
-        if !filt.is_empty() {
-            let filter = filter::parse_filter(filt)?;
-            let mut context = filter::FilterContext {
-                now: Local::now(),
-                ..Default::default()
-            };
-            let lists = self.get_lists().await?;
-            for list in lists {
-                context.lists_name_to_id.insert(list.name, list.id);
+        let filter = if filt.is_empty() {
+            None
+        } else {
+            Some(filter::parse_filter(filt)?)
+        };
+        let clause = self.filter_clause(filter.as_ref()).await?;
+        self.query_tasks(clause).await
+    }
+
+    /// Return tasks from the cache matching an already parsed or built
+    /// filter.  See [filter::Filter].
+    pub async fn get_tasks_matching(
+        &self,
+        filter: &filter::RtmFilter,
+    ) -> std::result::Result<RTMTasks, crate::Error> {
+        let clause = self.filter_clause(Some(filter)).await?;
+        self.query_tasks(clause).await
+    }
+
+    /// Build the context for evaluating filters against the cache.
+    async fn filter_context(
+        &self,
+    ) -> std::result::Result<filter::FilterContext<chrono_tz::Tz>, crate::Error> {
+        let mut context = filter::FilterContext {
+            lists_name_to_id: Default::default(),
+            saved_filters: Default::default(),
+            now: Utc::now().with_timezone(&self.timezone().await?),
+        };
+        let lists = self.get_lists().await?;
+        for list in lists {
+            if let (true, Some(filter)) = (list.smart, list.filter) {
+                context.saved_filters.insert(list.name.clone(), filter);
             }
-            let (clause, binds) = filter.to_sqlite_where_clause(&context)?;
-            filter_clause = clause;
-            filter_binds = binds;
+            context.lists_name_to_id.insert(list.name, list.id);
+        }
+        Ok(context)
+    }
 
-            log::info!("Filter clause: {filter_clause}");
+    /// Convert an optional filter to a where clause.
+    async fn filter_clause(
+        &self,
+        filter: Option<&filter::RtmFilter>,
+    ) -> std::result::Result<SqlExpr, crate::Error> {
+        match filter {
+            Some(filter) => {
+                let clause = filter.to_sqlite_where_clause(&self.filter_context().await?)?;
+                log::info!("Filter clause: {}", clause.sql());
+                Ok(clause)
+            }
+            None => Ok(SqlExpr::new("TRUE")),
         }
+    }
 
+    /// Return the (not deleted) tasks matching a where clause.
+    async fn query_tasks(
+        &self,
+        where_clause: SqlExpr,
+    ) -> std::result::Result<RTMTasks, crate::Error> {
         #[derive(sqlx::FromRow)]
         struct Data {
             list_id: String,
@@ -214,20 +348,16 @@ impl TaskCache {
             t_data: String,
         }
 
-        let query_str = format!(
+        let mut query = SqlExpr::new(
             r#"SELECT ts.list_id, json(ts.data) as ts_data, json(t.data) as t_data
              FROM taskseries ts, tasks t
              USING (list_id, taskseries_id)
              WHERE
                 t.deleted != TRUE AND
-                {filter_clause};
-                "#
+                "#,
         );
-        let mut query = sqlx::query_as(&query_str);
-        for bind in filter_binds {
-            query = query.bind(bind);
-        }
-        let data: Vec<Data> = query.fetch_all(&self.pool).await?;
+        query.push_expr(where_clause);
+        let data: Vec<Data> = query.query_as().fetch_all(&self.pool).await?;
         let mut result = RTMTasks {
             rev: Default::default(),
             list: Vec::new(),
@@ -237,20 +367,86 @@ impl TaskCache {
                 id: item.list_id,
                 taskseries: None,
             };
-            let mut ts_json: serde_json::Value = serde_json::from_str(&item.ts_data).unwrap();
-            let t_json: serde_json::Value =
-                vec![serde_json::from_str::<serde_json::Value>(&item.t_data).unwrap()].into();
-            ts_json
-                .as_object_mut()
-                .unwrap()
-                .insert("task".to_string(), t_json);
-            let ts: TaskSeries = serde_json::from_value(ts_json).unwrap();
-            list.taskseries = Some(vec![ts]);
+            list.taskseries = Some(vec![taskseries_from_json(&item.ts_data, &item.t_data)]);
             result.list.push(list);
         }
         Ok(result)
     }
 
+    /// Return the tasks matching the filter along with all their ancestors
+    /// and incomplete descendants, arranged as a tree.
+    pub async fn get_task_tree(&self, filt: &str) -> std::result::Result<TaskTree, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct Data {
+            list_id: String,
+            ts_data: String,
+            t_data: String,
+            matched: bool,
+        }
+
+        let filter = if filt.is_empty() {
+            None
+        } else {
+            Some(filter::parse_filter(filt)?)
+        };
+        let clause = self.filter_clause(filter.as_ref()).await?;
+        let mut query = SqlExpr::new(
+            r#"WITH RECURSIVE
+             matched(task_id) AS (
+                SELECT t.task_id
+                FROM taskseries ts, tasks t
+                USING (list_id, taskseries_id)
+                WHERE
+                    t.deleted != TRUE AND
+                    "#,
+        );
+        query.push_expr(clause).push(
+            r#"
+             ),
+             ancestors(task_id) AS (
+                SELECT task_id FROM matched
+                UNION
+                SELECT jsonb_extract(ts.data, '$.parent_task_id')
+                FROM ancestors a
+                JOIN tasks t ON t.task_id = a.task_id
+                JOIN taskseries ts
+                    ON ts.list_id = t.list_id AND ts.taskseries_id = t.taskseries_id
+                WHERE IFNULL(jsonb_extract(ts.data, '$.parent_task_id'), '') <> ''
+             ),
+             descendants(task_id) AS (
+                SELECT task_id FROM matched
+                UNION
+                SELECT t.task_id
+                FROM descendants d
+                JOIN taskseries ts ON jsonb_extract(ts.data, '$.parent_task_id') = d.task_id
+                JOIN tasks t
+                    ON t.list_id = ts.list_id AND t.taskseries_id = ts.taskseries_id
+                WHERE
+                    t.deleted != TRUE AND
+                    jsonb_extract(t.data, '$.completed') = ''
+             )
+             SELECT ts.list_id, json(ts.data) as ts_data, json(t.data) as t_data,
+                t.task_id IN (SELECT task_id FROM matched) AS matched
+             FROM taskseries ts, tasks t
+             USING (list_id, taskseries_id)
+             WHERE
+                t.deleted != TRUE AND
+                (t.task_id IN ancestors OR t.task_id IN descendants)
+            "#,
+        );
+        let data: Vec<Data> = query.query_as().fetch_all(&self.pool).await?;
+        Ok(TaskTree::build(
+            data.into_iter()
+                .map(|item| TaskNode {
+                    taskseries: taskseries_from_json(&item.ts_data, &item.t_data),
+                    list_id: item.list_id,
+                    matched: item.matched,
+                    children: Vec::new(),
+                })
+                .collect(),
+        ))
+    }
+
     /// Return tasks which are children of a given task
     pub async fn get_task_children(
         &self,
@@ -284,20 +480,87 @@ impl TaskCache {
                 id: item.list_id,
                 taskseries: None,
             };
-            let mut ts_json: serde_json::Value = serde_json::from_str(&item.ts_data).unwrap();
-            let t_json: serde_json::Value =
-                vec![serde_json::from_str::<serde_json::Value>(&item.t_data).unwrap()].into();
-            ts_json
-                .as_object_mut()
-                .unwrap()
-                .insert("task".to_string(), t_json);
-            let ts: TaskSeries = serde_json::from_value(ts_json).unwrap();
-            list.taskseries = Some(vec![ts]);
+            list.taskseries = Some(vec![taskseries_from_json(&item.ts_data, &item.t_data)]);
             result.list.push(list);
         }
         Ok(result)
     }
 
+    /// Return the handles for all the tasks in `tasks`, assigning new ones
+    /// where needed.  The result is keyed by task series and task id.
+    pub async fn assign_handles(
+        &self,
+        tasks: &RTMTasks,
+    ) -> std::result::Result<HashMap<(String, String), TaskHandle>, crate::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut result = HashMap::new();
+        for list in &tasks.list {
+            for ts in list.taskseries.iter().flatten() {
+                for task in &ts.task {
+                    let (handle,): (i64,) = sqlx::query_as(
+                        "INSERT INTO task_handles(taskseries_id, task_id)
+                        VALUES(?1, ?2)
+                        ON CONFLICT DO UPDATE SET task_id = task_id
+                        RETURNING handle",
+                    )
+                    .bind(&ts.id)
+                    .bind(&task.id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                    result.insert((ts.id.clone(), task.id.clone()), TaskHandle(handle));
+                }
+            }
+        }
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    /// Return the task series and task ids a handle was given to, or `None`
+    /// if it is unknown.  Handles can be given to tasks which aren't in the
+    /// cache yet, such as online results, so this doesn't need a sync.
+    pub async fn handle_task_ids(
+        &self,
+        handle: TaskHandle,
+    ) -> std::result::Result<Option<(String, String)>, crate::Error> {
+        Ok(
+            sqlx::query_as("SELECT taskseries_id, task_id FROM task_handles WHERE handle = ?")
+                .bind(handle.0)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    /// Look up a task by its handle, returning its list id and a task
+    /// series containing just that task, or `None` if it has been deleted
+    /// or isn't in the cache yet.  Sync first for the current list; see
+    /// [TaskCache::handle_task_ids].
+    ///
+    /// Handles don't include the list, as a task keeps its handle when
+    /// moved; the copy left in the old list is marked deleted, so there
+    /// should only ever be one current copy.
+    pub async fn get_task_by_handle(
+        &self,
+        handle: TaskHandle,
+    ) -> std::result::Result<Option<(String, TaskSeries)>, crate::Error> {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT ts.list_id, json(ts.data), json(t.data)
+             FROM task_handles h
+             JOIN tasks t USING (taskseries_id, task_id)
+             JOIN taskseries ts USING (list_id, taskseries_id)
+             WHERE h.handle = ? AND t.deleted IS NOT TRUE",
+        )
+        .bind(handle.0)
+        .fetch_all(&self.pool)
+        .await?;
+        if rows.len() > 1 {
+            anyhow::bail!("Task {handle} is in more than one list in the cache.");
+        }
+        Ok(rows
+            .into_iter()
+            .next()
+            .map(|(list_id, ts_data, t_data)| (list_id, taskseries_from_json(&ts_data, &t_data))))
+    }
+
     /// Add a task and update the cache.
     pub async fn add_task(
         &self,
@@ -316,21 +579,219 @@ impl TaskCache {
         Ok(result)
     }
 
+    /// Add a task parsed from Smart Add text locally, using the cached
+    /// lists, and update the cache.  See [crate::API::add_new_task].
+    pub async fn add_new_task(
+        &self,
+        timeline: &RTMTimeline,
+        task: &crate::smartadd::NewTask,
+        parent: Option<&Task>,
+    ) -> std::result::Result<crate::smartadd::AddOutcome, crate::Error> {
+        let lists = self.get_lists().await?;
+        let result = self
+            .api
+            .add_new_task(timeline, task, &lists, parent)
+            .await?;
+        self.sync().await?;
+        Ok(result)
+    }
+
     /// Get a new timeline
+    ///
+    /// Timelines are saved in the cache and reused for up to
+    /// [history::TIMELINE_MAX_AGE], so that changes made by separate runs
+    /// can still be undone.
     pub async fn get_timeline(&self) -> std::result::Result<RTMTimeline, crate::Error> {
-        self.api.get_timeline().await
+        let now = Utc::now();
+        let recent: Option<(String,)> = sqlx::query_as(
+            "SELECT timeline FROM timelines
+             WHERE created > ?
+             ORDER BY created DESC
+             LIMIT 1",
+        )
+        .bind(now - history::TIMELINE_MAX_AGE)
+        .fetch_optional(&self.pool)
+        .await?;
+        if let Some((timeline,)) = recent {
+            return Ok(RTMTimeline(timeline));
+        }
+        let timeline = self.api.get_timeline().await?;
+        sqlx::query("INSERT INTO timelines(timeline, created) VALUES(?, ?)")
+            .bind(&timeline.0)
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(timeline)
+    }
+
+    /// Record a change so that it can be undone later with
+    /// [TaskCache::undo_latest].  Transactions which can't be undone are
+    /// ignored.
+    pub async fn record_transaction(
+        &self,
+        timeline: &RTMTimeline,
+        transaction: &RTMTransaction,
+        description: &str,
+    ) -> std::result::Result<(), crate::Error> {
+        if !transaction.undoable || transaction.id.is_empty() {
+            return Ok(());
+        }
+        sqlx::query(
+            "INSERT INTO transactions(timeline, transaction_id, description, created)
+             VALUES(?, ?, ?, ?)",
+        )
+        .bind(&timeline.0)
+        .bind(&transaction.id)
+        .bind(description)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record several changes, such as those made by
+    /// [API::bulk_apply](crate::API::bulk_apply), as one batch which
+    /// [TaskCache::undo_latest] undoes together.  Transactions which can't be
+    /// undone are ignored.
+    pub async fn record_batch(
+        &self,
+        timeline: &RTMTimeline,
+        transactions: &[(String, RTMTransaction)],
+    ) -> std::result::Result<(), crate::Error> {
+        let mut db = self.pool.begin().await?;
+        let mut batch: Option<i64> = None;
+        let now = Utc::now();
+        for (description, transaction) in transactions {
+            if !transaction.undoable || transaction.id.is_empty() {
+                continue;
+            }
+            let (id,): (i64,) = sqlx::query_as(
+                "INSERT INTO transactions(timeline, transaction_id, description, created, batch)
+                 VALUES(?, ?, ?, ?, ?)
+                 RETURNING id",
+            )
+            .bind(&timeline.0)
+            .bind(&transaction.id)
+            .bind(description)
+            .bind(now)
+            .bind(batch)
+            .fetch_one(&mut *db)
+            .await?;
+            if batch.is_none() {
+                batch = Some(id);
+                sqlx::query("UPDATE transactions SET batch = id WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut *db)
+                    .await?;
+            }
+        }
+        db.commit().await?;
+        Ok(())
+    }
+
+    /// Return up to `limit` recorded changes, most recent first.  Each
+    /// transaction in a batch is returned, but the batch counts as one
+    /// change.
+    pub async fn get_history(
+        &self,
+        limit: u32,
+    ) -> std::result::Result<Vec<HistoryEntry>, crate::Error> {
+        self.query_history(true, limit).await
+    }
+
+    /// Return the transactions in up to `limit` recorded changes, most
+    /// recent first, optionally including those already undone.
+    async fn query_history(
+        &self,
+        include_undone: bool,
+        limit: u32,
+    ) -> std::result::Result<Vec<HistoryEntry>, crate::Error> {
+        #[derive(sqlx::FromRow)]
+        struct Row {
+            id: i64,
+            timeline: String,
+            transaction_id: String,
+            description: String,
+            created: chrono::DateTime<Utc>,
+            undone: bool,
+            batch: Option<i64>,
+        }
+
+        // A batch counts as one change towards the limit.
+        let rows: Vec<Row> = sqlx::query_as(
+            "SELECT id, timeline, transaction_id, description, created, undone, batch
+                 FROM transactions
+                 WHERE (?1 OR NOT undone) AND COALESCE(batch, id) IN (
+                     SELECT COALESCE(batch, id) AS change FROM transactions
+                     WHERE ?1 OR NOT undone
+                     GROUP BY change
+                     ORDER BY MAX(id) DESC
+                     LIMIT ?2)
+                 ORDER BY id DESC",
+        )
+        .bind(include_undone)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| HistoryEntry {
+                id: row.id,
+                timeline: RTMTimeline(row.timeline),
+                transaction_id: row.transaction_id,
+                description: row.description,
+                created: row.created,
+                undone: row.undone,
+                batch: row.batch,
+            })
+            .collect())
+    }
+
+    /// Undo the `count` most recent changes which haven't already been
+    /// undone, newest first, and update the cache.  Returns the changes
+    /// undone.  A batch counts as one change.  If RTM refuses to undo one,
+    /// the error is returned and the older changes are left alone.
+    pub async fn undo_latest(
+        &self,
+        count: u32,
+    ) -> std::result::Result<Vec<HistoryEntry>, crate::Error> {
+        let mut undone = Vec::new();
+        for entry in self.query_history(false, count).await? {
+            if let Err(e) = self
+                .undo_transaction(&entry.timeline, &entry.transaction_id)
+                .await
+            {
+                if !undone.is_empty() {
+                    self.sync().await?;
+                }
+                return Err(e.context(format!("Unable to undo {}", entry.description)));
+            }
+            undone.push(HistoryEntry {
+                undone: true,
+                ..entry
+            });
+        }
+        if !undone.is_empty() {
+            self.sync().await?;
+        }
+        Ok(undone)
     }
     /// Get lists
     pub async fn get_lists(&self) -> std::result::Result<Vec<RTMList>, crate::Error> {
-        let items: Vec<(String, String)> = sqlx::query_as(
+        let items: Vec<(String, String, bool, Option<String>)> = sqlx::query_as(
             r#"
-            SELECT list_id, name FROM lists"#,
+            SELECT list_id, name, smart, filter FROM lists"#,
         )
         .fetch_all(&self.pool)
         .await?;
         Ok(items
             .into_iter()
-            .map(|(id, name)| RTMList { id, name })
+            .map(|(id, name, smart, filter)| RTMList {
+                id,
+                name,
+                smart,
+                filter,
+            })
             .collect())
     }
     /// Mark complete
@@ -369,7 +830,34 @@ impl TaskCache {
         timeline: &RTMTimeline,
         transaction_id: &str,
     ) -> std::result::Result<(), crate::Error> {
-        self.api.undo_transaction(timeline, transaction_id).await
+        self.api.undo_transaction(timeline, transaction_id).await?;
+        sqlx::query(
+            "UPDATE transactions SET undone = TRUE
+             WHERE timeline = ? AND transaction_id = ?",
+        )
+        .bind(&timeline.0)
+        .bind(transaction_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Return productivity statistics over the tasks in the cache, as of
+    /// now in the account's time zone (see [TaskCache::timezone]).
+    pub async fn stats(&self, options: &StatsOptions) -> std::result::Result<Stats, crate::Error> {
+        let tasks = self.query_tasks(SqlExpr::new("TRUE")).await?;
+        let list_names = self
+            .get_lists()
+            .await?
+            .into_iter()
+            .map(|list| (list.id, list.name))
+            .collect();
+        Ok(Stats::compute(
+            &tasks.entries(),
+            &list_names,
+            options,
+            &Utc::now().with_timezone(&self.timezone().await?),
+        ))
     }
 
     /// Return all tasks in a given list, according to the optional filter.
@@ -378,67 +866,315 @@ impl TaskCache {
         list_id: &str,
         filt: &str,
     ) -> std::result::Result<RTMTasks, crate::Error> {
-        let mut filter_clause = String::new();
-        let mut filter_binds = vec![list_id.to_string()];
-        if !filt.is_empty() {
-            let filter = filter::parse_filter(filt)?;
-            let mut context = filter::FilterContext {
-                now: Local::now(),
-                ..Default::default()
-            };
-            let lists = self.get_lists().await?;
-            for list in lists {
-                context.lists_name_to_id.insert(list.name, list.id);
-            }
-            let (clause, binds) = filter.to_sqlite_where_clause(&context)?;
-            filter_clause = clause;
-            filter_binds.extend(binds);
+        let filter = if filt.is_empty() {
+            None
+        } else {
+            Some(filter::parse_filter(filt)?)
+        };
+        let mut clause = SqlExpr::new("t.list_id = ");
+        clause
+            .push_bind(list_id)
+            .push(" AND ")
+            .push_expr(self.filter_clause(filter.as_ref()).await?);
+        self.query_tasks(clause).await
+    }
+}
 
-            log::info!("Filter clause: {filter_clause}");
-        }
+#[cfg(test)]
+mod tests {
+    use super::{handle, TaskCache};
+    use crate::RTMTransaction;
+    use crate::API;
 
-        #[derive(sqlx::FromRow)]
-        struct Data {
-            list_id: String,
-            ts_data: String,
-            t_data: String,
+    async fn test_cache() -> TaskCache {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+        let server = mockito::Server::new_async().await;
+        TaskCache {
+            pool,
+            api: API::new_test("key".into(), "secret".into(), server),
         }
+    }
 
-        let query_str = format!(
-            r#"SELECT ts.list_id, json(ts.data) as ts_data, json(t.data) as t_data
-             FROM taskseries ts, tasks t
-             USING (list_id, taskseries_id)
-             WHERE
-                t.deleted != TRUE AND
-                list_id = ? AND
-                {filter_clause};
-                "#
+    async fn add_task(cache: &TaskCache, id: &str, parent: &str, completed: bool) {
+        let ts_data = serde_json::json!({
+            "id": id,
+            "created": "2000-01-01T00:00:00Z",
+            "modified": "2000-01-01T00:00:00Z",
+            "name": id,
+            "source": "js",
+            "url": "",
+            "location_id": "",
+            "tags": [],
+            "participants": [],
+            "notes": [],
+            "parent_task_id": parent,
+        });
+        let t_data = serde_json::json!({
+            "id": id, "due": "", "has_due_time": "0", "added": "",
+            "completed": if completed { "2000-01-01T00:00:00Z" } else { "" },
+            "deleted": "", "priority": "N", "postponed": "0", "estimate": ""
+        });
+        sqlx::query(
+            "INSERT INTO taskseries(list_id, taskseries_id, data) VALUES('1', ?, jsonb(?))",
+        )
+        .bind(id)
+        .bind(ts_data.to_string())
+        .execute(&cache.pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO tasks(list_id, taskseries_id, task_id, data) VALUES('1', ?, ?, jsonb(?))",
+        )
+        .bind(id)
+        .bind(id)
+        .bind(t_data.to_string())
+        .execute(&cache.pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_task_tree() -> Result<(), anyhow::Error> {
+        let cache = test_cache().await;
+        // Four levels, with a completed task at the top.
+        add_task(&cache, "project", "", true).await;
+        add_task(&cache, "phase", "project", false).await;
+        add_task(&cache, "step", "phase", false).await;
+        add_task(&cache, "detail", "step", false).await;
+        add_task(&cache, "done detail", "step", true).await;
+        add_task(&cache, "other", "", false).await;
+
+        let tree = cache.get_task_tree("name:step").await?;
+        let shape: Vec<_> = tree
+            .iter()
+            .map(|(depth, node)| (depth, node.task().id.as_str(), node.matched))
+            .collect();
+        assert_eq!(
+            shape,
+            [
+                (0, "project", false),
+                (1, "phase", false),
+                (2, "step", true),
+                (3, "detail", false),
+            ]
         );
-        let mut query = sqlx::query_as(&query_str);
-        for bind in filter_binds {
-            query = query.bind(bind);
-        }
-        let data: Vec<Data> = query.fetch_all(&self.pool).await?;
-        let mut result = RTMTasks {
-            rev: Default::default(),
-            list: Vec::new(),
+
+        let tree = cache.get_task_tree("status:incomplete").await?;
+        let shape: Vec<_> = tree
+            .iter()
+            .map(|(depth, node)| (depth, node.task().id.as_str(), node.matched))
+            .collect();
+        assert_eq!(
+            shape,
+            [
+                (0, "other", true),
+                (0, "project", false),
+                (1, "phase", true),
+                (2, "step", true),
+                (3, "detail", true),
+            ]
+        );
+
+        assert!(cache.get_task_tree("name:nothing").await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transaction_log() -> Result<(), anyhow::Error> {
+        let cache = test_cache().await;
+        sqlx::query("INSERT INTO timelines(timeline, created) VALUES('old', ?), ('new', ?)")
+            .bind(chrono::Utc::now() - chrono::TimeDelta::days(2))
+            .bind(chrono::Utc::now() - chrono::TimeDelta::minutes(5))
+            .execute(&cache.pool)
+            .await?;
+        let timeline = cache.get_timeline().await?;
+        assert_eq!(timeline.0, "new");
+
+        let transaction = |id: &str, undoable| RTMTransaction {
+            id: id.into(),
+            undoable,
         };
-        for item in data {
-            let mut list = RTMLists {
-                id: item.list_id,
-                taskseries: None,
-            };
-            let mut ts_json: serde_json::Value = serde_json::from_str(&item.ts_data).unwrap();
-            let t_json: serde_json::Value =
-                vec![serde_json::from_str::<serde_json::Value>(&item.t_data).unwrap()].into();
-            ts_json
-                .as_object_mut()
-                .unwrap()
-                .insert("task".to_string(), t_json);
-            let ts: TaskSeries = serde_json::from_value(ts_json).unwrap();
-            list.taskseries = Some(vec![ts]);
-            result.list.push(list);
+        cache
+            .record_transaction(&timeline, &transaction("1", true), "first")
+            .await?;
+        cache
+            .record_transaction(&timeline, &transaction("2", false), "not undoable")
+            .await?;
+        cache
+            .record_transaction(&timeline, &transaction("3", true), "second")
+            .await?;
+        sqlx::query("UPDATE transactions SET undone = TRUE WHERE transaction_id = '1'")
+            .execute(&cache.pool)
+            .await?;
+
+        let history = cache.get_history(10).await?;
+        let summary: Vec<_> = history
+            .iter()
+            .map(|e| (e.description.as_str(), e.undone))
+            .collect();
+        assert_eq!(summary, [("second", false), ("first", true)]);
+        assert_eq!(cache.get_history(1).await?.len(), 1);
+
+        let pending = cache.query_history(false, 10).await?;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].transaction_id, "3");
+        assert_eq!(pending[0].timeline.0, "new");
+
+        cache
+            .record_batch(
+                &timeline,
+                &[
+                    ("bulk 1".into(), transaction("4", true)),
+                    ("bulk 2".into(), transaction("5", false)),
+                    ("bulk 3".into(), transaction("6", true)),
+                ],
+            )
+            .await?;
+        let latest = cache.query_history(false, 1).await?;
+        let summary: Vec<_> = latest
+            .iter()
+            .map(|e| (e.transaction_id.as_str(), e.batch))
+            .collect();
+        let batch = latest[1].id;
+        assert_eq!(summary, [("6", Some(batch)), ("4", Some(batch))]);
+        assert_eq!(cache.query_history(false, 2).await?.len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_task_handles() -> Result<(), anyhow::Error> {
+        let cache = test_cache().await;
+        add_task(&cache, "a", "", false).await;
+        add_task(&cache, "b", "", false).await;
+
+        let tasks = cache.get_tasks_filtered("name:b").await?;
+        let handles = cache.assign_handles(&tasks).await?;
+        let b = handles[&("b".to_string(), "b".to_string())];
+        let tasks = cache.get_tasks_filtered("").await?;
+        let handles = cache.assign_handles(&tasks).await?;
+        assert_eq!(handles.len(), 2);
+        // Existing handles are kept.
+        assert_eq!(handles[&("b".to_string(), "b".to_string())], b);
+        let a = handles[&("a".to_string(), "a".to_string())];
+        assert_ne!(a, b);
+
+        let (list_id, ts) = cache.get_task_by_handle(a).await?.unwrap();
+        assert_eq!(list_id, "1");
+        assert_eq!(ts.name, "a");
+        assert_eq!(ts.task.len(), 1);
+        assert!(cache
+            .get_task_by_handle(handle::TaskHandle(a.0 + b.0))
+            .await?
+            .is_none());
+
+        // After a move, the copy left in the old list is deleted.
+        let copy = |list_id: &str, deleted: bool| {
+            sqlx::query(
+                "INSERT INTO taskseries(list_id, taskseries_id, data)
+                 SELECT ?1, taskseries_id, data FROM taskseries
+                 WHERE list_id = '1' AND taskseries_id = 'a';
+                 INSERT INTO tasks(list_id, taskseries_id, task_id, deleted, data)
+                 SELECT ?1, taskseries_id, task_id, ?2, data FROM tasks
+                 WHERE list_id = '1' AND task_id = 'a';",
+            )
+            .bind(list_id.to_string())
+            .bind(deleted)
+            .execute(&cache.pool)
+        };
+        copy("2", true).await?;
+        let (list_id, _) = cache.get_task_by_handle(a).await?.unwrap();
+        assert_eq!(list_id, "1");
+        copy("3", false).await?;
+        assert!(cache.get_task_by_handle(a).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handle_before_sync() -> Result<(), anyhow::Error> {
+        use mockito::Matcher;
+
+        let series = r#"{"id":"2","created":"2026-10-18T09:00:00Z","modified":"2026-10-18T09:00:00Z","name":"Online","source":"js","url":"","location_id":"","tags":[],"participants":[],"notes":[],"parent_task_id":"","task":[{"id":"3","due":"","has_due_time":"0","added":"2026-10-18T09:00:00Z","completed":"","deleted":"","priority":"N","postponed":"0","estimate":""}]}"#;
+        let tasks = format!(r#"{{"rev":"1","list":[{{"id":"1","taskseries":[{series}]}}]}}"#);
+        let mut server = mockito::Server::new_async().await;
+        let method = |name: &str| Matcher::UrlEncoded("method".into(), name.into());
+        let mut mocks = vec![];
+        for (name, body) in [
+            ("rtm.tasks.getList", format!(r#""tasks":{tasks}"#)),
+            ("rtm.lists.getList", r#""lists":{"list":[]}"#.into()),
+            ("rtm.settings.getList", r#""settings":{}"#.into()),
+        ] {
+            mocks.push(
+                server
+                    .mock("GET", "/")
+                    .match_query(method(name))
+                    .with_body(format!(r#"{{"rsp":{{"stat":"ok",{body}}}}}"#))
+                    .create_async()
+                    .await,
+            );
         }
-        Ok(result)
+        let mut cache = test_cache().await;
+        cache.api = API::new_test("key".into(), "secret".into(), server);
+        cache.api.token = Some("token".into());
+
+        // A handle shown for an online result, before the cache has it.
+        let online: crate::RTMTasks = serde_json::from_str(&tasks)?;
+        let handles = cache.assign_handles(&online).await?;
+        let handle = handles[&("2".to_string(), "3".to_string())];
+        assert_eq!(
+            cache.handle_task_ids(handle).await?,
+            Some(("2".to_string(), "3".to_string()))
+        );
+        assert!(cache.get_task_by_handle(handle).await?.is_none());
+
+        cache.sync().await?;
+        let (list_id, ts) = cache.get_task_by_handle(handle).await?.unwrap();
+        assert_eq!(list_id, "1");
+        assert_eq!(ts.name, "Online");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timezone() -> Result<(), anyhow::Error> {
+        let cache = test_cache().await;
+        assert_eq!(cache.timezone().await?, crate::system_timezone());
+        sqlx::query("INSERT INTO account_settings(id, timezone) VALUES(1, 'Australia/Sydney')")
+            .execute(&cache.pool)
+            .await?;
+        assert_eq!(cache.timezone().await?, chrono_tz::Australia::Sydney);
+        let context = cache.filter_context().await?;
+        assert_eq!(context.now.timezone(), chrono_tz::Australia::Sydney);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_owner() {
+        let mut cache = test_cache().await;
+        // Unknown users aren't checked.
+        TaskCache::check_owner(&cache.pool, &cache.api)
+            .await
+            .unwrap();
+
+        let user = |id: &str| crate::User {
+            id: id.into(),
+            username: "user".into(),
+            fullname: "A User".into(),
+        };
+        cache.api.user = Some(user("1"));
+        TaskCache::check_owner(&cache.pool, &cache.api)
+            .await
+            .unwrap();
+        TaskCache::check_owner(&cache.pool, &cache.api)
+            .await
+            .unwrap();
+        cache.api.user = Some(user("2"));
+        assert!(matches!(
+            TaskCache::check_owner(&cache.pool, &cache.api).await,
+            Err(super::CacheError::WrongUser { cached, current }) if cached == "1" && current == "2"
+        ));
     }
 }
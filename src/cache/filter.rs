@@ -2,316 +2,305 @@
 
 use std::{borrow::Cow, collections::HashMap};
 
-use anyhow::{anyhow, bail};
-use chrono::{Datelike, Local, NaiveDate, TimeDelta, TimeZone};
+use super::sql::{SqlExpr, SqlValue};
+use crate::filter::add_days;
+pub use crate::filter::{Filter, RtmDate, RtmFilter};
+use anyhow::{anyhow, bail, Context as _};
+use chrono::{
+    DateTime, Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc,
+};
 use nom::{
     branch::alt,
     bytes::complete::{tag, tag_no_case, take_while_m_n},
     character::complete::{alpha1, digit1, multispace0, multispace1, none_of, space1},
-    combinator::{fail, map, map_res, recognize},
+    combinator::{fail, map, map_res, opt, recognize},
     error::ParseError,
     multi::{many0, separated_list1},
     sequence::delimited,
     Mode, Parser,
 };
 
-/// Represent a date from an RTM filter
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
-pub enum RtmDate {
-    /// A time relative to the current time
-    RelativeTime(chrono::TimeDelta),
-    /// A day relative to today
-    RelativeDay(i32),
-    /// A time relative to the start of a day
-    RelativeDayStart(i32),
-    /// A fixed date.
-    AbsoluteDate(chrono::NaiveDate),
-    /// A fixed date and time
-    AbsoluteDatetime(chrono::NaiveDateTime),
-    /// The given time either today or tomorrow (if we've passed it today).
-    NextTime(chrono::NaiveTime),
-    /// A month/day indicating the next one coming.
-    /// Both month and day start at 1.
-    NextDate { month: u8, day: u8 },
-    /// A month/day indicating the next one coming.
-    /// Both month and day start at 1.  Indicates the
-    /// time at the beginning of the day.
-    NextDateStart { month: u8, day: u8 },
-    /// A month/day indicating the next one coming.
-    /// Both month and day start at 1.  Indicates the
-    /// time at the end of the day.
-    NextDateEnd { month: u8, day: u8 },
+/// The UTC instant of the start of `date` in the time zone `tz`.  If
+/// midnight doesn't exist on that day (a DST change at midnight), this is
+/// the first valid time after it.
+pub(crate) fn local_day_start<Tz: TimeZone>(tz: &Tz, date: NaiveDate) -> DateTime<Utc> {
+    local_instant(tz, date.and_time(NaiveTime::MIN))
+}
+
+/// Convert a local date and time in `tz` to a UTC instant.  Ambiguous
+/// times resolve to the earliest; times skipped by a DST change move
+/// forward to the first valid time.
+fn local_instant<Tz: TimeZone>(tz: &Tz, dt: NaiveDateTime) -> DateTime<Utc> {
+    let mut dt = dt;
+    loop {
+        if let Some(t) = tz.from_local_datetime(&dt).earliest() {
+            return t.with_timezone(&Utc);
+        }
+        // DST gaps are whole multiples of 15 minutes in practice.
+        dt += TimeDelta::minutes(15);
+    }
+}
+
+/// A date from a filter converted to UTC instants, for comparing against
+/// due (or start) times in the database.
+#[derive(Debug, PartialEq, Eq)]
+struct SqlBound {
+    /// The bound itself, compared against times which have a time of day.
+    instant: DateTime<Utc>,
+    /// The start of the local day containing `instant`, compared against
+    /// date-only times (which are stored as the start of their local day).
+    day_start: DateTime<Utc>,
+}
+
+impl SqlBound {
+    fn new<Tz: TimeZone>(instant: DateTime<Utc>, tz: &Tz) -> SqlBound {
+        let day_start = local_day_start(tz, instant.with_timezone(tz).date_naive());
+        SqlBound { instant, day_start }
+    }
+
+    /// The values to bind for a comparison with a time column, for the
+    /// cases where it has a time and is date-only respectively.
+    fn binds(&self) -> [SqlValue; 2] {
+        [SqlValue::Time(self.instant), SqlValue::Time(self.day_start)]
+    }
+}
+
+/// Make a `LIKE` pattern matching `s` anywhere, escaping any wildcards
+/// with `\`.
+fn like_pattern(s: &str) -> String {
+    let mut pattern = "%".to_string();
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
 }
 
 impl RtmDate {
-    /// Convert to a time suitable for use in SQL statements.
-    fn to_sql_date(self, context: &FilterContext) -> String {
-        match self {
-            RtmDate::RelativeTime(time_delta) => (context.now + time_delta).to_rfc3339(),
-            RtmDate::RelativeDay(offset) => {
-                let d = if offset >= 0 {
-                    context.now.date_naive() + chrono::Days::new(offset as u64)
-                } else {
-                    context.now.date_naive() - chrono::Days::new(offset.unsigned_abs() as u64)
-                };
-                d.format("%Y-%m-%d").to_string()
-            }
-            RtmDate::RelativeDayStart(offset) => {
-                let d = if offset >= 0 {
-                    context.now.date_naive() + chrono::Days::new(offset as u64)
-                } else {
-                    context.now.date_naive() - chrono::Days::new(offset.unsigned_abs() as u64)
-                };
-                d.format("%Y-%m-%dT00:00:00").to_string()
-            }
-            RtmDate::NextDate { month, day } => {
-                let today = context.now.date_naive();
-                let m32 = month as u32;
-                let day32 = day as u32;
-                let d = if today.month() > m32 || ((today.month() == m32) && (today.day() > day32))
-                {
-                    NaiveDate::from_ymd_opt(today.year() + 1, m32, day32).unwrap()
-                } else {
-                    NaiveDate::from_ymd_opt(today.year(), m32, day32).unwrap()
-                };
-                d.format("%Y-%m-%d").to_string()
+    /// The next occurrence (from today, inclusive) of a month/day.
+    fn next_date(today: NaiveDate, month: u8, day: u8) -> NaiveDate {
+        let m32 = month as u32;
+        let day32 = day as u32;
+        let this_year = if today.month() > m32 || ((today.month() == m32) && (today.day() > day32))
+        {
+            today.year() + 1
+        } else {
+            today.year()
+        };
+        // Skip forward to the next leap year for 29 Feb.
+        (this_year..)
+            .find_map(|y| NaiveDate::from_ymd_opt(y, m32, day32))
+            .unwrap()
+    }
+
+    /// Convert to UTC instants for comparing in SQL statements, using the
+    /// time zone and current time from the context.
+    fn to_sql_bound<Tz: TimeZone>(self, context: &FilterContext<Tz>) -> SqlBound {
+        let tz = context.now.timezone();
+        let today = context.now.date_naive();
+        let instant = match self {
+            RtmDate::RelativeTime(time_delta) => (context.now.clone() + time_delta).to_utc(),
+            RtmDate::RelativeDay(offset) | RtmDate::RelativeDayStart(offset) => {
+                local_day_start(&tz, add_days(today, offset))
             }
-            RtmDate::NextDateStart { month, day } => {
-                let today = context.now.date_naive();
-                let m32 = month as u32;
-                let day32 = day as u32;
-                let d = if today.month() > m32 || ((today.month() == m32) && (today.day() > day32))
-                {
-                    NaiveDate::from_ymd_opt(today.year() + 1, m32, day32).unwrap()
-                } else {
-                    NaiveDate::from_ymd_opt(today.year(), m32, day32).unwrap()
-                };
-                d.format("%Y-%m-%dT00:00:00").to_string()
+            RtmDate::NextDate { month, day } | RtmDate::NextDateStart { month, day } => {
+                local_day_start(&tz, RtmDate::next_date(today, month, day))
             }
             RtmDate::NextDateEnd { month, day } => {
-                let today = context.now.date_naive();
-                let m32 = month as u32;
-                let day32 = day as u32;
-                let d = if today.month() > m32 || ((today.month() == m32) && (today.day() > day32))
-                {
-                    NaiveDate::from_ymd_opt(today.year() + 1, m32, day32).unwrap()
-                } else {
-                    NaiveDate::from_ymd_opt(today.year(), m32, day32).unwrap()
-                };
-                d.format("%Y-%m-%dT23:59:59").to_string()
+                local_day_start(&tz, add_days(RtmDate::next_date(today, month, day), 1))
             }
             RtmDate::NextTime(t) => {
-                let n_today = context.now.with_time(t).unwrap();
-                let nt = if n_today > context.now {
+                let n_today = local_instant(&tz, today.and_time(t));
+                if n_today > context.now.to_utc() {
                     n_today
                 } else {
-                    n_today + TimeDelta::days(1)
-                };
-                nt.to_rfc3339()
+                    local_instant(&tz, add_days(today, 1).and_time(t))
+                }
             }
-            RtmDate::AbsoluteDate(d) => d.format("%Y-%m-%d").to_string(),
-            RtmDate::AbsoluteDatetime(dt) => Local.from_local_datetime(&dt).unwrap().to_rfc3339(),
-        }
-    }
-
-    /// Convert a date to a time at the start of the day
-    /// Dates with time are not affected.
-    fn start_of_day(&self) -> Self {
-        use RtmDate::*;
-        match self {
-            RelativeDay(offs) => RelativeDayStart(*offs),
-            AbsoluteDate(d) => AbsoluteDatetime(d.and_hms_opt(0, 0, 0).unwrap()),
-            NextDate { month, day } => NextDateStart {
-                month: *month,
-                day: *day,
-            },
-            // If we have a time, nothing changes.
-            d @ (RelativeTime(_)
-            | RelativeDayStart(_)
-            | AbsoluteDatetime(_)
-            | NextTime(_)
-            | NextDateStart { .. }
-            | NextDateEnd { .. }) => *d,
-        }
-    }
-
-    /// Convert a date to a time at the end of the day
-    /// Dates with time are not affected.
-    fn end_of_day(&self) -> RtmDate {
-        use RtmDate::*;
-        match self {
-            RelativeDay(offs) => RelativeDayStart(*offs + 1),
-            AbsoluteDate(d) => AbsoluteDatetime(d.and_hms_opt(23, 59, 59).unwrap()),
-            NextDate { month, day } => NextDateEnd {
-                month: *month,
-                day: *day,
-            },
-            // If we have a time, nothing changes.
-            d @ (RelativeTime(_)
-            | RelativeDayStart(_)
-            | AbsoluteDatetime(_)
-            | NextTime(_)
-            | NextDateStart { .. }
-            | NextDateEnd { .. }) => *d,
-        }
+            RtmDate::AbsoluteDate(d) => local_day_start(&tz, d),
+            RtmDate::AbsoluteDatetime(dt) => local_instant(&tz, dt),
+        };
+        SqlBound::new(instant, &tz)
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
-/// An RTM Filter expression
-pub enum RtmFilter {
-    /// Match on the whether the task is completed or not.
-    Complete(bool),
-    /// Match on the contents of the name.
-    Name(String),
-    /// Match on the contents of the name.
-    List(String),
-    /// Match on a tag.
-    Tag(String),
-    /// Match item with no due date
-    DueNever,
-    /// Match value due before a time
-    DueBefore(RtmDate),
-    /// Match value due before a time
-    DueWithin(RtmDate, RtmDate),
-    /// Start within a day, or no start date
-    Start(Option<chrono::NaiveDate>),
-    /// Start before a certain time
-    StartBefore(RtmDate),
-    /// Match all of the sub expressions
-    And(Vec<RtmFilter>),
-    /// Match all of the sub expressions
-    Or(Vec<RtmFilter>),
-    /// Negated filter
-    Not(Box<RtmFilter>),
-    /// Given by
-    GivenBy(String),
-}
-
 /// Context required when interpreting filters
-#[derive(Default)]
-pub struct FilterContext {
+pub struct FilterContext<Tz: TimeZone = Local> {
     /// Mapping from list names to list id
     pub lists_name_to_id: HashMap<String, String>,
-    /// The current time
-    pub now: chrono::DateTime<Local>,
+    /// Saved filters (including smart lists) by name, which `list:NAME`
+    /// refers to in preference to a real list.
+    pub saved_filters: HashMap<String, String>,
+    /// The current time, in the user's time zone.  Relative dates such as
+    /// "today" are interpreted in this time zone.
+    pub now: chrono::DateTime<Tz>,
+}
+
+impl Default for FilterContext<Local> {
+    fn default() -> Self {
+        FilterContext {
+            lists_name_to_id: Default::default(),
+            saved_filters: Default::default(),
+            now: Local::now(),
+        }
+    }
 }
 
 impl RtmFilter {
-    /// Return a SQL expression for a where clause, and some values to bind.
-    /// The values should correspond to '?' markers in the expression.
-    pub(crate) fn to_sqlite_where_clause(
+    /// Replace any `list:NAME` where `NAME` is a saved filter (or smart
+    /// list) with that filter, recursively, using
+    /// [crate::filter::expand_saved].  Returns an error if a saved filter
+    /// refers back to itself, or the result can't be parsed.
+    pub fn expand_saved(
+        &self,
+        saved: &HashMap<String, String>,
+    ) -> Result<RtmFilter, anyhow::Error> {
+        let text = self.to_string();
+        let expanded = crate::filter::expand_saved(&text, saved)?;
+        if expanded == text {
+            return Ok(self.clone());
+        }
+        parse_filter(&expanded).with_context(|| format!("Invalid saved filter in {expanded:?}"))
+    }
+
+    /// Return a SQL expression for a where clause, with any values from
+    /// the filter as bound parameters.
+    pub(crate) fn to_sqlite_where_clause<Tz: TimeZone>(
+        &self,
+        context: &FilterContext<Tz>,
+    ) -> Result<SqlExpr, anyhow::Error> {
+        self.expand_saved(&context.saved_filters)?
+            .where_clause(context)
+    }
+
+    fn where_clause<Tz: TimeZone>(
         &self,
-        context: &FilterContext,
-    ) -> Result<(String, Vec<String>), anyhow::Error> {
+        context: &FilterContext<Tz>,
+    ) -> Result<SqlExpr, anyhow::Error> {
         let result = match self {
             RtmFilter::Complete(val) => {
                 if *val {
-                    (r#"jsonb_extract(t.data, "$.completed") <> """#.to_string(), Vec::new())
+                    SqlExpr::new("jsonb_extract(t.data, '$.completed') <> ''")
                 } else {
-                    (r#"jsonb_extract(t.data, "$.completed") = """#.to_string(), Vec::new())
+                    SqlExpr::new("jsonb_extract(t.data, '$.completed') = ''")
                 }
             }
-            RtmFilter::Name(s) => {
-                (r#"jsonb_extract(ts.data, "$.name") LIKE ?"#.into(),
-                vec![format!("%{s}%")])
-            }
-            RtmFilter::Tag(s) => {
-                (r#"EXISTS (SELECT * FROM json_each(jsonb_extract(ts.data,'$.tags')) WHERE json_each.value = ?)"#.into(), vec![s.to_string()])
-            }
-            RtmFilter::And(rtm_filters) => {
-                let mut result = String::new();
-                let mut binds = Vec::new();
-                for filt in rtm_filters {
-                    result.push('(');
-                    let (sub_where, sub_binds) = filt.to_sqlite_where_clause(context)?;
-                    result += &sub_where;
-                    binds.extend(sub_binds);
-
-                    result.push_str(") AND ");
-                }
-                debug_assert!(result.ends_with(") AND "));
-                for _ in 0..5 {
-                    // Remove the last " AND "
-                    result.pop().unwrap();
-                }
-                (result, binds)
-            }
-            RtmFilter::Or(rtm_filters) => {
-                let mut result = String::new();
-                let mut binds = Vec::new();
-                for filt in rtm_filters {
-                    result.push('(');
-                    let (sub_where, sub_binds) = filt.to_sqlite_where_clause(context)?;
-                    result += &sub_where;
-                    binds.extend(sub_binds);
-                    result.push_str(") OR ");
-                }
-                debug_assert!(result.ends_with(") OR "));
-                for _ in 0..4 {
-                    // Remove the last " OR "
-                    result.pop().unwrap();
-                }
-                (result, binds)
-            }
-            RtmFilter::DueNever => {
-                (r#"t.due_time IS NULL"#.into(), vec![])
-            }
-            RtmFilter::DueBefore(time) => {
-                (r#"t.due_time IS NOT NULL AND t.due_time < ?"#.into(),
-                    vec![time.to_sql_date(context)])
-            }
-            RtmFilter::DueWithin(from, to) => {
-                (format!(
-                    r#"t.due_time IS NOT NULL AND t.due_time < "{}" AND t.due_time >= "{}""#,
-                    to.to_sql_date(context),
-                    from.to_sql_date(context)
-                ), Vec::new())
-            }
+            RtmFilter::Name(s) => SqlExpr::with_binds(
+                r"jsonb_extract(ts.data, '$.name') LIKE ? ESCAPE '\'",
+                [like_pattern(s).into()],
+            ),
+            RtmFilter::Tag(s) => SqlExpr::with_binds(
+                "EXISTS (SELECT * FROM json_each(jsonb_extract(ts.data, '$.tags.tag')) WHERE json_each.value = ?)",
+                [s.as_str().into()],
+            ),
+            RtmFilter::And(rtm_filters) => SqlExpr::join(
+                rtm_filters
+                    .iter()
+                    .map(|filt| filt.where_clause(context))
+                    .collect::<Result<Vec<_>, _>>()?,
+                " AND ",
+            ),
+            RtmFilter::Or(rtm_filters) => SqlExpr::join(
+                rtm_filters
+                    .iter()
+                    .map(|filt| filt.where_clause(context))
+                    .collect::<Result<Vec<_>, _>>()?,
+                " OR ",
+            ),
+            RtmFilter::DueNever => SqlExpr::new("t.due_time IS NULL"),
+            RtmFilter::DueBefore(time) => SqlExpr::with_binds(
+                "t.due_time IS NOT NULL AND ((t.has_due_time AND t.due_time < ?) OR (NOT t.has_due_time AND t.due_time < ?))",
+                time.to_sql_bound(context).binds(),
+            ),
+            RtmFilter::DueWithin(from, to) => SqlExpr::with_binds(
+                "t.due_time IS NOT NULL AND ((t.has_due_time AND t.due_time < ?) OR (NOT t.has_due_time AND t.due_time < ?)) AND ((t.has_due_time AND t.due_time >= ?) OR (NOT t.has_due_time AND t.due_time >= ?))",
+                to.to_sql_bound(context)
+                    .binds()
+                    .into_iter()
+                    .chain(from.to_sql_bound(context).binds()),
+            ),
             RtmFilter::Start(starttime) => {
                 if starttime.is_none() {
-                    (r#"jsonb_extract(t.data, "$.start") == """#.into(), Vec::new())
+                    SqlExpr::new("jsonb_extract(t.data, '$.start') = ''")
                 } else {
                     bail!("Relative start: not supported");
                 }
             }
-            RtmFilter::StartBefore(starttime) => {
-                (r#"jsonb_extract(t.data, "$.start") <> "" AND jsonb_extract(t.data, "$.start") < ?"#.into(),
-                    vec![starttime.to_sql_date(context)])
-            }
+            RtmFilter::StartBefore(starttime) => SqlExpr::with_binds(
+                "jsonb_extract(t.data, '$.start') <> '' AND ((jsonb_extract(t.data, '$.has_start_time') = '1' AND datetime(jsonb_extract(t.data, '$.start')) < ?) OR (jsonb_extract(t.data, '$.has_start_time') <> '1' AND datetime(jsonb_extract(t.data, '$.start')) < ?))",
+                starttime.to_sql_bound(context).binds(),
+            ),
             RtmFilter::Not(filt) => {
-                let (clause, binds) = filt.to_sqlite_where_clause(context)?;
-                (format!("NOT {}", clause), binds)
+                let mut expr = SqlExpr::new("NOT ");
+                expr.push_expr(filt.where_clause(context)?);
+                expr
             }
-            RtmFilter::List(listname) => {
-                match context.lists_name_to_id.get(listname) {
-                    Some(id) => {
-                        let id: u64 = id.parse()?;
-
-                        (r#"t.list_id = ?"#.into(), vec![id.to_string()])
-                    }
-                    None => {
-                        log::warn!("Invalid list name: {listname}");
-                        // Since the list doesn't exist, this is equivalent to false.
-                        ("FALSE".into(), Vec::new())
-                    }
+            RtmFilter::List(listname) => match context.lists_name_to_id.get(listname) {
+                Some(id) => SqlExpr::with_binds("t.list_id = ?", [id.as_str().into()]),
+                None => {
+                    log::warn!("Invalid list name: {listname}");
+                    // Since the list doesn't exist, this is equivalent to false.
+                    SqlExpr::new("FALSE")
                 }
-            }
+            },
             RtmFilter::GivenBy(_name) => {
                 // This is not possible to work out from the API.
-                (r#"(jsonb_extract(ts.data, "$.participants.contact") is not NULL AND json_array_length(jsonb_extract(ts.data, "$.participants.contact")) >= 1)"#.into(), Vec::new())
+                SqlExpr::new("(jsonb_extract(ts.data, '$.participants.contact') IS NOT NULL AND json_array_length(jsonb_extract(ts.data, '$.participants.contact')) >= 1)")
+            }
+            RtmFilter::Source(source) => SqlExpr::with_binds(
+                "jsonb_extract(ts.data, '$.source') = ?",
+                [source.as_str().into()],
+            ),
+            RtmFilter::IsSubtask(val) => {
+                if *val {
+                    SqlExpr::new("IFNULL(jsonb_extract(ts.data, '$.parent_task_id'), '') <> ''")
+                } else {
+                    SqlExpr::new("IFNULL(jsonb_extract(ts.data, '$.parent_task_id'), '') = ''")
+                }
+            }
+            RtmFilter::HasSubtasks(val) => {
+                let mut expr = SqlExpr::new(if *val { "" } else { "NOT " });
+                expr.push(
+                    "EXISTS (SELECT * FROM taskseries sub_ts, tasks sub_t USING (list_id, taskseries_id) WHERE sub_t.deleted != TRUE AND jsonb_extract(sub_ts.data, '$.parent_task_id') = t.task_id)",
+                );
+                expr
             }
         };
         Ok(result)
     }
 }
 
+/// Parsing and expanding [Filter]s, which needs the `cache` feature.
+impl Filter {
+    /// Parse a filter from an RTM filter string.
+    pub fn parse(filter: &str) -> Result<Filter, anyhow::Error> {
+        parse_filter(filter).map(Filter::from)
+    }
+
+    /// Replace references to saved filters with their definitions.  See
+    /// [RtmFilter::expand_saved].
+    pub fn expand_saved(&self, saved: &HashMap<String, String>) -> Result<Filter, anyhow::Error> {
+        self.as_ref().expand_saved(saved).map(Filter::from)
+    }
+}
+
 #[derive(Debug)]
 struct Term<'a> {
     key: &'a str,
     value: Cow<'a, str>,
 }
 impl<'a> Term<'a> {
+    fn bool_value(&self) -> Result<bool, anyhow::Error> {
+        match self.value.as_ref() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => bail!("Expected true or false for {}, not {other}", self.key),
+        }
+    }
+
     fn to_filt(&self) -> Result<RtmFilter, anyhow::Error> {
         let filt = match self.key {
             "status" => match self.value.as_ref() {
@@ -325,10 +314,17 @@ impl<'a> Term<'a> {
                 RtmFilter::DueBefore(date.start_of_day())
             }
             "dueWithin" => {
-                if self.value.as_ref() == "1 day of today" {
-                    RtmFilter::DueWithin(RtmDate::RelativeDay(0), RtmDate::RelativeDay(1))
-                } else {
+                let Some((period, anchor)) = self.value.split_once(" of ") else {
                     bail!("Unknown date format {}", self.value);
+                };
+                match (parse_date(period)?, parse_date(anchor)?) {
+                    (RtmDate::RelativeDay(days), RtmDate::RelativeDay(start)) if days > 0 => {
+                        RtmFilter::DueWithin(
+                            RtmDate::RelativeDay(start),
+                            RtmDate::RelativeDay(start + days),
+                        )
+                    }
+                    _ => bail!("Unknown date format {}", self.value),
                 }
             }
             "due" => {
@@ -348,15 +344,14 @@ impl<'a> Term<'a> {
                 }
             }
             "startBefore" => {
-                let v = self.value.as_ref();
-                if v == "1 hour" {
-                    RtmFilter::StartBefore(RtmDate::RelativeTime(chrono::TimeDelta::hours(1)))
-                } else {
-                    bail!("Unsupported time format {}", self.value);
-                }
+                let date = parse_date(&self.value)?;
+                RtmFilter::StartBefore(date.start_of_day())
             }
             "list" => RtmFilter::List(self.value.to_string()),
             "tag" => RtmFilter::Tag(self.value.to_string()),
+            "source" => RtmFilter::Source(self.value.to_string()),
+            "isSubtask" => RtmFilter::IsSubtask(self.bool_value()?),
+            "hasSubtasks" => RtmFilter::HasSubtasks(self.bool_value()?),
             "givenBy" => {
                 // We don't seem to have enough information in the API
                 // yet.
@@ -587,32 +582,45 @@ fn parse_date_hhmm(s: &str) -> nom::IResult<&str, RtmDate> {
     ))
 }
 
+// An optional " ago" suffix, which negates a relative date.
+fn parse_ago(s: &str) -> nom::IResult<&str, i64> {
+    let (rest, ago) = opt((multispace1, tag_no_case("ago"))).parse(s)?;
+    Ok((rest, if ago.is_some() { -1 } else { 1 }))
+}
+
 fn parse_date_days(s: &str) -> nom::IResult<&str, RtmDate> {
-    let (rest, count) = map_res(digit1, str::parse).parse(s)?;
+    let (rest, count): (_, i32) = map_res(digit1, str::parse).parse(s)?;
     let (rest, _) = multispace0(rest)?;
     let (rest, _) = alt((tag_no_case("days"), tag_no_case("day"))).parse(rest)?;
-    Ok((rest, RtmDate::RelativeDay(count)))
+    let (rest, sign) = parse_ago(rest)?;
+    Ok((rest, RtmDate::RelativeDay(count * sign as i32)))
 }
 
 fn parse_date_weeks(s: &str) -> nom::IResult<&str, RtmDate> {
     let (rest, count): (_, i32) = map_res(digit1, str::parse).parse(s)?;
     let (rest, _) = multispace0(rest)?;
     let (rest, _) = alt((tag_no_case("weeks"), tag_no_case("week"))).parse(rest)?;
-    Ok((rest, RtmDate::RelativeDay(count * 7)))
+    let (rest, sign) = parse_ago(rest)?;
+    Ok((rest, RtmDate::RelativeDay(count * 7 * sign as i32)))
 }
 
 fn parse_date_mins(s: &str) -> nom::IResult<&str, RtmDate> {
-    let (rest, count) = map_res(digit1, str::parse).parse(s)?;
+    let (rest, count): (_, i64) = map_res(digit1, str::parse).parse(s)?;
     let (rest, _) = multispace0(rest)?;
     let (rest, _) = alt((tag_no_case("mins"), tag_no_case("min"))).parse(rest)?;
-    Ok((rest, RtmDate::RelativeTime(TimeDelta::minutes(count))))
+    let (rest, sign) = parse_ago(rest)?;
+    Ok((
+        rest,
+        RtmDate::RelativeTime(TimeDelta::minutes(count * sign)),
+    ))
 }
 
 fn parse_date_hours(s: &str) -> nom::IResult<&str, RtmDate> {
-    let (rest, count) = map_res(digit1, str::parse).parse(s)?;
+    let (rest, count): (_, i64) = map_res(digit1, str::parse).parse(s)?;
     let (rest, _) = multispace0(rest)?;
     let (rest, _) = alt((tag_no_case("hours"), tag_no_case("hour"))).parse(rest)?;
-    Ok((rest, RtmDate::RelativeTime(TimeDelta::hours(count))))
+    let (rest, sign) = parse_ago(rest)?;
+    Ok((rest, RtmDate::RelativeTime(TimeDelta::hours(count * sign))))
 }
 
 fn parse_date(s: &str) -> Result<RtmDate, anyhow::Error> {
@@ -684,6 +692,7 @@ fn parse_expr(filter: &str) -> nom::IResult<&'_ str, SubExpr<'_>> {
     result
 }
 
+/// Parse an RTM filter string into an [RtmFilter].
 pub fn parse_filter(filter: &str) -> Result<RtmFilter, anyhow::Error> {
     log::trace!("parse_filter({filter:?})");
     let (rest, expr) =
@@ -702,8 +711,13 @@ pub fn parse_filter(filter: &str) -> Result<RtmFilter, anyhow::Error> {
 mod tests {
     use crate::cache::filter::RtmDate;
 
-    use super::{parse_date, parse_filter, RtmFilter};
-    use chrono::FixedOffset;
+    use std::collections::HashMap;
+
+    use super::{
+        add_days, local_day_start, local_instant, parse_date, parse_filter, Filter, FilterContext,
+        RtmFilter, SqlExpr, SqlValue,
+    };
+    use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
     use RtmFilter::*;
 
     fn log_init() {
@@ -753,6 +767,11 @@ mod tests {
             ("(NOT name:a)", Not(Box::new(Name("a".into())))),
             ("NOT (name:a)", Not(Box::new(Name("a".into())))),
             ("list:foo", List("foo".into())),
+            ("isSubtask:true", IsSubtask(true)),
+            (
+                "hasSubtasks:false AND NOT isSubtask:false",
+                And(vec![HasSubtasks(false), Not(Box::new(IsSubtask(false)))]),
+            ),
             (r#"list:"Hello world""#, List("Hello world".into())),
         ] {
             eprintln!("Testing expr: {s}");
@@ -764,12 +783,13 @@ mod tests {
     #[test]
     fn test_filter_sql() -> Result<(), anyhow::Error> {
         log_init();
-        let context = super::FilterContext {
+        let context = super::FilterContext::<chrono::Local> {
             lists_name_to_id: [
                 ("foo".to_string(), "12345678".to_string()),
                 ("My List".to_string(), "87654321".to_string()),
             ]
             .into(),
+            saved_filters: Default::default(),
             now: chrono::DateTime::<FixedOffset>::parse_from_rfc3339("2000-01-01T01:02:03Z")
                 .unwrap()
                 .into(),
@@ -778,28 +798,71 @@ mod tests {
         for (filt_s, expected, expected_binds) in &[
             (
                 "status:completed",
-                r#"jsonb_extract(t.data, "$.completed") <> """#,
+                "jsonb_extract(t.data, '$.completed') <> ''",
                 &[][..],
             ),
-            ("list:foo", r#"t.list_id = ?"#, &["12345678"]),
-            (r#"list:"My List""#, r#"t.list_id = ?"#, &["87654321"]),
+            ("list:foo", "t.list_id = ?", &["12345678"]),
+            (r#"list:"My List""#, "t.list_id = ?", &["87654321"]),
+            ("list:missing", "FALSE", &[]),
             (
                 "name:foo",
-                r#"jsonb_extract(ts.data, "$.name") LIKE ?"#,
+                r"jsonb_extract(ts.data, '$.name') LIKE ? ESCAPE '\'",
                 &["%foo%"],
             ),
+            (
+                r#"name:"50%_off\""#,
+                r"jsonb_extract(ts.data, '$.name') LIKE ? ESCAPE '\'",
+                &[r"%50\%\_off\\%"],
+            ),
+            (
+                "NOT name:a OR name:b",
+                r"(NOT (jsonb_extract(ts.data, '$.name') LIKE ? ESCAPE '\')) OR (jsonb_extract(ts.data, '$.name') LIKE ? ESCAPE '\')",
+                &["%a%", "%b%"],
+            ),
         ] {
             let filt = parse_filter(filt_s)?;
-            let (clause, binds) = filt.to_sqlite_where_clause(&context)?;
-            assert_eq!(&clause, expected);
-            assert_eq!(binds.len(), expected_binds.len());
-            for (b, eb) in binds.iter().zip(expected_binds.iter()) {
-                assert_eq!(b, eb);
-            }
+            let clause = filt.to_sqlite_where_clause(&context)?;
+            assert_eq!(clause.sql(), *expected);
+            let expected_binds = expected_binds
+                .iter()
+                .map(|&b| SqlValue::from(b))
+                .collect::<Vec<_>>();
+            assert_eq!(clause.binds(), expected_binds);
         }
         Ok(())
     }
 
+    #[test]
+    fn test_expand_saved() -> Result<(), anyhow::Error> {
+        log_init();
+        let saved: HashMap<String, String> = [
+            ("Work", "tag:work"),
+            ("Urgent work", "list:Work AND dueBefore:tomorrow"),
+            ("Loop A", "list:\"Loop B\" OR tag:a"),
+            ("Loop B", "NOT list:\"Loop A\""),
+            ("Broken", "tag:"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(
+            parse_filter(r#"list:"Urgent work" OR list:Inbox"#)?.expand_saved(&saved)?,
+            parse_filter("(tag:work AND dueBefore:tomorrow) OR list:Inbox")?
+        );
+        let err = parse_filter(r#"name:x AND list:"Loop B""#)?
+            .expand_saved(&saved)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Saved filter refers to itself: Loop B -> Loop A -> Loop B"
+        );
+        let err = parse_filter("list:Broken")?
+            .expand_saved(&saved)
+            .unwrap_err();
+        assert_eq!(err.to_string(), r#"Invalid saved filter in "(tag:)""#);
+        Ok(())
+    }
+
     #[test]
     fn test_parse_dates() -> Result<(), anyhow::Error> {
         log_init();
@@ -836,10 +899,387 @@ mod tests {
             ("3 days", RtmDate::RelativeDay(3)),
             ("1 week", RtmDate::RelativeDay(7)),
             ("3 weeks", RtmDate::RelativeDay(21)),
+            ("2 days ago", RtmDate::RelativeDay(-2)),
+            (
+                "3 hours ago",
+                RtmDate::RelativeTime(chrono::TimeDelta::hours(-3)),
+            ),
         ] {
             eprintln!("Testing date: {s:?}");
             assert_eq!(parse_date(s)?, *d);
         }
         Ok(())
     }
+
+    #[test]
+    fn test_filter_round_trip() -> Result<(), anyhow::Error> {
+        log_init();
+        for filt in [
+            Filter::incomplete()
+                .and(Filter::tag("work")?)
+                .and(Filter::due_before(RtmDate::RelativeDay(-3))),
+            Filter::list("My List")?.or(!Filter::tag("home")?),
+            Filter::due_within(RtmDate::RelativeDay(0), RtmDate::RelativeDay(1)),
+            Filter::due_before(RtmDate::AbsoluteDate(
+                chrono::NaiveDate::from_ymd_opt(2000, 1, 2).unwrap(),
+            )),
+            Filter::start_before(RtmDate::RelativeTime(chrono::TimeDelta::hours(1)))
+                .and(Filter::completed().or(Filter::source("api:k:1")?)),
+            Filter::is_subtask(true).or(!Filter::has_subtasks(false)),
+        ] {
+            let s = filt.to_string();
+            eprintln!("Round trip: {s}");
+            assert_eq!(Filter::parse(&s)?, filt);
+        }
+        Ok(())
+    }
+
+    /// A set of tasks in an in-memory database, for checking which match
+    /// a filter.
+    struct TestDb {
+        runtime: tokio::runtime::Runtime,
+        pool: sqlx::SqlitePool,
+    }
+
+    impl TestDb {
+        fn new() -> TestDb {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let pool = runtime.block_on(async {
+                let pool = sqlx::sqlite::SqlitePoolOptions::new()
+                    .max_connections(1)
+                    .connect("sqlite::memory:")
+                    .await
+                    .unwrap();
+                sqlx::migrate!().run(&pool).await.unwrap();
+                pool
+            });
+            TestDb { runtime, pool }
+        }
+
+        /// Add a task with a due date (as UTC) which is date-only if
+        /// `has_time` is false.
+        fn add_task(&self, id: &str, due: DateTime<Utc>, has_time: bool) {
+            self.add_task_data(
+                id,
+                serde_json::json!({"id": id, "name": id}),
+                serde_json::json!({
+                    "id": id,
+                    "due": due.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+                    "has_due_time": if has_time { "1" } else { "0" },
+                    "completed": "",
+                }),
+            );
+        }
+
+        /// Add a subtask (or top level task if `parent` is empty), which
+        /// is completed if `completed` is true.
+        fn add_subtask(&self, id: &str, parent: &str, completed: bool) {
+            self.add_task_data(
+                id,
+                serde_json::json!({"id": id, "name": id, "parent_task_id": parent}),
+                serde_json::json!({
+                    "id": id,
+                    "due": "",
+                    "has_due_time": "0",
+                    "completed": if completed { "2000-01-01T00:00:00Z" } else { "" },
+                }),
+            );
+        }
+
+        /// Add an undated task with a name, tags and source.
+        fn add_named_task(&self, id: &str, name: &str, tags: &[&str], source: &str) {
+            self.add_task_data(
+                id,
+                serde_json::json!({
+                    "id": id,
+                    "name": name,
+                    "tags": if tags.is_empty() {
+                        serde_json::json!([])
+                    } else {
+                        serde_json::json!({"tag": tags})
+                    },
+                    "source": source,
+                }),
+                serde_json::json!({
+                    "id": id,
+                    "due": "",
+                    "has_due_time": "0",
+                    "completed": "",
+                    "start": "",
+                }),
+            );
+        }
+
+        fn add_task_data(&self, id: &str, ts_data: serde_json::Value, t_data: serde_json::Value) {
+            self.runtime.block_on(async {
+                sqlx::query("INSERT INTO taskseries(list_id, taskseries_id, data) VALUES('1', ?, jsonb(?))")
+                    .bind(id)
+                    .bind(ts_data.to_string())
+                    .execute(&self.pool)
+                    .await
+                    .unwrap();
+                sqlx::query("INSERT INTO tasks(list_id, taskseries_id, task_id, data) VALUES('1', ?, ?, jsonb(?))")
+                    .bind(id)
+                    .bind(id)
+                    .bind(t_data.to_string())
+                    .execute(&self.pool)
+                    .await
+                    .unwrap();
+            });
+        }
+
+        /// Return the ids of tasks matching the filter.
+        fn matching<Tz: TimeZone>(&self, filter: &str, context: &FilterContext<Tz>) -> Vec<String> {
+            let clause = parse_filter(filter)
+                .unwrap()
+                .to_sqlite_where_clause(context)
+                .unwrap();
+            let mut query = SqlExpr::new(
+                "SELECT t.task_id FROM taskseries ts, tasks t USING (list_id, taskseries_id) WHERE ",
+            );
+            query.push_expr(clause).push(" ORDER BY t.task_id");
+            self.runtime.block_on(async {
+                query
+                    .query_as::<(String,)>()
+                    .fetch_all(&self.pool)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(id,)| id)
+                    .collect()
+            })
+        }
+
+        /// Return the number of tasks in the database.
+        fn count(&self) -> i64 {
+            self.runtime.block_on(async {
+                sqlx::query_scalar("SELECT COUNT(*) FROM tasks")
+                    .fetch_one(&self.pool)
+                    .await
+                    .unwrap()
+            })
+        }
+    }
+
+    #[test]
+    fn test_filter_subtasks() {
+        log_init();
+        let db = TestDb::new();
+        db.add_subtask("a", "", false);
+        db.add_subtask("a1", "a", false);
+        db.add_subtask("a11", "a1", true);
+        db.add_subtask("b", "", false);
+        db.add_subtask("c", "", false);
+        db.add_subtask("c1", "c", false);
+        let context = FilterContext::default();
+        for (filter, expected) in &[
+            ("isSubtask:true", &["a1", "a11", "c1"][..]),
+            ("isSubtask:false", &["a", "b", "c"]),
+            ("hasSubtasks:true", &["a", "a1", "c"]),
+            ("hasSubtasks:false", &["a11", "b", "c1"]),
+            ("isSubtask:true AND hasSubtasks:true", &["a1"]),
+            ("NOT isSubtask:true AND NOT hasSubtasks:true", &["b"]),
+        ] {
+            eprintln!("Filter: {filter}");
+            assert_eq!(db.matching(filter, &context), *expected);
+        }
+    }
+
+    #[test]
+    fn test_filter_execute() {
+        log_init();
+        let db = TestDb::new();
+        db.add_task_data(
+            "done",
+            serde_json::json!({"id": "done", "name": "Finished", "tags": [], "source": "js"}),
+            serde_json::json!({"id": "done", "due": "", "has_due_time": "0", "completed": "2000-01-01T00:00:00Z", "start": ""}),
+        );
+        db.add_named_task("a", "Buy milk", &["shopping", "home"], "js");
+        db.add_named_task("b", "50% off sale", &["shopping"], "api:k:1");
+        db.add_named_task("c", "It's a_name", &[], "email");
+        db.add_named_task(
+            "d",
+            "'); DROP TABLE tasks; --",
+            &["'); DROP TABLE tasks; --"],
+            "js",
+        );
+        let context = FilterContext {
+            lists_name_to_id: [("Inbox".to_string(), "1".to_string())].into(),
+            saved_filters: [
+                ("Shops".to_string(), "tag:shopping".to_string()),
+                (
+                    "Bargains".to_string(),
+                    r#"list:Shops AND name:"%""#.to_string(),
+                ),
+            ]
+            .into(),
+            now: Utc.with_ymd_and_hms(2000, 1, 1, 12, 0, 0).unwrap(),
+        };
+        for (filter, expected) in &[
+            ("status:completed", &["done"][..]),
+            ("status:incomplete", &["a", "b", "c", "d"]),
+            ("name:milk", &["a"]),
+            (r#"name:"%""#, &["b"]),
+            (r#"name:"50% off""#, &["b"]),
+            (r#"name:"_""#, &["c"]),
+            (r#"name:"'""#, &["c", "d"]),
+            ("tag:shopping", &["a", "b"]),
+            ("tag:home AND tag:shopping", &["a"]),
+            ("NOT tag:shopping", &["c", "d", "done"]),
+            ("NOT tag:shopping AND name:it", &["c"]),
+            ("tag:shopping OR name:finished", &["a", "b", "done"]),
+            ("list:Inbox AND NOT status:completed", &["a", "b", "c", "d"]),
+            ("list:Missing", &[]),
+            ("list:Shops", &["a", "b"]),
+            ("list:Bargains", &["b"]),
+            ("NOT list:Bargains AND list:Inbox", &["a", "c", "d", "done"]),
+            (r#"source:"api:k:1""#, &["b"]),
+            ("due:never AND name:buy", &["a"]),
+            ("start:never", &["a", "b", "c", "d", "done"]),
+            ("startBefore:today", &[]),
+            (r#"tag:"'); DROP TABLE tasks; --""#, &["d"]),
+            (r#"name:"x'); DROP TABLE tasks; --""#, &[]),
+            (r#"tag:"x' OR '1'='1""#, &[]),
+        ] {
+            eprintln!("Filter: {filter}");
+            assert_eq!(db.matching(filter, &context), *expected);
+        }
+        assert_eq!(db.count(), 5);
+    }
+
+    const ZONES: &[chrono_tz::Tz] = &[
+        chrono_tz::UTC,
+        chrono_tz::Europe::London,
+        chrono_tz::America::New_York,
+        chrono_tz::America::Sao_Paulo,
+        chrono_tz::Australia::Sydney,
+        chrono_tz::Asia::Kolkata,
+        chrono_tz::Asia::Tehran,
+        chrono_tz::Pacific::Chatham,
+    ];
+
+    /// Check that date-only and timed tasks due `offset` days from today
+    /// match the day-based filters they should, as of `now`.
+    fn check_due_days(now: DateTime<chrono_tz::Tz>, offset: i32, time: NaiveTime) {
+        let tz = now.timezone();
+        let today = now.date_naive();
+        let due_day = add_days(today, offset);
+        let db = TestDb::new();
+        db.add_task("date", local_day_start(&tz, due_day), false);
+        db.add_task("timed", local_instant(&tz, due_day.and_time(time)), true);
+        let context = FilterContext {
+            lists_name_to_id: Default::default(),
+            saved_filters: Default::default(),
+            now,
+        };
+        let both = vec!["date".to_string(), "timed".to_string()];
+        for (filter, expected) in [
+            ("dueBefore:today", offset < 0),
+            ("due:today", offset <= 0),
+            ("dueBefore:tomorrow", offset <= 0),
+            (r#"dueWithin:"1 day of today""#, offset == 0),
+            (
+                r#"dueWithin:"2 days of tomorrow""#,
+                offset == 1 || offset == 2,
+            ),
+            (r#"dueBefore:"2 days ago""#, offset < -2),
+        ] {
+            let expected = if expected { both.clone() } else { vec![] };
+            assert_eq!(
+                db.matching(filter, &context),
+                expected,
+                "{filter} at {now} with task due {due_day} {time}"
+            );
+        }
+    }
+
+    /// All the UTC instants at which the zone's offset changes in a year.
+    fn transitions(tz: chrono_tz::Tz, year: i32) -> Vec<DateTime<Utc>> {
+        use chrono::Offset;
+        let start = Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap();
+        let offset_at = |t: DateTime<Utc>| t.with_timezone(&tz).offset().fix();
+        (1..365 * 24 * 4)
+            .map(|i| start + TimeDelta::minutes(15 * i))
+            .filter(|t| offset_at(*t) != offset_at(*t - TimeDelta::minutes(15)))
+            .collect()
+    }
+
+    proptest::proptest! {
+        #![proptest_config(proptest::prelude::ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_due_days_any_time(
+            tz in proptest::sample::select(ZONES),
+            now_secs in 1_420_070_400i64..1_893_456_000,
+            offset in -4i32..=4,
+            time_mins in 0u32..24 * 60,
+        ) {
+            let now = DateTime::from_timestamp(now_secs, 0).unwrap().with_timezone(&tz);
+            let time = NaiveTime::from_num_seconds_from_midnight_opt(time_mins * 60, 0).unwrap();
+            check_due_days(now, offset, time);
+        }
+
+        #[test]
+        fn prop_due_days_around_dst(
+            tz in proptest::sample::select(ZONES),
+            year in 2015i32..2030,
+            which in 0usize..4,
+            delta_mins in -48i64 * 60..48 * 60,
+            offset in -2i32..=2,
+            time_mins in 0u32..24 * 60,
+        ) {
+            let changes = transitions(tz, year);
+            let base = if changes.is_empty() {
+                Utc.with_ymd_and_hms(year, 3, 1, 0, 0, 0).unwrap()
+            } else {
+                changes[which % changes.len()]
+            };
+            let now = (base + TimeDelta::minutes(delta_mins)).with_timezone(&tz);
+            let time = NaiveTime::from_num_seconds_from_midnight_opt(time_mins * 60, 0).unwrap();
+            check_due_days(now, offset, time);
+        }
+    }
+
+    #[test]
+    fn test_sql_bound_dst() {
+        log_init();
+        // The UK clocks went forward at 01:00 UTC on 31 March 2024, so
+        // that day is only 23 hours long.
+        let tz = chrono_tz::Europe::London;
+        let now = Utc
+            .with_ymd_and_hms(2024, 3, 31, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&tz);
+        let context = FilterContext {
+            lists_name_to_id: Default::default(),
+            saved_filters: Default::default(),
+            now,
+        };
+        let bound = RtmDate::RelativeDay(1).to_sql_bound(&context);
+        assert_eq!(
+            bound.binds(),
+            [
+                SqlValue::Time(Utc.with_ymd_and_hms(2024, 3, 31, 23, 0, 0).unwrap()),
+                SqlValue::Time(Utc.with_ymd_and_hms(2024, 3, 31, 23, 0, 0).unwrap()),
+            ]
+        );
+        let bound = RtmDate::RelativeTime(TimeDelta::hours(2)).to_sql_bound(&context);
+        assert_eq!(
+            bound.binds(),
+            [
+                SqlValue::Time(Utc.with_ymd_and_hms(2024, 3, 31, 14, 0, 0).unwrap()),
+                SqlValue::Time(Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap()),
+            ]
+        );
+        // Tehran used to change clocks at midnight, so 21 March 2008 started
+        // at 01:00.
+        let tz = chrono_tz::Asia::Tehran;
+        assert_eq!(
+            local_day_start(&tz, NaiveDate::from_ymd_opt(2008, 3, 21).unwrap()),
+            Utc.with_ymd_and_hms(2008, 3, 20, 20, 30, 0).unwrap()
+        );
+    }
 }
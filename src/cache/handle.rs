@@ -0,0 +1,55 @@
+//! Short handles for tasks.
+
+use anyhow::bail;
+
+/// A short handle for a task, such as `4f`, which is easier to type than
+/// the list, task series and task ids.  Handles are assigned by the
+/// [TaskCache](super::TaskCache) and are never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaskHandle(pub i64);
+
+impl std::fmt::Display for TaskHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut digits = Vec::new();
+        let mut n = self.0.unsigned_abs();
+        loop {
+            digits.push(std::char::from_digit((n % 36) as u32, 36).unwrap());
+            n /= 36;
+            if n == 0 {
+                break;
+            }
+        }
+        let s: String = digits.iter().rev().collect();
+        f.pad(&s)
+    }
+}
+
+impl std::str::FromStr for TaskHandle {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<TaskHandle, crate::Error> {
+        match i64::from_str_radix(s, 36) {
+            Ok(n) if n > 0 && !s.starts_with(['+', '-']) => Ok(TaskHandle(n)),
+            _ => bail!("Invalid task handle {s:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TaskHandle;
+
+    #[test]
+    fn test_handle_round_trip() {
+        for n in [1, 9, 10, 35, 36, 159, 46655, 1 << 40] {
+            let handle = TaskHandle(n);
+            assert_eq!(handle.to_string().parse::<TaskHandle>().unwrap(), handle);
+        }
+        assert_eq!(TaskHandle(159).to_string(), "4f");
+        assert_eq!("4F".parse::<TaskHandle>().unwrap(), TaskHandle(159));
+        assert_eq!(format!("{:>3}", TaskHandle(1)), "  1");
+        for bad in ["", "0", "-4", "+4", "4.", "zzzzzzzzzzzzzzzzz"] {
+            assert!(bad.parse::<TaskHandle>().is_err(), "{bad:?}");
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! The log of changes made through the cache, for undoing them later.
+
+use chrono::{DateTime, Utc};
+
+use crate::RTMTimeline;
+
+/// How long a timeline is reused for before a new one is created.
+pub const TIMELINE_MAX_AGE: chrono::TimeDelta = chrono::TimeDelta::hours(12);
+
+/// A transaction recorded by [TaskCache::record_transaction](super::TaskCache::record_transaction).
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    /// The local id of this entry.
+    pub id: i64,
+    /// The timeline the transaction was made on, needed to undo it.
+    pub timeline: RTMTimeline,
+    /// The RTM transaction id.
+    pub transaction_id: String,
+    /// A description of the change.
+    pub description: String,
+    /// When the change was made.
+    pub created: DateTime<Utc>,
+    /// True if the change has been undone.
+    pub undone: bool,
+    /// For transactions recorded together by
+    /// [TaskCache::record_batch](super::TaskCache::record_batch), the id of
+    /// the first of them.  A batch counts as one change.
+    pub batch: Option<i64>,
+}
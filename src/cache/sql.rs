@@ -0,0 +1,201 @@
+//! A small builder for SQL queries with bound parameters.
+//!
+//! The SQL text of a [SqlExpr] can only be extended with `&'static str`
+//! fragments; any values which come from outside (filter arguments, ids,
+//! dates) must be added as bound parameters.
+
+use chrono::{DateTime, Utc};
+use sqlx::{sqlite::SqliteRow, FromRow, Sqlite};
+
+/// A value to bind to a `?` parameter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SqlValue {
+    /// A text value
+    Text(String),
+    /// An integer value
+    Integer(i64),
+    /// A boolean value
+    Bool(bool),
+    /// A point in time, bound in the same format as SQLite's `datetime()`.
+    Time(DateTime<Utc>),
+}
+
+impl From<&str> for SqlValue {
+    fn from(s: &str) -> SqlValue {
+        SqlValue::Text(s.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(s: String) -> SqlValue {
+        SqlValue::Text(s)
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(i: i64) -> SqlValue {
+        SqlValue::Integer(i)
+    }
+}
+
+impl From<bool> for SqlValue {
+    fn from(b: bool) -> SqlValue {
+        SqlValue::Bool(b)
+    }
+}
+
+impl From<DateTime<Utc>> for SqlValue {
+    fn from(t: DateTime<Utc>) -> SqlValue {
+        SqlValue::Time(t)
+    }
+}
+
+/// Format a time in the same way as SQLite's `datetime()`, so that they
+/// can be compared.
+pub(crate) fn to_sql_time(t: &DateTime<Utc>) -> String {
+    t.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// A fragment of SQL along with the values for its `?` parameters.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct SqlExpr {
+    sql: String,
+    binds: Vec<SqlValue>,
+}
+
+impl SqlExpr {
+    /// An expression with no parameters.
+    pub(crate) fn new(sql: &'static str) -> SqlExpr {
+        SqlExpr {
+            sql: sql.into(),
+            binds: Vec::new(),
+        }
+    }
+
+    /// An expression whose `?` parameters are bound to `binds` in order.
+    pub(crate) fn with_binds(
+        sql: &'static str,
+        binds: impl IntoIterator<Item = SqlValue>,
+    ) -> SqlExpr {
+        let result = SqlExpr {
+            sql: sql.into(),
+            binds: binds.into_iter().collect(),
+        };
+        debug_assert_eq!(result.sql.matches('?').count(), result.binds.len());
+        result
+    }
+
+    /// Append some SQL text.
+    pub(crate) fn push(&mut self, sql: &'static str) -> &mut Self {
+        self.sql.push_str(sql);
+        self
+    }
+
+    /// Append a `?` parameter bound to `value`.
+    pub(crate) fn push_bind(&mut self, value: impl Into<SqlValue>) -> &mut Self {
+        self.sql.push('?');
+        self.binds.push(value.into());
+        self
+    }
+
+    /// Append another expression, in parentheses.
+    pub(crate) fn push_expr(&mut self, expr: SqlExpr) -> &mut Self {
+        self.sql.push('(');
+        self.sql.push_str(&expr.sql);
+        self.sql.push(')');
+        self.binds.extend(expr.binds);
+        self
+    }
+
+    /// Join expressions with an operator such as `" AND "`, with each
+    /// in parentheses.
+    pub(crate) fn join(exprs: impl IntoIterator<Item = SqlExpr>, op: &'static str) -> SqlExpr {
+        let mut result = SqlExpr::default();
+        for (i, expr) in exprs.into_iter().enumerate() {
+            if i > 0 {
+                result.push(op);
+            }
+            result.push_expr(expr);
+        }
+        result
+    }
+
+    /// The SQL text.
+    pub(crate) fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// The values bound to the parameters, in order.
+    #[cfg(test)]
+    pub(crate) fn binds(&self) -> &[SqlValue] {
+        &self.binds
+    }
+
+    /// Build a query from this expression with all the values bound.
+    pub(crate) fn query_as<'q, O>(
+        &'q self,
+    ) -> sqlx::query::QueryAs<'q, Sqlite, O, <Sqlite as sqlx::Database>::Arguments<'q>>
+    where
+        O: for<'r> FromRow<'r, SqliteRow>,
+    {
+        debug_assert_eq!(self.sql.matches('?').count(), self.binds.len());
+        let mut query = sqlx::query_as(&self.sql);
+        for bind in &self.binds {
+            query = match bind {
+                SqlValue::Text(s) => query.bind(s),
+                SqlValue::Integer(i) => query.bind(i),
+                SqlValue::Bool(b) => query.bind(b),
+                SqlValue::Time(t) => query.bind(to_sql_time(t)),
+            };
+        }
+        query
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SqlExpr, SqlValue};
+    use chrono::{TimeZone, Utc};
+
+    #[tokio::test]
+    async fn test_typed_binds() -> Result<(), anyhow::Error> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await?;
+        let mut expr = SqlExpr::new("SELECT ");
+        expr.push_bind("it's")
+            .push(", ")
+            .push_bind(42)
+            .push(", ")
+            .push_bind(true)
+            .push(", ")
+            .push_expr(SqlExpr::with_binds(
+                "datetime('2000-01-02T03:04:05Z') = ?",
+                [SqlValue::Time(
+                    Utc.with_ymd_and_hms(2000, 1, 2, 3, 4, 5).unwrap(),
+                )],
+            ));
+        assert_eq!(
+            expr.sql(),
+            "SELECT ?, ?, ?, (datetime('2000-01-02T03:04:05Z') = ?)"
+        );
+        let row: (String, i64, bool, bool) = expr.query_as().fetch_one(&pool).await?;
+        assert_eq!(row, ("it's".to_string(), 42, true, true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_join() {
+        let expr = SqlExpr::join(
+            [
+                SqlExpr::with_binds("a = ?", ["x".into()]),
+                SqlExpr::new("b"),
+                SqlExpr::with_binds("c = ?", [SqlValue::Integer(1)]),
+            ],
+            " OR ",
+        );
+        assert_eq!(expr.sql(), "(a = ?) OR (b) OR (c = ?)");
+        assert_eq!(expr.binds(), &["x".into(), SqlValue::Integer(1)]);
+    }
+}
@@ -0,0 +1,340 @@
+//! Productivity statistics over the tasks in the cache.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Days, NaiveDate, TimeDelta, TimeZone, Utc, Weekday};
+use serde::Serialize;
+
+use super::filter::local_day_start;
+use crate::sort::{parse_estimate, TaskEntry};
+
+/// Which statistics [TaskCache::stats](super::TaskCache::stats) reports.
+#[derive(Debug, Clone)]
+pub struct StatsOptions {
+    /// The first local day of the period.
+    pub from: NaiveDate,
+    /// The last local day of the period.
+    pub to: NaiveDate,
+    /// How many of the longest-outstanding tasks to include.
+    pub longest: usize,
+}
+
+/// A count for a day, or for the week starting on that (Monday) day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DayCount {
+    /// The local date.
+    pub date: NaiveDate,
+    /// The number of tasks.
+    pub count: usize,
+}
+
+/// How many tasks in a list or with a tag were completed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CompletionRate {
+    /// The list or tag name.
+    pub name: String,
+    /// Tasks completed during the period.
+    pub completed: usize,
+    /// Tasks completed during the period or still incomplete.
+    pub total: usize,
+    /// `completed / total`.
+    pub rate: f64,
+}
+
+/// An incomplete task, for the longest outstanding.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutstandingTask {
+    /// The task's name.
+    pub name: String,
+    /// The name of its list.
+    pub list: String,
+    /// When it was added.
+    pub added: DateTime<Utc>,
+    /// How many whole days it has been outstanding.
+    pub age_days: i64,
+}
+
+/// Statistics over a period, from [TaskCache::stats](super::TaskCache::stats).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Stats {
+    /// The first local day of the period.
+    pub from: NaiveDate,
+    /// The last local day of the period.
+    pub to: NaiveDate,
+    /// Tasks completed on each day of the period.
+    pub completed_per_day: Vec<DayCount>,
+    /// Tasks completed in each week (starting on Monday) overlapping the
+    /// period, counting only the days within it.
+    pub completed_per_week: Vec<DayCount>,
+    /// Tasks which were overdue at the end of each day.
+    pub overdue_per_day: Vec<DayCount>,
+    /// Tasks with a due date completed on time during the period.
+    pub on_time: usize,
+    /// Tasks with a due date completed late during the period.
+    pub late: usize,
+    /// The average number of hours late of those completed late.
+    pub average_lateness_hours: Option<f64>,
+    /// The completion rate for each list.
+    pub lists: Vec<CompletionRate>,
+    /// The completion rate for each tag.
+    pub tags: Vec<CompletionRate>,
+    /// The total estimate, in minutes, of tasks completed during the period.
+    pub estimate_completed_minutes: i64,
+    /// The total estimate, in minutes, of incomplete tasks.
+    pub estimate_outstanding_minutes: i64,
+    /// The incomplete tasks added longest ago, oldest first.
+    pub longest_outstanding: Vec<OutstandingTask>,
+}
+
+/// When a task becomes late: its due time, or the end of its due day if it
+/// has no time.
+fn deadline<Tz: TimeZone>(entry: &TaskEntry<'_>, tz: &Tz) -> Option<DateTime<Utc>> {
+    let due = entry.task.due?;
+    if entry.task.has_due_time {
+        Some(due)
+    } else {
+        let date = due.with_timezone(tz).date_naive();
+        Some(local_day_start(tz, date + Days::new(1)))
+    }
+}
+
+fn rates(counts: BTreeMap<String, (usize, usize)>) -> Vec<CompletionRate> {
+    counts
+        .into_iter()
+        .map(|(name, (completed, total))| CompletionRate {
+            name,
+            completed,
+            total,
+            rate: completed as f64 / total as f64,
+        })
+        .collect()
+}
+
+impl Stats {
+    /// Calculate statistics from `entries` as of `now`, using its time zone
+    /// for day boundaries.  `list_names` maps list ids to names.
+    pub fn compute<Tz: TimeZone>(
+        entries: &[TaskEntry<'_>],
+        list_names: &HashMap<String, String>,
+        options: &StatsOptions,
+        now: &DateTime<Tz>,
+    ) -> Stats {
+        let tz = now.timezone();
+        let now = now.with_timezone(&Utc);
+        let period_start = local_day_start(&tz, options.from);
+        let period_end = local_day_start(&tz, options.to + Days::new(1)).min(now);
+        let days: Vec<NaiveDate> = options
+            .from
+            .iter_days()
+            .take_while(|d| *d <= options.to)
+            .collect();
+
+        let entries: Vec<&TaskEntry<'_>> = entries
+            .iter()
+            .filter(|e| e.task.deleted.is_none())
+            .collect();
+        let completed_in_period = |entry: &TaskEntry<'_>| {
+            entry
+                .task
+                .completed
+                .filter(|c| *c >= period_start && *c < period_end)
+        };
+
+        let mut per_day: BTreeMap<NaiveDate, usize> = days.iter().map(|d| (*d, 0)).collect();
+        let mut lateness = Vec::new();
+        let mut on_time = 0;
+        let mut lists: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        let mut tags: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+        let mut estimate_completed = TimeDelta::zero();
+        let mut estimate_outstanding = TimeDelta::zero();
+        let mut outstanding = Vec::new();
+        for entry in &entries {
+            let completed = completed_in_period(entry);
+            if entry.task.completed.is_some() && completed.is_none() {
+                // Completed outside the period.
+                continue;
+            }
+            let estimate = parse_estimate(&entry.task.estimate).unwrap_or_default();
+            let list = list_names
+                .get(entry.list_id)
+                .cloned()
+                .unwrap_or_else(|| entry.list_id.to_string());
+            let done = usize::from(completed.is_some());
+            let counts = lists.entry(list.clone()).or_default();
+            counts.0 += done;
+            counts.1 += 1;
+            for tag in &entry.series.tags {
+                let counts = tags.entry(tag.clone()).or_default();
+                counts.0 += done;
+                counts.1 += 1;
+            }
+            match completed {
+                Some(completed) => {
+                    *per_day
+                        .entry(completed.with_timezone(&tz).date_naive())
+                        .or_default() += 1;
+                    estimate_completed += estimate;
+                    match deadline(entry, &tz) {
+                        Some(deadline) if completed > deadline => {
+                            lateness.push(completed - deadline)
+                        }
+                        Some(_) => on_time += 1,
+                        None => (),
+                    }
+                }
+                None => {
+                    estimate_outstanding += estimate;
+                    if let Some(added) = entry.task.added {
+                        outstanding.push(OutstandingTask {
+                            name: entry.series.name.clone(),
+                            list,
+                            added,
+                            age_days: (now - added).num_days(),
+                        });
+                    }
+                }
+            }
+        }
+        outstanding.sort_by(|a, b| (a.added, &a.name).cmp(&(b.added, &b.name)));
+        outstanding.truncate(options.longest);
+
+        let mut per_week: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        for (date, count) in &per_day {
+            *per_week
+                .entry(date.week(Weekday::Mon).first_day())
+                .or_default() += count;
+        }
+
+        let overdue_per_day = days
+            .iter()
+            .map(|&date| {
+                let end = local_day_start(&tz, date + Days::new(1)).min(now);
+                let count = entries
+                    .iter()
+                    .filter(|e| e.task.added.is_none_or(|added| added < end))
+                    .filter(|e| e.task.completed.is_none_or(|done| done >= end))
+                    .filter(|e| deadline(e, &tz).is_some_and(|deadline| deadline < end))
+                    .count();
+                DayCount { date, count }
+            })
+            .collect();
+
+        let to_counts = |counts: BTreeMap<NaiveDate, usize>| {
+            counts
+                .into_iter()
+                .map(|(date, count)| DayCount { date, count })
+                .collect()
+        };
+        let average_lateness_hours = (!lateness.is_empty()).then(|| {
+            let total: TimeDelta = lateness.iter().sum();
+            total.num_seconds() as f64 / 3600.0 / lateness.len() as f64
+        });
+        Stats {
+            from: options.from,
+            to: options.to,
+            completed_per_day: to_counts(per_day),
+            completed_per_week: to_counts(per_week),
+            overdue_per_day,
+            on_time,
+            late: lateness.len(),
+            average_lateness_hours,
+            lists: rates(lists),
+            tags: rates(tags),
+            estimate_completed_minutes: estimate_completed.num_minutes(),
+            estimate_outstanding_minutes: estimate_outstanding.num_minutes(),
+            longest_outstanding: outstanding,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DayCount, Stats, StatsOptions};
+    use crate::RTMTasks;
+    use chrono::{NaiveDate, TimeZone};
+    use serde_json::Value;
+    use std::collections::HashMap;
+
+    /// A task series with a single task, with times as e.g. "2026-10-01"
+    /// (midnight UTC) or "" for none.
+    fn series(name: &str, tags: &[&str], added: &str, due: &str, completed: &str) -> Value {
+        let time = |s: &str| match s.len() {
+            0 => String::new(),
+            10 => format!("{s}T00:00:00Z"),
+            _ => s.to_string(),
+        };
+        crate::tests::series(
+            name,
+            serde_json::json!({"tags": {"tag": tags}}),
+            serde_json::json!({
+                "due": time(due),
+                "has_due_time": if due.len() > 10 { "1" } else { "0" },
+                "added": time(added), "completed": time(completed), "estimate": "PT30M"
+            }),
+        )
+    }
+
+    #[test]
+    fn test_stats() {
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [
+                    // Completed before the period.
+                    series("old", &[], "2026-09-01", "", "2026-09-02"),
+                    // Due on the 5th (all day) and completed late on the 6th.
+                    series("late", &["work"], "2026-10-01", "2026-10-05", "2026-10-06T12:00:00Z"),
+                    // Completed on time, on the last day of the due date.
+                    series("prompt", &["work"], "2026-10-01", "2026-10-06", "2026-10-06T22:00:00Z"),
+                    series("undated", &[], "2026-10-02", "", "2026-10-07"),
+                    series("oldest", &["work"], "2026-08-01", "2026-10-06", ""),
+                    series("newer", &[], "2026-10-06", "", ""),
+                ],
+            }, {
+                "id": "2",
+                "taskseries": [series("other", &["home"], "2026-10-03", "", "")],
+            }],
+        }))
+        .unwrap();
+        let list_names = HashMap::from([("1".to_string(), "Inbox".to_string())]);
+        let options = StatsOptions {
+            from: NaiveDate::from_ymd_opt(2026, 10, 5).unwrap(),
+            to: NaiveDate::from_ymd_opt(2026, 10, 7).unwrap(),
+            longest: 2,
+        };
+        let now = chrono::Utc
+            .with_ymd_and_hms(2026, 10, 7, 12, 0, 0)
+            .unwrap()
+            .with_timezone(&chrono_tz::UTC);
+        let stats = Stats::compute(&tasks.entries(), &list_names, &options, &now);
+
+        let counts = |counts: &[DayCount]| counts.iter().map(|c| c.count).collect::<Vec<_>>();
+        assert_eq!(counts(&stats.completed_per_day), [0, 2, 1]);
+        // Weeks starting on the 5th.
+        assert_eq!(counts(&stats.completed_per_week), [3]);
+        // Only "oldest", once its due day is over.
+        assert_eq!(counts(&stats.overdue_per_day), [0, 0, 1]);
+        assert_eq!((stats.on_time, stats.late), (1, 1));
+        assert_eq!(stats.average_lateness_hours, Some(12.0));
+
+        let rates: Vec<_> = stats
+            .lists
+            .iter()
+            .chain(&stats.tags)
+            .map(|r| (r.name.as_str(), r.completed, r.total))
+            .collect();
+        assert_eq!(
+            rates,
+            [("2", 0, 1), ("Inbox", 3, 5), ("home", 0, 1), ("work", 2, 3)]
+        );
+        assert_eq!(stats.estimate_completed_minutes, 90);
+        assert_eq!(stats.estimate_outstanding_minutes, 90);
+        let names: Vec<_> = stats
+            .longest_outstanding
+            .iter()
+            .map(|t| (t.name.as_str(), t.age_days))
+            .collect();
+        assert_eq!(names, [("oldest", 67), ("other", 4)]);
+    }
+}
@@ -0,0 +1,190 @@
+//! Tasks arranged by their subtask relationships.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    sort::{compare_tasks, Sort},
+    Task, TaskSeries,
+};
+
+/// A task along with its subtasks.
+#[derive(Debug, Clone)]
+pub struct TaskNode {
+    /// The id of the list containing the task.
+    pub list_id: String,
+    /// The task series, containing just this task.
+    pub taskseries: TaskSeries,
+    /// True if the task matched the filter, or false if it is only included
+    /// as an ancestor or descendant of one which did.
+    pub matched: bool,
+    /// The subtasks, ordered by name unless [TaskTree::sort] is used.
+    pub children: Vec<TaskNode>,
+}
+
+impl TaskNode {
+    /// The task itself.
+    pub fn task(&self) -> &Task {
+        &self.taskseries.task[0]
+    }
+
+    fn sort_key(&self) -> (&str, &str) {
+        (&self.taskseries.name, &self.task().id)
+    }
+}
+
+/// A set of tasks arranged as a tree by their subtask relationships.
+#[derive(Debug, Clone, Default)]
+pub struct TaskTree {
+    /// The top level tasks, ordered by name unless [TaskTree::sort] is used.
+    pub roots: Vec<TaskNode>,
+}
+
+impl TaskTree {
+    /// Arrange tasks (with no children yet) into a tree.  Tasks whose
+    /// parent isn't included become roots, as does one task from any loop
+    /// of parents.
+    pub(crate) fn build(nodes: Vec<TaskNode>) -> TaskTree {
+        let ids: HashSet<String> = nodes.iter().map(|n| n.task().id.clone()).collect();
+        let mut by_parent: HashMap<String, Vec<TaskNode>> = HashMap::new();
+        let mut roots = Vec::new();
+        for node in nodes {
+            match node.taskseries.parent_task_id.as_deref() {
+                Some(parent) if ids.contains(parent) && parent != node.task().id => {
+                    by_parent.entry(parent.to_string()).or_default().push(node);
+                }
+                _ => roots.push(node),
+            }
+        }
+
+        fn attach(node: &mut TaskNode, by_parent: &mut HashMap<String, Vec<TaskNode>>) {
+            if let Some(mut children) = by_parent.remove(&node.task().id) {
+                for child in &mut children {
+                    attach(child, by_parent);
+                }
+                children.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+                node.children = children;
+            }
+        }
+        for root in &mut roots {
+            attach(root, &mut by_parent);
+        }
+        // Anything left over is in a loop, which we break arbitrarily.
+        while let Some(parent) = by_parent.keys().next().cloned() {
+            for mut node in by_parent.remove(&parent).unwrap() {
+                attach(&mut node, &mut by_parent);
+                roots.push(node);
+            }
+        }
+        roots.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        TaskTree { roots }
+    }
+
+    /// Sort the roots and each task's children by `sorts`, and then by
+    /// name.
+    pub fn sort(&mut self, sorts: &[Sort]) {
+        fn sort_nodes(nodes: &mut [TaskNode], sorts: &[Sort]) {
+            nodes.sort_by(|a, b| {
+                compare_tasks(sorts, (&a.taskseries, a.task()), (&b.taskseries, b.task()))
+                    .then_with(|| a.sort_key().cmp(&b.sort_key()))
+            });
+            for node in nodes {
+                sort_nodes(&mut node.children, sorts);
+            }
+        }
+        sort_nodes(&mut self.roots, sorts);
+    }
+
+    /// Iterate over all the tasks depth first, along with their depth in
+    /// the tree (0 for the roots).
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &TaskNode)> {
+        let mut stack: Vec<(usize, &TaskNode)> = self.roots.iter().rev().map(|n| (0, n)).collect();
+        std::iter::from_fn(move || {
+            let (depth, node) = stack.pop()?;
+            stack.extend(node.children.iter().rev().map(|c| (depth + 1, c)));
+            Some((depth, node))
+        })
+    }
+
+    /// True if there are no tasks.
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TaskNode, TaskTree};
+
+    fn node(id: &str, parent: &str) -> TaskNode {
+        let taskseries = serde_json::from_value(crate::tests::series(
+            id,
+            serde_json::json!({"parent_task_id": parent}),
+            serde_json::json!({}),
+        ))
+        .unwrap();
+        TaskNode {
+            list_id: "1".into(),
+            taskseries,
+            matched: true,
+            children: Vec::new(),
+        }
+    }
+
+    fn shape(tree: &TaskTree) -> Vec<(usize, String)> {
+        tree.iter()
+            .map(|(depth, node)| (depth, node.task().id.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let tree = TaskTree::build(vec![
+            node("c", "b"),
+            node("b2", "a"),
+            node("b", "a"),
+            node("a", ""),
+            node("orphan", "missing"),
+            node("d", "c"),
+        ]);
+        assert_eq!(
+            shape(&tree),
+            [
+                (0, "a"),
+                (1, "b"),
+                (2, "c"),
+                (3, "d"),
+                (1, "b2"),
+                (0, "orphan")
+            ]
+            .map(|(d, id)| (d, id.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_sort_tree() {
+        let mut a = node("a", "");
+        a.taskseries.task[0].priority = "2".into();
+        let mut b1 = node("b1", "b");
+        b1.taskseries.task[0].priority = "1".into();
+        let mut tree = TaskTree::build(vec![a, node("b", ""), node("b2", "b"), b1]);
+        tree.sort(&["priority".parse().unwrap()]);
+        assert_eq!(
+            shape(&tree),
+            [(0, "a"), (0, "b"), (1, "b1"), (1, "b2")].map(|(d, id)| (d, id.to_string()))
+        );
+        tree.sort(&["-name".parse().unwrap()]);
+        assert_eq!(
+            shape(&tree),
+            [(0, "b"), (1, "b2"), (1, "b1"), (0, "a")].map(|(d, id)| (d, id.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_build_tree_loop() {
+        let tree = TaskTree::build(vec![node("x", "y"), node("y", "x"), node("z", "z")]);
+        let ids = shape(&tree);
+        assert_eq!(ids.len(), 3);
+        assert_eq!(tree.roots.len(), 2);
+        assert!(ids.contains(&(0, "z".to_string())));
+    }
+}
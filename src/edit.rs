@@ -0,0 +1,566 @@
+//! Editing a task as text.
+//!
+//! [TaskForm::from_entry] describes a task series as `field: value` lines
+//! followed by its notes, each starting with a `--- note` line:
+//!
+//! ```text
+//! name: Water plants
+//! list: Around the house
+//! due: 2026-10-20
+//! priority: 1
+//! tags: home, garden
+//! url:
+//! estimate: 15 minutes
+//! repeat: every FREQ=WEEKLY;INTERVAL=1
+//! --- note 123: Which plants
+//! The ones on the windowsill.
+//! ```
+//!
+//! After the text has been edited, [TaskForm::parse] reads it back and
+//! [TaskForm::changes] lists what is different, which [API::apply_task_edits]
+//! makes through the matching setters.  Values such as `due` and `estimate`
+//! are passed to RTM as written, so they may use any form RTM understands,
+//! such as "tomorrow 5pm".
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{anyhow, bail};
+use chrono::TimeZone;
+
+use crate::sort::{parse_estimate, TaskEntry};
+use crate::{RTMList, RTMTimeline, RTMTransaction, API};
+
+/// The comment at the top of the text, explaining the format.
+const HEADER: &str = "\
+# Edit the task, then save and quit.  Empty values are cleared, and lines
+# starting with # are ignored.  Each note starts with a \"--- note\" line;
+# delete a note's lines to delete it, or add \"--- note: Title\" to add one.
+";
+
+/// A note in a [TaskForm].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteForm {
+    /// The note id, or `None` for a new note.
+    pub id: Option<String>,
+    /// The title, which may be empty.
+    pub title: String,
+    /// The text.
+    pub text: String,
+}
+
+/// The editable fields of a task series, as text.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskForm {
+    /// The task's name.
+    pub name: String,
+    /// The list name.
+    pub list: String,
+    /// The due date, as `YYYY-MM-DD` or `YYYY-MM-DD HH:MM` when read from a
+    /// task.
+    pub due: String,
+    /// 1, 2, 3 or none.
+    pub priority: String,
+    /// The tags.
+    pub tags: Vec<String>,
+    /// The URL.
+    pub url: String,
+    /// The time estimate, such as "1 hour 30 minutes".
+    pub estimate: String,
+    /// How the task repeats: "every" or "after" followed by a rule.
+    pub repeat: String,
+    /// The notes.
+    pub notes: Vec<NoteForm>,
+}
+
+/// A change found by [TaskForm::changes].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TaskEdit {
+    /// Rename the task.
+    Name(String),
+    /// Move to the list with this name.
+    List(String),
+    /// Set the due date from text, or clear it if empty.
+    Due(String),
+    /// Set the priority ("1", "2", "3" or "N").
+    Priority(String),
+    /// Add tags.
+    AddTags(Vec<String>),
+    /// Remove tags.
+    RemoveTags(Vec<String>),
+    /// Set or clear the URL.
+    Url(String),
+    /// Set or clear the estimate.
+    Estimate(String),
+    /// Set or clear the recurrence.
+    Repeat(String),
+    /// Add a note.
+    AddNote {
+        /// The title.
+        title: String,
+        /// The text.
+        text: String,
+    },
+    /// Change a note.
+    EditNote {
+        /// The note id.
+        id: String,
+        /// The new title.
+        title: String,
+        /// The new text.
+        text: String,
+    },
+    /// Delete a note.
+    DeleteNote {
+        /// The note id.
+        id: String,
+        /// The title, for describing the change.
+        title: String,
+    },
+}
+
+impl fmt::Display for TaskEdit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_clear = |field: &str, value: &str| {
+            if value.is_empty() {
+                format!("Clear {field}")
+            } else {
+                format!("Set {field} {value:?}")
+            }
+        };
+        match self {
+            TaskEdit::Name(name) => write!(f, "Rename to {name:?}"),
+            TaskEdit::List(list) => write!(f, "Move to {list}"),
+            TaskEdit::Due(due) => f.write_str(&or_clear("due", due)),
+            TaskEdit::Priority(priority) => write!(f, "Set priority {priority}"),
+            TaskEdit::AddTags(tags) => write!(f, "Add tags {}", tags.join(", ")),
+            TaskEdit::RemoveTags(tags) => write!(f, "Remove tags {}", tags.join(", ")),
+            TaskEdit::Url(url) => f.write_str(&or_clear("URL", url)),
+            TaskEdit::Estimate(estimate) => f.write_str(&or_clear("estimate", estimate)),
+            TaskEdit::Repeat(repeat) => f.write_str(&or_clear("repeat", repeat)),
+            TaskEdit::AddNote { title, .. } => write!(f, "Add note {title:?}"),
+            TaskEdit::EditNote { title, .. } => write!(f, "Edit note {title:?}"),
+            TaskEdit::DeleteNote { title, .. } => write!(f, "Delete note {title:?}"),
+        }
+    }
+}
+
+/// Describe an estimate such as `PT1H30M` as "1 hour 30 minutes".
+fn describe_estimate(estimate: &str) -> String {
+    let Some(duration) = parse_estimate(estimate) else {
+        return estimate.to_string();
+    };
+    let minutes = duration.num_minutes();
+    let unit = |n: i64, unit: &str| match n {
+        1 => format!("1 {unit}"),
+        n => format!("{n} {unit}s"),
+    };
+    match (minutes / 60, minutes % 60) {
+        (0, m) => unit(m, "minute"),
+        (h, 0) => unit(h, "hour"),
+        (h, m) => format!("{} {}", unit(h, "hour"), unit(m, "minute")),
+    }
+}
+
+/// Parse a priority as written in the form.
+fn parse_priority(s: &str) -> Result<String, crate::Error> {
+    match s.to_lowercase().as_str() {
+        "1" | "2" | "3" => Ok(s.into()),
+        "" | "0" | "n" | "none" => Ok("N".into()),
+        _ => bail!("Priority must be 1, 2, 3 or none, not {s:?}"),
+    }
+}
+
+impl TaskForm {
+    /// Describe a task, using `list_names` (list id to name) and `tz` for
+    /// the due date.
+    pub fn from_entry<Tz: TimeZone>(
+        entry: &TaskEntry<'_>,
+        list_names: &HashMap<String, String>,
+        tz: &Tz,
+    ) -> TaskForm
+    where
+        Tz::Offset: fmt::Display,
+    {
+        let (series, task) = (entry.series, entry.task);
+        let due = match task.due {
+            None => String::new(),
+            Some(due) if task.has_due_time => {
+                due.with_timezone(tz).format("%Y-%m-%d %H:%M").to_string()
+            }
+            Some(due) => due.with_timezone(tz).format("%Y-%m-%d").to_string(),
+        };
+        let repeat = match &series.repeat {
+            None => String::new(),
+            Some(repeat) if repeat.every => format!("every {}", repeat.rule),
+            Some(repeat) => format!("after {}", repeat.rule),
+        };
+        TaskForm {
+            name: series.name.clone(),
+            list: list_names
+                .get(entry.list_id)
+                .cloned()
+                .unwrap_or_else(|| entry.list_id.to_string()),
+            due,
+            priority: match task.priority.as_str() {
+                "N" => "none".into(),
+                p => p.into(),
+            },
+            tags: series.tags.clone(),
+            url: series.url.clone(),
+            estimate: describe_estimate(&task.estimate),
+            repeat,
+            notes: series
+                .notes
+                .iter()
+                .map(|note| NoteForm {
+                    id: Some(note.id.clone()),
+                    title: note.title.clone(),
+                    text: note.text.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Parse the text written by [TaskForm]'s `Display` implementation.
+    pub fn parse(s: &str) -> Result<TaskForm, crate::Error> {
+        let mut form = TaskForm::default();
+        let mut name = None;
+        let mut lines = s.lines().enumerate().peekable();
+        while let Some((number, line)) = lines.next() {
+            let error = |e: &str| anyhow!("Line {}: {e}: {line:?}", number + 1);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix("--- note") {
+                let (id, title) = header
+                    .split_once(':')
+                    .ok_or_else(|| error("Expected \"--- note ID: Title\""))?;
+                let id = id.trim();
+                let mut text = Vec::new();
+                while let Some((_, line)) = lines.next_if(|(_, l)| !l.starts_with("--- note")) {
+                    text.push(line);
+                }
+                while text.last().is_some_and(|l| l.trim().is_empty()) {
+                    text.pop();
+                }
+                form.notes.push(NoteForm {
+                    id: (!id.is_empty()).then(|| id.to_string()),
+                    title: title.trim().to_string(),
+                    text: text.join("\n"),
+                });
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| error("Expected \"field: value\""))?;
+            let value = value.trim().to_string();
+            match key.trim() {
+                "name" => name = Some(value),
+                "list" => form.list = value,
+                "due" => form.due = value,
+                "priority" => form.priority = value,
+                "tags" => {
+                    form.tags = value
+                        .split([',', ' '])
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "url" => form.url = value,
+                "estimate" => form.estimate = value,
+                "repeat" => form.repeat = value,
+                _ => return Err(error("Unknown field")),
+            }
+        }
+        form.name = name.ok_or_else(|| anyhow!("The name is missing"))?;
+        if form.name.is_empty() {
+            bail!("The name can't be empty");
+        }
+        Ok(form)
+    }
+
+    /// List the changes needed to turn `original` into `self`.
+    pub fn changes(&self, original: &TaskForm) -> Result<Vec<TaskEdit>, crate::Error> {
+        let mut edits = Vec::new();
+        if self.name != original.name {
+            edits.push(TaskEdit::Name(self.name.clone()));
+        }
+        if self.list != original.list {
+            edits.push(TaskEdit::List(self.list.clone()));
+        }
+        if self.due != original.due {
+            edits.push(TaskEdit::Due(self.due.clone()));
+        }
+        let priority = parse_priority(&self.priority)?;
+        if priority != parse_priority(&original.priority)? {
+            edits.push(TaskEdit::Priority(priority));
+        }
+        let added: Vec<String> = (self.tags.iter())
+            .filter(|t| !original.tags.contains(t))
+            .cloned()
+            .collect();
+        if !added.is_empty() {
+            edits.push(TaskEdit::AddTags(added));
+        }
+        let removed: Vec<String> = (original.tags.iter())
+            .filter(|t| !self.tags.contains(t))
+            .cloned()
+            .collect();
+        if !removed.is_empty() {
+            edits.push(TaskEdit::RemoveTags(removed));
+        }
+        if self.url != original.url {
+            edits.push(TaskEdit::Url(self.url.clone()));
+        }
+        if self.estimate != original.estimate {
+            edits.push(TaskEdit::Estimate(self.estimate.clone()));
+        }
+        if self.repeat != original.repeat {
+            edits.push(TaskEdit::Repeat(self.repeat.clone()));
+        }
+        for note in &self.notes {
+            match &note.id {
+                None => edits.push(TaskEdit::AddNote {
+                    title: note.title.clone(),
+                    text: note.text.clone(),
+                }),
+                Some(id) => match original.notes.iter().find(|n| n.id == note.id) {
+                    None => bail!("No note with id {id}"),
+                    Some(old) if old.title == note.title && old.text == note.text => (),
+                    Some(_) => edits.push(TaskEdit::EditNote {
+                        id: id.clone(),
+                        title: note.title.clone(),
+                        text: note.text.clone(),
+                    }),
+                },
+            }
+        }
+        for old in &original.notes {
+            if !self.notes.iter().any(|n| n.id == old.id) {
+                edits.push(TaskEdit::DeleteNote {
+                    id: old.id.clone().unwrap_or_default(),
+                    title: old.title.clone(),
+                });
+            }
+        }
+        Ok(edits)
+    }
+}
+
+impl fmt::Display for TaskForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(HEADER)?;
+        writeln!(f, "name: {}", self.name)?;
+        writeln!(f, "list: {}", self.list)?;
+        writeln!(f, "due: {}", self.due)?;
+        writeln!(f, "priority: {}", self.priority)?;
+        writeln!(f, "tags: {}", self.tags.join(", "))?;
+        writeln!(f, "url: {}", self.url)?;
+        writeln!(f, "estimate: {}", self.estimate)?;
+        writeln!(f, "repeat: {}", self.repeat)?;
+        for note in &self.notes {
+            writeln!(
+                f,
+                "--- note {}: {}",
+                note.id.as_deref().unwrap_or(""),
+                note.title
+            )?;
+            writeln!(f, "{}", note.text)?;
+        }
+        Ok(())
+    }
+}
+
+impl API {
+    /// Make the changes found by [TaskForm::changes] to a task, on one
+    /// timeline.  `lists` is used to find the list to move to.  Each change
+    /// made is added to `transactions` with its transaction, for undoing.
+    /// If a change fails, the error is returned and the later changes
+    /// aren't made.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn apply_task_edits(
+        &self,
+        timeline: &RTMTimeline,
+        entry: &TaskEntry<'_>,
+        edits: &[TaskEdit],
+        lists: &[RTMList],
+        transactions: &mut Vec<(String, RTMTransaction)>,
+    ) -> Result<(), crate::Error> {
+        let mut list_id = entry.list_id.to_string();
+        let (series, task) = (entry.series.id.as_str(), entry.task.id.as_str());
+        for edit in edits {
+            let l = list_id.as_str();
+            let transaction = match edit {
+                TaskEdit::Name(name) => self.set_name_id(timeline, l, series, task, name).await?,
+                TaskEdit::List(name) => {
+                    let to = match lists.iter().find(|list| list.name == *name) {
+                        Some(list) if list.smart => {
+                            bail!("Can't move tasks to smart list {name:?}.")
+                        }
+                        Some(list) => list.id.clone(),
+                        None => bail!("No list called {name:?}."),
+                    };
+                    let transaction = self.move_to_id(timeline, l, &to, series, task).await?;
+                    list_id = to;
+                    transaction
+                }
+                TaskEdit::Due(due) => {
+                    self.parse_due_date_id(timeline, l, series, task, due)
+                        .await?
+                }
+                TaskEdit::Priority(priority) => {
+                    self.set_priority_id(timeline, l, series, task, priority)
+                        .await?
+                }
+                TaskEdit::AddTags(tags) => {
+                    let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+                    self.add_tags_id(timeline, l, series, task, &tags).await?
+                }
+                TaskEdit::RemoveTags(tags) => {
+                    let tags: Vec<&str> = tags.iter().map(String::as_str).collect();
+                    self.remove_tags_id(timeline, l, series, task, &tags)
+                        .await?
+                }
+                TaskEdit::Url(url) => self.set_url_id(timeline, l, series, task, url).await?,
+                TaskEdit::Estimate(estimate) => {
+                    self.set_estimate_id(timeline, l, series, task, estimate)
+                        .await?
+                }
+                TaskEdit::Repeat(repeat) => {
+                    // RTM takes "every" rules without the prefix.
+                    let repeat = match repeat.strip_prefix("every FREQ=") {
+                        Some(rule) => format!("FREQ={rule}"),
+                        None => repeat.clone(),
+                    };
+                    self.set_recurrence_id(timeline, l, series, task, &repeat)
+                        .await?
+                }
+                TaskEdit::AddNote { title, text } => {
+                    self.add_note_id(timeline, l, series, task, title, text)
+                        .await?
+                }
+                TaskEdit::EditNote { id, title, text } => {
+                    self.edit_note(timeline, id, title, text).await?
+                }
+                TaskEdit::DeleteNote { id, .. } => self.delete_note(timeline, id).await?,
+            };
+            if let Some(transaction) = transaction {
+                transactions.push((edit.to_string(), transaction));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NoteForm, TaskEdit, TaskForm};
+    use crate::RTMTasks;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_task_form() {
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [{
+                    "id": "2",
+                    "created": "2026-10-01T09:00:00Z",
+                    "modified": "2026-10-01T09:00:00Z",
+                    "name": "Water plants",
+                    "source": "js",
+                    "url": "",
+                    "location_id": "",
+                    "tags": {"tag": ["home", "garden"]},
+                    "participants": [],
+                    "notes": {"note": [{
+                        "id": "9", "created": "2026-10-01T09:00:00Z",
+                        "modified": "2026-10-01T09:00:00Z", "title": "Which",
+                        "$t": "The ones on\nthe windowsill"
+                    }]},
+                    "parent_task_id": "",
+                    "rrule": {"every": "1", "$t": "FREQ=WEEKLY;INTERVAL=1"},
+                    "task": [{
+                        "id": "3", "due": "2026-10-19T23:00:00Z", "has_due_time": "0",
+                        "added": "", "completed": "", "deleted": "",
+                        "priority": "N", "postponed": "0", "estimate": "PT1H30M"
+                    }],
+                }],
+            }],
+        }))
+        .unwrap();
+        let list_names = HashMap::from([("1".to_string(), "House".to_string())]);
+        let entries = tasks.entries();
+        let original = TaskForm::from_entry(&entries[0], &list_names, &chrono_tz::Europe::London);
+        let text = original.to_string();
+        assert!(text.ends_with(
+            "name: Water plants\n\
+             list: House\n\
+             due: 2026-10-20\n\
+             priority: none\n\
+             tags: home, garden\n\
+             url: \n\
+             estimate: 1 hour 30 minutes\n\
+             repeat: every FREQ=WEEKLY;INTERVAL=1\n\
+             --- note 9: Which\n\
+             The ones on\n\
+             the windowsill\n"
+        ));
+        assert_eq!(TaskForm::parse(&text).unwrap(), original);
+        assert!(original.changes(&original).unwrap().is_empty());
+
+        let edited = TaskForm::parse(
+            "name: Water the plants\n\
+             list: House\n\
+             due: tomorrow\n\
+             priority: 2\n\
+             tags: garden,weekly\n\
+             estimate: 1 hour 30 minutes\n\
+             repeat: every FREQ=WEEKLY;INTERVAL=1\n\
+             --- note: Remember\n\
+             The hose\n\
+             \n",
+        )
+        .unwrap();
+        assert_eq!(
+            edited.notes,
+            [NoteForm {
+                id: None,
+                title: "Remember".into(),
+                text: "The hose".into()
+            }]
+        );
+        assert_eq!(
+            edited.changes(&original).unwrap(),
+            [
+                TaskEdit::Name("Water the plants".into()),
+                TaskEdit::Due("tomorrow".into()),
+                TaskEdit::Priority("2".into()),
+                TaskEdit::AddTags(vec!["weekly".into()]),
+                TaskEdit::RemoveTags(vec!["home".into()]),
+                TaskEdit::AddNote {
+                    title: "Remember".into(),
+                    text: "The hose".into()
+                },
+                TaskEdit::DeleteNote {
+                    id: "9".into(),
+                    title: "Which".into()
+                },
+            ]
+        );
+
+        for bad in [
+            "list: House\n",
+            "name:\n",
+            "name: x\ncolour: red\n",
+            "name: x\n--- note\n",
+        ] {
+            assert!(TaskForm::parse(bad).is_err(), "{bad:?}");
+        }
+        let bad_priority = TaskForm::parse("name: x\npriority: 4\n").unwrap();
+        assert!(bad_priority.changes(&original).is_err());
+    }
+}
@@ -0,0 +1,671 @@
+//! Working with RTM filter strings.
+//!
+//! [Filter] builds filter expressions without assembling strings by hand,
+//! and formats them as RTM filter strings.
+//!
+//! Saved filters are referred to as `list:NAME`, like smart lists, and
+//! [expand_saved] replaces each with its definition:
+//!
+//! ```
+//! use std::collections::HashMap;
+//! use rememberthemilk::filter::expand_saved;
+//!
+//! let saved = HashMap::from([("Work".to_string(), "tag:work".to_string())]);
+//! assert_eq!(
+//!     expand_saved("list:Work AND priority:1", &saved).unwrap(),
+//!     "(tag:work) AND priority:1"
+//! );
+//! ```
+
+use std::{borrow::Cow, collections::HashMap, fmt};
+
+use anyhow::bail;
+use chrono::{Datelike, NaiveDate, NaiveTime};
+
+/// Represent a date from an RTM filter
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RtmDate {
+    /// A time relative to the current time
+    RelativeTime(chrono::TimeDelta),
+    /// A day relative to today
+    RelativeDay(i32),
+    /// A time relative to the start of a day
+    RelativeDayStart(i32),
+    /// A fixed date.
+    AbsoluteDate(chrono::NaiveDate),
+    /// A fixed date and time
+    AbsoluteDatetime(chrono::NaiveDateTime),
+    /// The given time either today or tomorrow (if we've passed it today).
+    NextTime(chrono::NaiveTime),
+    /// A month/day indicating the next one coming.
+    /// Both month and day start at 1.
+    NextDate {
+        /// The month (1-12)
+        month: u8,
+        /// The day of the month (1-31)
+        day: u8,
+    },
+    /// A month/day indicating the next one coming.
+    /// Both month and day start at 1.  Indicates the
+    /// time at the beginning of the day.
+    NextDateStart {
+        /// The month (1-12)
+        month: u8,
+        /// The day of the month (1-31)
+        day: u8,
+    },
+    /// A month/day indicating the next one coming.
+    /// Both month and day start at 1.  Indicates the
+    /// time at the end of the day.
+    NextDateEnd {
+        /// The month (1-12)
+        month: u8,
+        /// The day of the month (1-31)
+        day: u8,
+    },
+}
+
+/// Add a (possibly negative) number of days to a date.
+pub(crate) fn add_days(date: NaiveDate, offset: i32) -> NaiveDate {
+    if offset >= 0 {
+        date + chrono::Days::new(offset as u64)
+    } else {
+        date - chrono::Days::new(offset.unsigned_abs() as u64)
+    }
+}
+
+impl RtmDate {
+    /// Convert a date to a time at the start of the day
+    /// Dates with time are not affected.
+    pub(crate) fn start_of_day(&self) -> Self {
+        use RtmDate::*;
+        match self {
+            RelativeDay(offs) => RelativeDayStart(*offs),
+            AbsoluteDate(d) => AbsoluteDatetime(d.and_hms_opt(0, 0, 0).unwrap()),
+            NextDate { month, day } => NextDateStart {
+                month: *month,
+                day: *day,
+            },
+            // If we have a time, nothing changes.
+            d @ (RelativeTime(_)
+            | RelativeDayStart(_)
+            | AbsoluteDatetime(_)
+            | NextTime(_)
+            | NextDateStart { .. }
+            | NextDateEnd { .. }) => *d,
+        }
+    }
+
+    /// Convert a date to a time at the end of the day
+    /// Dates with time are not affected.
+    pub(crate) fn end_of_day(&self) -> RtmDate {
+        use RtmDate::*;
+        match self {
+            RelativeDay(offs) => RelativeDayStart(*offs + 1),
+            AbsoluteDate(d) => AbsoluteDatetime(add_days(*d, 1).and_time(NaiveTime::MIN)),
+            NextDate { month, day } => NextDateEnd {
+                month: *month,
+                day: *day,
+            },
+            // If we have a time, nothing changes.
+            d @ (RelativeTime(_)
+            | RelativeDayStart(_)
+            | AbsoluteDatetime(_)
+            | NextTime(_)
+            | NextDateStart { .. }
+            | NextDateEnd { .. }) => *d,
+        }
+    }
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+fn fmt_count(f: &mut fmt::Formatter<'_>, count: i64, unit: &str) -> fmt::Result {
+    let plural = if count.abs() == 1 { "" } else { "s" };
+    if count < 0 {
+        write!(f, "{} {unit}{plural} ago", -count)
+    } else {
+        write!(f, "{count} {unit}{plural}")
+    }
+}
+
+/// Formats the date in the RTM filter syntax, e.g. `today`, `3 days`
+/// or `25 Apr`.
+impl fmt::Display for RtmDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RtmDate::*;
+        match self {
+            RelativeTime(delta) => {
+                let mins = delta.num_minutes();
+                if mins % 60 == 0 {
+                    fmt_count(f, mins / 60, "hour")
+                } else {
+                    fmt_count(f, mins, "min")
+                }
+            }
+            RelativeDay(offs) | RelativeDayStart(offs) => match offs {
+                0 => f.write_str("today"),
+                1 => f.write_str("tomorrow"),
+                -1 => f.write_str("yesterday"),
+                n => fmt_count(f, *n as i64, "day"),
+            },
+            AbsoluteDate(d) => write!(f, "{}", d.format("%Y-%m-%d")),
+            AbsoluteDatetime(dt) => {
+                if dt.time() == chrono::NaiveTime::MIN {
+                    write!(f, "{}", dt.format("%Y-%m-%d"))
+                } else {
+                    write!(f, "{}", dt.format("%Y-%m-%d %H:%M"))
+                }
+            }
+            NextTime(t) => write!(f, "{}", t.format("%H:%M")),
+            NextDate { month, day } | NextDateStart { month, day } => {
+                write!(f, "{day} {}", MONTH_NAMES[(*month as usize - 1) % 12])
+            }
+            // The end of a day is the start of the next one.  That is
+            // taken from a year which isn't a leap year, so the end of
+            // 28 Feb is written as 1 Mar, which also includes 29 Feb in a
+            // leap year.
+            NextDateEnd { month, day } => {
+                let next = NaiveDate::from_ymd_opt(2001, *month as u32, *day as u32)
+                    .and_then(|d| d.succ_opt())
+                    .unwrap_or_else(|| NaiveDate::from_ymd_opt(2001, 3, 1).unwrap());
+                write!(
+                    f,
+                    "{} {}",
+                    next.day(),
+                    MONTH_NAMES[next.month0() as usize % 12]
+                )
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+/// An RTM Filter expression
+pub enum RtmFilter {
+    /// Match on the whether the task is completed or not.
+    Complete(bool),
+    /// Match on the contents of the name.
+    Name(String),
+    /// Match on the contents of the name.
+    List(String),
+    /// Match on a tag.
+    Tag(String),
+    /// Match item with no due date
+    DueNever,
+    /// Match value due before a time
+    DueBefore(RtmDate),
+    /// Match value due before a time
+    DueWithin(RtmDate, RtmDate),
+    /// Start within a day, or no start date
+    Start(Option<chrono::NaiveDate>),
+    /// Start before a certain time
+    StartBefore(RtmDate),
+    /// Match all of the sub expressions
+    And(Vec<RtmFilter>),
+    /// Match all of the sub expressions
+    Or(Vec<RtmFilter>),
+    /// Negated filter
+    Not(Box<RtmFilter>),
+    /// Given by
+    GivenBy(String),
+    /// Match the task source, e.g. `api:<key>:<external id>`.
+    Source(String),
+    /// Match on whether the task is a subtask of another.
+    IsSubtask(bool),
+    /// Match on whether the task has any (not deleted) subtasks.
+    HasSubtasks(bool),
+}
+
+impl RtmFilter {
+    /// True if this needs parentheses when nested inside another
+    /// expression.
+    fn is_compound(&self) -> bool {
+        matches!(self, RtmFilter::And(_) | RtmFilter::Or(_))
+    }
+}
+
+/// Quote a filter value if it isn't a single word.  The filter syntax has
+/// no escape for `"`, so a value containing one can't be written.
+fn quote_value(s: &str) -> Result<Cow<'_, str>, crate::Error> {
+    if s.contains('"') {
+        bail!("{s:?} can't be written in a filter, as it contains '\"'");
+    }
+    Ok(
+        if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphabetic()) {
+            s.into()
+        } else {
+            format!("\"{s}\"").into()
+        },
+    )
+}
+
+/// Formats the filter as an RTM filter string, suitable for passing to
+/// [crate::API::get_tasks_filtered].  This fails for a value containing
+/// `"`, which [Filter] doesn't allow.
+impl fmt::Display for RtmFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn quote(s: &str) -> Result<Cow<'_, str>, fmt::Error> {
+            quote_value(s).map_err(|_| fmt::Error)
+        }
+        fn join(f: &mut fmt::Formatter<'_>, filts: &[RtmFilter], op: &str) -> fmt::Result {
+            for (i, filt) in filts.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " {op} ")?;
+                }
+                if filt.is_compound() {
+                    write!(f, "({filt})")?;
+                } else {
+                    write!(f, "{filt}")?;
+                }
+            }
+            Ok(())
+        }
+        match self {
+            RtmFilter::Complete(true) => f.write_str("status:completed"),
+            RtmFilter::Complete(false) => f.write_str("status:incomplete"),
+            RtmFilter::Name(s) => write!(f, "name:{}", quote(s)?),
+            RtmFilter::List(s) => write!(f, "list:{}", quote(s)?),
+            RtmFilter::Tag(s) => write!(f, "tag:{}", quote(s)?),
+            RtmFilter::DueNever => f.write_str("due:never"),
+            RtmFilter::DueBefore(date) => {
+                write!(f, "dueBefore:{}", quote(&date.to_string())?)
+            }
+            RtmFilter::DueWithin(
+                from @ (RtmDate::RelativeDay(start) | RtmDate::RelativeDayStart(start)),
+                RtmDate::RelativeDay(end) | RtmDate::RelativeDayStart(end),
+            ) if end > start => {
+                let days = end - start;
+                let plural = if days == 1 { "" } else { "s" };
+                write!(f, "dueWithin:\"{days} day{plural} of {from}\"")
+            }
+            RtmFilter::DueWithin(from, to) => write!(
+                f,
+                "(NOT dueBefore:{} AND dueBefore:{})",
+                quote(&from.to_string())?,
+                quote(&to.to_string())?
+            ),
+            RtmFilter::Start(None) => f.write_str("start:never"),
+            RtmFilter::Start(Some(d)) => write!(f, "start:{}", d.format("%Y-%m-%d")),
+            RtmFilter::StartBefore(date) => {
+                write!(f, "startBefore:{}", quote(&date.to_string())?)
+            }
+            RtmFilter::And(filts) => join(f, filts, "AND"),
+            RtmFilter::Or(filts) => join(f, filts, "OR"),
+            RtmFilter::Not(filt) => {
+                if filt.is_compound() {
+                    write!(f, "NOT ({filt})")
+                } else {
+                    write!(f, "NOT {filt}")
+                }
+            }
+            RtmFilter::GivenBy(s) => write!(f, "givenBy:{}", quote(s)?),
+            RtmFilter::Source(s) => write!(f, "source:{}", quote(s)?),
+            RtmFilter::IsSubtask(b) => write!(f, "isSubtask:{b}"),
+            RtmFilter::HasSubtasks(b) => write!(f, "hasSubtasks:{b}"),
+        }
+    }
+}
+
+/// A builder for [RtmFilter] expressions, avoiding the need to assemble
+/// filter strings by hand.
+///
+/// ```
+/// use rememberthemilk::filter::{Filter, RtmDate};
+///
+/// let filter = Filter::incomplete()
+///     .and(Filter::tag("work")?)
+///     .and(Filter::due_before(RtmDate::RelativeDay(1)));
+/// assert_eq!(
+///     filter.to_string(),
+///     "status:incomplete AND tag:work AND dueBefore:tomorrow"
+/// );
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+/// Values are quoted as needed when converting to a string.  RTM filters
+/// can't contain a `"` inside a quoted value, so the constructors taking a
+/// value return an error for one containing `"`.
+///
+/// The result can be converted to a string for
+/// [API::get_tasks_filtered](crate::API::get_tasks_filtered), or with the
+/// `cache` feature passed directly to `TaskCache::get_tasks_matching`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Filter(RtmFilter);
+
+impl Filter {
+    /// A filter on a string value, checking that it can be written.
+    fn with_value(
+        value: impl Into<String>,
+        filter: fn(String) -> RtmFilter,
+    ) -> Result<Filter, crate::Error> {
+        let value = value.into();
+        quote_value(&value)?;
+        Ok(Filter(filter(value)))
+    }
+
+    /// Match incomplete tasks (`status:incomplete`).
+    pub fn incomplete() -> Filter {
+        Filter(RtmFilter::Complete(false))
+    }
+
+    /// Match completed tasks (`status:completed`).
+    pub fn completed() -> Filter {
+        Filter(RtmFilter::Complete(true))
+    }
+
+    /// Match tasks whose name contains `name`.  Fails if it contains `"`.
+    pub fn name(name: impl Into<String>) -> Result<Filter, crate::Error> {
+        Filter::with_value(name, RtmFilter::Name)
+    }
+
+    /// Match tasks in the list with the given name.  Fails if it contains `"`.
+    pub fn list(list: impl Into<String>) -> Result<Filter, crate::Error> {
+        Filter::with_value(list, RtmFilter::List)
+    }
+
+    /// Match tasks with the given tag.  Fails if it contains `"`.
+    pub fn tag(tag: impl Into<String>) -> Result<Filter, crate::Error> {
+        Filter::with_value(tag, RtmFilter::Tag)
+    }
+
+    /// Match tasks from the given source.  Fails if it contains `"`.
+    pub fn source(source: impl Into<String>) -> Result<Filter, crate::Error> {
+        Filter::with_value(source, RtmFilter::Source)
+    }
+
+    /// Match tasks which are (or are not) subtasks of another task.
+    pub fn is_subtask(val: bool) -> Filter {
+        Filter(RtmFilter::IsSubtask(val))
+    }
+
+    /// Match tasks which have (or don't have) subtasks.
+    pub fn has_subtasks(val: bool) -> Filter {
+        Filter(RtmFilter::HasSubtasks(val))
+    }
+
+    /// Match tasks with no due date.
+    pub fn due_never() -> Filter {
+        Filter(RtmFilter::DueNever)
+    }
+
+    /// Match tasks due before `date` (`dueBefore:`).
+    pub fn due_before(date: RtmDate) -> Filter {
+        Filter(RtmFilter::DueBefore(date.start_of_day()))
+    }
+
+    /// Match tasks due on or before `date`.
+    pub fn due_by(date: RtmDate) -> Filter {
+        Filter(RtmFilter::DueBefore(date.end_of_day()))
+    }
+
+    /// Match tasks due from the start of `from` up to the start of `to`.
+    pub fn due_within(from: RtmDate, to: RtmDate) -> Filter {
+        Filter(RtmFilter::DueWithin(from, to))
+    }
+
+    /// Match tasks with no start date.
+    pub fn start_never() -> Filter {
+        Filter(RtmFilter::Start(None))
+    }
+
+    /// Match tasks starting before `date`.
+    pub fn start_before(date: RtmDate) -> Filter {
+        Filter(RtmFilter::StartBefore(date))
+    }
+
+    /// Match tasks which match both this filter and `other`.
+    pub fn and(self, other: Filter) -> Filter {
+        match self.0 {
+            RtmFilter::And(mut filts) => {
+                filts.push(other.0);
+                Filter(RtmFilter::And(filts))
+            }
+            filt => Filter(RtmFilter::And(vec![filt, other.0])),
+        }
+    }
+
+    /// Match tasks which match either this filter or `other`.
+    pub fn or(self, other: Filter) -> Filter {
+        match self.0 {
+            RtmFilter::Or(mut filts) => {
+                filts.push(other.0);
+                Filter(RtmFilter::Or(filts))
+            }
+            filt => Filter(RtmFilter::Or(vec![filt, other.0])),
+        }
+    }
+
+    /// Return the underlying filter expression.
+    pub fn into_inner(self) -> RtmFilter {
+        self.0
+    }
+}
+
+impl std::ops::Not for Filter {
+    type Output = Filter;
+
+    fn not(self) -> Filter {
+        Filter(RtmFilter::Not(Box::new(self.0)))
+    }
+}
+
+impl From<RtmFilter> for Filter {
+    fn from(filt: RtmFilter) -> Filter {
+        Filter(filt)
+    }
+}
+
+impl From<Filter> for RtmFilter {
+    fn from(filt: Filter) -> RtmFilter {
+        filt.0
+    }
+}
+
+impl AsRef<RtmFilter> for Filter {
+    fn as_ref(&self) -> &RtmFilter {
+        &self.0
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Expand any references to saved filters (as `list:NAME`), each replaced
+/// by the saved filter in parentheses, recursively.  The rest of the
+/// filter, including smart lists and anything in quotes, is left unchanged,
+/// so that RTM interprets it exactly as written.  Inside quotes, `\"` is
+/// taken as a quote which doesn't end the value.  Returns an error if a
+/// saved filter refers back to itself.
+pub fn expand_saved<'s>(
+    filter: &str,
+    saved: impl IntoIterator<Item = (&'s String, &'s String)>,
+) -> Result<String, crate::Error> {
+    let saved: HashMap<&str, &str> = saved
+        .into_iter()
+        .map(|(name, filter)| (name.as_str(), filter.as_str()))
+        .collect();
+    expand_saved_from(filter, &saved, &mut Vec::new())
+}
+
+/// The length of the quoted value at the start of `s` (just after the
+/// opening quote), and the value itself, or None if it isn't closed.
+fn quoted_len(s: &str) -> Option<(usize, &str)> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some((i + 1, &s[..i])),
+            _ => (),
+        }
+    }
+    None
+}
+
+fn expand_saved_from<'s>(
+    filter: &str,
+    saved: &HashMap<&'s str, &'s str>,
+    expanding: &mut Vec<&'s str>,
+) -> Result<String, crate::Error> {
+    let mut result = String::new();
+    // The end of the text already copied to `result`.
+    let mut copied = 0;
+    // The end of the quoted value being skipped over.
+    let mut skip_to = 0;
+    let mut prev = None;
+    for (i, c) in filter.char_indices() {
+        let at_term = matches!(prev, None | Some('(') | Some(' ' | '\t'));
+        prev = Some(c);
+        if i < skip_to.max(copied) {
+            continue;
+        }
+        if c == '"' {
+            // An unclosed quote runs to the end of the filter.
+            skip_to = quoted_len(&filter[i + 1..]).map_or(filter.len(), |(len, _)| i + 1 + len);
+            continue;
+        }
+        if !at_term {
+            continue;
+        }
+        let Some(key) = filter.get(i..i + 5) else {
+            continue;
+        };
+        if !key.eq_ignore_ascii_case("list:") {
+            continue;
+        }
+        let value_start = i + 5;
+        let rest = &filter[value_start..];
+        let (name, len) = match rest.strip_prefix('"') {
+            Some(quoted) => match quoted_len(quoted) {
+                Some((len, name)) => (name, len + 1),
+                None => continue,
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == ')')
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+        let Some((&name, &definition)) = saved.get_key_value(name) else {
+            continue;
+        };
+        if expanding.contains(&name) {
+            bail!(
+                "Saved filter refers to itself: {} -> {name}",
+                expanding.join(" -> ")
+            );
+        }
+        expanding.push(name);
+        let expanded = expand_saved_from(definition, saved, expanding)?;
+        expanding.pop();
+        result.push_str(&filter[copied..i]);
+        result.push('(');
+        result.push_str(&expanded);
+        result.push(')');
+        copied = value_start + len;
+    }
+    result.push_str(&filter[copied..]);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_saved, Filter, RtmDate};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_filter_builder() -> Result<(), anyhow::Error> {
+        for (filt, expected) in [
+            (
+                Filter::incomplete()
+                    .and(Filter::tag("work")?)
+                    .and(Filter::due_before(RtmDate::RelativeDay(0))),
+                "status:incomplete AND tag:work AND dueBefore:today",
+            ),
+            (
+                Filter::list("My List")?.or(Filter::tag("a b")?),
+                r#"list:"My List" OR tag:"a b""#,
+            ),
+            (
+                !Filter::completed().or(Filter::due_never()),
+                "NOT (status:completed OR due:never)",
+            ),
+            (
+                Filter::incomplete().and(Filter::name("x")?.or(Filter::name("y")?)),
+                "status:incomplete AND (name:x OR name:y)",
+            ),
+            (
+                Filter::due_within(RtmDate::RelativeDay(1), RtmDate::RelativeDay(4)),
+                r#"dueWithin:"3 days of tomorrow""#,
+            ),
+            (
+                Filter::due_by(RtmDate::NextDate { month: 4, day: 25 }),
+                r#"dueBefore:"26 Apr""#,
+            ),
+            (
+                Filter::due_by(RtmDate::NextDate { month: 12, day: 31 }),
+                r#"dueBefore:"1 Jan""#,
+            ),
+            (
+                Filter::due_by(RtmDate::NextDate { month: 2, day: 29 }),
+                r#"dueBefore:"1 Mar""#,
+            ),
+            (
+                Filter::source("api:key:ext-1")?,
+                r#"source:"api:key:ext-1""#,
+            ),
+            (
+                Filter::start_before(RtmDate::RelativeTime(chrono::TimeDelta::minutes(90))),
+                r#"startBefore:"90 mins""#,
+            ),
+        ] {
+            assert_eq!(filt.to_string(), expected);
+        }
+        // There's no way to write a `"` in a value.
+        assert!(Filter::name(r#"say "hi""#).is_err());
+        assert!(Filter::tag(r#"a"b"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_saved() {
+        let saved: BTreeMap<String, String> = [
+            ("Today", "due:today"),
+            ("Work", "tag:work AND list:Today"),
+            ("Loop", "list:Loop"),
+            ("My Errands", "tag:errands"),
+        ]
+        .into_iter()
+        .map(|(name, filter)| (name.to_string(), filter.to_string()))
+        .collect();
+        let expand = |filter| expand_saved(filter, &saved).unwrap();
+
+        // Anything else is sent as written.
+        assert_eq!(expand("due:today"), "due:today");
+        assert_eq!(
+            expand(r#"name:"say \"hi\"" OR list:Inbox"#),
+            r#"name:"say \"hi\"" OR list:Inbox"#
+        );
+        assert_eq!(
+            expand(r#"name:"a\" list:Work" OR list:Today"#),
+            r#"name:"a\" list:Work" OR (due:today)"#
+        );
+        assert_eq!(
+            expand("list:Work OR (priority:1 AND list:\"My Errands\")"),
+            "(tag:work AND (due:today)) OR (priority:1 AND (tag:errands))"
+        );
+        assert_eq!(expand("name:\"list:Today\""), "name:\"list:Today\"");
+        assert_eq!(
+            expand("name:\"unclosed list:Today"),
+            "name:\"unclosed list:Today"
+        );
+        assert!(expand_saved("list:Loop", &saved).is_err());
+    }
+}
@@ -0,0 +1,580 @@
+//! Exporting tasks as iCalendar (RFC 5545), and importing to-dos from it.
+//!
+//! ```no_run
+//! use rememberthemilk::ical::{to_ical, IcalOptions};
+//! # let tasks = rememberthemilk::RTMTasks::default();
+//!
+//! let ics = to_ical(&tasks.entries(), &IcalOptions::default(), &chrono::Local);
+//! std::fs::write("tasks.ics", ics).unwrap();
+//! ```
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+
+use crate::sort::{parse_estimate, TaskEntry};
+
+/// Options for [to_ical].
+#[derive(Debug, Clone, Default)]
+pub struct IcalOptions {
+    /// Also add a `VEVENT` for each task with a due date, for calendars
+    /// which don't show `VTODO`s.
+    pub events: bool,
+}
+
+/// Escape a TEXT value.
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                result.push('\\');
+                result.push(c);
+            }
+            '\n' => result.push_str("\\n"),
+            '\r' => (),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Format a UTC date-time value.
+fn utc(t: &DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Builds the content lines of a calendar.
+#[derive(Default)]
+struct Writer {
+    out: String,
+}
+
+impl Writer {
+    /// Add a content line, folding it at 75 octets.
+    fn line(&mut self, name: &str, value: &str) {
+        let line = format!("{name}:{value}");
+        let mut width = 0;
+        for c in line.chars() {
+            if width + c.len_utf8() > 75 {
+                self.out.push_str("\r\n ");
+                width = 1;
+            }
+            self.out.push(c);
+            width += c.len_utf8();
+        }
+        self.out.push_str("\r\n");
+    }
+
+    /// Add a due date (or date and time) property.
+    fn due<Tz: TimeZone>(&mut self, name: &str, entry: &TaskEntry<'_>, tz: &Tz) {
+        let Some(due) = entry.task.due else { return };
+        if entry.task.has_due_time {
+            self.line(name, &utc(&due));
+        } else {
+            let date = due.with_timezone(tz).date_naive();
+            self.line(
+                &format!("{name};VALUE=DATE"),
+                &date.format("%Y%m%d").to_string(),
+            );
+        }
+    }
+
+    /// Add the properties common to `VTODO`s and `VEVENT`s.
+    fn common(&mut self, entry: &TaskEntry<'_>, uid: &str) {
+        let series = entry.series;
+        self.line("UID", uid);
+        self.line("DTSTAMP", &utc(&series.modified));
+        self.line("CREATED", &utc(&series.created));
+        self.line("LAST-MODIFIED", &utc(&series.modified));
+        self.line("SUMMARY", &escape(&series.name));
+        if !series.url.is_empty() {
+            self.line("URL", &series.url);
+        }
+        if !series.tags.is_empty() {
+            let tags: Vec<String> = series.tags.iter().map(|t| escape(t)).collect();
+            self.line("CATEGORIES", &tags.join(","));
+        }
+        if !series.notes.is_empty() {
+            let notes: Vec<String> = series
+                .notes
+                .iter()
+                .map(|note| match note.title.as_str() {
+                    "" => note.text.clone(),
+                    title => format!("{title}\n{}", note.text),
+                })
+                .collect();
+            self.line("DESCRIPTION", &escape(&notes.join("\n\n")));
+        }
+        // "After" repeats depend on when the task is completed, which
+        // iCalendar can't express.
+        if let Some(repeat) = series.repeat.as_ref().filter(|r| r.every) {
+            self.line("RRULE", &repeat.rule);
+        }
+    }
+
+    fn todo<Tz: TimeZone>(&mut self, entry: &TaskEntry<'_>, uid: &str, tz: &Tz) {
+        let task = entry.task;
+        self.line("BEGIN", "VTODO");
+        self.common(entry, uid);
+        self.due("DUE", entry, tz);
+        // RTM's three priorities map onto the high, medium and low ranges.
+        let priority = match task.priority.as_str() {
+            "1" => Some("1"),
+            "2" => Some("5"),
+            "3" => Some("9"),
+            _ => None,
+        };
+        if let Some(priority) = priority {
+            self.line("PRIORITY", priority);
+        }
+        match task.completed {
+            Some(completed) => {
+                self.line("STATUS", "COMPLETED");
+                self.line("COMPLETED", &utc(&completed));
+            }
+            None => self.line("STATUS", "NEEDS-ACTION"),
+        }
+        self.line("END", "VTODO");
+    }
+
+    fn event<Tz: TimeZone>(&mut self, entry: &TaskEntry<'_>, uid: &str, tz: &Tz) {
+        if entry.task.due.is_none() {
+            return;
+        }
+        self.line("BEGIN", "VEVENT");
+        self.common(entry, uid);
+        self.due("DTSTART", entry, tz);
+        if entry.task.has_due_time {
+            if let Some(estimate) = parse_estimate(&entry.task.estimate) {
+                self.line("DURATION", &format!("PT{}S", estimate.num_seconds()));
+            }
+        }
+        self.line("TRANSP", "TRANSPARENT");
+        self.line("END", "VEVENT");
+    }
+}
+
+/// Convert tasks to an iCalendar object with a `VTODO` for each, using `tz`
+/// for the dates of tasks due on a day rather than at a time.  Deleted
+/// tasks are left out.
+pub fn to_ical<Tz: TimeZone>(entries: &[TaskEntry<'_>], options: &IcalOptions, tz: &Tz) -> String {
+    let mut writer = Writer::default();
+    writer.line("BEGIN", "VCALENDAR");
+    writer.line("VERSION", "2.0");
+    writer.line(
+        "PRODID",
+        concat!("-//rememberthemilk-rs//", env!("CARGO_PKG_VERSION"), "//EN"),
+    );
+    for entry in entries {
+        if entry.task.deleted.is_some() {
+            continue;
+        }
+        // The list isn't part of the UID, so it stays the same when the
+        // task is moved to another list.
+        let uid = format!("{}-{}@rememberthemilk", entry.series.id, entry.task.id);
+        writer.todo(entry, &uid, tz);
+        if options.events {
+            writer.event(entry, &format!("event-{uid}"), tz);
+        }
+    }
+    writer.line("END", "VCALENDAR");
+    writer.out
+}
+
+/// A to-do parsed from an iCalendar file by [parse_todos].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IcalTodo {
+    /// The unique id, if it has one.
+    pub uid: Option<String>,
+    /// The summary, used as the task name.
+    pub summary: String,
+    /// The due date or time.
+    pub due: Option<DateTime<Utc>>,
+    /// True if `due` has a time, not just a date.
+    pub has_due_time: bool,
+    /// The priority from 1 (highest) to 9 (lowest), or 0 for none.
+    pub priority: u8,
+    /// The categories, used as tags.
+    pub categories: Vec<String>,
+    /// The description.
+    pub description: Option<String>,
+    /// The URL.
+    pub url: Option<String>,
+    /// The recurrence rule.
+    pub rrule: Option<String>,
+    /// True if the to-do has been completed.
+    pub completed: bool,
+}
+
+impl IcalTodo {
+    /// The RTM priority ("1", "2", "3" or "N") for the iCalendar one.
+    pub fn rtm_priority(&self) -> &'static str {
+        match self.priority {
+            1..=4 => "1",
+            5 => "2",
+            6..=9 => "3",
+            _ => "N",
+        }
+    }
+}
+
+/// Undo TEXT escaping.
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => result.push('\n'),
+                Some(c) => result.push(c),
+                None => (),
+            },
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Split a TEXT list at unescaped commas, unescaping each item.
+fn split_text_list(s: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            ',' => {
+                items.push(unescape(&s[start..i]));
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    items.push(unescape(&s[start..]));
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+/// A content line split into its parts.
+struct ContentLine<'a> {
+    /// The property name, in upper case.
+    name: String,
+    /// The parameters, with names in upper case.
+    params: Vec<(String, String)>,
+    value: &'a str,
+}
+
+impl ContentLine<'_> {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Split a content line into its name, parameters and value.
+fn split_line(line: &str) -> Option<ContentLine<'_>> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?.to_ascii_uppercase();
+    let params = parts
+        .filter_map(|p| p.split_once('='))
+        .map(|(k, v)| (k.to_ascii_uppercase(), v.trim_matches('"').to_string()))
+        .collect();
+    Some(ContentLine {
+        name,
+        params,
+        value,
+    })
+}
+
+/// Parse a DATE or DATE-TIME value, returning the instant and whether it
+/// has a time.  Dates and times which aren't in UTC are taken to be in
+/// `tz`.
+fn parse_date_time<Tz: TimeZone>(
+    value: &str,
+    tz: &Tz,
+) -> Result<(DateTime<Utc>, bool), crate::Error> {
+    let local = |dt: NaiveDateTime| {
+        tz.from_local_datetime(&dt)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+            .ok_or_else(|| anyhow!("Invalid local time {value:?}"))
+    };
+    if let Some(utc) = value.strip_suffix('Z') {
+        let dt = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")?;
+        Ok((dt.and_utc(), true))
+    } else if value.contains('T') {
+        Ok((
+            local(NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")?)?,
+            true,
+        ))
+    } else {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d")?;
+        Ok((local(date.and_time(NaiveTime::MIN))?, false))
+    }
+}
+
+/// Parse the `VTODO`s in an iCalendar file, taking dates and times which
+/// aren't in UTC to be in `tz` (`TZID` parameters are not supported).
+/// Other components are ignored.
+pub fn parse_todos<Tz: TimeZone>(ics: &str, tz: &Tz) -> Result<Vec<IcalTodo>, crate::Error> {
+    let unfolded = ics
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+    let mut todos = Vec::new();
+    let mut todo: Option<IcalTodo> = None;
+    // How deeply nested we are within the current VTODO, such as in a
+    // VALARM.
+    let mut depth = 0;
+    for (number, line) in unfolded.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let context = || format!("Line {}: {line:?}", number + 1);
+        let line = split_line(line).ok_or_else(|| anyhow!(context()))?;
+        let value = line.value;
+        match (line.name.as_str(), todo.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VTODO") => {
+                todo = Some(IcalTodo::default());
+                depth = 0;
+            }
+            ("BEGIN", Some(_)) => depth += 1,
+            ("END", Some(_)) if depth > 0 => depth -= 1,
+            ("END", Some(_)) => {
+                let done = todo.take().unwrap();
+                if done.summary.is_empty() {
+                    bail!("{}: to-do has no SUMMARY", context());
+                }
+                todos.push(done);
+            }
+            (name, Some(todo)) if depth == 0 => match name {
+                "UID" => todo.uid = Some(value.to_string()),
+                "SUMMARY" => todo.summary = unescape(value),
+                "DUE" => {
+                    let (due, has_time) = parse_date_time(value, tz).with_context(context)?;
+                    let is_date = line.param("VALUE") == Some("DATE");
+                    todo.due = Some(due);
+                    todo.has_due_time = has_time && !is_date;
+                }
+                "PRIORITY" => todo.priority = value.trim().parse().with_context(context)?,
+                "CATEGORIES" => todo.categories.extend(split_text_list(value)),
+                "DESCRIPTION" => todo.description = Some(unescape(value)),
+                "URL" => todo.url = Some(value.to_string()),
+                "RRULE" => todo.rrule = Some(value.to_string()),
+                "STATUS" => todo.completed |= value.eq_ignore_ascii_case("COMPLETED"),
+                "COMPLETED" => todo.completed = true,
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+    if todo.is_some() {
+        bail!("Unterminated VTODO");
+    }
+    Ok(todos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, parse_todos, to_ical, IcalOptions, IcalTodo};
+    use crate::RTMTasks;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a, b; c\\d\r\ne"), "a\\, b\\; c\\\\d\\ne");
+    }
+
+    #[test]
+    fn test_to_ical() {
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [{
+                    "id": "2",
+                    "created": "2026-10-01T09:00:00Z",
+                    "modified": "2026-10-02T09:00:00Z",
+                    "name": "Write report, then send",
+                    "source": "js",
+                    "url": "https://example.com/",
+                    "location_id": "",
+                    "tags": {"tag": ["work", "urgent"]},
+                    "participants": [],
+                    "notes": {"note": [{
+                        "id": "4",
+                        "created": "2026-10-01T09:00:00Z",
+                        "modified": "2026-10-01T09:00:00Z",
+                        "title": "Outline",
+                        "$t": "Intro\nSummary",
+                    }]},
+                    "parent_task_id": "",
+                    "rrule": {"every": "1", "$t": "FREQ=WEEKLY;INTERVAL=1"},
+                    "task": [{
+                        "id": "3", "due": "2026-10-19T23:00:00Z", "has_due_time": "0",
+                        "added": "2026-10-01T09:00:00Z", "completed": "", "deleted": "",
+                        "priority": "2", "postponed": "0", "estimate": "PT1H"
+                    }, {
+                        "id": "5", "due": "2026-10-12T14:30:00Z", "has_due_time": "1",
+                        "added": "2026-10-01T09:00:00Z", "completed": "2026-10-12T15:00:00Z",
+                        "deleted": "", "priority": "N", "postponed": "0", "estimate": "PT1H"
+                    }],
+                }],
+            }],
+        }))
+        .unwrap();
+        let options = IcalOptions { events: true };
+        let ics = to_ical(&tasks.entries(), &options, &chrono_tz::Europe::London);
+        let lines: Vec<&str> = ics.split("\r\n").collect();
+        let common = [
+            "DTSTAMP:20261002T090000Z",
+            "CREATED:20261001T090000Z",
+            "LAST-MODIFIED:20261002T090000Z",
+            "SUMMARY:Write report\\, then send",
+            "URL:https://example.com/",
+            "CATEGORIES:work,urgent",
+            "DESCRIPTION:Outline\\nIntro\\nSummary",
+            "RRULE:FREQ=WEEKLY;INTERVAL=1",
+        ];
+        let mut expected = vec![
+            "BEGIN:VCALENDAR",
+            "VERSION:2.0",
+            concat!(
+                "PRODID:-//rememberthemilk-rs//",
+                env!("CARGO_PKG_VERSION"),
+                "//EN"
+            ),
+            "BEGIN:VTODO",
+            "UID:2-3@rememberthemilk",
+        ];
+        expected.extend(common);
+        expected.extend([
+            "DUE;VALUE=DATE:20261020",
+            "PRIORITY:5",
+            "STATUS:NEEDS-ACTION",
+            "END:VTODO",
+            "BEGIN:VEVENT",
+            "UID:event-2-3@rememberthemilk",
+        ]);
+        expected.extend(common);
+        expected.extend([
+            "DTSTART;VALUE=DATE:20261020",
+            "TRANSP:TRANSPARENT",
+            "END:VEVENT",
+            "BEGIN:VTODO",
+            "UID:2-5@rememberthemilk",
+        ]);
+        expected.extend(common);
+        expected.extend([
+            "DUE:20261012T143000Z",
+            "STATUS:COMPLETED",
+            "COMPLETED:20261012T150000Z",
+            "END:VTODO",
+            "BEGIN:VEVENT",
+            "UID:event-2-5@rememberthemilk",
+        ]);
+        expected.extend(common);
+        expected.extend([
+            "DTSTART:20261012T143000Z",
+            "DURATION:PT3600S",
+            "TRANSP:TRANSPARENT",
+            "END:VEVENT",
+            "END:VCALENDAR",
+            "",
+        ]);
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn test_folding() {
+        let mut writer = super::Writer::default();
+        writer.line("SUMMARY", &"é".repeat(40));
+        for line in writer.out.split("\r\n") {
+            assert!(line.len() <= 75, "{line:?}");
+        }
+        assert_eq!(
+            writer.out.replace("\r\n ", ""),
+            format!("SUMMARY:{}\r\n", "é".repeat(40))
+        );
+    }
+
+    #[test]
+    fn test_parse_todos() {
+        let tz = chrono_tz::Europe::London;
+        let ics = "BEGIN:VCALENDAR\r\n\
+            VERSION:2.0\r\n\
+            BEGIN:VEVENT\r\n\
+            SUMMARY:Not a to-do\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VTODO\r\n\
+            UID:plan-1\r\n\
+            SUMMARY:Draft\\, review\r\n\
+            DESCRIPTION:A long description which has been folded over two\r\n  \
+             lines\\nand has a newline\r\n\
+            DUE;VALUE=DATE:20261020\r\n\
+            PRIORITY:7\r\n\
+            CATEGORIES:client,plan\\,b\r\n\
+            RRULE:FREQ=WEEKLY;INTERVAL=2\r\n\
+            BEGIN:VALARM\r\n\
+            DESCRIPTION:Reminder\r\n\
+            END:VALARM\r\n\
+            END:VTODO\r\n\
+            BEGIN:VTODO\r\n\
+            SUMMARY:Floating\r\n\
+            DUE:20260701T090000\r\n\
+            STATUS:COMPLETED\r\n\
+            END:VTODO\r\n\
+            END:VCALENDAR\r\n";
+        let todos = parse_todos(ics, &tz).unwrap();
+        assert_eq!(
+            todos,
+            [
+                IcalTodo {
+                    uid: Some("plan-1".into()),
+                    summary: "Draft, review".into(),
+                    due: Some(chrono::Utc.with_ymd_and_hms(2026, 10, 19, 23, 0, 0).unwrap()),
+                    has_due_time: false,
+                    priority: 7,
+                    categories: vec!["client".into(), "plan,b".into()],
+                    description: Some(
+                        "A long description which has been folded over two lines\nand has a newline"
+                            .into()
+                    ),
+                    url: None,
+                    rrule: Some("FREQ=WEEKLY;INTERVAL=2".into()),
+                    completed: false,
+                },
+                IcalTodo {
+                    summary: "Floating".into(),
+                    // 09:00 BST.
+                    due: Some(chrono::Utc.with_ymd_and_hms(2026, 7, 1, 8, 0, 0).unwrap()),
+                    has_due_time: true,
+                    completed: true,
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(todos[0].rtm_priority(), "3");
+
+        for bad in [
+            "BEGIN:VTODO\r\nSUMMARY:x\r\n",
+            "BEGIN:VTODO\r\nEND:VTODO\r\n",
+            "BEGIN:VTODO\r\nSUMMARY:x\r\nDUE:tomorrow\r\nEND:VTODO\r\n",
+            "no colon\r\n",
+        ] {
+            assert!(parse_todos(bad, &tz).is_err(), "{bad:?}");
+        }
+    }
+}
@@ -58,8 +58,22 @@ use chrono::{DateTime, Duration, NaiveTime, Utc};
 use serde::{de::DeserializeOwned, de::Unexpected, Deserialize, Serialize};
 use serde_json::{from_reader, from_str};
 
+pub mod agenda;
+pub mod backup;
+pub mod bulk;
 #[cfg(feature = "cache")]
 pub mod cache;
+pub mod edit;
+pub mod filter;
+pub mod ical;
+pub mod recur;
+pub mod report;
+pub mod secret;
+pub mod smartadd;
+pub mod sort;
+pub mod taskwarrior;
+pub mod template;
+pub mod todotxt;
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename = "err")]
@@ -85,6 +99,10 @@ pub struct RTMConfig {
     pub token: Option<String>,
     /// Details of the currently authenticated user.
     pub user: Option<User>,
+    /// Where the API secret and token are kept, if not in this
+    /// configuration.  See the [secret] module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_store: Option<secret::SecretStoreConfig>,
 }
 
 impl RTMConfig {
@@ -102,6 +120,7 @@ pub struct API {
     api_secret: String,
     token: Option<String>,
     user: Option<User>,
+    secret_store: Option<secret::SecretStoreConfig>,
     #[cfg(test)]
     server: std::rc::Rc<mockito::ServerGuard>,
 }
@@ -174,6 +193,23 @@ pub struct User {
     fullname: String,
 }
 
+impl User {
+    /// The user's unique ID.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The user's username.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// The user's full name.
+    pub fn fullname(&self) -> &str {
+        &self.fullname
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(rename = "auth")]
 struct Auth {
@@ -240,6 +276,15 @@ where
     }
 }
 
+/// Serialise a bool as "0" or "1", as RTM sends it, so that it can be read
+/// back with [bool_from_string].
+fn bool_to_string<S>(value: &bool, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(if *value { "1" } else { "0" })
+}
+
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 #[serde(untagged)]
 enum TagSer {
@@ -377,6 +422,9 @@ pub struct Task {
     pub completed: Option<DateTime<Utc>>,
     /// The task's priority
     pub priority: String,
+    /// The time estimate, if any, such as `PT1H30M`.
+    #[serde(default)]
+    pub estimate: String,
 }
 
 /// Describes how much time is left to complete this task, or perhaps
@@ -459,6 +507,16 @@ pub struct RTMList {
     pub id: String,
     /// The name of this list.
     pub name: String,
+    /// True if this is a smart list, whose contents are defined by a search.
+    #[serde(
+        default,
+        deserialize_with = "bool_from_string",
+        serialize_with = "bool_to_string"
+    )]
+    pub smart: bool,
+    /// The search filter defining a smart list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -482,28 +540,15 @@ struct ListsResponse {
     lists: ListContainer,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-struct AddTagResponse {
-    stat: Stat,
-    list: RTMLists,
-}
-
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
 struct UndoResponse {
     stat: Stat,
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-struct MarkDoneResponse {
+struct ModifyTaskResponse {
     stat: Stat,
     transaction: Option<RTMTransaction>,
-    list: RTMLists,
-}
-
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
-struct SetURLResponse {
-    stat: Stat,
-    list: RTMLists,
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -555,6 +600,15 @@ pub struct AuthState {
     pub url: String,
 }
 
+/// The system's time zone, for when the user's RTM time zone isn't known,
+/// or UTC if it can't be found.
+pub fn system_timezone() -> chrono_tz::Tz {
+    iana_time_zone::get_timezone()
+        .ok()
+        .and_then(|tz| tz.parse().ok())
+        .unwrap_or(chrono_tz::UTC)
+}
+
 impl API {
     /// Create a new rememberthemilk API instance, with no user associated.
     ///
@@ -568,6 +622,7 @@ impl API {
             api_secret,
             token: None,
             user: None,
+            secret_store: None,
             #[cfg(test)]
             server: std::rc::Rc::new(mockito::Server::new()),
         }
@@ -581,6 +636,7 @@ impl API {
             api_secret,
             token: None,
             user: None,
+            secret_store: None,
             server: std::rc::Rc::new(server),
         }
     }
@@ -591,16 +647,26 @@ impl API {
     /// token.  If not, then the next step is callnig [API::start_auth].
     ///
     /// The `config` will usually be generated from a previous session, where
-    /// [API::to_config] was used to save the session state.
-    pub fn from_config(config: RTMConfig) -> API {
-        API {
-            api_key: config.api_key.unwrap(),
-            api_secret: config.api_secret.unwrap(),
+    /// [API::to_config] was used to save the session state.  If it has a
+    /// [secret store](RTMConfig::secret_store), the API secret and token
+    /// are loaded from it (see [RTMConfig::load_secrets]).
+    ///
+    /// Fails if the secret store can't be read, or there is no API key or
+    /// secret.
+    pub fn from_config(mut config: RTMConfig) -> Result<API, Error> {
+        config.load_secrets(None)?;
+        let (Some(api_key), Some(api_secret)) = (config.api_key, config.api_secret) else {
+            bail!("No API key and secret configured");
+        };
+        Ok(API {
+            api_key,
+            api_secret,
             token: config.token,
             user: config.user,
+            secret_store: config.secret_store,
             #[cfg(test)]
             server: std::rc::Rc::new(mockito::Server::new()),
-        }
+        })
     }
 
     #[allow(missing_docs)]
@@ -611,6 +677,7 @@ impl API {
             api_secret: config.api_secret.unwrap(),
             token: config.token,
             user: config.user,
+            secret_store: config.secret_store,
             server: std::rc::Rc::new(server),
         }
     }
@@ -624,16 +691,24 @@ impl API {
     /// every time.
     ///
     /// Note that this contains app and user secrets, so should not be stored
-    /// anywhere where other users may be able to access.
+    /// anywhere where other users may be able to access.  Use
+    /// [RTMConfig::store_secrets] first to move them to the configured
+    /// secret store, if any.
     pub fn to_config(&self) -> RTMConfig {
         RTMConfig {
             api_key: Some(self.api_key.clone()),
             api_secret: Some(self.api_secret.clone()),
             token: self.token.clone(),
             user: self.user.clone(),
+            secret_store: self.secret_store.clone(),
         }
     }
 
+    /// The authenticated user, if known.
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
     fn sign_keys(&self, keys: &[(&str, &str)]) -> String {
         let mut my_keys = keys.iter().collect::<Vec<&(&str, &str)>>();
         my_keys.sort();
@@ -915,6 +990,13 @@ impl API {
         filter
     }
 
+    /// Return a [Filter](filter::Filter) which matches external id
+    /// `extid`, added by the current application.  Fails if `extid`
+    /// contains `"`, which can't be written in a filter.
+    pub fn filter_extid(&self, extid: &str) -> Result<filter::Filter, Error> {
+        filter::Filter::source(format!("api:{}:{}", self.api_key, extid))
+    }
+
     /// Retrieve a filtered list of tasks within one list.
     ///
     /// The `list_id` is a lists's id.
@@ -979,6 +1061,18 @@ impl API {
         }
     }
 
+    /// Return the time zone from the user's RTM settings, or `None` if it
+    /// isn't set (or isn't one chrono-tz knows).  Dates such as "today"
+    /// should be interpreted in this zone rather than the local one.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn get_timezone(&self) -> Result<Option<chrono_tz::Tz>, Error> {
+        let rsp = self.call_method("rtm.settings.getList", &[]).await?;
+        Ok(rsp["settings"]["timezone"]
+            .as_str()
+            .and_then(|tz| tz.parse().ok()))
+    }
+
     /// Request a fresh remember timeline.
     ///
     /// A timeline is required for any request which modifies data on the
@@ -1040,11 +1134,130 @@ impl API {
         }
     }
 
-    /// Add one or more tags to a task.
+    /// Call a method which makes a change on a timeline, returning the
+    /// transaction.
+    ///
+    /// * `params` are passed in addition to the authentication and
+    ///   timeline.
+    async fn call_on_timeline(
+        &self,
+        timeline: &RTMTimeline,
+        method: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Option<RTMTransaction>, Error> {
+        let mut all_params = vec![("timeline", timeline.0.as_str())];
+        all_params.extend_from_slice(params);
+        let rsp = self.call_method(method, &all_params).await?;
+        Ok(serde_json::from_value::<ModifyTaskResponse>(rsp)?.transaction)
+    }
+
+    /// Call a method which modifies a single task, returning the
+    /// transaction.
+    ///
+    /// * `params` are passed in addition to the authentication, timeline
+    ///   and task ids.
+    async fn modify_task(
+        &self,
+        timeline: &RTMTimeline,
+        method: &str,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Option<RTMTransaction>, Error> {
+        let mut all_params = vec![
+            ("list_id", list_id),
+            ("taskseries_id", taskseries_id),
+            ("task_id", task_id),
+        ];
+        all_params.extend_from_slice(params);
+        self.call_on_timeline(timeline, method, &all_params).await
+    }
+
+    /// Call an authenticated method, returning the `rsp` object.
+    ///
+    /// * `params` are passed in addition to the authentication.
+    async fn call_method(
+        &self,
+        method: &str,
+        params: &[(&str, &str)],
+    ) -> Result<serde_json::Value, Error> {
+        let Some(ref tok) = self.token else {
+            bail!("Unable to call {}", method)
+        };
+        let mut all_params = vec![
+            ("method", method),
+            ("format", "json"),
+            ("api_key", &self.api_key),
+            ("auth_token", tok),
+        ];
+        all_params.extend_from_slice(params);
+        let response = self
+            .make_authenticated_request(&self.get_rest_url(), &all_params)
+            .await?;
+        log::trace!("{} response: {}", method, response);
+        let mut response: serde_json::Value = from_str(&response)?;
+        let rsp = response
+            .get_mut("rsp")
+            .ok_or_else(|| anyhow::anyhow!("Response did not have rsp field"))?
+            .take();
+        if rsp["stat"] != "ok" {
+            bail!("Error calling {}: {}", method, rsp["err"]["msg"]);
+        }
+        Ok(rsp)
+    }
+
+    /// Add a list, or a smart list if `filter` is given.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn add_list(
+        &self,
+        timeline: &RTMTimeline,
+        name: &str,
+        filter: Option<&str>,
+    ) -> Result<RTMList, Error> {
+        let mut params = vec![("timeline", timeline.0.as_str()), ("name", name)];
+        if let Some(filter) = filter {
+            params.push(("filter", filter));
+        }
+        let mut rsp = self.call_method("rtm.lists.add", &params).await?;
+        Ok(serde_json::from_value(rsp["list"].take())?)
+    }
+
+    /// Archive a list.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn archive_list(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        let mut rsp = self
+            .call_method(
+                "rtm.lists.archive",
+                &[("timeline", &timeline.0), ("list_id", list_id)],
+            )
+            .await?;
+        Ok(serde_json::from_value(rsp["transaction"].take())?)
+    }
+
+    /// Add a contact, by username or email address.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn add_contact(&self, timeline: &RTMTimeline, contact: &str) -> Result<(), Error> {
+        self.call_method(
+            "rtm.contacts.add",
+            &[("timeline", &timeline.0), ("contact", contact)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Set a task's URL.
     ///
     /// * `timeline`: a timeline as retrieved using [API::get_timeline]
-    /// * `list`, `taskseries` and `task` identify the task to tag.
-    /// * `url` is a String with url to add to this task.
+    /// * `list`, `taskseries` and `task` identify the task to change.
+    /// * `url` is the new URL, or empty to remove it.
     ///
     /// Requires a valid user authentication token.
     pub async fn set_url(
@@ -1055,30 +1268,29 @@ impl API {
         task: &Task,
         url: &str,
     ) -> Result<(), Error> {
-        if let Some(ref tok) = self.token {
-            let params = &[
-                ("method", "rtm.tasks.setURL"),
-                ("format", "json"),
-                ("api_key", &self.api_key),
-                ("auth_token", tok),
-                ("timeline", &timeline.0),
-                ("list_id", &list.id),
-                ("taskseries_id", &taskseries.id),
-                ("task_id", &task.id),
-                ("url", url),
-            ];
-            let response = self
-                .make_authenticated_request(&self.get_rest_url(), params)
-                .await?;
-            let rsp = from_str::<RTMResponse<SetURLResponse>>(&response)?.rsp;
-            if let Stat::Ok = rsp.stat {
-                Ok(())
-            } else {
-                bail!("Error adding task")
-            }
-        } else {
-            bail!("Unable to fetch tasks")
-        }
+        self.set_url_id(timeline, &list.id, &taskseries.id, &task.id, url)
+            .await?;
+        Ok(())
+    }
+
+    /// Set a task's URL, passing only ids.
+    pub async fn set_url_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        url: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setURL",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("url", url)],
+        )
+        .await
     }
 
     /// Add one or more tags to a task.
@@ -1096,31 +1308,53 @@ impl API {
         task: &Task,
         tags: &[&str],
     ) -> Result<(), Error> {
-        if let Some(ref tok) = self.token {
-            let tags = tags.join(",");
-            let params = &[
-                ("method", "rtm.tasks.addTags"),
-                ("format", "json"),
-                ("api_key", &self.api_key),
-                ("auth_token", tok),
-                ("timeline", &timeline.0),
-                ("list_id", &list.id),
-                ("taskseries_id", &taskseries.id),
-                ("task_id", &task.id),
-                ("tags", &tags),
-            ];
-            let response = self
-                .make_authenticated_request(&self.get_rest_url(), params)
-                .await?;
-            let rsp = from_str::<RTMResponse<AddTagResponse>>(&response)?.rsp;
-            if let Stat::Ok = rsp.stat {
-                Ok(())
-            } else {
-                bail!("Error adding task")
-            }
-        } else {
-            bail!("Unable to add task")
-        }
+        self.add_tags_id(timeline, &list.id, &taskseries.id, &task.id, tags)
+            .await?;
+        Ok(())
+    }
+
+    /// Add one or more tags to a task, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn add_tags_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        tags: &[&str],
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.addTags",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("tags", &tags.join(","))],
+        )
+        .await
+    }
+
+    /// Remove one or more tags from a task, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn remove_tags_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        tags: &[&str],
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.removeTags",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("tags", &tags.join(","))],
+        )
+        .await
     }
 
     /// Mark a task as complete
@@ -1153,29 +1387,330 @@ impl API {
         taskseries_id: &str,
         task_id: &str,
     ) -> Result<Option<RTMTransaction>, Error> {
-        if let Some(ref tok) = self.token {
-            let params = &[
-                ("method", "rtm.tasks.complete"),
-                ("format", "json"),
-                ("api_key", &self.api_key),
-                ("auth_token", tok),
-                ("timeline", &timeline.0),
-                ("list_id", list_id),
-                ("taskseries_id", taskseries_id),
-                ("task_id", task_id),
-            ];
-            let response = self
-                .make_authenticated_request(&self.get_rest_url(), params)
-                .await?;
-            let rsp = from_str::<RTMResponse<MarkDoneResponse>>(&response)?.rsp;
-            if let Stat::Ok = rsp.stat {
-                Ok(rsp.transaction)
-            } else {
-                bail!("Error completing task")
-            }
-        } else {
-            bail!("Unable to complete task")
-        }
+        self.modify_task(
+            timeline,
+            "rtm.tasks.complete",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[],
+        )
+        .await
+    }
+
+    /// Mark a completed task as incomplete, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn mark_incomplete_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.uncomplete",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[],
+        )
+        .await
+    }
+
+    /// Delete a task, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn delete_task_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.delete",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[],
+        )
+        .await
+    }
+
+    /// Postpone a task by a day, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn postpone_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.postpone",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[],
+        )
+        .await
+    }
+
+    /// Rename a task, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn set_name_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        name: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setName",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("name", name)],
+        )
+        .await
+    }
+
+    /// Set a task's priority, passing only ids.
+    ///
+    /// * `priority` is "1", "2" or "3", or "N" for no priority.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn set_priority_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        priority: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setPriority",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("priority", priority)],
+        )
+        .await
+    }
+
+    /// Set a task's due date from a string which RTM parses, such as
+    /// "tomorrow 3pm", passing only ids.  An empty string removes the due
+    /// date.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn parse_due_date_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        due: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setDueDate",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("due", due), ("parse", "1")],
+        )
+        .await
+    }
+
+    /// Set a task's due date or time, passing only ids.  A date without a
+    /// time should be given as the start of the day in the user's time
+    /// zone.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn set_due_date_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        due: DateTime<Utc>,
+        has_due_time: bool,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        let due = due.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+        let has_due_time = if has_due_time { "1" } else { "0" };
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setDueDate",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("due", &due), ("has_due_time", has_due_time)],
+        )
+        .await
+    }
+
+    /// Set how a task repeats, passing only ids.  `repeat` is either a
+    /// description such as "every week" or a recurrence rule such as
+    /// `FREQ=WEEKLY;INTERVAL=1`; an empty string stops it repeating.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn set_recurrence_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        repeat: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setRecurrence",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("repeat", repeat)],
+        )
+        .await
+    }
+
+    /// Set a task's time estimate, such as "2 hours", passing only ids.  An
+    /// empty string removes the estimate.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn set_estimate_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        estimate: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setEstimate",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("estimate", estimate)],
+        )
+        .await
+    }
+
+    /// Set a task's location, passing only ids.  An empty `location_id`
+    /// removes the location.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn set_location_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        location_id: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.setLocation",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("location_id", location_id)],
+        )
+        .await
+    }
+
+    /// Move a task to another list, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn move_to_id(
+        &self,
+        timeline: &RTMTimeline,
+        from_list_id: &str,
+        to_list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        let params = &[
+            ("from_list_id", from_list_id),
+            ("to_list_id", to_list_id),
+            ("taskseries_id", taskseries_id),
+            ("task_id", task_id),
+        ];
+        self.call_on_timeline(timeline, "rtm.tasks.moveTo", params)
+            .await
+    }
+
+    /// Add a note to a task, passing only ids.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn add_note_id(
+        &self,
+        timeline: &RTMTimeline,
+        list_id: &str,
+        taskseries_id: &str,
+        task_id: &str,
+        title: &str,
+        text: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        self.modify_task(
+            timeline,
+            "rtm.tasks.notes.add",
+            list_id,
+            taskseries_id,
+            task_id,
+            &[("note_title", title), ("note_text", text)],
+        )
+        .await
+    }
+
+    /// Change a note's title and text.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn edit_note(
+        &self,
+        timeline: &RTMTimeline,
+        note_id: &str,
+        title: &str,
+        text: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        let mut rsp = self
+            .call_method(
+                "rtm.tasks.notes.edit",
+                &[
+                    ("timeline", &timeline.0),
+                    ("note_id", note_id),
+                    ("note_title", title),
+                    ("note_text", text),
+                ],
+            )
+            .await?;
+        Ok(serde_json::from_value(rsp["transaction"].take())?)
+    }
+
+    /// Delete a note.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn delete_note(
+        &self,
+        timeline: &RTMTimeline,
+        note_id: &str,
+    ) -> Result<Option<RTMTransaction>, Error> {
+        let mut rsp = self
+            .call_method(
+                "rtm.tasks.notes.delete",
+                &[("timeline", &timeline.0), ("note_id", note_id)],
+            )
+            .await?;
+        Ok(serde_json::from_value(rsp["transaction"].take())?)
     }
 
     /// Add a new task
@@ -1196,6 +1731,27 @@ impl API {
         external_id: Option<&str>,
         smart: bool,
     ) -> Result<Option<RTMLists>, Error> {
+        let list_id = list.map(|list| list.id.as_str());
+        let parent_id = parent.map(|parent| parent.id.as_str());
+        let (added, _) = self
+            .add_task_id(timeline, name, list_id, parent_id, external_id, smart)
+            .await?;
+        Ok(added)
+    }
+
+    /// Add a new task, as [API::add_task], passing only ids.  The
+    /// transaction is returned too, for undoing.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn add_task_id(
+        &self,
+        timeline: &RTMTimeline,
+        name: &str,
+        list_id: Option<&str>,
+        parent_id: Option<&str>,
+        external_id: Option<&str>,
+        smart: bool,
+    ) -> Result<(Option<RTMLists>, Option<RTMTransaction>), Error> {
         if let Some(ref tok) = self.token {
             let mut params = vec![
                 ("method", "rtm.tasks.add"),
@@ -1205,11 +1761,11 @@ impl API {
                 ("timeline", &timeline.0),
                 ("name", name),
             ];
-            if let Some(list) = list {
-                params.push(("list_id", &list.id));
+            if let Some(list_id) = list_id {
+                params.push(("list_id", list_id));
             }
-            if let Some(parent) = parent {
-                params.push(("task_id", &parent.id));
+            if let Some(parent_id) = parent_id {
+                params.push(("task_id", parent_id));
             }
             if let Some(external_id) = external_id {
                 params.push(("external_id", external_id));
@@ -1223,19 +1779,10 @@ impl API {
             log::trace!("Add task response: {}", response);
             let rsp = from_str::<RTMResponse<AddTaskResponse>>(&response)?.rsp;
             if let Stat::Ok = rsp.stat {
-                if let Some(list) = rsp.list {
-                    if let Some(series) = &list.taskseries {
-                        if !series.is_empty() {
-                            Ok(Some(list))
-                        } else {
-                            Ok(None)
-                        }
-                    } else {
-                        Ok(None)
-                    }
-                } else {
-                    Ok(None)
-                }
+                let list = rsp
+                    .list
+                    .filter(|list| list.taskseries.as_ref().is_some_and(|s| !s.is_empty()));
+                Ok((list, rsp.transaction))
             } else {
                 bail!("Error adding task")
             }
@@ -0,0 +1,357 @@
+//! Expanding recurrence rules into future dates.
+//!
+//! This handles the subset of RFC 5545 recurrence rules which RTM produces:
+//! `FREQ` (daily, weekly, monthly or yearly), `INTERVAL`, `BYDAY` (with
+//! optional ordinals for monthly rules, such as `2TU` or `-1FR`),
+//! `BYMONTHDAY`, `COUNT` and `UNTIL`.  `COUNT` is parsed, but
+//! [Recurrence::dates_after] ignores it, as a task doesn't say which
+//! occurrence of its series it is.
+
+use anyhow::{anyhow, bail};
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+
+use crate::RRule;
+
+/// How often a rule repeats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    /// Every `interval` days.
+    Daily,
+    /// Every `interval` weeks.
+    Weekly,
+    /// Every `interval` months.
+    Monthly,
+    /// Every `interval` years.
+    Yearly,
+}
+
+/// A parsed recurrence rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    /// The basic frequency.
+    pub frequency: Frequency,
+    /// The number of periods between repeats.
+    pub interval: u32,
+    /// Days of the week, with an optional ordinal within the month (e.g.
+    /// `Some(-1)` for the last one).
+    pub by_day: Vec<(Option<i32>, Weekday)>,
+    /// Days of the month, negative counting back from the end.
+    pub by_month_day: Vec<i32>,
+    /// The total number of occurrences, counting from the first.  Not
+    /// used by [Recurrence::dates_after].
+    pub count: Option<u32>,
+    /// The last date an occurrence may fall on.
+    pub until: Option<NaiveDate>,
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    Some(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+impl std::str::FromStr for Recurrence {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Recurrence, crate::Error> {
+        let mut frequency = None;
+        let mut result = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            count: None,
+            until: None,
+        };
+        for part in s.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid recurrence rule part {part:?}"))?;
+            match key {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => bail!("Unsupported recurrence frequency {value:?}"),
+                    })
+                }
+                "INTERVAL" => {
+                    result.interval = value.parse()?;
+                    if result.interval == 0 {
+                        bail!("Invalid recurrence interval 0");
+                    }
+                }
+                "BYDAY" => {
+                    for day in value.split(',') {
+                        let split = day.len().saturating_sub(2);
+                        let (ordinal, weekday) = day.split_at(split);
+                        let weekday = parse_weekday(weekday)
+                            .ok_or_else(|| anyhow!("Invalid day {day:?} in recurrence rule"))?;
+                        let ordinal = match ordinal {
+                            "" => None,
+                            n => Some(n.parse()?),
+                        };
+                        result.by_day.push((ordinal, weekday));
+                    }
+                }
+                "BYMONTHDAY" => {
+                    for day in value.split(',') {
+                        result.by_month_day.push(day.parse()?);
+                    }
+                }
+                "COUNT" => result.count = Some(value.parse()?),
+                "UNTIL" => {
+                    let date = value.get(..8).unwrap_or(value);
+                    result.until = Some(NaiveDate::parse_from_str(date, "%Y%m%d")?);
+                }
+                // The week start doesn't affect the rules RTM produces.
+                "WKST" => (),
+                _ => bail!("Unsupported recurrence rule part {key:?}"),
+            }
+        }
+        result.frequency = frequency.ok_or_else(|| anyhow!("Recurrence rule has no FREQ"))?;
+        Ok(result)
+    }
+}
+
+impl RRule {
+    /// Parse the recurrence rule.
+    pub fn recurrence(&self) -> Result<Recurrence, crate::Error> {
+        self.rule.parse()
+    }
+}
+
+/// The last day of a month.
+fn month_end(year: i32, month: u32) -> NaiveDate {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+    first + Months::new(1) - Days::new(1)
+}
+
+impl Recurrence {
+    /// The period between repeats as RTM writes it, such as "1 week" or
+    /// "3 days", for use after "every" or "after".  Only the frequency and
+    /// interval are included.
+    pub fn period_text(&self) -> String {
+        let unit = match self.frequency {
+            Frequency::Daily => "day",
+            Frequency::Weekly => "week",
+            Frequency::Monthly => "month",
+            Frequency::Yearly => "year",
+        };
+        let plural = if self.interval == 1 { "" } else { "s" };
+        format!("{} {unit}{plural}", self.interval)
+    }
+
+    /// The candidate dates in the period (day, week, month or year)
+    /// containing `period`, in order.
+    fn dates_in_period(&self, start: NaiveDate, period: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = match self.frequency {
+            Frequency::Daily => vec![period],
+            Frequency::Weekly => {
+                let monday = period.week(Weekday::Mon).first_day();
+                let weekdays: Vec<Weekday> = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|&(_, day)| day).collect()
+                };
+                weekdays
+                    .into_iter()
+                    .map(|day| monday + Days::new(day.num_days_from_monday().into()))
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let (year, month) = (period.year(), period.month());
+                let last = month_end(year, month);
+                let mut dates = Vec::new();
+                for &(ordinal, weekday) in &self.by_day {
+                    let matching: Vec<NaiveDate> = (1..=last.day())
+                        .filter_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+                        .filter(|d| d.weekday() == weekday)
+                        .collect();
+                    match ordinal {
+                        None => dates.extend(matching),
+                        Some(n) if n > 0 => dates.extend(matching.get(n as usize - 1)),
+                        Some(n) if n < 0 => {
+                            let from_end = n.unsigned_abs() as usize;
+                            if from_end <= matching.len() {
+                                dates.push(matching[matching.len() - from_end]);
+                            }
+                        }
+                        Some(_) => (),
+                    }
+                }
+                for &day in &self.by_month_day {
+                    let day = if day < 0 {
+                        last.day() as i32 + 1 + day
+                    } else {
+                        day
+                    };
+                    if day > 0 {
+                        dates.extend(NaiveDate::from_ymd_opt(year, month, day as u32));
+                    }
+                }
+                if self.by_day.is_empty() && self.by_month_day.is_empty() {
+                    dates.extend(NaiveDate::from_ymd_opt(year, month, start.day()));
+                }
+                dates
+            }
+            Frequency::Yearly => NaiveDate::from_ymd_opt(period.year(), start.month(), start.day())
+                .into_iter()
+                .collect(),
+        };
+        dates.sort();
+        dates.dedup();
+        dates
+    }
+
+    /// The start of the `n`th period after the one containing `start`.
+    fn period(&self, start: NaiveDate, n: u64) -> Option<NaiveDate> {
+        let n = n.checked_mul(self.interval.into())?;
+        match self.frequency {
+            Frequency::Daily => start.checked_add_days(Days::new(n)),
+            Frequency::Weekly => start.checked_add_days(Days::new(n.checked_mul(7)?)),
+            Frequency::Monthly | Frequency::Yearly => {
+                let months = if self.frequency == Frequency::Yearly {
+                    n.checked_mul(12)?
+                } else {
+                    n
+                };
+                start
+                    .with_day(1)?
+                    .checked_add_months(Months::new(months.try_into().ok()?))
+            }
+        }
+    }
+
+    /// Return the dates of the occurrences after the one on `start`, up to
+    /// and including `last`.  `COUNT` is ignored, as it counts from the
+    /// start of the series, which may be long before `start`.
+    pub fn dates_after(&self, start: NaiveDate, last: NaiveDate) -> Vec<NaiveDate> {
+        let last = match self.until {
+            Some(until) => last.min(until),
+            None => last,
+        };
+        let mut result = Vec::new();
+        for n in 0.. {
+            let Some(period) = self.period(start, n) else {
+                break;
+            };
+            let period_start = match self.frequency {
+                Frequency::Weekly => period.week(Weekday::Mon).first_day(),
+                _ => period,
+            };
+            if period_start > last {
+                break;
+            }
+            for date in self.dates_in_period(start, period) {
+                if date <= start || date > last {
+                    continue;
+                }
+                result.push(date);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Frequency, Recurrence};
+    use chrono::{NaiveDate, Weekday};
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn dates(rule: &str, start: &str, last: &str) -> Vec<String> {
+        let rule: Recurrence = rule.parse().unwrap();
+        rule.dates_after(date(start), date(last))
+            .into_iter()
+            .map(|d| d.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let rule: Recurrence = "FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR,2TU;WKST=MO"
+            .parse()
+            .unwrap();
+        assert_eq!(rule.frequency, Frequency::Monthly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(
+            rule.by_day,
+            [(Some(-1), Weekday::Fri), (Some(2), Weekday::Tue)]
+        );
+        assert_eq!(rule.period_text(), "2 months");
+        let daily: Recurrence = "FREQ=DAILY;INTERVAL=1".parse().unwrap();
+        assert_eq!(daily.period_text(), "1 day");
+        for bad in [
+            "",
+            "INTERVAL=2",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;BYDAY=XX",
+            "FREQ",
+        ] {
+            assert!(bad.parse::<Recurrence>().is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_dates_after() {
+        assert_eq!(
+            dates("FREQ=DAILY;INTERVAL=2", "2026-10-30", "2026-11-04"),
+            ["2026-11-01", "2026-11-03"]
+        );
+        // 2026-10-19 is a Monday.
+        assert_eq!(
+            dates(
+                "FREQ=WEEKLY;INTERVAL=1;BYDAY=MO,TH",
+                "2026-10-19",
+                "2026-10-29"
+            ),
+            ["2026-10-22", "2026-10-26", "2026-10-29"]
+        );
+        assert_eq!(
+            dates("FREQ=WEEKLY;INTERVAL=2", "2026-10-21", "2026-11-30"),
+            ["2026-11-04", "2026-11-18"]
+        );
+        assert_eq!(
+            dates("FREQ=MONTHLY;INTERVAL=1", "2026-01-31", "2026-05-31"),
+            ["2026-03-31", "2026-05-31"]
+        );
+        assert_eq!(
+            dates("FREQ=MONTHLY;BYDAY=-1FR", "2026-10-30", "2027-01-31"),
+            ["2026-11-27", "2026-12-25", "2027-01-29"]
+        );
+        assert_eq!(
+            dates("FREQ=MONTHLY;BYMONTHDAY=1,-1", "2026-10-01", "2026-11-30"),
+            ["2026-10-31", "2026-11-01", "2026-11-30"]
+        );
+        assert_eq!(
+            dates("FREQ=YEARLY", "2024-02-29", "2029-01-01"),
+            ["2028-02-29"]
+        );
+        // The task may not be the first of the series, so COUNT is ignored.
+        assert_eq!(
+            dates("FREQ=DAILY;COUNT=3", "2026-10-19", "2026-10-23"),
+            ["2026-10-20", "2026-10-21", "2026-10-22", "2026-10-23"]
+        );
+        assert_eq!(
+            dates(
+                "FREQ=DAILY;UNTIL=20261021T000000",
+                "2026-10-19",
+                "2026-12-01"
+            ),
+            ["2026-10-20", "2026-10-21"]
+        );
+    }
+}
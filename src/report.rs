@@ -0,0 +1,451 @@
+//! Rendering tasks as a Markdown or HTML report.
+//!
+//! Tasks are grouped by list, with subtasks nested under their parents.
+//! The output is built from three [ReportTemplates], which can be replaced
+//! to change the layout.  Templates contain `{{name}}` placeholders:
+//!
+//! * page: `{{title}}`, `{{generated}}` and `{{lists}}`
+//! * list: `{{name}}` and `{{tasks}}`
+//! * task: `{{checkbox}}`, `{{name}}`, `{{due}}`, `{{tags}}`, `{{link}}`,
+//!   `{{notes}}` and `{{subtasks}}`
+//!
+//! Values are escaped for the format before being substituted.
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::bail;
+use chrono::{DateTime, TimeZone};
+
+use crate::sort::{group_entries, DueBucket, GroupBy, GroupKey, TaskEntry};
+
+/// The format of a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// Markdown, with task list checkboxes.
+    Markdown,
+    /// A standalone HTML page.
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ReportFormat, anyhow::Error> {
+        Ok(match s {
+            "md" | "markdown" => ReportFormat::Markdown,
+            "html" => ReportFormat::Html,
+            _ => bail!("Unknown report format {s:?}"),
+        })
+    }
+}
+
+impl ReportFormat {
+    /// The usual file extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "md",
+            ReportFormat::Html => "html",
+        }
+    }
+
+    /// Escape text for the format.
+    fn escape(self, s: &str) -> String {
+        let mut out = String::with_capacity(s.len());
+        for c in s.chars() {
+            match (self, c) {
+                (ReportFormat::Html, '&') => out.push_str("&amp;"),
+                (ReportFormat::Html, '<') => out.push_str("&lt;"),
+                (ReportFormat::Html, '>') => out.push_str("&gt;"),
+                (ReportFormat::Html, '"') => out.push_str("&quot;"),
+                (ReportFormat::Markdown, '\\' | '*' | '_' | '[' | ']' | '`' | '<' | '>' | '#') => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                _ => out.push(c),
+            }
+        }
+        out
+    }
+}
+
+const MARKDOWN_PAGE: &str = "# {{title}}\n\n_Generated {{generated}}_\n\n{{lists}}";
+const MARKDOWN_LIST: &str = "## {{name}}\n\n{{tasks}}\n";
+const MARKDOWN_TASK: &str = "- {{checkbox}} {{name}}{{due}}{{tags}}{{link}}\n{{notes}}{{subtasks}}";
+
+const HTML_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{title}}</title>
+<style>
+body { font-family: sans-serif; max-width: 50em; margin: auto; }
+ul { list-style: none; padding-left: 1.5em; }
+.due { border-radius: 0.3em; padding: 0 0.3em; background: #ddd; }
+.due.overdue { background: #f99; }
+.tag { color: #666; }
+.note { margin: 0.2em 0 0.2em 1.5em; color: #444; }
+</style>
+</head>
+<body>
+<h1>{{title}}</h1>
+<p><em>Generated {{generated}}</em></p>
+{{lists}}</body>
+</html>
+"#;
+const HTML_LIST: &str = "<section>\n<h2>{{name}}</h2>\n<ul>\n{{tasks}}</ul>\n</section>\n";
+const HTML_TASK: &str =
+    "<li>{{checkbox}} {{name}}{{due}}{{tags}}{{link}}{{notes}}{{subtasks}}</li>\n";
+
+/// The templates a report is built from; see the [module](self)
+/// documentation for the placeholders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportTemplates {
+    /// The format the templates produce.
+    pub format: ReportFormat,
+    /// The whole report.
+    pub page: String,
+    /// Each list.
+    pub list: String,
+    /// Each task.
+    pub task: String,
+}
+
+impl ReportTemplates {
+    /// The default templates for a format.
+    pub fn new(format: ReportFormat) -> ReportTemplates {
+        let (page, list, task) = match format {
+            ReportFormat::Markdown => (MARKDOWN_PAGE, MARKDOWN_LIST, MARKDOWN_TASK),
+            ReportFormat::Html => (HTML_PAGE, HTML_LIST, HTML_TASK),
+        };
+        ReportTemplates {
+            format,
+            page: page.into(),
+            list: list.into(),
+            task: task.into(),
+        }
+    }
+}
+
+/// Replace the `{{name}}` placeholders in `template`.  Unknown placeholders
+/// are left alone, and the values aren't searched for placeholders.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let value = after.find("}}").and_then(|end| {
+            let name = after[..end].trim();
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (*value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                out.push_str(value);
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Renders tasks using a set of templates.
+struct Renderer<'a, 't, Tz: TimeZone> {
+    templates: &'t ReportTemplates,
+    now: &'t DateTime<Tz>,
+    /// The subtasks of each task, by parent task id.
+    children: HashMap<&'a str, Vec<TaskEntry<'a>>>,
+}
+
+impl<Tz: TimeZone> Renderer<'_, '_, Tz>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    fn format(&self) -> ReportFormat {
+        self.templates.format
+    }
+
+    fn due(&self, entry: &TaskEntry<'_>) -> String {
+        let Some(due) = entry.task.due else {
+            return String::new();
+        };
+        let due_local = due.with_timezone(&self.now.timezone());
+        let date = if entry.task.has_due_time {
+            due_local.format("%a %Y-%m-%d %H:%M").to_string()
+        } else {
+            due_local.format("%a %Y-%m-%d").to_string()
+        };
+        let overdue = entry.task.completed.is_none()
+            && DueBucket::of(entry.task, self.now) == DueBucket::Overdue;
+        match (self.format(), overdue) {
+            (ReportFormat::Markdown, false) => format!(" **due {date}**"),
+            (ReportFormat::Markdown, true) => format!(" **overdue {date}**"),
+            (ReportFormat::Html, false) => format!(" <span class=\"due\">due {date}</span>"),
+            (ReportFormat::Html, true) => {
+                format!(" <span class=\"due overdue\">overdue {date}</span>")
+            }
+        }
+    }
+
+    fn tags(&self, entry: &TaskEntry<'_>) -> String {
+        entry
+            .series
+            .tags
+            .iter()
+            .map(|tag| {
+                let tag = self.format().escape(tag);
+                match self.format() {
+                    ReportFormat::Markdown => format!(" \\#{tag}"),
+                    ReportFormat::Html => format!(" <span class=\"tag\">#{tag}</span>"),
+                }
+            })
+            .collect()
+    }
+
+    fn link(&self, entry: &TaskEntry<'_>) -> String {
+        let url = &entry.series.url;
+        if url.is_empty() {
+            return String::new();
+        }
+        match self.format() {
+            ReportFormat::Markdown => {
+                format!(" [link](<{}>)", url.replace('<', "%3C").replace('>', "%3E"))
+            }
+            ReportFormat::Html => {
+                format!(" <a href=\"{}\">link</a>", self.format().escape(url))
+            }
+        }
+    }
+
+    fn notes(&self, entry: &TaskEntry<'_>) -> String {
+        let mut out = String::new();
+        for note in &entry.series.notes {
+            let mut lines: Vec<String> = Vec::new();
+            if !note.title.is_empty() {
+                lines.push(match self.format() {
+                    ReportFormat::Markdown => format!("**{}**", self.format().escape(&note.title)),
+                    ReportFormat::Html => {
+                        format!("<strong>{}</strong>", self.format().escape(&note.title))
+                    }
+                });
+            }
+            lines.extend(note.text.lines().map(|line| self.format().escape(line)));
+            match self.format() {
+                ReportFormat::Markdown => {
+                    for line in lines {
+                        out.push_str("  > ");
+                        out.push_str(&line);
+                        out.push('\n');
+                    }
+                }
+                ReportFormat::Html => {
+                    out.push_str("<div class=\"note\">");
+                    out.push_str(&lines.join("<br>"));
+                    out.push_str("</div>");
+                }
+            }
+        }
+        out
+    }
+
+    fn task(&self, entry: &TaskEntry<'_>) -> String {
+        let format = self.format();
+        let done = entry.task.completed.is_some();
+        let checkbox = match (format, done) {
+            (ReportFormat::Markdown, false) => "[ ]",
+            (ReportFormat::Markdown, true) => "[x]",
+            (ReportFormat::Html, false) => "<input type=\"checkbox\" disabled>",
+            (ReportFormat::Html, true) => "<input type=\"checkbox\" disabled checked>",
+        };
+        let subtasks = match self.children.get(entry.task.id.as_str()) {
+            None => String::new(),
+            Some(children) => {
+                let inner: String = children.iter().map(|child| self.task(child)).collect();
+                match format {
+                    ReportFormat::Markdown => {
+                        inner.lines().map(|line| format!("  {line}\n")).collect()
+                    }
+                    ReportFormat::Html => format!("\n<ul>\n{inner}</ul>\n"),
+                }
+            }
+        };
+        fill(
+            &self.templates.task,
+            &[
+                ("checkbox", checkbox),
+                ("name", &format.escape(&entry.series.name)),
+                ("due", &self.due(entry)),
+                ("tags", &self.tags(entry)),
+                ("link", &self.link(entry)),
+                ("notes", &self.notes(entry)),
+                ("subtasks", &subtasks),
+            ],
+        )
+    }
+}
+
+/// Render tasks as a report titled `title`.  `list_names` maps list ids to
+/// names.  Subtasks are shown under their parent if it is one of the tasks
+/// and in the same list, and otherwise at the top level.  Due dates use
+/// `now`'s time zone.
+pub fn render_report<Tz: TimeZone>(
+    entries: &[TaskEntry<'_>],
+    list_names: &HashMap<String, String>,
+    templates: &ReportTemplates,
+    title: &str,
+    now: &DateTime<Tz>,
+) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let format = templates.format;
+    let mut lists = String::new();
+    for group in group_entries(entries.to_vec(), GroupBy::List, now) {
+        let GroupKey::List(list_id) = &group.key else {
+            continue;
+        };
+        let ids: HashSet<&str> = group.tasks.iter().map(|e| e.task.id.as_str()).collect();
+        let mut renderer = Renderer {
+            templates,
+            now,
+            children: HashMap::new(),
+        };
+        let mut top = Vec::new();
+        for entry in &group.tasks {
+            match entry.series.parent_task_id.as_deref() {
+                Some(parent) if ids.contains(parent) && parent != entry.task.id => {
+                    renderer.children.entry(parent).or_default().push(*entry)
+                }
+                _ => top.push(*entry),
+            }
+        }
+        let tasks: String = top.iter().map(|entry| renderer.task(entry)).collect();
+        let name = list_names.get(list_id).unwrap_or(list_id);
+        lists.push_str(&fill(
+            &templates.list,
+            &[("name", &format.escape(name)), ("tasks", &tasks)],
+        ));
+    }
+    fill(
+        &templates.page,
+        &[
+            ("title", &format.escape(title)),
+            ("generated", &now.format("%Y-%m-%d %H:%M").to_string()),
+            ("lists", &lists),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fill, render_report, ReportFormat, ReportTemplates};
+    use crate::RTMTasks;
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    fn tasks() -> RTMTasks {
+        let series = |id: &str, name: &str, parent: &str, due: &str, completed: &str| {
+            crate::tests::series(
+                id,
+                serde_json::json!({
+                    "name": name,
+                    "url": if id == "1" { "https://example.com/a_b" } else { "" },
+                    "tags": if id == "1" { serde_json::json!({"tag": ["work"]}) } else { serde_json::json!([]) },
+                    "notes": if id == "2" {
+                        serde_json::json!({"note": [{
+                            "id": "9", "created": "2026-10-01T09:00:00Z",
+                            "modified": "2026-10-01T09:00:00Z", "title": "Why",
+                            "$t": "Because <reasons>"
+                        }]})
+                    } else {
+                        serde_json::json!([])
+                    },
+                    "parent_task_id": parent,
+                }),
+                serde_json::json!({"due": due, "completed": completed}),
+            )
+        };
+        serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "100",
+                "taskseries": [
+                    series("1", "Write *report*", "", "2026-10-15T23:00:00Z", ""),
+                    series("2", "Gather figures", "1", "", "2026-10-16T10:00:00Z"),
+                ],
+            }, {
+                "id": "200",
+                "taskseries": [series("3", "Plan", "", "2026-10-21T23:00:00Z", "")],
+            }],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let tasks = tasks();
+        let list_names = HashMap::from([("100".to_string(), "Work".to_string())]);
+        let now = chrono_tz::Europe::London
+            .with_ymd_and_hms(2026, 10, 18, 12, 0, 0)
+            .unwrap();
+        let templates = ReportTemplates::new(ReportFormat::Markdown);
+        let report = render_report(&tasks.entries(), &list_names, &templates, "Week 42", &now);
+        assert_eq!(
+            report,
+            "# Week 42\n\n_Generated 2026-10-18 12:00_\n\n\
+             ## Work\n\n\
+             - [ ] Write \\*report\\* **overdue Fri 2026-10-16** \\#work [link](<https://example.com/a_b>)\n\
+             \x20 - [x] Gather figures\n\
+             \x20   > **Why**\n\
+             \x20   > Because \\<reasons\\>\n\
+             \n\
+             ## 200\n\n\
+             - [ ] Plan **due Thu 2026-10-22**\n\n"
+        );
+    }
+
+    #[test]
+    fn test_render_html() {
+        let tasks = tasks();
+        let now = chrono_tz::Europe::London
+            .with_ymd_and_hms(2026, 10, 18, 12, 0, 0)
+            .unwrap();
+        let mut templates = ReportTemplates::new(ReportFormat::Html);
+        templates.page = "{{lists}}".into();
+        templates.list = "<h2>{{name}}</h2><ul>{{tasks}}</ul>\n".into();
+        let report = render_report(&tasks.entries()[..2], &HashMap::new(), &templates, "", &now);
+        assert_eq!(
+            report,
+            "<h2>100</h2><ul><li><input type=\"checkbox\" disabled> Write *report* \
+             <span class=\"due overdue\">overdue Fri 2026-10-16</span> \
+             <span class=\"tag\">#work</span> <a href=\"https://example.com/a_b\">link</a>\n\
+             <ul>\n\
+             <li><input type=\"checkbox\" disabled checked> Gather figures\
+             <div class=\"note\"><strong>Why</strong><br>Because &lt;reasons&gt;</div></li>\n\
+             </ul>\n\
+             </li>\n\
+             </ul>\n"
+        );
+    }
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(
+            fill("{{a}}, {{ b }}, {{c}}, {{a", &[("a", "{{b}}"), ("b", "2")]),
+            "{{b}}, 2, {{c}}, {{a"
+        );
+        assert!("pdf".parse::<ReportFormat>().is_err());
+        assert_eq!(
+            "md".parse::<ReportFormat>().unwrap(),
+            ReportFormat::Markdown
+        );
+    }
+}
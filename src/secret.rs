@@ -0,0 +1,440 @@
+//! Keeping the API secret and user token out of the plain configuration.
+//!
+//! By default [RTMConfig] holds the API secret and authentication token
+//! itself, and whoever saves it is responsible for keeping the file
+//! private (see [restrict_permissions]).  Setting
+//! [RTMConfig::secret_store] moves them into a [SecretStore] instead:
+//!
+//! * [SecretStoreConfig::File]: a separate file, readable only by its
+//!   owner.
+//! * [SecretStoreConfig::Encrypted]: a file encrypted with a passphrase,
+//!   taken from the `RTM_PASSPHRASE` environment variable unless given
+//!   explicitly.  This needs the `encrypted-secrets` feature.
+//! * [SecretStoreConfig::Command]: external commands which print and save
+//!   the secrets, such as `pass show rtm` and `pass insert -mf rtm`.
+//!
+//! [RTMConfig::store_secrets] saves the secrets and removes them from the
+//! configuration, and [RTMConfig::load_secrets] (called by
+//! [crate::API::from_config]) puts them back.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, RTMConfig};
+
+/// The environment variable holding the passphrase for
+/// [SecretStoreConfig::Encrypted], if not given explicitly.
+pub const PASSPHRASE_VAR: &str = "RTM_PASSPHRASE";
+
+/// The secret parts of an [RTMConfig].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Secrets {
+    /// The API secret.
+    pub api_secret: Option<String>,
+    /// The user authentication token.
+    pub token: Option<String>,
+}
+
+/// Somewhere to keep [Secrets].
+pub trait SecretStore {
+    /// Read the secrets, or `None` if none have been saved.
+    fn load(&self) -> Result<Option<Secrets>, Error>;
+    /// Save the secrets, replacing any already saved.
+    fn store(&self, secrets: &Secrets) -> Result<(), Error>;
+}
+
+/// Which [SecretStore] to use, as saved in [RTMConfig::secret_store].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SecretStoreConfig {
+    /// A file readable only by its owner.
+    File {
+        /// The file's path.
+        path: PathBuf,
+    },
+    /// A file encrypted with a passphrase.
+    Encrypted {
+        /// The file's path.
+        path: PathBuf,
+    },
+    /// External commands, run with `sh -c`.  The secrets are passed as
+    /// JSON: printed by `get` and written to the standard input of `set`.
+    Command {
+        /// The command printing the secrets.  It should succeed without
+        /// printing anything if none have been saved; if it fails, loading
+        /// the secrets fails.
+        get: String,
+        /// The command saving the secrets.
+        set: String,
+    },
+}
+
+impl SecretStoreConfig {
+    /// Open the configured store.  `passphrase` is only used by
+    /// [SecretStoreConfig::Encrypted], and defaults to the value of
+    /// [PASSPHRASE_VAR].
+    pub fn open(&self, passphrase: Option<&str>) -> Result<Box<dyn SecretStore>, Error> {
+        Ok(match self {
+            SecretStoreConfig::File { path } => Box::new(FileStore { path: path.clone() }),
+            SecretStoreConfig::Encrypted { path } => {
+                let passphrase = match passphrase {
+                    Some(passphrase) => passphrase.to_string(),
+                    None => std::env::var(PASSPHRASE_VAR).map_err(|_| {
+                        anyhow!("{PASSPHRASE_VAR} must be set to unlock {}", path.display())
+                    })?,
+                };
+                encrypted_store(path, passphrase)?
+            }
+            SecretStoreConfig::Command { get, set } => Box::new(CommandStore {
+                get: get.clone(),
+                set: set.clone(),
+            }),
+        })
+    }
+}
+
+#[cfg(feature = "encrypted-secrets")]
+fn encrypted_store(path: &Path, passphrase: String) -> Result<Box<dyn SecretStore>, Error> {
+    Ok(Box::new(EncryptedFileStore {
+        path: path.to_path_buf(),
+        passphrase,
+    }))
+}
+
+#[cfg(not(feature = "encrypted-secrets"))]
+fn encrypted_store(_path: &Path, _passphrase: String) -> Result<Box<dyn SecretStore>, Error> {
+    bail!("Encrypted secret stores need the encrypted-secrets feature")
+}
+
+/// Make `path` readable and writable only by its owner, if it isn't
+/// already.  Does nothing on platforms without Unix permissions.
+pub fn restrict_permissions(path: &Path) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        if permissions.mode() & 0o077 != 0 {
+            log::warn!("Making {} private", path.display());
+            permissions.set_mode(0o600);
+            std::fs::set_permissions(path, permissions)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Write a file which only its owner can read.
+fn write_private(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Opening {}", path.display()))?;
+    // The mode only applies to new files.
+    restrict_permissions(path)?;
+    file.write_all(data)?;
+    Ok(())
+}
+
+/// Read a file, or `None` if it doesn't exist.
+fn read_private(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    match std::fs::read(path) {
+        Ok(data) => {
+            restrict_permissions(path)?;
+            Ok(Some(data))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow!(e).context(format!("Reading {}", path.display()))),
+    }
+}
+
+/// Secrets kept as JSON in a file readable only by its owner.
+#[derive(Debug)]
+pub struct FileStore {
+    /// The file's path.
+    pub path: PathBuf,
+}
+
+impl SecretStore for FileStore {
+    fn load(&self) -> Result<Option<Secrets>, Error> {
+        match read_private(&self.path)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store(&self, secrets: &Secrets) -> Result<(), Error> {
+        write_private(&self.path, &serde_json::to_vec_pretty(secrets)?)
+    }
+}
+
+/// Secrets encrypted with ChaCha20-Poly1305, using a key derived from a
+/// passphrase with PBKDF2.
+#[cfg(feature = "encrypted-secrets")]
+pub struct EncryptedFileStore {
+    /// The file's path.
+    pub path: PathBuf,
+    /// The passphrase.
+    pub passphrase: String,
+}
+
+/// The contents of an [EncryptedFileStore] file.
+#[cfg(feature = "encrypted-secrets")]
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[cfg(feature = "encrypted-secrets")]
+impl EncryptedFileStore {
+    /// PBKDF2-HMAC-SHA256 iterations for new files.
+    const ITERATIONS: u32 = 600_000;
+
+    fn key(&self, salt: &[u8], iterations: u32) -> Result<ring::aead::LessSafeKey, Error> {
+        use ring::{aead, pbkdf2};
+
+        let iterations =
+            std::num::NonZeroU32::new(iterations).ok_or_else(|| anyhow!("Invalid iterations"))?;
+        let mut key = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            salt,
+            self.passphrase.as_bytes(),
+            &mut key,
+        );
+        let key = aead::UnboundKey::new(&aead::CHACHA20_POLY1305, &key)
+            .map_err(|_| anyhow!("Invalid key"))?;
+        Ok(aead::LessSafeKey::new(key))
+    }
+}
+
+#[cfg(feature = "encrypted-secrets")]
+impl SecretStore for EncryptedFileStore {
+    fn load(&self) -> Result<Option<Secrets>, Error> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use ring::aead;
+
+        let Some(data) = read_private(&self.path)? else {
+            return Ok(None);
+        };
+        let file: EncryptedFile = serde_json::from_slice(&data)?;
+        let salt = STANDARD.decode(file.salt)?;
+        let nonce = aead::Nonce::try_assume_unique_for_key(&STANDARD.decode(file.nonce)?)
+            .map_err(|_| anyhow!("Invalid nonce in {}", self.path.display()))?;
+        let mut data = STANDARD.decode(file.ciphertext)?;
+        let plaintext = self
+            .key(&salt, file.iterations)?
+            .open_in_place(nonce, aead::Aad::empty(), &mut data)
+            .map_err(|_| {
+                anyhow!(
+                    "Couldn't decrypt {}: wrong passphrase?",
+                    self.path.display()
+                )
+            })?;
+        Ok(Some(serde_json::from_slice(plaintext)?))
+    }
+
+    fn store(&self, secrets: &Secrets) -> Result<(), Error> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use ring::{aead, rand::SecureRandom};
+
+        let rng = ring::rand::SystemRandom::new();
+        let mut salt = [0; 16];
+        let mut nonce = [0; aead::NONCE_LEN];
+        rng.fill(&mut salt)
+            .and_then(|_| rng.fill(&mut nonce))
+            .map_err(|_| anyhow!("Couldn't generate random data"))?;
+        let mut data = serde_json::to_vec(secrets)?;
+        self.key(&salt, Self::ITERATIONS)?
+            .seal_in_place_append_tag(
+                aead::Nonce::assume_unique_for_key(nonce),
+                aead::Aad::empty(),
+                &mut data,
+            )
+            .map_err(|_| anyhow!("Couldn't encrypt secrets"))?;
+        let file = EncryptedFile {
+            iterations: Self::ITERATIONS,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(data),
+        };
+        write_private(&self.path, &serde_json::to_vec_pretty(&file)?)
+    }
+}
+
+/// Secrets kept by external commands.  See [SecretStoreConfig::Command].
+#[derive(Debug)]
+pub struct CommandStore {
+    /// The command printing the secrets.
+    pub get: String,
+    /// The command saving the secrets.
+    pub set: String,
+}
+
+impl SecretStore for CommandStore {
+    fn load(&self) -> Result<Option<Secrets>, Error> {
+        let output = std::process::Command::new("sh")
+            .args(["-c", &self.get])
+            .stderr(std::process::Stdio::inherit())
+            .output()
+            .with_context(|| format!("Running {:?}", self.get))?;
+        if !output.status.success() {
+            // Carrying on without the secrets would lead to them being
+            // replaced, so this has to stop here.
+            bail!("{:?} failed: {}", self.get, output.status);
+        }
+        if output.stdout.iter().all(u8::is_ascii_whitespace) {
+            return Ok(None);
+        }
+        let secrets = serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Parsing the output of {:?}", self.get))?;
+        Ok(Some(secrets))
+    }
+
+    fn store(&self, secrets: &Secrets) -> Result<(), Error> {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", &self.set])
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Running {:?}", self.set))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&serde_json::to_vec(secrets)?)?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{:?} failed: {status}", self.set);
+        }
+        Ok(())
+    }
+}
+
+impl RTMConfig {
+    /// Fill in the API secret and token from the configured
+    /// [secret store](RTMConfig::secret_store), if any.  Values already
+    /// present are kept.  See [SecretStoreConfig::open] for `passphrase`.
+    pub fn load_secrets(&mut self, passphrase: Option<&str>) -> Result<(), Error> {
+        let Some(store) = &self.secret_store else {
+            return Ok(());
+        };
+        if self.api_secret.is_some() && self.token.is_some() {
+            return Ok(());
+        }
+        if let Some(secrets) = store.open(passphrase)?.load()? {
+            self.api_secret = self.api_secret.take().or(secrets.api_secret);
+            self.token = self.token.take().or(secrets.token);
+        }
+        Ok(())
+    }
+
+    /// Save the API secret and token to the configured
+    /// [secret store](RTMConfig::secret_store), if any, and remove them
+    /// from this configuration so that it can be saved in plain text.
+    /// See [SecretStoreConfig::open] for `passphrase`.
+    pub fn store_secrets(&mut self, passphrase: Option<&str>) -> Result<(), Error> {
+        let Some(store) = &self.secret_store else {
+            return Ok(());
+        };
+        let secrets = Secrets {
+            api_secret: self.api_secret.clone(),
+            token: self.token.clone(),
+        };
+        store.open(passphrase)?.store(&secrets)?;
+        self.api_secret = None;
+        self.token = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SecretStoreConfig, Secrets};
+    use crate::RTMConfig;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rtm-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn config(store: SecretStoreConfig) -> RTMConfig {
+        RTMConfig {
+            api_key: Some("key".into()),
+            api_secret: Some("secret".into()),
+            token: Some("token".into()),
+            user: None,
+            secret_store: Some(store),
+        }
+    }
+
+    fn round_trip(store: SecretStoreConfig, passphrase: Option<&str>) {
+        let mut config = config(store);
+        config.store_secrets(passphrase).unwrap();
+        assert_eq!((&config.api_secret, &config.token), (&None, &None));
+        config.load_secrets(passphrase).unwrap();
+        assert_eq!(config.api_secret.as_deref(), Some("secret"));
+        assert_eq!(config.token.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn test_file_store() {
+        let path = temp_path("secrets.json");
+        round_trip(SecretStoreConfig::File { path: path.clone() }, None);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&path), 0o600);
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            round_trip(SecretStoreConfig::File { path: path.clone() }, None);
+            assert_eq!(mode(&path), 0o600);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "encrypted-secrets")]
+    #[test]
+    fn test_encrypted_store() {
+        let path = temp_path("secrets.enc");
+        let store = SecretStoreConfig::Encrypted { path: path.clone() };
+        round_trip(store.clone(), Some("open sesame"));
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+        assert!(store.open(Some("wrong")).unwrap().load().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_store() {
+        let path = temp_path("secrets.cmd");
+        let store = SecretStoreConfig::Command {
+            get: format!("cat {}", path.display()),
+            set: format!("cat > {}", path.display()),
+        };
+        // A failure isn't the same as having no secrets.
+        assert!(store.open(None).unwrap().load().is_err());
+        std::fs::write(&path, "\n").unwrap();
+        assert_eq!(store.open(None).unwrap().load().unwrap(), None);
+        round_trip(store.clone(), None);
+        let saved: Secrets = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved.token.as_deref(), Some("token"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,408 @@
+//! Parsing RTM's Smart Add syntax locally.
+//!
+//! [NewTask::parse] splits a task description such as
+//! `Call Bob ^tomorrow 5pm !1 #work =15 min //About the trip` into its
+//! parts, so that they can be shown before the task is added, and then
+//! applied with [API::add_new_task] instead of relying on the server's
+//! parsing.  The syntax is:
+//!
+//! * `^due`, `*repeat` and `=estimate`, which continue up to the next
+//!   Smart Add word
+//! * `!1`, `!2` or `!3` for the priority
+//! * `#tag`, or `#list` if there is a list of that name (see
+//!   [NewTask::resolve_list])
+//! * `@location`
+//! * `//note`, which takes the rest of the text
+//! * a URL starting with `http://`, `https://` or `www.`
+//!
+//! Anything else is part of the name.
+
+use std::fmt;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::{RTMList, RTMLists, RTMTimeline, RTMTransaction, Task, API};
+
+/// A task to add, as parsed from Smart Add text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewTask {
+    /// The name, with the Smart Add parts removed.
+    pub name: String,
+    /// The due date, as text for RTM to parse.
+    pub due: Option<String>,
+    /// The priority: "1", "2" or "3".
+    pub priority: Option<String>,
+    /// The tags.
+    pub tags: Vec<String>,
+    /// The name of the list to add to.
+    pub list: Option<String>,
+    /// The location name.
+    pub location: Option<String>,
+    /// How the task repeats, such as "every week".
+    pub repeat: Option<String>,
+    /// The time estimate, such as "15 min".
+    pub estimate: Option<String>,
+    /// A note.
+    pub note: Option<String>,
+    /// A URL.
+    pub url: Option<String>,
+}
+
+/// A Smart Add word, which ends a multi-word value.
+fn is_special(word: &str) -> bool {
+    let value = |prefix: &str| word.strip_prefix(prefix).is_some_and(|v| !v.is_empty());
+    value("^")
+        || value("*")
+        || value("=")
+        || value("#")
+        || value("@")
+        || matches!(word, "!1" | "!2" | "!3")
+        || word.starts_with("//")
+        || is_url(word)
+}
+
+fn is_url(word: &str) -> bool {
+    ["http://", "https://", "www."]
+        .iter()
+        .any(|prefix| word.len() > prefix.len() && word.starts_with(prefix))
+}
+
+impl NewTask {
+    /// Parse Smart Add text.  All `#` words are taken as tags; see
+    /// [NewTask::resolve_list] for choosing a list.
+    pub fn parse(text: &str) -> NewTask {
+        let mut task = NewTask::default();
+        let mut name = Vec::new();
+        let (text, note) = match text.split_once(" //") {
+            Some((text, note)) => (text, Some(note)),
+            None => match text.strip_prefix("//") {
+                Some(note) => ("", Some(note)),
+                None => (text, None),
+            },
+        };
+        task.note = note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+        let mut words = text.split_whitespace().peekable();
+        while let Some(word) = words.next() {
+            let mut multi_word = |first: &str| {
+                let mut value = vec![first];
+                while let Some(word) = words.next_if(|w| !is_special(w)) {
+                    value.push(word);
+                }
+                Some(value.join(" "))
+            };
+            if !is_special(word) {
+                name.push(word);
+            } else if let Some(due) = word.strip_prefix('^') {
+                task.due = multi_word(due);
+            } else if let Some(repeat) = word.strip_prefix('*') {
+                task.repeat = multi_word(repeat);
+            } else if let Some(estimate) = word.strip_prefix('=') {
+                task.estimate = multi_word(estimate);
+            } else if let Some(priority) = word.strip_prefix('!') {
+                task.priority = Some(priority.to_string());
+            } else if let Some(tag) = word.strip_prefix('#') {
+                if !task.tags.iter().any(|t| t == tag) {
+                    task.tags.push(tag.to_string());
+                }
+            } else if let Some(location) = word.strip_prefix('@') {
+                task.location = Some(location.to_string());
+            } else if task.url.is_none() {
+                task.url = Some(word.to_string());
+            } else {
+                name.push(word);
+            }
+        }
+        task.name = name.join(" ");
+        task
+    }
+
+    /// Use the first tag which names a list (ignoring case) as the list,
+    /// as RTM does, removing it from the tags.  Smart lists are skipped.
+    pub fn resolve_list(&mut self, lists: &[RTMList]) {
+        if self.list.is_some() {
+            return;
+        }
+        let found = self.tags.iter().enumerate().find_map(|(i, tag)| {
+            let list = lists
+                .iter()
+                .find(|l| !l.smart && l.name.eq_ignore_ascii_case(tag))?;
+            Some((i, list.name.clone()))
+        });
+        if let Some((i, name)) = found {
+            self.tags.remove(i);
+            self.list = Some(name);
+        }
+    }
+}
+
+impl fmt::Display for NewTask {
+    /// A one line summary, such as `Call Bob  due: tomorrow  tags: work`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        let fields = [
+            ("list", self.list.clone()),
+            ("due", self.due.clone()),
+            ("priority", self.priority.clone()),
+            ("tags", Some(self.tags.join(", ")).filter(|t| !t.is_empty())),
+            ("location", self.location.clone()),
+            ("repeat", self.repeat.clone()),
+            ("estimate", self.estimate.clone()),
+            ("url", self.url.clone()),
+            ("note", self.note.clone()),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                write!(f, "  {field}: {value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The result of [API::add_new_task].
+#[derive(Debug, Default)]
+pub struct AddOutcome {
+    /// The list containing the new task, as returned by [API::add_task],
+    /// or `None` if RTM didn't return it.
+    pub added: Option<RTMLists>,
+    /// The transactions made, starting with the add, with a description
+    /// of each, for undoing.
+    pub transactions: Vec<(String, RTMTransaction)>,
+    /// The parts of the task which couldn't be set, with the error from
+    /// RTM.  The task was added without them.
+    pub failures: Vec<(String, crate::Error)>,
+}
+
+impl AddOutcome {
+    fn record(
+        &mut self,
+        name: &str,
+        change: String,
+        result: Result<Option<RTMTransaction>, crate::Error>,
+    ) {
+        match result {
+            Ok(transaction) => self
+                .transactions
+                .extend(transaction.map(|t| (format!("{change}: {name}"), t))),
+            Err(error) => self.failures.push((change, error)),
+        }
+    }
+}
+
+impl API {
+    /// Add a task parsed by [NewTask::parse], setting each part with its
+    /// own request rather than asking RTM to parse the text.  `lists` is
+    /// used to find the list by name, after [NewTask::resolve_list].
+    ///
+    /// An unknown list or location is an error before anything is added.
+    /// Once the task has been added, a part which can't be set is recorded
+    /// in the outcome's failures and the rest are still set.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn add_new_task(
+        &self,
+        timeline: &RTMTimeline,
+        task: &NewTask,
+        lists: &[RTMList],
+        parent: Option<&Task>,
+    ) -> Result<AddOutcome, crate::Error> {
+        if task.name.is_empty() {
+            bail!("The task has no name");
+        }
+        let mut task = task.clone();
+        task.resolve_list(lists);
+        let list_id = match &task.list {
+            Some(name) => match lists.iter().find(|l| l.name == *name) {
+                Some(l) => Some(l.id.as_str()),
+                None => bail!("No list called {name:?}."),
+            },
+            None => None,
+        };
+        let location_id = match &task.location {
+            Some(name) => {
+                let rsp = self.call_method("rtm.locations.getList", &[]).await?;
+                let locations = &rsp["locations"]["location"];
+                let found = match locations {
+                    serde_json::Value::Array(locations) => locations.iter().find(|l| {
+                        l["name"]
+                            .as_str()
+                            .is_some_and(|n| n.eq_ignore_ascii_case(name))
+                    }),
+                    location => Some(location).filter(|l| {
+                        l["name"]
+                            .as_str()
+                            .is_some_and(|n| n.eq_ignore_ascii_case(name))
+                    }),
+                };
+                match found.and_then(|l| l["id"].as_str()) {
+                    Some(id) => Some(id.to_string()),
+                    None => bail!("No location called {name:?}."),
+                }
+            }
+            None => None,
+        };
+
+        let parent_id = parent.map(|p| p.id.as_str());
+        let (added, transaction) = self
+            .add_task_id(timeline, &task.name, list_id, parent_id, None, false)
+            .await?;
+        let mut outcome = AddOutcome::default();
+        outcome.record(&task.name, "Add".into(), Ok(transaction));
+        let Some((l, s, t)) = added.as_ref().and_then(|list| {
+            let series = list.taskseries.as_ref()?.first()?;
+            let new_task = series.task.first()?;
+            Some((list.id.clone(), series.id.clone(), new_task.id.clone()))
+        }) else {
+            return Ok(outcome);
+        };
+        outcome.added = added;
+        let (l, s, t) = (l.as_str(), s.as_str(), t.as_str());
+        let name = task.name.as_str();
+        if let Some(due) = &task.due {
+            let result = self.parse_due_date_id(timeline, l, s, t, due).await;
+            outcome.record(name, format!("Set due {due:?}"), result);
+        }
+        if let Some(priority) = &task.priority {
+            let result = self.set_priority_id(timeline, l, s, t, priority).await;
+            outcome.record(name, format!("Set priority {priority}"), result);
+        }
+        if !task.tags.is_empty() {
+            let tags: Vec<&str> = task.tags.iter().map(String::as_str).collect();
+            let result = self.add_tags_id(timeline, l, s, t, &tags).await;
+            outcome.record(name, format!("Add tags {}", task.tags.join(", ")), result);
+        }
+        if let (Some(location), Some(location_id)) = (&task.location, &location_id) {
+            let result = self.set_location_id(timeline, l, s, t, location_id).await;
+            outcome.record(name, format!("Set location {location:?}"), result);
+        }
+        if let Some(repeat) = &task.repeat {
+            let result = self.set_recurrence_id(timeline, l, s, t, repeat).await;
+            outcome.record(name, format!("Set repeat {repeat:?}"), result);
+        }
+        if let Some(estimate) = &task.estimate {
+            let result = self.set_estimate_id(timeline, l, s, t, estimate).await;
+            outcome.record(name, format!("Set estimate {estimate:?}"), result);
+        }
+        if let Some(url) = &task.url {
+            let result = self.set_url_id(timeline, l, s, t, url).await;
+            outcome.record(name, format!("Set URL {url:?}"), result);
+        }
+        if let Some(note) = &task.note {
+            let result = self.add_note_id(timeline, l, s, t, "", note).await;
+            outcome.record(name, "Add note".into(), result);
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::Matcher;
+
+    use super::NewTask;
+    use crate::{RTMConfig, RTMList, RTMTimeline, API};
+
+    #[test]
+    fn test_parse() {
+        let task = NewTask::parse(
+            "Call Bob ^tomorrow at 5pm !1 #work #Errands @Office *every week =15 min \
+             https://example.com/bob about the trip //Remember ^ the #photos",
+        );
+        assert_eq!(
+            task,
+            NewTask {
+                name: "Call Bob about the trip".into(),
+                due: Some("tomorrow at 5pm".into()),
+                priority: Some("1".into()),
+                tags: vec!["work".into(), "Errands".into()],
+                list: None,
+                location: Some("Office".into()),
+                repeat: Some("every week".into()),
+                estimate: Some("15 min".into()),
+                note: Some("Remember ^ the #photos".into()),
+                url: Some("https://example.com/bob".into()),
+            }
+        );
+        assert_eq!(
+            task.to_string(),
+            "Call Bob about the trip  due: tomorrow at 5pm  priority: 1  tags: work, Errands  \
+             location: Office  repeat: every week  estimate: 15 min  \
+             url: https://example.com/bob  note: Remember ^ the #photos"
+        );
+
+        // Lone or unknown markers are part of the name.
+        let task = NewTask::parse("Buy 2 # of nails ! !4 @ for 5 = 5 //");
+        assert_eq!(task.name, "Buy 2 # of nails ! !4 @ for 5 = 5");
+        assert_eq!(task.note, None);
+        assert_eq!(task.priority, None);
+
+        let mut task = NewTask::parse("Milk #errands #shopping");
+        let list = |name: &str, smart| RTMList {
+            id: name.into(),
+            name: name.into(),
+            smart,
+            filter: None,
+        };
+        task.resolve_list(&[list("Errands", true), list("Shopping", false)]);
+        assert_eq!(task.list.as_deref(), Some("Shopping"));
+        assert_eq!(task.tags, ["errands"]);
+    }
+
+    #[tokio::test]
+    async fn test_add_new_task() {
+        let mut server = mockito::Server::new_async().await;
+        let method = |name: &str| Matcher::UrlEncoded("method".into(), name.into());
+        let added = server
+            .mock("GET", "/")
+            .match_query(Matcher::AllOf(vec![
+                method("rtm.tasks.add"),
+                Matcher::UrlEncoded("name".into(), "Milk".into()),
+            ]))
+            .with_body(r#"{"rsp":{"stat":"ok","transaction":{"id":"10","undoable":"1"},"list":{"id":"1","taskseries":[{"id":"2","created":"2026-10-18T09:00:00Z","modified":"2026-10-18T09:00:00Z","name":"Milk","source":"api","url":"","location_id":"","tags":[],"participants":[],"notes":[],"parent_task_id":"","task":[{"id":"3","due":"","has_due_time":"0","added":"2026-10-18T09:00:00Z","completed":"","deleted":"","priority":"N","postponed":"0","estimate":""}]}]}}}"#)
+            .create_async()
+            .await;
+        let priority = server
+            .mock("GET", "/")
+            .match_query(method("rtm.tasks.setPriority"))
+            .with_body(r#"{"rsp":{"stat":"fail","err":{"code":"4000","msg":"Bad priority"}}}"#)
+            .create_async()
+            .await;
+        let tags = server
+            .mock("GET", "/")
+            .match_query(Matcher::AllOf(vec![
+                method("rtm.tasks.addTags"),
+                Matcher::UrlEncoded("tags".into(), "dairy".into()),
+            ]))
+            .with_body(r#"{"rsp":{"stat":"ok","transaction":{"id":"11","undoable":"1"}}}"#)
+            .create_async()
+            .await;
+        let config = RTMConfig {
+            api_key: Some("key".into()),
+            api_secret: Some("secret".into()),
+            token: Some("token".into()),
+            user: None,
+            secret_store: None,
+        };
+        let api = API::from_config_test(config, server);
+        let timeline = RTMTimeline("tl".into());
+
+        let task = NewTask::parse("Milk !1 #dairy");
+        let outcome = api.add_new_task(&timeline, &task, &[], None).await.unwrap();
+        added.assert_async().await;
+        priority.assert_async().await;
+        tags.assert_async().await;
+        assert!(outcome.added.is_some());
+        // The priority failed, but the tags were still added.
+        let changes: Vec<_> = outcome.transactions.iter().map(|t| t.0.as_str()).collect();
+        assert_eq!(changes, ["Add: Milk", "Add tags dairy: Milk"]);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].0, "Set priority 1");
+
+        // An unknown list is an error before anything is added.
+        let mut task = NewTask::parse("Milk");
+        task.list = Some("Groceries".into());
+        let result = api.add_new_task(&timeline, &task, &[], None).await;
+        assert!(result.is_err());
+    }
+}
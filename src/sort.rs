@@ -0,0 +1,606 @@
+//! Sorting and grouping of tasks.
+//!
+//! ```
+//! use rememberthemilk::sort::{group_entries, sort_entries, GroupBy, Sort};
+//! # let tasks = rememberthemilk::RTMTasks::default();
+//!
+//! // Overdue first, then by priority.
+//! let mut entries = tasks.entries();
+//! sort_entries(&mut entries, &["priority".parse::<Sort>().unwrap()]);
+//! for group in group_entries(entries, GroupBy::Due, &chrono::Local::now()) {
+//!     println!("{}", group.key);
+//! }
+//! ```
+use std::{cmp::Ordering, collections::HashMap, fmt, str::FromStr};
+
+use anyhow::bail;
+use chrono::{DateTime, TimeDelta, TimeZone};
+
+use crate::{RTMTasks, Task, TaskSeries};
+
+/// A task along with its task series and list.
+#[derive(Debug, Clone, Copy)]
+pub struct TaskEntry<'a> {
+    /// The id of the list containing the task.
+    pub list_id: &'a str,
+    /// The task series.
+    pub series: &'a TaskSeries,
+    /// The task itself, one of `series.task`.
+    pub task: &'a Task,
+}
+
+impl RTMTasks {
+    /// Return all the tasks, in the order they were returned.
+    pub fn entries(&self) -> Vec<TaskEntry<'_>> {
+        let mut result = Vec::new();
+        for list in &self.list {
+            for series in list.taskseries.iter().flatten() {
+                for task in &series.task {
+                    result.push(TaskEntry {
+                        list_id: &list.id,
+                        series,
+                        task,
+                    });
+                }
+            }
+        }
+        result
+    }
+}
+
+/// A field to sort tasks by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Due date, earliest first.
+    Due,
+    /// Priority, highest first.
+    Priority,
+    /// Name, ignoring case.
+    Name,
+    /// The date the task was added, earliest first.
+    Added,
+    /// The time estimate, shortest first.
+    Estimate,
+}
+
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<SortKey, anyhow::Error> {
+        Ok(match s {
+            "due" => SortKey::Due,
+            "priority" => SortKey::Priority,
+            "name" => SortKey::Name,
+            "added" => SortKey::Added,
+            "estimate" => SortKey::Estimate,
+            _ => bail!("Unknown sort key {s:?}"),
+        })
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SortKey::Due => "due",
+            SortKey::Priority => "priority",
+            SortKey::Name => "name",
+            SortKey::Added => "added",
+            SortKey::Estimate => "estimate",
+        })
+    }
+}
+
+/// One sort key and direction.  Parsed from strings like `due` or
+/// `-priority` (for descending).  Tasks with no value for the key always
+/// sort last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    /// The field to sort by.
+    pub key: SortKey,
+    /// Reverse the order.
+    pub descending: bool,
+}
+
+impl FromStr for Sort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Sort, anyhow::Error> {
+        match s.strip_prefix('-') {
+            Some(key) => Ok(Sort {
+                key: key.parse()?,
+                descending: true,
+            }),
+            None => Ok(Sort {
+                key: s.parse()?,
+                descending: false,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.descending {
+            f.write_str("-")?;
+        }
+        self.key.fmt(f)
+    }
+}
+
+/// Parse an estimate such as `PT1H30M` or `P1DT2H`.
+pub(crate) fn parse_estimate(s: &str) -> Option<TimeDelta> {
+    let s = s.strip_prefix('P')?;
+    let (days, time) = s.split_once('T').unwrap_or((s, ""));
+    let mut total = TimeDelta::zero();
+    let mut add = |part: &str, units: &[(char, i64)]| -> Option<()> {
+        let mut rest = part;
+        while !rest.is_empty() {
+            let end = rest.find(|c: char| !c.is_ascii_digit())?;
+            let n: i64 = rest[..end].parse().ok()?;
+            let unit = rest[end..].chars().next()?;
+            let &(_, secs) = units.iter().find(|(c, _)| *c == unit)?;
+            total += TimeDelta::seconds(n * secs);
+            rest = &rest[end + 1..];
+        }
+        Some(())
+    };
+    add(days, &[('W', 7 * 86400), ('D', 86400)])?;
+    add(time, &[('H', 3600), ('M', 60), ('S', 1)])?;
+    if days.is_empty() && time.is_empty() {
+        None
+    } else {
+        Some(total)
+    }
+}
+
+/// The priority as a number, with `None` for no priority.
+fn priority_num(task: &Task) -> Option<u8> {
+    match task.priority.as_str() {
+        "1" => Some(1),
+        "2" => Some(2),
+        "3" => Some(3),
+        _ => None,
+    }
+}
+
+/// Compare optional values, with `None` last whichever the direction.
+fn cmp_option<T: Ord>(a: Option<T>, b: Option<T>, descending: bool) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+impl Sort {
+    /// Compare two tasks by this key.
+    pub fn compare(&self, a: (&TaskSeries, &Task), b: (&TaskSeries, &Task)) -> Ordering {
+        let ((a_series, a_task), (b_series, b_task)) = (a, b);
+        match self.key {
+            SortKey::Due => cmp_option(a_task.due, b_task.due, self.descending),
+            SortKey::Priority => {
+                cmp_option(priority_num(a_task), priority_num(b_task), self.descending)
+            }
+            SortKey::Name => cmp_option(
+                Some(a_series.name.to_lowercase()),
+                Some(b_series.name.to_lowercase()),
+                self.descending,
+            ),
+            SortKey::Added => cmp_option(a_task.added, b_task.added, self.descending),
+            SortKey::Estimate => cmp_option(
+                parse_estimate(&a_task.estimate),
+                parse_estimate(&b_task.estimate),
+                self.descending,
+            ),
+        }
+    }
+}
+
+/// Compare two tasks by each of `sorts` in turn.
+pub fn compare_tasks(sorts: &[Sort], a: (&TaskSeries, &Task), b: (&TaskSeries, &Task)) -> Ordering {
+    sorts
+        .iter()
+        .map(|sort| sort.compare(a, b))
+        .find(|ord| ord.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Sort tasks by each of `sorts` in turn.  The sort is stable, so tasks
+/// which compare equal stay in their original order.
+pub fn sort_entries(entries: &mut [TaskEntry<'_>], sorts: &[Sort]) {
+    entries.sort_by(|a, b| compare_tasks(sorts, (a.series, a.task), (b.series, b.task)));
+}
+
+/// How to group tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// By list, in order of first appearance.
+    List,
+    /// By tag, alphabetically.  Tasks with several tags appear in each
+    /// tag's group.
+    Tag,
+    /// By due date relative to today; see [DueBucket].
+    Due,
+    /// By priority, highest first.
+    Priority,
+    /// By parent task, in order of first appearance.
+    Parent,
+}
+
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<GroupBy, anyhow::Error> {
+        Ok(match s {
+            "list" => GroupBy::List,
+            "tag" => GroupBy::Tag,
+            "due" => GroupBy::Due,
+            "priority" => GroupBy::Priority,
+            "parent" => GroupBy::Parent,
+            _ => bail!("Unknown grouping {s:?}"),
+        })
+    }
+}
+
+/// When a task is due, relative to now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DueBucket {
+    /// Due before now (or before today, for tasks with no due time).
+    Overdue,
+    /// Due later today.
+    Today,
+    /// Due tomorrow.
+    Tomorrow,
+    /// Due within the next week.
+    ThisWeek,
+    /// Due more than a week ahead.
+    Later,
+    /// No due date.
+    NoDue,
+}
+
+impl DueBucket {
+    /// Find when a task is due relative to `now`, using `now`'s time zone
+    /// for day boundaries.
+    pub fn of<Tz: TimeZone>(task: &Task, now: &DateTime<Tz>) -> DueBucket {
+        let Some(due) = task.due else {
+            return DueBucket::NoDue;
+        };
+        let today = now.date_naive();
+        let due_day = due.with_timezone(&now.timezone()).date_naive();
+        let overdue = if task.has_due_time {
+            due < *now
+        } else {
+            due_day < today
+        };
+        let days = (due_day - today).num_days();
+        if overdue {
+            DueBucket::Overdue
+        } else if days <= 0 {
+            DueBucket::Today
+        } else if days == 1 {
+            DueBucket::Tomorrow
+        } else if days < 7 {
+            DueBucket::ThisWeek
+        } else {
+            DueBucket::Later
+        }
+    }
+}
+
+impl fmt::Display for DueBucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DueBucket::Overdue => "Overdue",
+            DueBucket::Today => "Today",
+            DueBucket::Tomorrow => "Tomorrow",
+            DueBucket::ThisWeek => "This week",
+            DueBucket::Later => "Later",
+            DueBucket::NoDue => "No due date",
+        })
+    }
+}
+
+/// What the tasks in a [Group] have in common.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GroupKey {
+    /// The list id.
+    List(String),
+    /// The tag, or `None` for untagged tasks.
+    Tag(Option<String>),
+    /// When the tasks are due.
+    Due(DueBucket),
+    /// The priority (1-3), or `None`.
+    Priority(Option<u8>),
+    /// The parent task, or `None` for top level tasks.
+    Parent {
+        /// The parent task id.
+        id: Option<String>,
+        /// The parent's name, if it is one of the tasks being grouped.
+        name: Option<String>,
+    },
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupKey::List(id) => write!(f, "List {id}"),
+            GroupKey::Tag(Some(tag)) => write!(f, "#{tag}"),
+            GroupKey::Tag(None) => f.write_str("No tags"),
+            GroupKey::Due(bucket) => bucket.fmt(f),
+            GroupKey::Priority(Some(p)) => write!(f, "Priority {p}"),
+            GroupKey::Priority(None) => f.write_str("No priority"),
+            GroupKey::Parent {
+                name: Some(name), ..
+            } => f.write_str(name),
+            GroupKey::Parent {
+                id: Some(id),
+                name: None,
+            } => write!(f, "Subtasks of {id}"),
+            GroupKey::Parent { id: None, .. } => f.write_str("Top level"),
+        }
+    }
+}
+
+/// A set of tasks with something in common.
+#[derive(Debug, Clone)]
+pub struct Group<'a> {
+    /// What the tasks have in common.
+    pub key: GroupKey,
+    /// The tasks, in the order they were given.
+    pub tasks: Vec<TaskEntry<'a>>,
+}
+
+/// Split tasks into groups, keeping their order within each group.  Due
+/// dates are grouped relative to `now`.
+pub fn group_entries<'a, Tz: TimeZone>(
+    entries: Vec<TaskEntry<'a>>,
+    by: GroupBy,
+    now: &DateTime<Tz>,
+) -> Vec<Group<'a>> {
+    let names: HashMap<&str, &str> = entries
+        .iter()
+        .map(|e| (e.task.id.as_str(), e.series.name.as_str()))
+        .collect();
+    let mut groups: Vec<Group<'a>> = Vec::new();
+    let mut index: HashMap<GroupKey, usize> = HashMap::new();
+    for entry in entries {
+        let keys = match by {
+            GroupBy::List => vec![GroupKey::List(entry.list_id.into())],
+            GroupBy::Tag if entry.series.tags.is_empty() => vec![GroupKey::Tag(None)],
+            GroupBy::Tag => entry
+                .series
+                .tags
+                .iter()
+                .map(|tag| GroupKey::Tag(Some(tag.clone())))
+                .collect(),
+            GroupBy::Due => vec![GroupKey::Due(DueBucket::of(entry.task, now))],
+            GroupBy::Priority => vec![GroupKey::Priority(priority_num(entry.task))],
+            GroupBy::Parent => {
+                let id = entry.series.parent_task_id.clone();
+                let name = id
+                    .as_deref()
+                    .and_then(|id| names.get(id))
+                    .map(|name| name.to_string());
+                vec![GroupKey::Parent { id, name }]
+            }
+        };
+        for key in keys {
+            let idx = *index.entry(key.clone()).or_insert_with(|| {
+                groups.push(Group {
+                    key,
+                    tasks: Vec::new(),
+                });
+                groups.len() - 1
+            });
+            groups[idx].tasks.push(entry);
+        }
+    }
+    match by {
+        GroupBy::List | GroupBy::Parent => (),
+        GroupBy::Tag | GroupBy::Due | GroupBy::Priority => {
+            groups.sort_by(|a, b| match (&a.key, &b.key) {
+                (GroupKey::Tag(a), GroupKey::Tag(b)) => cmp_option(a.as_ref(), b.as_ref(), false),
+                (GroupKey::Due(a), GroupKey::Due(b)) => a.cmp(b),
+                (GroupKey::Priority(a), GroupKey::Priority(b)) => cmp_option(*a, *b, false),
+                _ => Ordering::Equal,
+            });
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{group_entries, parse_estimate, sort_entries, DueBucket, GroupBy, GroupKey, Sort};
+    use crate::{RTMLists, RTMTasks, TaskSeries};
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    fn series(
+        id: &str,
+        due: &str,
+        has_due_time: bool,
+        priority: &str,
+        tags: &[&str],
+        estimate: &str,
+    ) -> TaskSeries {
+        serde_json::from_value(crate::tests::series(
+            id,
+            serde_json::json!({"tags": {"tag": tags}}),
+            serde_json::json!({
+                "due": due, "has_due_time": if has_due_time { "1" } else { "0" },
+                "priority": priority, "estimate": estimate
+            }),
+        ))
+        .unwrap()
+    }
+
+    fn tasks() -> RTMTasks {
+        RTMTasks {
+            rev: "".into(),
+            list: vec![
+                RTMLists {
+                    id: "1".into(),
+                    taskseries: Some(vec![
+                        series("later", "2000-01-20T00:00:00Z", false, "N", &[], ""),
+                        series(
+                            "overdue",
+                            "2000-01-01T09:00:00Z",
+                            true,
+                            "2",
+                            &["work"],
+                            "PT2H",
+                        ),
+                        series("undated", "", false, "1", &["home", "work"], "PT30M"),
+                    ]),
+                },
+                RTMLists {
+                    id: "2".into(),
+                    taskseries: Some(vec![
+                        series("today", "2000-01-01T00:00:00Z", false, "3", &[], "P1D"),
+                        series(
+                            "tomorrow",
+                            "2000-01-02T00:00:00Z",
+                            false,
+                            "1",
+                            &["home"],
+                            "",
+                        ),
+                        series("week", "2000-01-05T12:00:00Z", true, "N", &[], "PT45M"),
+                    ]),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_parse_estimate() {
+        assert_eq!(parse_estimate("PT1H30M"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_estimate("P1DT2H"), Some(TimeDelta::hours(26)));
+        assert_eq!(parse_estimate("P1W"), Some(TimeDelta::days(7)));
+        assert_eq!(parse_estimate("PT45S"), Some(TimeDelta::seconds(45)));
+        assert_eq!(parse_estimate(""), None);
+        assert_eq!(parse_estimate("P"), None);
+        assert_eq!(parse_estimate("2 hours"), None);
+        assert_eq!(parse_estimate("PT2X"), None);
+    }
+
+    #[test]
+    fn test_sort() {
+        let tasks = tasks();
+        for (sorts, expected) in [
+            (
+                "due",
+                ["today", "overdue", "tomorrow", "week", "later", "undated"],
+            ),
+            (
+                "-due",
+                ["later", "week", "tomorrow", "overdue", "today", "undated"],
+            ),
+            (
+                "priority,due",
+                ["tomorrow", "undated", "overdue", "today", "week", "later"],
+            ),
+            (
+                "-priority,name",
+                ["today", "overdue", "tomorrow", "undated", "later", "week"],
+            ),
+            (
+                "estimate",
+                ["undated", "week", "overdue", "today", "later", "tomorrow"],
+            ),
+            (
+                "name",
+                ["later", "overdue", "today", "tomorrow", "undated", "week"],
+            ),
+        ] {
+            let sorts = sorts
+                .split(',')
+                .map(|s| s.parse::<Sort>().unwrap())
+                .collect::<Vec<_>>();
+            let mut entries = tasks.entries();
+            sort_entries(&mut entries, &sorts);
+            let names: Vec<_> = entries.iter().map(|e| e.series.name.as_str()).collect();
+            assert_eq!(names, expected, "sorting by {sorts:?}");
+        }
+        assert!("size".parse::<Sort>().is_err());
+        assert_eq!("-due".parse::<Sort>().unwrap().to_string(), "-due");
+    }
+
+    #[test]
+    fn test_group() {
+        let tasks = tasks();
+        let now = Utc.with_ymd_and_hms(2000, 1, 1, 12, 0, 0).unwrap();
+        let summary = |by: GroupBy| -> Vec<(String, Vec<String>)> {
+            let mut entries = tasks.entries();
+            sort_entries(&mut entries, &["priority".parse().unwrap()]);
+            group_entries(entries, by, &now)
+                .into_iter()
+                .map(|g| {
+                    (
+                        g.key.to_string(),
+                        g.tasks.iter().map(|e| e.series.name.clone()).collect(),
+                    )
+                })
+                .collect()
+        };
+        let s = |v: &[(&str, &[&str])]| -> Vec<(String, Vec<String>)> {
+            v.iter()
+                .map(|(k, ts)| (k.to_string(), ts.iter().map(|t| t.to_string()).collect()))
+                .collect()
+        };
+        assert_eq!(
+            summary(GroupBy::Due),
+            s(&[
+                ("Overdue", &["overdue"]),
+                ("Today", &["today"]),
+                ("Tomorrow", &["tomorrow"]),
+                ("This week", &["week"]),
+                ("Later", &["later"]),
+                ("No due date", &["undated"]),
+            ])
+        );
+        assert_eq!(
+            summary(GroupBy::Tag),
+            s(&[
+                ("#home", &["undated", "tomorrow"]),
+                ("#work", &["undated", "overdue"]),
+                ("No tags", &["today", "later", "week"]),
+            ])
+        );
+        assert_eq!(
+            summary(GroupBy::Priority),
+            s(&[
+                ("Priority 1", &["undated", "tomorrow"]),
+                ("Priority 2", &["overdue"]),
+                ("Priority 3", &["today"]),
+                ("No priority", &["later", "week"]),
+            ])
+        );
+        assert_eq!(
+            summary(GroupBy::List),
+            s(&[
+                ("List 1", &["undated", "overdue", "later"]),
+                ("List 2", &["tomorrow", "today", "week"]),
+            ])
+        );
+        assert_eq!(
+            summary(GroupBy::Parent),
+            s(&[(
+                "Top level",
+                &["undated", "tomorrow", "overdue", "today", "later", "week"]
+            )])
+        );
+        let entries = tasks.entries();
+        let today = entries.iter().find(|e| e.series.name == "today").unwrap();
+        // A date-only task is still due today all day.
+        let evening = Utc.with_ymd_and_hms(2000, 1, 1, 23, 59, 0).unwrap();
+        assert_eq!(DueBucket::of(today.task, &evening), DueBucket::Today);
+        assert_eq!(
+            group_entries(vec![*today], GroupBy::Due, &evening)[0].key,
+            GroupKey::Due(DueBucket::Today)
+        );
+    }
+}
@@ -0,0 +1,440 @@
+//! Converting tasks to and from Taskwarrior's `task export` JSON.
+//!
+//! Lists become projects, notes become annotations, and subtasks become
+//! dependencies of their parent (which can't be done until they are).
+//! Each task's UUID is its external id if it was imported from
+//! Taskwarrior, or otherwise made from its RTM ids; the RTM ids are also
+//! included as the `rtm_id` attribute.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::recur::Frequency;
+use crate::sort::TaskEntry;
+
+/// A Taskwarrior annotation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskwarriorAnnotation {
+    /// When it was added.
+    #[serde(with = "tw_date")]
+    pub entry: DateTime<Utc>,
+    /// The text.
+    pub description: String,
+}
+
+/// A task in Taskwarrior's JSON format.  Attributes not listed here are
+/// ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskwarriorTask {
+    /// The task's UUID.
+    pub uuid: String,
+    /// The description, used as the task name.
+    pub description: String,
+    /// `pending`, `completed`, `deleted`, `waiting` or `recurring`.
+    pub status: String,
+    /// The project, used as the list name.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    /// The tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// When the task was created.
+    #[serde(default, with = "tw_date_opt", skip_serializing_if = "Option::is_none")]
+    pub entry: Option<DateTime<Utc>>,
+    /// When the task was last modified.
+    #[serde(default, with = "tw_date_opt", skip_serializing_if = "Option::is_none")]
+    pub modified: Option<DateTime<Utc>>,
+    /// The due time.
+    #[serde(default, with = "tw_date_opt", skip_serializing_if = "Option::is_none")]
+    pub due: Option<DateTime<Utc>>,
+    /// When the task was completed or deleted.
+    #[serde(default, with = "tw_date_opt", skip_serializing_if = "Option::is_none")]
+    pub end: Option<DateTime<Utc>>,
+    /// `H`, `M` or `L`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    /// The annotations, used as notes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<TaskwarriorAnnotation>,
+    /// The recurrence period, such as `weekly` or `2w`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recur: Option<String>,
+    /// The UUIDs of the tasks this one depends on.
+    #[serde(
+        default,
+        deserialize_with = "deser_depends",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub depends: Vec<String>,
+    /// The RTM list, task series and task ids, for tasks exported from RTM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rtm_id: Option<String>,
+}
+
+/// Dates in Taskwarrior's `20261020T230000Z` format.
+mod tw_date {
+    use super::*;
+
+    const FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+    pub fn serialize<S: Serializer>(t: &DateTime<Utc>, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(&t.format(FORMAT).to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<DateTime<Utc>, D::Error> {
+        let s = String::deserialize(de)?;
+        NaiveDateTime::parse_from_str(&s, FORMAT)
+            .map(|t| t.and_utc())
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Optional dates in Taskwarrior's format.
+mod tw_date_opt {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(t: &Option<DateTime<Utc>>, ser: S) -> Result<S::Ok, S::Error> {
+        match t {
+            Some(t) => tw_date::serialize(t, ser),
+            None => ser.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        de: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        tw_date::deserialize(de).map(Some)
+    }
+}
+
+/// Dependencies come as an array, or from older versions as a
+/// comma-separated string.
+fn deser_depends<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Depends {
+        List(Vec<String>),
+        Text(String),
+    }
+    Ok(match Depends::deserialize(de)? {
+        Depends::List(list) => list,
+        Depends::Text(text) => text
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+/// True if `s` looks like a UUID.
+fn is_uuid(s: &str) -> bool {
+    s.len() == 36
+        && s.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// The UUID for an RTM task: its external id if that is a UUID, or else a
+/// name-based (version 3) UUID made from its ids.
+fn uuid_for(entry: &TaskEntry<'_>) -> String {
+    if let Some(extid) = entry.series.source.rsplit(':').next() {
+        if entry.series.source.starts_with("api:") && is_uuid(extid) {
+            return extid.to_ascii_lowercase();
+        }
+    }
+    let name = format!(
+        "rtm:{}-{}-{}",
+        entry.list_id, entry.series.id, entry.task.id
+    );
+    let mut bytes = md5::compute(name).0;
+    bytes[6] = (bytes[6] & 0x0f) | 0x30;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Convert tasks to Taskwarrior's format.  `list_names` maps list ids to
+/// names, used as projects.  Deleted tasks are left out.
+pub fn to_taskwarrior(
+    entries: &[TaskEntry<'_>],
+    list_names: &HashMap<String, String>,
+) -> Vec<TaskwarriorTask> {
+    let entries: Vec<&TaskEntry<'_>> = entries
+        .iter()
+        .filter(|e| e.task.deleted.is_none())
+        .collect();
+    let uuids: HashMap<&str, String> = entries
+        .iter()
+        .map(|e| (e.task.id.as_str(), uuid_for(e)))
+        .collect();
+    let mut children: HashMap<&str, Vec<String>> = HashMap::new();
+    for entry in &entries {
+        if let Some(parent) = &entry.series.parent_task_id {
+            children
+                .entry(parent.as_str())
+                .or_default()
+                .push(uuids[entry.task.id.as_str()].clone());
+        }
+    }
+    entries
+        .iter()
+        .map(|entry| {
+            let (series, task) = (entry.series, entry.task);
+            let recur = series.repeat.as_ref().and_then(|repeat| {
+                let recurrence = repeat
+                    .recurrence()
+                    .inspect_err(|e| log::warn!("Not exporting {:?}: {e}", repeat.rule))
+                    .ok()?;
+                let n = recurrence.interval;
+                Some(match (recurrence.frequency, n) {
+                    (Frequency::Daily, 1) => "daily".to_string(),
+                    (Frequency::Weekly, 1) => "weekly".to_string(),
+                    (Frequency::Monthly, 1) => "monthly".to_string(),
+                    (Frequency::Yearly, 1) => "yearly".to_string(),
+                    (Frequency::Daily, n) => format!("{n}d"),
+                    (Frequency::Weekly, n) => format!("{n}w"),
+                    (Frequency::Monthly, n) => format!("{n}mo"),
+                    (Frequency::Yearly, n) => format!("{n}y"),
+                })
+            });
+            TaskwarriorTask {
+                uuid: uuids[task.id.as_str()].clone(),
+                description: series.name.clone(),
+                status: if task.completed.is_some() {
+                    "completed"
+                } else {
+                    "pending"
+                }
+                .to_string(),
+                project: list_names.get(entry.list_id).cloned(),
+                tags: series.tags.clone(),
+                entry: task.added.or(Some(series.created)),
+                modified: Some(series.modified),
+                due: task.due,
+                end: task.completed,
+                priority: match task.priority.as_str() {
+                    "1" => Some("H".to_string()),
+                    "2" => Some("M".to_string()),
+                    "3" => Some("L".to_string()),
+                    _ => None,
+                },
+                annotations: series
+                    .notes
+                    .iter()
+                    .map(|note| TaskwarriorAnnotation {
+                        entry: note.created,
+                        description: match note.title.as_str() {
+                            "" => note.text.clone(),
+                            title => format!("{title}\n{}", note.text),
+                        },
+                    })
+                    .collect(),
+                recur,
+                depends: children.remove(task.id.as_str()).unwrap_or_default(),
+                rtm_id: Some(format!("{}-{}-{}", entry.list_id, series.id, task.id)),
+            }
+        })
+        .collect()
+}
+
+/// Parse Taskwarrior's JSON export: an array of tasks, or one task per line
+/// as written by older versions.
+pub fn parse_taskwarrior(s: &str) -> Result<Vec<TaskwarriorTask>, crate::Error> {
+    if s.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(s)?);
+    }
+    s.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line.trim_end_matches(','))
+                .map_err(|e| anyhow!("Line {}: {e}", number + 1))
+        })
+        .collect()
+}
+
+impl TaskwarriorTask {
+    /// The RTM priority ("1", "2", "3" or "N").
+    pub fn rtm_priority(&self) -> &'static str {
+        match self.priority.as_deref() {
+            Some("H") => "1",
+            Some("M") => "2",
+            Some("L") => "3",
+            _ => "N",
+        }
+    }
+
+    /// The recurrence as RTM understands it, such as "every 2 weeks" for
+    /// `2w`.
+    pub fn rtm_repeat(&self) -> Result<Option<String>, crate::Error> {
+        let Some(recur) = &self.recur else {
+            return Ok(None);
+        };
+        let (count, unit) = match recur.as_str() {
+            "daily" | "day" => (1, "day"),
+            "weekdays" => (1, "weekday"),
+            "weekly" | "week" => (1, "week"),
+            "biweekly" | "fortnight" => (2, "week"),
+            "monthly" | "month" => (1, "month"),
+            "bimonthly" => (2, "month"),
+            "quarterly" => (3, "month"),
+            "semiannual" => (6, "month"),
+            "annual" | "yearly" | "year" => (1, "year"),
+            "biannual" | "biyearly" => (2, "year"),
+            _ => {
+                let split = recur
+                    .find(|c: char| !c.is_ascii_digit())
+                    .ok_or_else(|| anyhow!("Unsupported recur:{recur}"))?;
+                let (count, unit) = recur.split_at(split);
+                let count: u32 = match count {
+                    "" => 1,
+                    n => n.parse()?,
+                };
+                let unit = match unit {
+                    "d" | "day" | "days" => "day",
+                    "w" | "wk" | "wks" | "week" | "weeks" => "week",
+                    "mo" | "mos" | "mth" | "mths" | "month" | "months" => "month",
+                    "q" | "qtr" | "qtrs" | "quarter" | "quarters" => {
+                        return Ok(Some(format!("every {} months", count * 3)));
+                    }
+                    "y" | "yr" | "yrs" | "year" | "years" => "year",
+                    _ => bail!("Unsupported recur:{recur}"),
+                };
+                (count, unit)
+            }
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        Ok(Some(format!("every {count} {unit}{plural}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_taskwarrior, to_taskwarrior, TaskwarriorTask};
+    use crate::RTMTasks;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_to_taskwarrior() {
+        let series = |id: &str, source: &str, parent: &str, completed: &str| {
+            crate::tests::series(
+                id,
+                serde_json::json!({
+                    "modified": "2026-10-02T09:00:00Z",
+                    "name": format!("Task {id}"),
+                    "source": source,
+                    "tags": {"tag": ["work"]},
+                    "parent_task_id": parent,
+                    "rrule": {"every": "1", "$t": "FREQ=WEEKLY;INTERVAL=2"},
+                }),
+                serde_json::json!({
+                    "due": "2026-10-19T23:00:00Z", "added": "2026-10-01T09:00:00Z",
+                    "completed": completed, "priority": "1"
+                }),
+            )
+        };
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [
+                    series("10", "js", "", ""),
+                    series("11", "api:key:0F6A2C3E-1111-4222-8333-444455556666", "10",
+                        "2026-10-05T12:00:00Z"),
+                ],
+            }],
+        }))
+        .unwrap();
+        let list_names = HashMap::from([("1".to_string(), "Office".to_string())]);
+        let exported = to_taskwarrior(&tasks.entries(), &list_names);
+        let json = serde_json::to_value(&exported).unwrap();
+        let parent_uuid = exported[0].uuid.clone();
+        assert_eq!(parent_uuid.len(), 36);
+        assert_eq!(&parent_uuid[14..15], "3");
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "uuid": parent_uuid,
+                "description": "Task 10",
+                "status": "pending",
+                "project": "Office",
+                "tags": ["work"],
+                "entry": "20261001T090000Z",
+                "modified": "20261002T090000Z",
+                "due": "20261019T230000Z",
+                "priority": "H",
+                "recur": "2w",
+                "depends": ["0f6a2c3e-1111-4222-8333-444455556666"],
+                "rtm_id": "1-10-10",
+            }, {
+                "uuid": "0f6a2c3e-1111-4222-8333-444455556666",
+                "description": "Task 11",
+                "status": "completed",
+                "project": "Office",
+                "tags": ["work"],
+                "entry": "20261001T090000Z",
+                "modified": "20261002T090000Z",
+                "due": "20261019T230000Z",
+                "end": "20261005T120000Z",
+                "priority": "H",
+                "recur": "2w",
+                "rtm_id": "1-11-11",
+            }])
+        );
+
+        // The same tasks always get the same UUIDs.
+        assert_eq!(to_taskwarrior(&tasks.entries(), &list_names), exported);
+        // And they can be read back.
+        let text = serde_json::to_string(&exported).unwrap();
+        assert_eq!(parse_taskwarrior(&text).unwrap(), exported);
+    }
+
+    #[test]
+    fn test_parse_taskwarrior() {
+        let tasks = parse_taskwarrior(
+            r#"{"uuid":"a","description":"One","status":"pending","depends":"b,c","recur":"quarterly","urgency":4.5}
+{"uuid":"b","description":"Two","status":"pending","recur":"3wks","annotations":[{"entry":"20261001T090000Z","description":"A note"}]},
+"#,
+        )
+        .unwrap();
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].depends, ["b", "c"]);
+        assert_eq!(
+            tasks[0].rtm_repeat().unwrap().as_deref(),
+            Some("every 3 months")
+        );
+        assert_eq!(
+            tasks[1].rtm_repeat().unwrap().as_deref(),
+            Some("every 3 weeks")
+        );
+        assert_eq!(tasks[1].annotations[0].description, "A note");
+        assert_eq!(tasks[1].rtm_priority(), "N");
+
+        let recur = |recur: &str| TaskwarriorTask {
+            recur: Some(recur.into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            recur("2q").rtm_repeat().unwrap().as_deref(),
+            Some("every 6 months")
+        );
+        assert_eq!(
+            recur("daily").rtm_repeat().unwrap().as_deref(),
+            Some("every 1 day")
+        );
+        assert!(recur("3x").rtm_repeat().is_err());
+        assert!(recur("").rtm_repeat().is_err());
+        assert!(parse_taskwarrior("[{\"uuid\": 1}]").is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! Creating a tree of tasks from a template.
+//!
+//! A template is JSON describing tasks and their subtasks.  Any string may
+//! contain `{{variable}}`s, which are filled in from the values given when
+//! the template is applied, or from the template's defaults.  Due dates may
+//! be relative to the day the template is applied, such as `+2d` or `+1w`;
+//! anything else is passed to RTM to parse, such as "friday".
+//!
+//! ```json
+//! {
+//!   "description": "Release checklist",
+//!   "variables": {"owner": "me"},
+//!   "tasks": [{
+//!     "name": "Release {{version}}",
+//!     "due": "+7d",
+//!     "tags": ["release"],
+//!     "priority": "1",
+//!     "subtasks": [
+//!       {"name": "Update the changelog for {{version}}", "due": "+2d"},
+//!       {"name": "Announce {{version}}", "notes": ["Ask {{owner}} to review"]}
+//!     ]
+//!   }]
+//! }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Days, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::bulk::Throttle;
+use crate::{RTMLists, RTMTimeline, RTMTransaction, Task, API};
+
+/// A task in a [Template], with its subtasks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskTemplate {
+    /// The task's name.
+    pub name: String,
+    /// The due date, either relative such as `+2d` or text for RTM to
+    /// parse.
+    #[serde(default)]
+    pub due: Option<String>,
+    /// Tags to add.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The priority: 1, 2, 3 or none.
+    #[serde(default)]
+    pub priority: Option<String>,
+    /// A URL.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// A time estimate, such as "30 minutes".
+    #[serde(default)]
+    pub estimate: Option<String>,
+    /// Notes to add, each as text.
+    #[serde(default)]
+    pub notes: Vec<String>,
+    /// Subtasks, created under this task.
+    #[serde(default)]
+    pub subtasks: Vec<TaskTemplate>,
+}
+
+/// A template for a tree of tasks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    /// What the template is for.
+    #[serde(default)]
+    pub description: String,
+    /// Default values for variables.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    /// The top level tasks.
+    pub tasks: Vec<TaskTemplate>,
+}
+
+/// A task created by [API::apply_template].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CreatedTask {
+    /// The list id.
+    pub list_id: String,
+    /// The task series id.
+    pub taskseries_id: String,
+    /// The task id.
+    pub task_id: String,
+    /// The task's name.
+    pub name: String,
+}
+
+/// What [API::apply_template] has done so far, kept even if it fails part
+/// way.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TemplateProgress {
+    /// The tasks created, so that they can be deleted again.
+    pub created: Vec<CreatedTask>,
+    /// The transaction adding each task, with a description, for undo.
+    pub transactions: Vec<(String, RTMTransaction)>,
+}
+
+/// Call `f` on each string in `task` and its subtasks.
+fn visit_strings<E>(
+    task: &mut TaskTemplate,
+    f: &mut impl FnMut(&mut String) -> Result<(), E>,
+) -> Result<(), E> {
+    f(&mut task.name)?;
+    for s in (task.due.iter_mut())
+        .chain(task.priority.iter_mut())
+        .chain(task.url.iter_mut())
+        .chain(task.estimate.iter_mut())
+        .chain(task.tags.iter_mut())
+        .chain(task.notes.iter_mut())
+    {
+        f(s)?;
+    }
+    for subtask in &mut task.subtasks {
+        visit_strings(subtask, f)?;
+    }
+    Ok(())
+}
+
+/// Replace the `{{variable}}`s in `s` using `values`, failing if one has
+/// no value.
+fn substitute(s: &str, values: &HashMap<String, String>) -> Result<String, crate::Error> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unclosed {{{{ in {s:?}"))?;
+        let name = after[..end].trim();
+        let value = values
+            .get(name)
+            .ok_or_else(|| anyhow!("No value for {{{{{name}}}}}"))?;
+        out.push_str(value);
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The number of days in a relative due date such as `+2d`, `+1w` or
+/// `-1d`, or `None` if it isn't one.
+pub fn relative_days(due: &str) -> Option<i64> {
+    let sign = match due.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let rest = &due[1..];
+    let (number, days_per_unit) = match (rest.strip_suffix('d'), rest.strip_suffix('w')) {
+        (Some(number), _) => (number, 1),
+        (_, Some(number)) => (number, 7),
+        _ => return None,
+    };
+    Some(sign * number.parse::<i64>().ok()? * days_per_unit)
+}
+
+/// The RTM priority ("1", "2", "3" or "N") for a template's priority.
+fn rtm_priority(priority: &str) -> Result<&'static str, crate::Error> {
+    Ok(match priority.to_lowercase().as_str() {
+        "1" => "1",
+        "2" => "2",
+        "3" => "3",
+        "" | "0" | "n" | "none" => "N",
+        _ => bail!("Priority must be 1, 2, 3 or none, not {priority:?}"),
+    })
+}
+
+/// Check the priorities of `tasks` and their subtasks, skipping any which
+/// still contain variables.
+fn check_priorities(tasks: &[TaskTemplate]) -> Result<(), crate::Error> {
+    for (_, task) in flatten(tasks) {
+        if let Some(priority) = task.priority.as_deref().filter(|p| !p.contains("{{")) {
+            rtm_priority(priority).map_err(|e| e.context(format!("In {:?}", task.name)))?;
+        }
+    }
+    Ok(())
+}
+
+impl Template {
+    /// Parse a template from JSON, checking any priorities which don't use
+    /// variables.
+    pub fn parse(json: &str) -> Result<Template, crate::Error> {
+        let template: Template = serde_json::from_str(json)?;
+        check_priorities(&template.tasks)?;
+        Ok(template)
+    }
+
+    /// The names of the variables used, sorted.
+    pub fn variables_used(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        let mut tasks = self.tasks.clone();
+        for task in &mut tasks {
+            let _ = visit_strings(task, &mut |s: &mut String| {
+                let mut rest = s.as_str();
+                while let Some(start) = rest.find("{{") {
+                    let after = &rest[start + 2..];
+                    let Some(end) = after.find("}}") else { break };
+                    names.insert(after[..end].trim().to_string());
+                    rest = &after[end + 2..];
+                }
+                Ok::<(), ()>(())
+            });
+        }
+        names
+    }
+
+    /// Return the tasks with variables filled in from `values`, falling
+    /// back to the template's defaults.  Fails if a variable has no value
+    /// or a priority isn't valid.
+    pub fn instantiate(
+        &self,
+        values: &HashMap<String, String>,
+    ) -> Result<Vec<TaskTemplate>, crate::Error> {
+        let mut all_values: HashMap<String, String> = self.variables.clone().into_iter().collect();
+        all_values.extend(values.iter().map(|(k, v)| (k.clone(), v.clone())));
+        let mut tasks = self.tasks.clone();
+        for task in &mut tasks {
+            visit_strings(task, &mut |s: &mut String| {
+                *s = substitute(s, &all_values)?;
+                Ok::<(), crate::Error>(())
+            })?;
+        }
+        check_priorities(&tasks)?;
+        Ok(tasks)
+    }
+}
+
+/// The tasks in tree order, each with the index of its parent.
+fn flatten(tasks: &[TaskTemplate]) -> Vec<(Option<usize>, &TaskTemplate)> {
+    fn add<'t>(
+        result: &mut Vec<(Option<usize>, &'t TaskTemplate)>,
+        parent: Option<usize>,
+        tasks: &'t [TaskTemplate],
+    ) {
+        for task in tasks {
+            result.push((parent, task));
+            let index = result.len() - 1;
+            add(result, Some(index), &task.subtasks);
+        }
+    }
+    let mut result = Vec::new();
+    add(&mut result, None, tasks);
+    result
+}
+
+impl API {
+    /// Create the tasks from [Template::instantiate] in `list` (or the
+    /// default list), with subtasks under their parents.  Relative due
+    /// dates count from `start`, using its time zone.  Requests are spaced
+    /// at least `interval` apart.  Each task created, and the transaction
+    /// adding it, is recorded in `progress`, so that they can be deleted or
+    /// undone again even if a later one fails.  Priorities are checked
+    /// before anything is created.
+    ///
+    /// Requires a valid user authentication token.
+    pub async fn apply_template<Tz: TimeZone>(
+        &self,
+        timeline: &RTMTimeline,
+        tasks: &[TaskTemplate],
+        list: Option<&RTMLists>,
+        start: &DateTime<Tz>,
+        interval: Duration,
+        progress: &mut TemplateProgress,
+    ) -> Result<(), crate::Error> {
+        let priorities = flatten(tasks)
+            .into_iter()
+            .map(|(_, task)| task.priority.as_deref().map(rtm_priority).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        let list_id = list.map(|l| l.id.as_str());
+        let mut throttle = Throttle::new(interval);
+        let mut added: Vec<Task> = Vec::new();
+        for ((parent, template), priority) in flatten(tasks).into_iter().zip(priorities) {
+            let parent = parent.map(|index| added[index].id.as_str());
+            throttle.wait().await;
+            let (new_list, transaction) = self
+                .add_task_id(timeline, &template.name, list_id, parent, None, false)
+                .await?;
+            progress
+                .transactions
+                .extend(transaction.map(|t| (format!("Add: {}", template.name), t)));
+            let Some((list_id, series, task)) = new_list.and_then(|l| {
+                let series = l.taskseries?.into_iter().next()?;
+                let task = series.task.first()?.clone();
+                Some((l.id, series, task))
+            }) else {
+                bail!("RTM didn't return the new task {:?}", template.name);
+            };
+            progress.created.push(CreatedTask {
+                list_id: list_id.clone(),
+                taskseries_id: series.id.clone(),
+                task_id: task.id.clone(),
+                name: template.name.clone(),
+            });
+            let (l, s, t) = (list_id.as_str(), series.id.as_str(), task.id.as_str());
+            if let Some(due) = &template.due {
+                throttle.wait().await;
+                match due_date(template, start.date_naive()) {
+                    Some(day) => {
+                        self.set_due_date_id(timeline, l, s, t, start_of_day(day, start), false)
+                            .await?;
+                    }
+                    None => {
+                        self.parse_due_date_id(timeline, l, s, t, due).await?;
+                    }
+                }
+            }
+            if let Some(priority) = priority {
+                throttle.wait().await;
+                self.set_priority_id(timeline, l, s, t, priority).await?;
+            }
+            if !template.tags.is_empty() {
+                let tags: Vec<&str> = template.tags.iter().map(String::as_str).collect();
+                throttle.wait().await;
+                self.add_tags_id(timeline, l, s, t, &tags).await?;
+            }
+            if let Some(url) = &template.url {
+                throttle.wait().await;
+                self.set_url_id(timeline, l, s, t, url).await?;
+            }
+            if let Some(estimate) = &template.estimate {
+                throttle.wait().await;
+                self.set_estimate_id(timeline, l, s, t, estimate).await?;
+            }
+            for note in &template.notes {
+                throttle.wait().await;
+                self.add_note_id(timeline, l, s, t, "", note).await?;
+            }
+            added.push(task);
+        }
+        Ok(())
+    }
+}
+
+/// The start of `day` in `now`'s time zone.
+fn start_of_day<Tz: TimeZone>(day: NaiveDate, now: &DateTime<Tz>) -> DateTime<Utc> {
+    now.timezone()
+        .from_local_datetime(&day.and_time(NaiveTime::MIN))
+        .earliest()
+        .map_or_else(
+            || day.and_time(NaiveTime::MIN).and_utc(),
+            |d| d.with_timezone(&Utc),
+        )
+}
+
+/// The due date a task from a template gets if applied on `today`, when it
+/// is relative.
+pub fn due_date(task: &TaskTemplate, today: NaiveDate) -> Option<NaiveDate> {
+    let days = relative_days(task.due.as_deref()?)?;
+    if days >= 0 {
+        today.checked_add_days(Days::new(days as u64))
+    } else {
+        today.checked_sub_days(Days::new(days.unsigned_abs()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{due_date, flatten, relative_days, Template};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_template() {
+        let template = Template::parse(
+            r#"{
+                "description": "Release checklist",
+                "variables": {"owner": "me"},
+                "tasks": [{
+                    "name": "Release {{version}}",
+                    "due": "+1w",
+                    "tags": ["release-{{ version }}"],
+                    "subtasks": [
+                        {"name": "Changelog", "due": "+2d",
+                         "subtasks": [{"name": "Check PRs"}]},
+                        {"name": "Announce", "notes": ["Ask {{owner}}"], "due": "friday"}
+                    ]
+                }, {
+                    "name": "Retrospective"
+                }]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            template.variables_used().into_iter().collect::<Vec<_>>(),
+            ["owner", "version"]
+        );
+        let values = HashMap::from([("version".to_string(), "1.4".to_string())]);
+        let tasks = template.instantiate(&values).unwrap();
+        assert_eq!(tasks[0].name, "Release 1.4");
+        assert_eq!(tasks[0].tags, ["release-1.4"]);
+        assert_eq!(tasks[0].subtasks[1].notes, ["Ask me"]);
+
+        let order: Vec<_> = flatten(&tasks)
+            .into_iter()
+            .map(|(parent, task)| (parent, task.name.as_str()))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (None, "Release 1.4"),
+                (Some(0), "Changelog"),
+                (Some(1), "Check PRs"),
+                (Some(0), "Announce"),
+                (None, "Retrospective"),
+            ]
+        );
+
+        let today = "2026-10-18".parse().unwrap();
+        assert_eq!(due_date(&tasks[0], today), "2026-10-25".parse().ok());
+        assert_eq!(due_date(&tasks[0].subtasks[1], today), None);
+
+        let err = template.instantiate(&HashMap::new()).unwrap_err();
+        assert_eq!(err.to_string(), "No value for {{version}}");
+        assert!(Template::parse(r#"{"tasks": [{"name": "x", "colour": "red"}]}"#).is_err());
+
+        // Priorities are checked when parsing, or once variables are filled
+        // in.
+        let err = Template::parse(r#"{"tasks": [{"name": "x", "priority": "high"}]}"#).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            r#"In "x": Priority must be 1, 2, 3 or none, not "high""#
+        );
+        let template =
+            Template::parse(r#"{"tasks": [{"name": "x", "priority": "{{p}}"}]}"#).unwrap();
+        let values = |p: &str| HashMap::from([("p".to_string(), p.to_string())]);
+        assert!(template.instantiate(&values("2")).is_ok());
+        assert!(template.instantiate(&values("4")).is_err());
+    }
+
+    #[test]
+    fn test_relative_days() {
+        assert_eq!(relative_days("+2d"), Some(2));
+        assert_eq!(relative_days("+1w"), Some(7));
+        assert_eq!(relative_days("-3d"), Some(-3));
+        assert_eq!(relative_days("+d"), None);
+        assert_eq!(relative_days("2d"), None);
+        assert_eq!(relative_days("+2m"), None);
+        assert_eq!(relative_days("+"), None);
+    }
+}
@@ -3,6 +3,45 @@ use chrono::TimeZone;
 #[cfg(test)]
 use serde_json::{from_str, to_string};
 
+/// A task series with a single task, both with id (and name) `id`, as JSON
+/// from RTM.  `fields` and `task_fields` are objects whose fields replace
+/// the defaults, which are empty apart from the times the series was
+/// created and modified.
+pub(crate) fn series(
+    id: &str,
+    fields: serde_json::Value,
+    task_fields: serde_json::Value,
+) -> serde_json::Value {
+    fn merge(value: &mut serde_json::Value, fields: serde_json::Value) {
+        if let serde_json::Value::Object(fields) = fields {
+            for (key, field) in fields {
+                value[key] = field;
+            }
+        }
+    }
+    let mut task = serde_json::json!({
+        "id": id, "due": "", "has_due_time": "0", "added": "", "completed": "",
+        "deleted": "", "priority": "N", "postponed": "0", "estimate": ""
+    });
+    merge(&mut task, task_fields);
+    let mut series = serde_json::json!({
+        "id": id,
+        "created": "2026-10-01T09:00:00Z",
+        "modified": "2026-10-01T09:00:00Z",
+        "name": id,
+        "source": "js",
+        "url": "",
+        "location_id": "",
+        "tags": [],
+        "participants": [],
+        "notes": [],
+        "parent_task_id": "",
+        "task": [task],
+    });
+    merge(&mut series, fields);
+    series
+}
+
 #[test]
 fn deser_check_token() {
     let json_rsp = r#"{"rsp":{"stat":"ok","auth":{"token":"410c57262293e9d937ee5be75eb7b0128fd61b61","perms":"delete","user":{"id":"1","username":"bob","fullname":"Bob T. Monkey"}}}}"#;
@@ -71,6 +110,7 @@ fn test_deser_taskseries() {
             deleted: None,
             has_due_time: false,
             priority: "N".into(),
+            estimate: "".into(),
         }],
         tags: vec!["computer".into()],
         notes: Default::default(),
@@ -83,6 +123,37 @@ fn test_deser_taskseries() {
     assert_eq!(tasks, expected);
 }
 
+#[test]
+fn test_deser_lists() {
+    let json = r#"
+        {"list":[
+          {"id":"100","name":"Inbox","deleted":"0","locked":"1","archived":"0","position":"-1","smart":"0"},
+          {"id":"101","name":"This week","deleted":"0","locked":"0","archived":"0","position":"0","smart":"1","filter":"(dueBefore:\"1 week of today\")"}
+        ]}"#;
+    let lists = from_str::<ListContainer>(json).unwrap().list;
+    assert_eq!(
+        lists,
+        vec![
+            RTMList {
+                id: "100".into(),
+                name: "Inbox".into(),
+                smart: false,
+                filter: None,
+            },
+            RTMList {
+                id: "101".into(),
+                name: "This week".into(),
+                smart: true,
+                filter: Some(r#"(dueBefore:"1 week of today")"#.into()),
+            },
+        ]
+    );
+    // Lists are written out as RTM sends them, so they can be read back.
+    let json = to_string(&lists).unwrap();
+    assert!(json.contains(r#""smart":"1""#));
+    assert_eq!(from_str::<Vec<RTMList>>(&json).unwrap(), lists);
+}
+
 #[test]
 fn test_deser_rrule() {
     let json = r#"{"every":"1","$t":"FREQ=WEEKLY;INTERVAL=1;WKST=MO"}"#;
@@ -117,6 +188,7 @@ fn test_deser_task_nodue() {
         deleted: None,
         has_due_time: false,
         priority: "N".into(),
+        estimate: "".into(),
     };
     println!("{}", to_string(&expected).unwrap());
     let task = from_str::<Task>(json).unwrap();
@@ -199,6 +271,7 @@ fn test_deser_tasklist_response() {
                         deleted: None,
                         has_due_time: false,
                         priority: "N".into(),
+                        estimate: "".into(),
                     }],
                     tags: vec!["computer".into()],
                     repeat: None,
@@ -236,6 +309,7 @@ async fn test_have_token() {
         api_secret: Some("secret".into()),
         token: Some("token".into()),
         user: None,
+        secret_store: None,
     };
     let m = server.mock("GET", "/")
         .match_query(Matcher::AllOf(vec![
@@ -316,6 +390,7 @@ fn test_deser_tasklist_response_notes() {
                         deleted: None,
                         has_due_time: false,
                         priority: "N".into(),
+                        estimate: "".into(),
                     }],
                     tags: vec!["computer".into()],
                     repeat: None,
@@ -337,3 +412,115 @@ fn test_deser_tasklist_response_notes() {
     let lists = from_str::<RTMResponse<TasksResponse>>(json).unwrap().rsp;
     assert_eq!(lists, expected);
 }
+
+#[tokio::test]
+async fn test_set_priority() {
+    let mut server = mockito::Server::new_async().await;
+    use mockito::Matcher;
+
+    let config = RTMConfig {
+        api_key: Some("key".into()),
+        api_secret: Some("secret".into()),
+        token: Some("token".into()),
+        user: None,
+        secret_store: None,
+    };
+    let m = server.mock("GET", "/")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("method".into(), "rtm.tasks.setPriority".into()),
+            Matcher::UrlEncoded("timeline".into(), "tl".into()),
+            Matcher::UrlEncoded("list_id".into(), "1".into()),
+            Matcher::UrlEncoded("taskseries_id".into(), "2".into()),
+            Matcher::UrlEncoded("task_id".into(), "3".into()),
+            Matcher::UrlEncoded("priority".into(), "1".into()),
+            Matcher::Regex("api_sig=.*".into()),
+        ]))
+        .with_body(r#"{"rsp":{"stat":"ok","transaction":{"id":"99","undoable":"1"},"list":{"id":"1","taskseries":[]}}}"#)
+        .create_async()
+        .await;
+
+    let api = API::from_config_test(config, server);
+    let timeline = RTMTimeline("tl".into());
+
+    let transaction = api
+        .set_priority_id(&timeline, "1", "2", "3", "1")
+        .await
+        .unwrap();
+    assert_eq!(
+        transaction,
+        Some(RTMTransaction {
+            id: "99".into(),
+            undoable: true
+        })
+    );
+    m.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_set_priority_error() {
+    let mut server = mockito::Server::new_async().await;
+    use mockito::Matcher;
+
+    let config = RTMConfig {
+        api_key: Some("key".into()),
+        api_secret: Some("secret".into()),
+        token: Some("token".into()),
+        user: None,
+        secret_store: None,
+    };
+    let m = server.mock("GET", "/")
+        .match_query(Matcher::UrlEncoded("method".into(), "rtm.tasks.setPriority".into()))
+        .with_body(r#"{"rsp":{"stat":"fail","err":{"code":"340","msg":"taskseries_id/task_id invalid or not provided"}}}"#)
+        .create_async()
+        .await;
+
+    let api = API::from_config_test(config, server);
+    let timeline = RTMTimeline("tl".into());
+
+    let err = api
+        .set_priority_id(&timeline, "1", "2", "3", "1")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        r#"Error calling rtm.tasks.setPriority: "taskseries_id/task_id invalid or not provided""#
+    );
+    m.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_set_due_date_id() {
+    let mut server = mockito::Server::new_async().await;
+    use mockito::Matcher;
+
+    let config = RTMConfig {
+        api_key: Some("key".into()),
+        api_secret: Some("secret".into()),
+        token: Some("token".into()),
+        user: None,
+        secret_store: None,
+    };
+    let m = server.mock("GET", "/")
+        .match_query(Matcher::AllOf(vec![
+            Matcher::UrlEncoded("method".into(), "rtm.tasks.setDueDate".into()),
+            Matcher::UrlEncoded("timeline".into(), "tl".into()),
+            Matcher::UrlEncoded("task_id".into(), "3".into()),
+            Matcher::UrlEncoded("due".into(), "2026-10-19T23:00:00Z".into()),
+            Matcher::UrlEncoded("has_due_time".into(), "0".into()),
+            Matcher::Regex("api_sig=.*".into()),
+        ]))
+        .with_body(r#"{"rsp":{"stat":"ok","transaction":{"id":"99","undoable":"1"},"list":{"id":"1","taskseries":[]}}}"#)
+        .create_async()
+        .await;
+
+    let api = API::from_config_test(config, server);
+    let timeline = RTMTimeline("tl".into());
+    let due = Utc.with_ymd_and_hms(2026, 10, 19, 23, 0, 0).unwrap();
+
+    let transaction = api
+        .set_due_date_id(&timeline, "1", "2", "3", due, false)
+        .await
+        .unwrap();
+    assert_eq!(transaction.map(|t| t.id), Some("99".into()));
+    m.assert_async().await;
+}
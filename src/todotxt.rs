@@ -0,0 +1,360 @@
+//! Converting tasks to and from [todo.txt](https://github.com/todotxt/todo.txt)
+//! lines.
+//!
+//! RTM lists become `+project`s (with spaces replaced by `_`), tags become
+//! `@context`s, and due dates and recurrence use the common `due:` and
+//! `rec:` extensions.  Each exported line has an `id:`: the task's external
+//! id if it was added with one (such as by an earlier import), or else the
+//! RTM ids of the task, which [TodoTxtTask::rtm_id] recognises.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail};
+use chrono::{NaiveDate, TimeZone};
+
+use crate::recur::Frequency;
+use crate::sort::TaskEntry;
+
+/// A task parsed from a todo.txt line by [parse_todotxt].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TodoTxtTask {
+    /// The `id:` value, or if there isn't one, an id made from the text so
+    /// that the same line always has the same id.
+    pub id: String,
+    /// True if the line starts with `x`.
+    pub completed: bool,
+    /// The completion date, if given.
+    pub completion_date: Option<NaiveDate>,
+    /// The priority letter, if any.
+    pub priority: Option<char>,
+    /// The creation date, if given.
+    pub creation_date: Option<NaiveDate>,
+    /// The description without projects, contexts or `key:value` tags.
+    pub name: String,
+    /// The `+project`s, without the `+`.
+    pub projects: Vec<String>,
+    /// The `@context`s, without the `@`.
+    pub contexts: Vec<String>,
+    /// The `due:` date.
+    pub due: Option<NaiveDate>,
+    /// The `rec:` value, such as `1w` or `+2m`.
+    pub rec: Option<String>,
+}
+
+impl TodoTxtTask {
+    /// The RTM priority ("1", "2", "3" or "N") for the priority letter.
+    pub fn rtm_priority(&self) -> &'static str {
+        match self.priority {
+            Some('A') => "1",
+            Some('B') => "2",
+            Some(_) => "3",
+            None => "N",
+        }
+    }
+
+    /// The RTM ids (`list-series-task`) if the `id:` is one written by
+    /// [to_todotxt] for a task with no external id, meaning the task came
+    /// from RTM in the first place.
+    pub fn rtm_id(&self) -> Option<&str> {
+        let mut parts = self.id.split('-');
+        let is_id = |part: Option<&str>| {
+            part.is_some_and(|p| !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()))
+        };
+        let triple = is_id(parts.next()) && is_id(parts.next()) && is_id(parts.next());
+        (triple && parts.next().is_none()).then_some(self.id.as_str())
+    }
+
+    /// The recurrence as RTM understands it, such as "every 2 weeks" for
+    /// `+2w` or "after 1 day" for `1d`.
+    pub fn rtm_repeat(&self) -> Result<Option<String>, crate::Error> {
+        let Some(rec) = &self.rec else {
+            return Ok(None);
+        };
+        let (kind, rest) = match rec.strip_prefix('+') {
+            Some(rest) => ("every", rest),
+            None => ("after", rec.as_str()),
+        };
+        let split = rest.len().saturating_sub(1);
+        let (count, unit) = rest.split_at(split);
+        let count: u32 = match count {
+            "" => 1,
+            n => n.parse().map_err(|_| anyhow!("Invalid rec:{rec}"))?,
+        };
+        let unit = match unit {
+            "d" => "day",
+            "b" => "weekday",
+            "w" => "week",
+            "m" => "month",
+            "y" => "year",
+            _ => bail!("Invalid rec:{rec}"),
+        };
+        let plural = if count == 1 { "" } else { "s" };
+        Ok(Some(format!("{kind} {count} {unit}{plural}")))
+    }
+}
+
+/// Make a word safe to use as a project or context.
+fn word(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join("_")
+}
+
+/// The external id a task was added with, if it has one which can be
+/// written as an `id:`.
+fn external_id<'a>(entry: &TaskEntry<'a>) -> Option<&'a str> {
+    let (_api_key, extid) = entry.series.source.strip_prefix("api:")?.split_once(':')?;
+    (!extid.is_empty() && !extid.contains(char::is_whitespace)).then_some(extid)
+}
+
+/// Convert tasks to todo.txt lines, using `tz` for dates.  `list_names` maps
+/// list ids to names, used as projects.  Deleted tasks are left out.
+pub fn to_todotxt<Tz: TimeZone>(
+    entries: &[TaskEntry<'_>],
+    list_names: &HashMap<String, String>,
+    tz: &Tz,
+) -> String {
+    let date = |t: &chrono::DateTime<chrono::Utc>| t.with_timezone(tz).date_naive();
+    let mut out = String::new();
+    for entry in entries {
+        let (series, task) = (entry.series, entry.task);
+        if task.deleted.is_some() {
+            continue;
+        }
+        let mut parts = Vec::new();
+        match task.completed {
+            Some(completed) => {
+                parts.push("x".to_string());
+                parts.push(date(&completed).to_string());
+            }
+            None => {
+                let priority = match task.priority.as_str() {
+                    "1" => Some("(A)"),
+                    "2" => Some("(B)"),
+                    "3" => Some("(C)"),
+                    _ => None,
+                };
+                parts.extend(priority.map(str::to_string));
+            }
+        }
+        if let Some(added) = task.added {
+            parts.push(date(&added).to_string());
+        }
+        parts.push(series.name.replace('\n', " "));
+        if let Some(list) = list_names.get(entry.list_id) {
+            parts.push(format!("+{}", word(list)));
+        }
+        parts.extend(series.tags.iter().map(|tag| format!("@{}", word(tag))));
+        if let Some(due) = task.due {
+            parts.push(format!("due:{}", date(&due)));
+        }
+        if let Some(repeat) = &series.repeat {
+            match repeat.recurrence() {
+                Ok(recurrence) => {
+                    let unit = match recurrence.frequency {
+                        Frequency::Daily => 'd',
+                        Frequency::Weekly => 'w',
+                        Frequency::Monthly => 'm',
+                        Frequency::Yearly => 'y',
+                    };
+                    let strict = if repeat.every { "+" } else { "" };
+                    parts.push(format!("rec:{strict}{}{unit}", recurrence.interval));
+                }
+                Err(e) => log::warn!("Not exporting {:?}: {e}", repeat.rule),
+            }
+        }
+        match external_id(entry) {
+            Some(extid) => parts.push(format!("id:{extid}")),
+            None => parts.push(format!("id:{}-{}-{}", entry.list_id, series.id, task.id)),
+        }
+        out.push_str(&parts.join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+/// Parse a `YYYY-MM-DD` date.
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    if s.len() == 10 {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+    } else {
+        None
+    }
+}
+
+/// Parse one todo.txt line.
+fn parse_line(line: &str) -> Result<TodoTxtTask, crate::Error> {
+    let mut task = TodoTxtTask::default();
+    let mut words = line.split_whitespace().peekable();
+    if words.peek() == Some(&"x") {
+        words.next();
+        task.completed = true;
+        task.completion_date = words.peek().and_then(|w| parse_date(w));
+        if task.completion_date.is_some() {
+            words.next();
+        }
+    } else if let Some(priority) = words.peek().and_then(|w| {
+        let letter = w.strip_prefix('(')?.strip_suffix(')')?;
+        let mut chars = letter.chars();
+        chars
+            .next()
+            .filter(|c| c.is_ascii_uppercase() && chars.next().is_none())
+    }) {
+        words.next();
+        task.priority = Some(priority);
+    }
+    task.creation_date = words.peek().and_then(|w| parse_date(w));
+    if task.creation_date.is_some() {
+        words.next();
+    }
+    let mut name = Vec::new();
+    let mut id = None;
+    for word in words {
+        if let Some(project) = word.strip_prefix('+').filter(|p| !p.is_empty()) {
+            task.projects.push(project.to_string());
+        } else if let Some(context) = word.strip_prefix('@').filter(|c| !c.is_empty()) {
+            task.contexts.push(context.to_string());
+        } else if let Some(due) = word.strip_prefix("due:") {
+            task.due = Some(parse_date(due).ok_or_else(|| anyhow!("Invalid date in {word}"))?);
+        } else if let Some(rec) = word.strip_prefix("rec:") {
+            task.rec = Some(rec.to_string());
+        } else if let Some(value) = word.strip_prefix("id:") {
+            id = Some(value.to_string());
+        } else {
+            name.push(word);
+        }
+    }
+    task.name = name.join(" ");
+    if task.name.is_empty() {
+        bail!("No description");
+    }
+    task.id = id.unwrap_or_else(|| {
+        let mut text = vec![task.name.clone()];
+        text.extend(task.projects.iter().map(|p| format!("+{p}")));
+        text.extend(task.contexts.iter().map(|c| format!("@{c}")));
+        format!("todotxt:{:x}", md5::compute(text.join(" ")))
+    });
+    Ok(task)
+}
+
+/// Parse a todo.txt file, skipping blank lines.
+pub fn parse_todotxt(s: &str) -> Result<Vec<TodoTxtTask>, crate::Error> {
+    s.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            parse_line(line).map_err(|e| anyhow!("Line {}: {e}: {line:?}", number + 1))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_todotxt, to_todotxt, TodoTxtTask};
+    use crate::RTMTasks;
+    use chrono::NaiveDate;
+    use std::collections::HashMap;
+
+    fn date(s: &str) -> Option<NaiveDate> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_to_todotxt() {
+        let task = |id: &str, priority: &str, due: &str, completed: &str| {
+            serde_json::json!({
+                "id": id, "due": due, "has_due_time": "0",
+                "added": "2026-10-01T09:00:00Z", "completed": completed, "deleted": "",
+                "priority": priority, "postponed": "0", "estimate": ""
+            })
+        };
+        let tasks: RTMTasks = serde_json::from_value(serde_json::json!({
+            "rev": "",
+            "list": [{
+                "id": "1",
+                "taskseries": [crate::tests::series(
+                    "2",
+                    serde_json::json!({
+                        "name": "Water plants",
+                        "tags": {"tag": ["home"]},
+                        "rrule": {"every": "1", "$t": "FREQ=WEEKLY;INTERVAL=2"},
+                        "task": [
+                            task("3", "1", "2026-10-19T23:00:00Z", ""),
+                            task("4", "2", "", "2026-10-05T12:00:00Z"),
+                        ],
+                    }),
+                    serde_json::json!({}),
+                )],
+            }],
+        }))
+        .unwrap();
+        let list_names = HashMap::from([("1".to_string(), "Around the house".to_string())]);
+        let tz = chrono_tz::Europe::London;
+        let text = to_todotxt(&tasks.entries(), &list_names, &tz);
+        assert_eq!(
+            text,
+            "(A) 2026-10-01 Water plants +Around_the_house @home due:2026-10-20 rec:+2w id:1-2-3\n\
+             x 2026-10-05 2026-10-01 Water plants +Around_the_house @home rec:+2w id:1-2-4\n"
+        );
+
+        let parsed = parse_todotxt(&text).unwrap();
+        assert_eq!(
+            parsed[1],
+            TodoTxtTask {
+                id: "1-2-4".into(),
+                completed: true,
+                completion_date: date("2026-10-05"),
+                priority: None,
+                creation_date: date("2026-10-01"),
+                name: "Water plants".into(),
+                projects: vec!["Around_the_house".into()],
+                contexts: vec!["home".into()],
+                due: None,
+                rec: Some("+2w".into()),
+            }
+        );
+        assert_eq!(parsed[0].priority, Some('A'));
+        assert_eq!(parsed[0].due, date("2026-10-20"));
+        assert_eq!(parsed[0].rtm_id(), Some("1-2-3"));
+    }
+
+    #[test]
+    fn test_parse_todotxt() {
+        let tasks = parse_todotxt(
+            "(B) Call Mom @phone +Family rec:1w\n\
+             \n\
+             x Call Mom @phone +Family rec:1w\n\
+             (b) lower case isn't a priority\n",
+        )
+        .unwrap();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].name, "Call Mom");
+        assert_eq!(tasks[0].rtm_priority(), "2");
+        assert_eq!(
+            tasks[0].rtm_repeat().unwrap().as_deref(),
+            Some("after 1 week")
+        );
+        // Completing a line doesn't change its id.
+        assert_eq!(tasks[0].id, tasks[1].id);
+        assert!(tasks[0].id.starts_with("todotxt:"));
+        assert_eq!(tasks[0].rtm_id(), None);
+        assert_eq!(tasks[2].name, "(b) lower case isn't a priority");
+        assert_eq!(tasks[2].rtm_priority(), "N");
+
+        for bad in ["+project @context", "Thing due:tomorrow", "x"] {
+            assert!(parse_todotxt(bad).is_err(), "{bad:?}");
+        }
+        let rec = |rec: &str| TodoTxtTask {
+            rec: Some(rec.into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            rec("+3m").rtm_repeat().unwrap().as_deref(),
+            Some("every 3 months")
+        );
+        assert_eq!(
+            rec("d").rtm_repeat().unwrap().as_deref(),
+            Some("after 1 day")
+        );
+        assert!(rec("1q").rtm_repeat().is_err());
+        assert!(rec("xw").rtm_repeat().is_err());
+    }
+}